@@ -0,0 +1,229 @@
+use crate::{
+    graph::PrincipalType,
+    models::{
+        roles::{Role, RoleAssignment},
+        scope::Scope,
+    },
+    ListFilter, PimClient,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssignmentState {
+    Active,
+    Eligible,
+}
+
+impl Display for AssignmentState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Active => write!(f, "active"),
+            Self::Eligible => write!(f, "eligible"),
+        }
+    }
+}
+
+/// A principal that holds, or is able to activate, a role
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RoleHolder {
+    pub role: Role,
+    pub scope: Scope,
+    pub state: AssignmentState,
+    pub principal_id: String,
+    pub display_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upn: Option<String>,
+    pub principal_type: PrincipalType,
+    /// The group the principal is a member of that holds the role
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub via_group: Option<String>,
+}
+
+impl RoleHolder {
+    fn from_assignment(assignment: RoleAssignment, state: AssignmentState) -> Option<Self> {
+        let object = assignment.object?;
+        Some(Self {
+            role: assignment.role,
+            scope: assignment.scope,
+            state,
+            principal_id: object.id,
+            display_name: object.display_name,
+            upn: object.upn,
+            principal_type: object.object_type,
+            via_group: None,
+        })
+    }
+}
+
+/// List the principals that hold, or are able to activate, `role` at `scope`
+///
+/// Assignments inherited from scopes above `scope` are included.  If `nested`
+/// is set, assignments at scopes below `scope` are included.  If
+/// `expand_groups` is set, the members of groups holding the role are
+/// included, including members of nested groups.
+///
+/// # Errors
+/// Will return `Err` if the assignments or group members cannot be listed
+pub fn who_has(
+    client: &PimClient,
+    role: &Role,
+    scope: &Scope,
+    nested: bool,
+    expand_groups: bool,
+) -> Result<BTreeSet<RoleHolder>> {
+    let included = |assignment: &RoleAssignment| {
+        assignment.role.0.eq_ignore_ascii_case(&role.0)
+            && (assignment.scope.contains(scope) || (nested && scope.contains(&assignment.scope)))
+    };
+
+    let active = client.list_active_role_assignments(Some(scope.clone()), None)?;
+    let eligible = client.list_eligible_role_assignments(Some(scope.clone()), None)?;
+
+    let mut results = active
+        .into_iter()
+        .map(|x| (x, AssignmentState::Active))
+        .chain(eligible.into_iter().map(|x| (x, AssignmentState::Eligible)))
+        .filter(|(x, _)| included(x))
+        .filter_map(|(x, state)| RoleHolder::from_assignment(x, state))
+        .collect::<BTreeSet<_>>();
+
+    if expand_groups {
+        let groups = results
+            .iter()
+            .filter(|x| x.principal_type == PrincipalType::Group)
+            .cloned()
+            .collect::<Vec<_>>();
+        for group in groups {
+            for member in client.nested_group_members(&group.principal_id)? {
+                let via_group = [group.display_name.clone()]
+                    .into_iter()
+                    .chain(member.via)
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                results.insert(RoleHolder {
+                    principal_id: member.object.id,
+                    display_name: member.object.display_name,
+                    upn: member.object.upn,
+                    principal_type: member.object.object_type,
+                    via_group: Some(via_group),
+                    ..group.clone()
+                });
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+/// How the current user can obtain a role
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    pub role: Role,
+    pub scope: Scope,
+    /// The state of the assignment that grants the role, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<AssignmentState>,
+    /// The scope of the assignment that grants the role
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignment_scope: Option<Scope>,
+    /// The group that holds the assignment, if not assigned to the user directly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub via_group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_date_time: Option<String>,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let (Some(state), Some(assignment_scope)) = (self.state, &self.assignment_scope) else {
+            return write!(
+                f,
+                "no, {} is neither active nor eligible at {}",
+                self.role, self.scope
+            );
+        };
+        match state {
+            AssignmentState::Active => write!(f, "yes, {} is active", self.role)?,
+            AssignmentState::Eligible => write!(f, "not yet, {} can be activated", self.role)?,
+        }
+        write!(f, " via the assignment at {assignment_scope}")?;
+        if let Some(via_group) = &self.via_group {
+            write!(f, " to the group {via_group}")?;
+        }
+        if let Some(end_date_time) = &self.end_date_time {
+            write!(f, " until {end_date_time}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Determine if the current user has `role` active at `scope`, can activate
+/// it, or has no way to obtain it
+///
+/// # Errors
+/// Will return `Err` if the assignments cannot be listed
+pub fn can_i(client: &PimClient, role: &Role, scope: &Scope) -> Result<Access> {
+    let grants =
+        |x: &RoleAssignment| x.role.0.eq_ignore_ascii_case(&role.0) && x.scope.contains(scope);
+
+    let mut access = Access {
+        role: role.clone(),
+        scope: scope.clone(),
+        state: None,
+        assignment_scope: None,
+        via_group: None,
+        end_date_time: None,
+    };
+
+    let active = client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
+    let (state, found) = if let Some(found) = active.into_iter().find(grants) {
+        (AssignmentState::Active, found)
+    } else {
+        let eligible = client.list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?;
+        let Some(found) = eligible.into_iter().find(grants) else {
+            return Ok(access);
+        };
+        (AssignmentState::Eligible, found)
+    };
+
+    access.via_group = assignment_group(client, &found, state)?;
+    access.state = Some(state);
+    access.assignment_scope = Some(found.scope);
+    access.end_date_time = found.end_date_time;
+    Ok(access)
+}
+
+/// Find the group that holds an assignment granted to the current user, if
+/// the assignment is not to the user directly
+fn assignment_group(
+    client: &PimClient,
+    assignment: &RoleAssignment,
+    state: AssignmentState,
+) -> Result<Option<String>> {
+    let scope = Some(assignment.scope.clone());
+    let filter = Some(ListFilter::AtScope);
+    let at_scope = match state {
+        AssignmentState::Active => client.list_active_role_assignments(scope, filter)?,
+        AssignmentState::Eligible => client.list_eligible_role_assignments(scope, filter)?,
+    };
+    let current_user = client.current_user()?;
+
+    let holders = at_scope
+        .into_iter()
+        .filter(|x| x.role == assignment.role && x.scope == assignment.scope)
+        .filter_map(|x| x.object)
+        .collect::<Vec<_>>();
+    if holders.iter().any(|x| x.id == current_user) {
+        return Ok(None);
+    }
+    Ok(holders
+        .into_iter()
+        .find(|x| x.object_type == PrincipalType::Group)
+        .map(|x| x.display_name))
+}
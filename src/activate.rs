@@ -1,7 +1,8 @@
+use crate::{hints::friendly_error, ActivationResult};
 use anyhow::{bail, Result};
 use reqwest::StatusCode;
 use serde_json::Value;
-use tracing::info;
+use tracing::{debug, info};
 
 // NOTE: serde_json doesn't panic on failed index slicing, it returns a Value
 // that allows further nested nulls
@@ -18,6 +19,10 @@ pub(crate) fn check_error_response(status: StatusCode, body: &Value) -> Result<(
                 return Ok(());
             }
         }
+        if let Some(friendly) = friendly_error(body) {
+            debug!("request failed: status:{status:#?} body:{body:#}");
+            bail!("request failed: {friendly}");
+        }
         bail!(
             "request failed: status:{status:#?} body:{}",
             serde_json::to_string_pretty(body)?
@@ -25,3 +30,85 @@ pub(crate) fn check_error_response(status: StatusCode, body: &Value) -> Result<(
     }
     Ok(())
 }
+
+/// Determine the status of an activation request from the response
+#[allow(clippy::indexing_slicing)]
+pub(crate) fn activation_status(body: &Value) -> ActivationResult {
+    if body["error"]["code"].as_str() == Some("RoleAssignmentExists") {
+        return ActivationResult::AlreadyActive;
+    }
+
+    if body["properties"]["status"]
+        .as_str()
+        .is_some_and(|x| x.starts_with("PendingApproval"))
+    {
+        return ActivationResult::PendingApproval;
+    }
+
+    ActivationResult::Succeeded
+}
+
+/// The state of an activation request that required approval
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ApprovalStatus {
+    Pending,
+    Approved,
+    /// The request was denied, canceled, or expired, with the reported status
+    Closed(String),
+}
+
+/// Determine the approval state of an activation request from the response
+#[allow(clippy::indexing_slicing)]
+pub(crate) fn approval_status(body: &Value) -> ApprovalStatus {
+    let status = body["properties"]["status"].as_str().unwrap_or_default();
+    match status {
+        "PendingApproval"
+        | "PendingApprovalProvisioning"
+        | "PendingEvaluation"
+        | "PendingAdminDecision" => ApprovalStatus::Pending,
+        "Accepted"
+        | "Granted"
+        | "PendingProvisioning"
+        | "PendingScheduleCreation"
+        | "Provisioned"
+        | "ScheduleCreated" => ApprovalStatus::Approved,
+        _ => ApprovalStatus::Closed(status.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{activation_status, approval_status, ApprovalStatus};
+    use crate::ActivationResult;
+    use serde_json::json;
+
+    #[test]
+    fn test_activation_status() {
+        let exists = json!({"error": {"code": "RoleAssignmentExists"}});
+        assert_eq!(activation_status(&exists), ActivationResult::AlreadyActive);
+
+        let pending = json!({"properties": {"status": "PendingApprovalProvisioning"}});
+        assert_eq!(
+            activation_status(&pending),
+            ActivationResult::PendingApproval
+        );
+
+        let provisioned = json!({"properties": {"status": "Provisioned"}});
+        assert_eq!(activation_status(&provisioned), ActivationResult::Succeeded);
+    }
+
+    #[test]
+    fn test_approval_status() {
+        let pending = json!({"properties": {"status": "PendingApproval"}});
+        assert_eq!(approval_status(&pending), ApprovalStatus::Pending);
+
+        let granted = json!({"properties": {"status": "Provisioned"}});
+        assert_eq!(approval_status(&granted), ApprovalStatus::Approved);
+
+        let denied = json!({"properties": {"status": "Denied"}});
+        assert_eq!(
+            approval_status(&denied),
+            ApprovalStatus::Closed("Denied".to_string())
+        );
+    }
+}
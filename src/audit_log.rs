@@ -0,0 +1,310 @@
+use crate::latest::cache_path;
+use anyhow::{bail, Context, Result};
+use humantime::{format_rfc3339_seconds, parse_rfc3339_weak};
+use reqwest::blocking::Request;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fmt::Write as _,
+    fs::{create_dir_all, read_to_string, File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    time::SystemTime,
+};
+use tracing::warn;
+
+const AUDIT_FILE: &str = "audit.jsonl";
+
+// how much of the end of the log is read at a time to find the last entry
+const TAIL_CHUNK: u64 = 4096;
+
+/// A request made through `az-pim` that changes role assignments, role
+/// definitions, or group memberships, stored in
+/// `$HOME/.cache/az-pim-cli/audit.jsonl`
+///
+/// Each entry includes the hash of the previous entry, such that removing or
+/// modifying entries can be detected using [`verify`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    /// The object id of the user that made the request
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub principal: Option<String>,
+    /// The command line of the process that made the request
+    pub command: Vec<String>,
+    pub method: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub body: Option<Value>,
+    /// The error, if the request failed
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+    /// The hash of the previous entry, or empty for the first entry
+    pub previous: String,
+    /// The hash of this entry, including `previous`
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// The time the request was made
+    ///
+    /// Returns `None` if the timestamp cannot be parsed.
+    #[must_use]
+    pub fn time(&self) -> Option<SystemTime> {
+        parse_rfc3339_weak(&self.timestamp).ok()
+    }
+
+    /// Calculate the hash of the entry, which covers every field other than
+    /// `hash`
+    fn calculate_hash(&self) -> Result<String> {
+        let entry = Self {
+            hash: String::new(),
+            ..self.clone()
+        };
+        let data = serde_json::to_string(&entry).context("unable to serialize audit entry")?;
+        let mut hash = String::new();
+        for byte in Sha256::digest(data.as_bytes()) {
+            write!(hash, "{byte:02x}").context("unable to format hash")?;
+        }
+        Ok(hash)
+    }
+}
+
+fn audit_path() -> Result<PathBuf> {
+    let cache_path = cache_path().context("unable to determine cache path")?;
+    Ok(cache_path.join(AUDIT_FILE))
+}
+
+fn read_log() -> Result<Option<String>> {
+    let path = audit_path()?;
+    match read_to_string(&path) {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("unable to read {}", path.display())),
+    }
+}
+
+/// Read the last non-empty line of the file, reading backwards from the end
+/// such that the cost does not grow with the size of the file
+fn last_line(file: &mut File) -> Result<Option<String>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut start = len;
+    let mut tail = Vec::new();
+    loop {
+        let text = String::from_utf8_lossy(&tail);
+        let trimmed = text.trim_end();
+        // the last line is complete once a newline precedes it, or the whole
+        // file has been read
+        if let Some(newline) = trimmed.rfind('\n') {
+            return Ok(trimmed
+                .get(newline + 1..)
+                .map(ToString::to_string)
+                .filter(|x| !x.trim().is_empty()));
+        }
+        if start == 0 {
+            return Ok(Some(trimmed.to_string()).filter(|x| !x.trim().is_empty()));
+        }
+        let chunk = TAIL_CHUNK.min(start);
+        start -= chunk;
+        file.seek(SeekFrom::Start(start))?;
+        let mut buf = vec![0; usize::try_from(chunk)?];
+        file.read_exact(&mut buf)?;
+        buf.extend(tail);
+        tail = buf;
+    }
+}
+
+fn append(mut entry: AuditEntry) -> Result<()> {
+    let path = audit_path()?;
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).context("unable to create cache path")?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("unable to open {}", path.display()))?;
+    // other processes may be appending to the log at the same time, and each
+    // entry must follow the entry before it
+    file.lock()
+        .with_context(|| format!("unable to lock {}", path.display()))?;
+
+    entry.previous = last_line(&mut file)
+        .with_context(|| format!("unable to read {}", path.display()))?
+        .map(|line| {
+            serde_json::from_str::<AuditEntry>(&line)
+                .map(|x| x.hash)
+                .context("unable to parse the last audit entry")
+        })
+        .transpose()?
+        .unwrap_or_default();
+    entry.hash = entry.calculate_hash()?;
+    let mut line = serde_json::to_string(&entry).context("unable to serialize audit entry")?;
+    line.push('\n');
+
+    file.write_all(line.as_bytes())
+        .with_context(|| format!("unable to write {}", path.display()))
+}
+
+/// Record a request that makes a change in the local audit log
+///
+/// Failing to record the request only logs a warning, as the request itself
+/// has already been made.
+pub(crate) fn record(request: &Request, principal: Option<String>, result: &Result<Value>) {
+    let body = request
+        .body()
+        .and_then(|x| x.as_bytes())
+        .and_then(|x| serde_json::from_slice(x).ok());
+    let entry = AuditEntry {
+        timestamp: format_rfc3339_seconds(SystemTime::now()).to_string(),
+        principal,
+        command: env::args().collect(),
+        method: request.method().to_string(),
+        url: request.url().to_string(),
+        body,
+        error: result.as_ref().err().map(|err| format!("{err:#}")),
+        previous: String::new(),
+        hash: String::new(),
+    };
+
+    if let Err(err) = append(entry) {
+        warn!("unable to record audit log: {err:?}");
+    }
+}
+
+fn parse(data: &str) -> Result<Vec<AuditEntry>> {
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("unable to parse line {}", number + 1))
+        })
+        .collect()
+}
+
+/// Load the audit log, optionally limited to entries made between `since`
+/// and `until`
+///
+/// # Errors
+/// Will return `Err` if the audit log exists and cannot be read or parsed
+pub fn load(since: Option<SystemTime>, until: Option<SystemTime>) -> Result<Vec<AuditEntry>> {
+    let Some(data) = read_log()? else {
+        return Ok(Vec::new());
+    };
+    let mut entries = parse(&data)?;
+    entries.retain(|entry| {
+        let time = entry.time();
+        since.is_none_or(|since| time.is_some_and(|x| x >= since))
+            && until.is_none_or(|until| time.is_some_and(|x| x < until))
+    });
+    Ok(entries)
+}
+
+/// Check that none of the entries in the audit log have been modified or
+/// removed, other than removing the most recent entries
+///
+/// Returns the number of entries.
+///
+/// # Errors
+/// Will return `Err` if the audit log cannot be read, or describing the
+/// first entry that does not match the hash chain
+pub fn verify() -> Result<usize> {
+    let Some(data) = read_log()? else {
+        return Ok(0);
+    };
+    verify_entries(&parse(&data)?)
+}
+
+fn verify_entries(entries: &[AuditEntry]) -> Result<usize> {
+    let mut previous = "";
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.previous != previous {
+            bail!(
+                "entry {} from {} does not follow the previous entry",
+                index + 1,
+                entry.timestamp
+            );
+        }
+        if entry.calculate_hash()? != entry.hash {
+            bail!(
+                "entry {} from {} has been modified",
+                index + 1,
+                entry.timestamp
+            );
+        }
+        previous = &entry.hash;
+    }
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{last_line, verify_entries, AuditEntry, TAIL_CHUNK};
+    use anyhow::Result;
+    use std::{env, fs::OpenOptions, io::Write, process};
+
+    fn chain(count: usize) -> Result<Vec<AuditEntry>> {
+        let mut entries = Vec::<AuditEntry>::new();
+        for i in 0..count {
+            let mut entry = AuditEntry {
+                timestamp: format!("2024-01-02T03:04:0{i}Z"),
+                principal: Some("00000000-0000-0000-0000-000000000000".to_string()),
+                command: vec!["az-pim".to_string(), "deactivate".to_string()],
+                method: "PUT".to_string(),
+                url: "https://management.azure.com/subscriptions/00000000-0000-0000-0000-000000000000".to_string(),
+                body: None,
+                error: None,
+                previous: entries.last().map(|x| x.hash.clone()).unwrap_or_default(),
+                hash: String::new(),
+            };
+            entry.hash = entry.calculate_hash()?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn test_verify() -> Result<()> {
+        let entries = chain(3)?;
+        assert_eq!(verify_entries(&entries)?, 3);
+
+        let mut modified = entries.clone();
+        if let Some(entry) = modified.get_mut(1) {
+            entry.method = "DELETE".to_string();
+        }
+        assert!(verify_entries(&modified).is_err());
+
+        let mut removed = entries.clone();
+        removed.remove(1);
+        assert!(verify_entries(&removed).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_last_line() -> Result<()> {
+        let path = env::temp_dir().join(format!("az-pim-audit-test-{}.jsonl", process::id()));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        assert_eq!(last_line(&mut file)?, None);
+
+        file.write_all(b"first\n")?;
+        assert_eq!(last_line(&mut file)?.as_deref(), Some("first"));
+
+        // lines longer than a single chunk are read in full
+        let long = "x".repeat(usize::try_from(TAIL_CHUNK)? * 2 + 1);
+        write!(file, "{long}\n\n")?;
+        assert_eq!(last_line(&mut file)?, Some(long));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
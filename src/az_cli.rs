@@ -1,7 +1,18 @@
-use anyhow::{ensure, Context, Result};
+use crate::hints::hint;
+use anyhow::{bail, ensure, Context, Result};
 use base64::prelude::{Engine, BASE64_STANDARD_NO_PAD};
+use clap::ValueEnum;
+use home::home_dir;
 use serde_json::Value;
-use std::process::Command;
+use std::{
+    env,
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs::read_to_string,
+    path::PathBuf,
+    process::Command,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub(crate) enum TokenScope {
@@ -18,20 +29,65 @@ impl TokenScope {
     }
 }
 
+/// How tokens are obtained from the Azure CLI
+#[allow(clippy::manual_assert, clippy::panic)]
+#[derive(Clone, Copy, Default, ValueEnum, PartialEq, Eq, Debug)]
+pub enum AuthMethod {
+    /// Use the Azure CLI's configured login
+    #[default]
+    AzureCli,
+    /// Use the Web Account Manager (WAM) broker on Windows, which satisfies
+    /// device compliance Conditional Access policies
+    ///
+    /// This enables the Azure CLI's broker support.  If a token cannot be
+    /// obtained, login using `az login`, which uses the broker as well.
+    Broker,
+}
+
+impl Display for AuthMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::AzureCli => write!(f, "azure-cli"),
+            Self::Broker => write!(f, "broker"),
+        }
+    }
+}
+
+// the Azure CLI reads settings from `AZURE_<SECTION>_<NAME>` environment
+// variables, which is used to enable the broker without changing its config
+const ENABLE_BROKER_ENV: &str = "AZURE_CORE_ENABLE_BROKER_ON_WINDOWS";
+
 #[cfg(target_os = "windows")]
 const AZ_CMD: &str = "az.cmd";
 #[cfg(not(target_os = "windows"))]
 const AZ_CMD: &str = "az";
 
+// environment variable used to specify the path to the Azure CLI
+const AZ_PATH_ENV: &str = "AZ_PIM_AZ_PATH";
+
+/// Find the Azure CLI, using `AZ_PIM_AZ_PATH` if set, otherwise `az` from
+/// the `PATH`
+fn find_az() -> PathBuf {
+    env::var_os(AZ_PATH_ENV).map_or_else(|| PathBuf::from(AZ_CMD), PathBuf::from)
+}
+
 /// Execute an Azure CLI command
 ///
 /// # Errors
 /// Will return `Err` if the Azure CLI fails
-fn az_cmd(args: &[&str]) -> Result<String> {
-    let output = Command::new(AZ_CMD)
-        .args(args)
+fn az_cmd(args: &[&str], auth_method: AuthMethod) -> Result<String> {
+    let az = find_az();
+    let mut command = Command::new(&az);
+    command.args(args);
+    if auth_method == AuthMethod::Broker {
+        if !cfg!(target_os = "windows") {
+            bail!("the broker authentication method is only supported on Windows");
+        }
+        command.env(ENABLE_BROKER_ENV, "true");
+    }
+    let output = command
         .output()
-        .with_context(|| format!("unable to launch {AZ_CMD}"))?;
+        .with_context(|| format!("unable to launch {}", az.display()))?;
     ensure!(
         output.status.success(),
         "az command failed {}",
@@ -43,10 +99,17 @@ fn az_cmd(args: &[&str]) -> Result<String> {
 
 /// Get an Oauth token from Azure CLI for the current user
 ///
+/// If `tenant` is not specified, the token is for the tenant of the Azure
+/// CLI's default subscription.
+///
 /// # Errors
 /// Will return `Err` if the Azure CLI fails
-pub(crate) fn get_token(scope: TokenScope) -> Result<String> {
-    az_cmd(&[
+pub(crate) fn get_token(
+    scope: TokenScope,
+    tenant: Option<&str>,
+    auth_method: AuthMethod,
+) -> Result<String> {
+    let mut args = vec![
         "account",
         "get-access-token",
         "--scope",
@@ -55,17 +118,124 @@ pub(crate) fn get_token(scope: TokenScope) -> Result<String> {
         "accessToken",
         "--output",
         "tsv",
-    ])
-    .with_context(|| format!("unable to obtain token to {}", scope.to_scope_endpoint()))
+    ];
+    if let Some(tenant) = tenant {
+        args.extend(["--tenant", tenant]);
+    }
+    az_cmd(&args, auth_method).map_err(|err| {
+        let context = format!("unable to obtain token to {}", scope.to_scope_endpoint());
+        match hint(&format!("{err:#}")) {
+            Some(hint) => err.context(format!("{context}\nhint: {hint}")),
+            None => err.context(context),
+        }
+    })
 }
 
-pub(crate) fn extract_oid(token: &str) -> Result<String> {
+/// List the ids of the tenants the current user has subscriptions in
+///
+/// # Errors
+/// Will return `Err` if the Azure CLI fails
+pub(crate) fn list_tenants() -> Result<Vec<String>> {
+    let output = az_cmd(
+        &[
+            "account",
+            "list",
+            "--query",
+            "[].tenantId",
+            "--output",
+            "tsv",
+        ],
+        AuthMethod::AzureCli,
+    )
+    .context("unable to list tenants")?;
+    let mut tenants = output
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    tenants.sort();
+    tenants.dedup();
+    Ok(tenants)
+}
+
+/// The Azure CLI's configuration directory, honoring `AZURE_CONFIG_DIR`
+fn config_dir() -> Option<PathBuf> {
+    env::var_os("AZURE_CONFIG_DIR")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|x| x.join(".azure")))
+}
+
+/// Get the Azure CLI's default subscription from `azureProfile.json`
+///
+/// # Errors
+/// Will return `Err` if the profile cannot be read or has no default
+/// subscription
+pub(crate) fn default_subscription() -> Result<Uuid> {
+    let path = config_dir()
+        .context("unable to find Azure CLI config directory")?
+        .join("azureProfile.json");
+    let profile =
+        read_to_string(&path).with_context(|| format!("unable to read {}", path.display()))?;
+    parse_default_subscription(&profile)
+        .with_context(|| format!("no default subscription in {}", path.display()))
+}
+
+fn parse_default_subscription(profile: &str) -> Result<Uuid> {
+    // the Azure CLI writes the profile with a byte order mark
+    let profile: Value = serde_json::from_str(profile.trim_start_matches('\u{feff}'))?;
+    let subscription = profile
+        .get("subscriptions")
+        .and_then(Value::as_array)
+        .context("no subscriptions")?
+        .iter()
+        .find(|x| x.get("isDefault").and_then(Value::as_bool) == Some(true))
+        .and_then(|x| x.get("id"))
+        .and_then(Value::as_str)
+        .context("no default subscription")?;
+    Ok(subscription.parse()?)
+}
+
+fn claims(token: &str) -> Result<Value> {
     let token = BASE64_STANDARD_NO_PAD.decode(token.split('.').nth(1).context("invalid token")?)?;
-    let token: Value = serde_json::from_slice(&token)?;
-    Ok(token
+    Ok(serde_json::from_slice(&token)?)
+}
+
+pub(crate) fn extract_oid(token: &str) -> Result<String> {
+    Ok(claims(token)?
         .get("oid")
         .context("no oid in token")?
         .as_str()
         .context("token is not string")?
         .to_string())
 }
+
+/// Determine when the token expires
+pub(crate) fn extract_expiration(token: &str) -> Result<SystemTime> {
+    let exp = claims(token)?
+        .get("exp")
+        .context("no exp in token")?
+        .as_u64()
+        .context("exp is not a number")?;
+    Ok(UNIX_EPOCH + Duration::from_secs(exp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_default_subscription;
+    use anyhow::Result;
+
+    #[test]
+    fn test_default_subscription() -> Result<()> {
+        let profile = "\u{feff}{\"subscriptions\": [
+            {\"id\": \"00000000-0000-0000-0000-000000000001\", \"isDefault\": false},
+            {\"id\": \"00000000-0000-0000-0000-000000000002\", \"isDefault\": true}
+        ]}";
+        assert_eq!(
+            parse_default_subscription(profile)?.to_string(),
+            "00000000-0000-0000-0000-000000000002"
+        );
+        assert!(parse_default_subscription("{\"subscriptions\": []}").is_err());
+        Ok(())
+    }
+}
@@ -1,25 +1,49 @@
 use crate::{
-    az_cli::{extract_oid, get_token, TokenScope},
+    audit_log,
+    az_cli::{extract_expiration, extract_oid, get_token, AuthMethod, TokenScope},
+    capture::{Capture, PendingEntry},
+    coalesce::Coalesce,
+    hints::friendly_error,
     models::scope::Scope,
 };
 use anyhow::{anyhow, Context, Result};
 use derive_setters::Setters;
 use parking_lot::Mutex;
+use rayon::prelude::*;
 use reqwest::{
     blocking::{Client, Request},
     Method, StatusCode,
 };
-use retry::{
-    delay::{jitter, Fixed},
-    retry, OperationResult,
+use retry::{delay::jitter, retry, OperationResult};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    env::var,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
 };
-use serde_json::Value;
-use std::{collections::BTreeMap, time::Duration};
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 
-const RETRY_COUNT: usize = 10;
+const DEFAULT_RETRY_COUNT: usize = 10;
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(5);
+// the maximum number of requests ARM accepts in a single batch request
+const BATCH_SIZE: usize = 20;
+const BATCH_PATH: &str = "/batch?api-version=2020-06-01";
+const DEFAULT_ARM_URL: &str = "https://management.azure.com";
+const DEFAULT_GRAPH_URL: &str = "https://graph.microsoft.com";
+// environment variables used to override the ARM and Graph endpoints, such
+// as for private endpoints or mock servers
+const ARM_URL_ENV: &str = "AZ_PIM_ARM_URL";
+const GRAPH_URL_ENV: &str = "AZ_PIM_GRAPH_URL";
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_COMMAND_NAME: &str = "Microsoft_Azure_PIMCommon.";
+// refresh tokens before they expire to allow for long running commands
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60 * 5);
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 #[allow(clippy::enum_variant_names, dead_code)]
 pub(crate) enum Operation {
     RoleAssignments,
@@ -29,6 +53,8 @@ pub(crate) enum Operation {
     RoleEligibilityScheduleRequests,
     RoleAssignmentScheduleRequests,
     EligibleChildResources,
+    RoleManagementPolicyAssignments,
+    RoleAssignmentApprovals,
 }
 
 impl Operation {
@@ -41,6 +67,8 @@ impl Operation {
             Self::RoleEligibilityScheduleRequests => "roleEligibilityScheduleRequests",
             Self::RoleAssignmentScheduleRequests => "roleAssignmentScheduleRequests",
             Self::EligibleChildResources => "eligibleChildResources",
+            Self::RoleManagementPolicyAssignments => "roleManagementPolicyAssignments",
+            Self::RoleAssignmentApprovals => "roleAssignmentApprovals",
         }
     }
 
@@ -52,7 +80,9 @@ impl Operation {
             | Self::RoleEligibilityScheduleInstances
             | Self::RoleEligibilityScheduleRequests
             | Self::RoleAssignmentScheduleRequests
-            | Self::EligibleChildResources => TokenScope::Management,
+            | Self::EligibleChildResources
+            | Self::RoleManagementPolicyAssignments
+            | Self::RoleAssignmentApprovals => TokenScope::Management,
         }
     }
 
@@ -63,11 +93,92 @@ impl Operation {
             | Self::RoleEligibilityScheduleInstances
             | Self::RoleEligibilityScheduleRequests
             | Self::RoleAssignmentScheduleRequests
-            | Self::EligibleChildResources => "2020-10-01",
+            | Self::EligibleChildResources
+            | Self::RoleManagementPolicyAssignments => "2020-10-01",
+            Self::RoleAssignmentApprovals => "2021-01-01-preview",
         }
     }
 }
 
+/// How requests that fail with transient errors, such as being rate limited,
+/// are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries, where `0` disables retrying requests
+    pub max_retries: usize,
+    /// Delay before the first retry, which doubles for each retry after
+    pub base_delay: Duration,
+    /// Upper bound on the delay between retries
+    pub max_delay: Duration,
+    /// Randomize each delay to between zero and the delay, which spreads out
+    /// the retries of concurrent requests
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_RETRY_COUNT,
+            base_delay: DEFAULT_RETRY_DELAY,
+            max_delay: DEFAULT_RETRY_DELAY,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry requests, such as for automation that should fail fast
+    #[must_use]
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// The delay before each of the retries
+    fn delays(self) -> impl Iterator<Item = Duration> {
+        (0..self.max_retries).map(move |attempt| {
+            let delay = u32::try_from(attempt)
+                .ok()
+                .and_then(|x| 2_u32.checked_pow(x))
+                .and_then(|x| self.base_delay.checked_mul(x))
+                .map_or(self.max_delay, |x| x.min(self.max_delay));
+            if self.jitter {
+                jitter(delay)
+            } else {
+                delay
+            }
+        })
+    }
+}
+
+pub(crate) type Validate = fn(StatusCode, &Value) -> Result<()>;
+
+/// Use the endpoint from the environment variable, if it is set
+fn endpoint(env_var: &str, default: &str) -> String {
+    let url = var(env_var).unwrap_or_else(|_| default.to_string());
+    url.trim_end_matches('/').to_string()
+}
+
+/// Check the status of a response, using `validate` if provided
+fn check_response(status: StatusCode, body: Value, validate: Option<Validate>) -> Result<Value> {
+    if let Some(validate) = validate {
+        validate(status, &body)?;
+        return Ok(body);
+    }
+
+    if !status.is_success() {
+        if let Some(friendly) = friendly_error(&body) {
+            debug!("request failed: status: {status} {body:#?}");
+            return Err(anyhow!("request failed: {friendly}"));
+        }
+        return Err(anyhow!("request failed: status: {status} {body:#?}"));
+    }
+
+    Ok(body)
+}
+
 macro_rules! try_or_stop {
     ($e:expr) => {
         match $e {
@@ -92,15 +203,78 @@ macro_rules! try_or_retry {
 
 pub(crate) struct Backend {
     pub(crate) client: Client,
-    tokens: Mutex<BTreeMap<TokenScope, String>>,
+    /// The tenant to request tokens for, rather than the Azure CLI's default
+    pub(crate) tenant: Option<String>,
+    /// Records the requests and responses, if enabled
+    pub(crate) capture: Option<Arc<Capture>>,
+    pub(crate) retry_policy: RetryPolicy,
+    /// The value of the `X-Ms-Command-Name` header sent to ARM
+    pub(crate) command_name: String,
+    /// How tokens are obtained from the Azure CLI
+    pub(crate) auth_method: AuthMethod,
+    /// The base URL of the ARM endpoint
+    pub(crate) arm_url: String,
+    /// The base URL of the Graph endpoint
+    pub(crate) graph_url: String,
+    /// Record requests that may modify resources in the local audit log
+    pub(crate) audit_log: bool,
+    tokens: Mutex<BTreeMap<TokenScope, (String, Option<SystemTime>)>>,
+    in_flight: Coalesce<String, Value>,
+    writes: AtomicU64,
 }
 
 impl Backend {
-    pub(crate) fn new() -> Self {
-        Self {
-            client: Client::new(),
+    pub(crate) fn new() -> Result<Self> {
+        Ok(Self {
+            client: Self::build_client(None)?,
+            tenant: None,
+            capture: None,
+            retry_policy: RetryPolicy::default(),
+            command_name: DEFAULT_COMMAND_NAME.to_string(),
+            auth_method: AuthMethod::default(),
+            arm_url: endpoint(ARM_URL_ENV, DEFAULT_ARM_URL),
+            graph_url: endpoint(GRAPH_URL_ENV, DEFAULT_GRAPH_URL),
+            audit_log: false,
             tokens: Mutex::new(BTreeMap::new()),
+            in_flight: Coalesce::new(),
+            writes: AtomicU64::new(0),
+        })
+    }
+
+    /// The URL of a Graph API path, such as `/v1.0/users`
+    pub(crate) fn graph(&self, path: &str) -> String {
+        format!("{}{path}", self.graph_url)
+    }
+
+    /// Build the HTTP client, appending `application` to the User-Agent
+    pub(crate) fn build_client(application: Option<&str>) -> Result<Client> {
+        let user_agent = match application {
+            Some(application) => format!("{USER_AGENT} {application}"),
+            None => USER_AGENT.to_string(),
+        };
+        Ok(Client::builder().user_agent(user_agent).build()?)
+    }
+
+    /// Send a request that may modify resources, recording it in the local
+    /// audit log if enabled
+    pub(crate) fn send_write(
+        &self,
+        request: &Request,
+        validate: Option<Validate>,
+    ) -> Result<Value> {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        let result = self.retry_request(request, validate);
+        if self.audit_log {
+            audit_log::record(request, self.principal_id().ok(), &result);
         }
+        result
+    }
+
+    /// The number of requests sent that may have modified resources
+    ///
+    /// This is used to identify cached responses that may be out of date.
+    pub(crate) fn writes(&self) -> u64 {
+        self.writes.load(Ordering::SeqCst)
     }
 
     pub(crate) fn principal_id(&self) -> Result<String> {
@@ -110,68 +284,193 @@ impl Backend {
 
     pub(crate) fn get_token(&self, scope: TokenScope) -> Result<String> {
         let mut tokens = self.tokens.lock();
-        if let Some(token) = tokens.get(&scope) {
-            return Ok(token.clone());
+        if let Some((token, expiration)) = tokens.get(&scope) {
+            let expiring = expiration
+                .is_some_and(|expiration| SystemTime::now() + TOKEN_REFRESH_MARGIN >= expiration);
+            if !expiring {
+                return Ok(token.clone());
+            }
+            debug!("refreshing token");
         }
 
-        let token = get_token(scope)?;
-        tokens.insert(scope, token.clone());
+        let token = get_token(scope, self.tenant.as_deref(), self.auth_method)?;
+        let expiration = extract_expiration(&token)
+            .inspect_err(|err| debug!("unable to determine token expiration: {err}"))
+            .ok();
+        tokens.insert(scope, (token.clone(), expiration));
         Ok(token)
     }
 
     fn try_request(
-        client: &Client,
+        &self,
         request: Request,
         validate: Option<for<'a> fn(StatusCode, &'a Value) -> Result<()>>,
     ) -> OperationResult<Value, anyhow::Error> {
         debug!("sending request: {request:?}");
-        let response = try_or_retry!(client.execute(request));
-        let status = response.status();
+        let pending = self
+            .capture
+            .as_ref()
+            .map(|capture| (capture, PendingEntry::new(&request)));
+        let response = self.client.execute(request).and_then(|response| {
+            let status = response.status();
+            response.text().map(|body| (status, body))
+        });
+        if let Some((capture, pending)) = pending {
+            match &response {
+                Ok((status, body)) => capture.finish(pending, Ok((*status, body))),
+                Err(err) => capture.finish(pending, Err(anyhow!("{err}"))),
+            }
+        }
+        let (status, body) = try_or_retry!(response);
 
         debug!("got status sending request: {status:?}");
         if status == StatusCode::TOO_MANY_REQUESTS {
             return OperationResult::Retry(anyhow!("rate limited"));
         }
 
-        debug!("getting response json");
-        let body = try_or_stop!(response.text());
         trace!("response body: {body:#?}");
         let body = try_or_stop!(serde_json::from_str(&body));
 
-        if let Some(validate) = validate {
-            try_or_stop!(validate(status, &body));
-            return OperationResult::Ok(body);
+        match check_response(status, body, validate) {
+            Ok(body) => OperationResult::Ok(body),
+            Err(err) => OperationResult::Err(err),
         }
+    }
 
-        if !status.is_success() {
-            return OperationResult::Err(anyhow!("request failed: status: {status} {body:#?}"));
+    /// Send a request, retrying transient failures
+    ///
+    /// Identical GET requests made concurrently share a single request.
+    pub(crate) fn retry_request(
+        &self,
+        request: &Request,
+        validate: Option<for<'a> fn(StatusCode, &'a Value) -> Result<()>>,
+    ) -> Result<Value> {
+        if request.method() == Method::GET {
+            let key = request.url().to_string();
+            return self
+                .in_flight
+                .run(&key, || self.retry_request_uncoalesced(request, validate));
         }
-
-        OperationResult::Ok(body)
+        self.retry_request_uncoalesced(request, validate)
     }
 
-    pub(crate) fn retry_request(
+    fn retry_request_uncoalesced(
         &self,
         request: &Request,
         validate: Option<for<'a> fn(StatusCode, &'a Value) -> Result<()>>,
     ) -> Result<Value> {
-        let retries = Fixed::from(Duration::from_secs(5))
-            .map(jitter)
-            .take(RETRY_COUNT);
+        let retries = self.retry_policy.delays();
         let operation = || {
             let Some(request) = request.try_clone() else {
                 return OperationResult::Err(anyhow!("unable to clone request"));
             };
-            Self::try_request(&self.client, request, validate)
+            self.try_request(request, validate)
         };
         retry(retries, operation).map_err(|e| e.error)
     }
 
-    pub(crate) fn request(&self, method: Method, operation: Operation) -> RequestBuilder {
+    /// Send a set of GET requests using the ARM batch API
+    ///
+    /// The requests are combined into batches to reduce the number of round
+    /// trips, and the results are returned in the same order as `requests`.
+    /// Requests that are throttled, or batches that fail entirely, are retried
+    /// individually.
+    pub(crate) fn send_batch(&self, requests: Vec<RequestBuilder<'_>>) -> Vec<Result<Value>> {
+        let mut chunks = Vec::new();
+        let mut requests = requests.into_iter().map(RequestBuilder::build).peekable();
+        while requests.peek().is_some() {
+            chunks.push(requests.by_ref().take(BATCH_SIZE).collect::<Vec<_>>());
+        }
+
+        chunks
+            .into_par_iter()
+            .flat_map_iter(|chunk| {
+                let built = chunk
+                    .iter()
+                    .filter_map(|x| x.as_ref().ok())
+                    .collect::<Vec<_>>();
+                let mut responses = self.try_batch(&built).unwrap_or_else(|err| {
+                    warn!("batch request failed, sending requests individually: {err:#}");
+                    BTreeMap::new()
+                });
+
+                let mut index = 0;
+                chunk
+                    .into_iter()
+                    .map(|entry| {
+                        let (request, validate) = entry?;
+                        let response = responses.remove(&index);
+                        index += 1;
+                        match response {
+                            Some((status, body)) if status != StatusCode::TOO_MANY_REQUESTS => {
+                                check_response(status, body, validate)
+                            }
+                            _ => self.retry_request(&request, validate),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Send a single batch request, returning the status and body of each of
+    /// the responses by their index in `requests`
+    fn try_batch(
+        &self,
+        requests: &[&(Request, Option<Validate>)],
+    ) -> Result<BTreeMap<usize, (StatusCode, Value)>> {
+        let body = json!({
+            "requests": requests
+                .iter()
+                .enumerate()
+                .map(|(index, (request, _))| json!({
+                    "httpMethod": request.method().as_str(),
+                    "name": index.to_string(),
+                    "url": request.url().as_str(),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        let request = self
+            .client
+            .post(format!("{}{BATCH_PATH}", self.arm_url))
+            .bearer_auth(self.get_token(TokenScope::Management)?)
+            .json(&body)
+            .build()?;
+        let response = self.retry_request(&request, None)?;
+        parse_batch_response(&response)
+    }
+
+    pub(crate) fn request(&self, method: Method, operation: Operation) -> RequestBuilder<'_> {
         RequestBuilder::new(self, method, operation)
     }
 }
 
+/// Parse the status and body of each of the responses to a batch request, by
+/// the index of the request they are for
+fn parse_batch_response(response: &Value) -> Result<BTreeMap<usize, (StatusCode, Value)>> {
+    let mut results = BTreeMap::new();
+    for entry in response
+        .get("responses")
+        .and_then(Value::as_array)
+        .context("missing responses in batch response")?
+    {
+        let index = entry
+            .get("name")
+            .and_then(Value::as_str)
+            .and_then(|x| x.parse::<usize>().ok())
+            .context("missing name in batch response")?;
+        let status = entry
+            .get("httpStatusCode")
+            .and_then(Value::as_u64)
+            .and_then(|x| u16::try_from(x).ok())
+            .and_then(|x| StatusCode::from_u16(x).ok())
+            .context("missing status in batch response")?;
+        let content = entry.get("content").cloned().unwrap_or(Value::Null);
+        results.insert(index, (status, content));
+    }
+    Ok(results)
+}
+
 #[derive(Setters)]
 #[setters(strip_option)]
 pub(crate) struct RequestBuilder<'a> {
@@ -212,6 +511,16 @@ impl<'a> RequestBuilder<'a> {
     }
 
     pub(crate) fn send(self) -> Result<Value> {
+        let backend = self.backend;
+        let (request, validate) = self.build()?;
+        if request.method() == Method::GET {
+            backend.retry_request(&request, validate)
+        } else {
+            backend.send_write(&request, validate)
+        }
+    }
+
+    fn build(self) -> Result<(Request, Option<Validate>)> {
         let Self {
             backend,
             method,
@@ -226,7 +535,8 @@ impl<'a> RequestBuilder<'a> {
         let scope = scope.map(|x| x.0).unwrap_or_default();
         let extra = extra.unwrap_or_default();
         let url = format!(
-            "https://management.azure.com{scope}/providers/Microsoft.Authorization/{}{extra}",
+            "{}{scope}/providers/Microsoft.Authorization/{}{extra}",
+            backend.arm_url,
             operation.as_str()
         );
 
@@ -234,7 +544,7 @@ impl<'a> RequestBuilder<'a> {
             .client
             .request(method, url)
             .query(&[("api-version", operation.api_version())])
-            .header("X-Ms-Command-Name", "Microsoft_Azure_PIMCommon.")
+            .header("X-Ms-Command-Name", &backend.command_name)
             .bearer_auth(backend.get_token(operation.token_scope())?);
 
         if let Some(query) = query {
@@ -244,7 +554,62 @@ impl<'a> RequestBuilder<'a> {
             builder = builder.json(&json);
         }
 
-        let request = builder.build()?;
-        backend.retry_request(&request, validate)
+        Ok((builder.build()?, validate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_batch_response, RetryPolicy};
+    use anyhow::Result;
+    use reqwest::StatusCode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_batch_response() -> Result<()> {
+        // responses are not necessarily in the order of the requests
+        let response = json!({
+            "responses": [
+                {"name": "2", "httpStatusCode": 200, "content": {"value": [2]}},
+                {"name": "0", "httpStatusCode": 429},
+                {"name": "1", "httpStatusCode": 200, "content": {"value": [1], "nextLink": "https://example.com/next"}},
+            ]
+        });
+        let results = parse_batch_response(&response)?;
+        assert_eq!(results.keys().copied().collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(
+            results.get(&0),
+            Some(&(StatusCode::TOO_MANY_REQUESTS, serde_json::Value::Null))
+        );
+        assert_eq!(
+            results.get(&1).map(|(_, x)| &x["nextLink"]),
+            Some(&json!("https://example.com/next"))
+        );
+        assert_eq!(
+            results.get(&2),
+            Some(&(StatusCode::OK, json!({"value": [2]})))
+        );
+
+        assert!(parse_batch_response(&json!({})).is_err());
+        assert!(parse_batch_response(&json!({"responses": [{"httpStatusCode": 200}]})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_delays() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(6),
+            jitter: false,
+        };
+        let delays = policy.delays().map(|x| x.as_secs()).collect::<Vec<_>>();
+        assert_eq!(delays, [1, 2, 4, 6, 6]);
+
+        assert_eq!(RetryPolicy::none().delays().count(), 0);
+        assert!(RetryPolicy::default()
+            .delays()
+            .all(|x| x <= Duration::from_secs(5)));
     }
 }
@@ -0,0 +1,430 @@
+use crate::{
+    graph::Object,
+    models::{
+        assignments::Assignment,
+        definitions::Definition,
+        roles::{Role, RoleAssignment},
+        scope::Scope,
+    },
+    PimClient,
+};
+use anyhow::{ensure, Context, Result};
+use humantime::{format_rfc3339_seconds, parse_rfc3339_weak};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Formatter, Result as FmtResult},
+    time::SystemTime,
+};
+use tracing::{info, warn};
+
+const RESTORE_JUSTIFICATION: &str = "restored from backup";
+
+/// The current version of the backup format
+pub const BACKUP_VERSION: u32 = 1;
+
+/// A single active or eligible role assignment in a backup
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BackupEntry {
+    pub role: Role,
+    pub scope: Scope,
+    pub role_definition_id: String,
+    pub principal_id: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub principal_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub principal_name: Option<String>,
+    /// ABAC condition of an active assignment
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub condition: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub condition_version: Option<String>,
+    /// When an eligible assignment expires
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub end_date_time: Option<String>,
+}
+
+impl BackupEntry {
+    fn result(&self, kind: RestoreKind, status: RestoreStatus) -> RestoreResult {
+        RestoreResult {
+            kind,
+            role: self.role.clone(),
+            scope: self.scope.clone(),
+            principal_id: Some(self.principal_id.clone()),
+            status,
+        }
+    }
+
+    fn from_assignment(assignment: &Assignment, definitions: &[Definition]) -> Self {
+        let properties = &assignment.properties;
+        let role = definitions
+            .iter()
+            .find(|x| x.id.eq_ignore_ascii_case(&properties.role_definition_id))
+            .map_or_else(
+                || properties.role_definition_id.clone(),
+                |x| x.properties.role_name.clone(),
+            );
+        Self {
+            role: Role(role),
+            scope: properties.scope.clone(),
+            role_definition_id: properties.role_definition_id.clone(),
+            principal_id: properties.principal_id.clone(),
+            principal_type: Some(properties.principal_type.clone()),
+            principal_name: assignment.object.as_ref().map(|x| x.display_name.clone()),
+            condition: properties.condition.clone(),
+            condition_version: properties.condition_version.clone(),
+            end_date_time: None,
+        }
+    }
+
+    fn from_eligible(assignment: RoleAssignment) -> Option<Self> {
+        Some(Self {
+            principal_id: assignment.principal_id?,
+            principal_name: assignment.object.map(|x| x.display_name),
+            role: assignment.role,
+            scope: assignment.scope,
+            role_definition_id: assignment.role_definition_id,
+            principal_type: assignment.principal_type,
+            condition: None,
+            condition_version: None,
+            end_date_time: assignment.end_date_time,
+        })
+    }
+}
+
+/// The kind of item restored from a backup
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestoreKind {
+    RoleDefinition,
+    Active,
+    Eligible,
+}
+
+impl Display for RestoreKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::RoleDefinition => write!(f, "role-definition"),
+            Self::Active => write!(f, "active"),
+            Self::Eligible => write!(f, "eligible"),
+        }
+    }
+}
+
+/// The outcome of restoring a single item
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", tag = "status", content = "reason")]
+pub enum RestoreStatus {
+    Created,
+    WouldCreate,
+    AlreadyExists,
+    Expired,
+    Failed(String),
+}
+
+impl Display for RestoreStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Created => write!(f, "created"),
+            Self::WouldCreate => write!(f, "would create"),
+            Self::AlreadyExists => write!(f, "already exists"),
+            Self::Expired => write!(f, "expired"),
+            Self::Failed(reason) => write!(f, "failed: {reason}"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RestoreResult {
+    pub kind: RestoreKind,
+    pub role: Role,
+    pub scope: Scope,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub principal_id: Option<String>,
+    #[serde(flatten)]
+    pub status: RestoreStatus,
+}
+
+/// Identify an assignment by scope, principal, and role name, ignoring case
+pub(crate) fn assignment_key(
+    scope: &Scope,
+    principal_id: &str,
+    role: &Role,
+) -> (String, String, String) {
+    (
+        scope.0.to_lowercase(),
+        principal_id.to_lowercase(),
+        role.0.to_lowercase(),
+    )
+}
+
+/// A versioned snapshot of the assignments and custom roles at a scope
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Backup {
+    pub version: u32,
+    pub created_on: String,
+    pub scope: Scope,
+    pub nested: bool,
+    pub active: Vec<BackupEntry>,
+    pub eligible: Vec<BackupEntry>,
+    pub role_definitions: Vec<Definition>,
+}
+
+impl Backup {
+    /// Capture the active assignments, eligible assignments, and custom role
+    /// definitions at `scope`
+    ///
+    /// If `nested` is set, assignments at scopes below `scope` are included.
+    /// Assignments inherited from scopes above `scope` are never included.
+    ///
+    /// # Errors
+    /// Will return `Err` if any of the requests fail
+    pub fn collect(client: &PimClient, scope: &Scope, nested: bool) -> Result<Self> {
+        let included = |x: &Scope| {
+            if nested {
+                scope.contains(x)
+            } else {
+                x == scope
+            }
+        };
+
+        let definitions = client.role_definitions(scope)?;
+
+        let mut active = client
+            .role_assignments(scope)?
+            .iter()
+            .filter(|x| included(&x.properties.scope))
+            .map(|x| BackupEntry::from_assignment(x, &definitions))
+            .collect::<Vec<_>>();
+        active.sort();
+
+        let mut eligible = client
+            .list_eligible_role_assignments(Some(scope.clone()), None)?
+            .into_iter()
+            .filter(|x| included(&x.scope))
+            .filter_map(BackupEntry::from_eligible)
+            .collect::<Vec<_>>();
+        eligible.sort();
+
+        let role_definitions = definitions
+            .into_iter()
+            .filter(|x| x.properties.type_ == "CustomRole")
+            .collect::<Vec<_>>();
+
+        info!(
+            "exported {} active assignments, {} eligible assignments, and {} custom roles",
+            active.len(),
+            eligible.len(),
+            role_definitions.len()
+        );
+
+        Ok(Self {
+            version: BACKUP_VERSION,
+            created_on: format_rfc3339_seconds(SystemTime::now()).to_string(),
+            scope: scope.clone(),
+            nested,
+            active,
+            eligible,
+            role_definitions,
+        })
+    }
+
+    /// Re-create the custom roles and assignments that no longer exist
+    ///
+    /// Eligible assignments that have since expired are skipped, and the rest
+    /// are made eligible for their remaining duration.  If `dry_run` is set,
+    /// nothing is created.
+    ///
+    /// # Errors
+    /// Will return `Err` if the existing assignments cannot be listed.  Errors
+    /// creating individual items are included in the results.
+    pub fn restore(&self, client: &PimClient, dry_run: bool) -> Result<Vec<RestoreResult>> {
+        let mut results = self.restore_definitions(client, dry_run)?;
+
+        let definitions = client.role_definitions(&self.scope)?;
+        let role_name = |id: &str| {
+            definitions
+                .iter()
+                .find(|x| x.id.eq_ignore_ascii_case(id))
+                .map_or_else(|| id.to_string(), |x| x.properties.role_name.clone())
+        };
+        let existing_active = client
+            .role_assignments(&self.scope)?
+            .iter()
+            .map(|x| {
+                let role = Role(role_name(&x.properties.role_definition_id));
+                assignment_key(&x.properties.scope, &x.properties.principal_id, &role)
+            })
+            .collect::<BTreeSet<_>>();
+        let existing_eligible = client
+            .list_eligible_role_assignments(Some(self.scope.clone()), None)?
+            .iter()
+            .filter_map(|x| {
+                let principal_id = x.principal_id.as_deref()?;
+                Some(assignment_key(&x.scope, principal_id, &x.role))
+            })
+            .collect::<BTreeSet<_>>();
+
+        for entry in &self.active {
+            let key = assignment_key(&entry.scope, &entry.principal_id, &entry.role);
+            let status = if existing_active.contains(&key) {
+                RestoreStatus::AlreadyExists
+            } else if dry_run {
+                RestoreStatus::WouldCreate
+            } else {
+                client
+                    .create_role_assignment(
+                        &entry.scope,
+                        &entry.role,
+                        &entry.principal_id,
+                        entry.condition.as_deref(),
+                        entry.condition_version.as_deref(),
+                    )
+                    .map_or_else(
+                        |err| RestoreStatus::Failed(format!("{err:#}")),
+                        |_| RestoreStatus::Created,
+                    )
+            };
+            results.push(entry.result(RestoreKind::Active, status));
+        }
+
+        let now = SystemTime::now();
+        for entry in &self.eligible {
+            let key = assignment_key(&entry.scope, &entry.principal_id, &entry.role);
+            let end = entry
+                .end_date_time
+                .as_deref()
+                .and_then(|x| parse_rfc3339_weak(x).ok());
+            let duration = end.map(|x| x.duration_since(now).unwrap_or_default());
+            let status = if existing_eligible.contains(&key) {
+                RestoreStatus::AlreadyExists
+            } else if duration.is_some_and(|x| x.is_zero()) {
+                RestoreStatus::Expired
+            } else if dry_run {
+                RestoreStatus::WouldCreate
+            } else {
+                client
+                    .create_eligible_role_assignment(
+                        &entry.scope,
+                        &entry.role,
+                        &entry.principal_id,
+                        Some(RESTORE_JUSTIFICATION),
+                        duration,
+                    )
+                    .map_or_else(
+                        |err| RestoreStatus::Failed(format!("{err:#}")),
+                        |()| RestoreStatus::Created,
+                    )
+            };
+            results.push(entry.result(RestoreKind::Eligible, status));
+        }
+
+        for result in &results {
+            if let RestoreStatus::Failed(reason) = &result.status {
+                warn!(
+                    "unable to restore {} {} at {}: {reason}",
+                    result.kind, result.role, result.scope
+                );
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Re-create custom roles that no longer exist at their first assignable
+    /// scope
+    fn restore_definitions(&self, client: &PimClient, dry_run: bool) -> Result<Vec<RestoreResult>> {
+        let mut results = Vec::new();
+        for definition in &self.role_definitions {
+            let properties = &definition.properties;
+            let role = Role(properties.role_name.clone());
+            let scope = match properties.assignable_scopes.first() {
+                Some(scope) => scope.parse::<Scope>()?,
+                None => self.scope.clone(),
+            };
+            let exists = client
+                .role_definitions(&scope)?
+                .iter()
+                .any(|x| x.properties.role_name.eq_ignore_ascii_case(&role.0));
+            let status = if exists {
+                RestoreStatus::AlreadyExists
+            } else if dry_run {
+                RestoreStatus::WouldCreate
+            } else {
+                client
+                    .create_role_definition(&scope, properties)
+                    .map_or_else(
+                        |err| RestoreStatus::Failed(format!("{err:#}")),
+                        |_| RestoreStatus::Created,
+                    )
+            };
+            results.push(RestoreResult {
+                kind: RestoreKind::RoleDefinition,
+                role,
+                scope,
+                principal_id: None,
+                status,
+            });
+        }
+        Ok(results)
+    }
+
+    /// Limit the backup to the assignments of `from`, reassigned to `to`
+    ///
+    /// Custom role definitions are not included.
+    #[must_use]
+    pub fn reassign(self, from: &str, to: &Object) -> Self {
+        let reassign = |entries: Vec<BackupEntry>| {
+            entries
+                .into_iter()
+                .filter(|x| x.principal_id.eq_ignore_ascii_case(from))
+                .map(|x| BackupEntry {
+                    principal_id: to.id.clone(),
+                    principal_type: Some(to.object_type.to_string()),
+                    principal_name: Some(to.display_name.clone()),
+                    ..x
+                })
+                .collect()
+        };
+        Self {
+            active: reassign(self.active),
+            eligible: reassign(self.eligible),
+            role_definitions: Vec::new(),
+            ..self
+        }
+    }
+
+    /// Parse a backup, checking that the format version is supported
+    ///
+    /// # Errors
+    /// Will return `Err` if the backup cannot be parsed or is from a newer
+    /// version of `az-pim`
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let backup: Self = serde_json::from_slice(data).context("unable to parse backup")?;
+        ensure!(
+            backup.version <= BACKUP_VERSION,
+            "unsupported backup version {}, expected {BACKUP_VERSION} or earlier",
+            backup.version
+        );
+        Ok(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backup, BACKUP_VERSION};
+    use anyhow::Result;
+
+    #[test]
+    fn test_parse_version() -> Result<()> {
+        let backup = |version: u32| {
+            format!(
+                r#"{{"version":{version},"created_on":"2024-01-02T03:04:05Z","scope":"/subscriptions/00000000-0000-0000-0000-000000000000","nested":false,"active":[],"eligible":[{{"role":"Owner","scope":"/subscriptions/00000000-0000-0000-0000-000000000000","role_definition_id":"/providers/Microsoft.Authorization/roleDefinitions/8e3af657-a8ff-443c-a75c-2fe8c4bcb635","principal_id":"00000000-0000-0000-0000-000000000001"}}],"role_definitions":[]}}"#
+            )
+        };
+        let parsed = Backup::parse(backup(BACKUP_VERSION).as_bytes())?;
+        assert_eq!(parsed.eligible.len(), 1);
+        assert!(Backup::parse(backup(BACKUP_VERSION + 1).as_bytes()).is_err());
+        Ok(())
+    }
+}
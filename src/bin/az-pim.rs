@@ -1,30 +1,75 @@
-use anyhow::{ensure, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use azure_pim_cli::{
+    access::{can_i, who_has, RoleHolder},
+    audit_log::{self, AuditEntry},
+    backup::{Backup, RestoreResult, RestoreStatus},
     check_latest_version,
-    interactive::{interactive_ui, Selected},
+    config::Config,
+    confirm,
+    dashboard::dashboard_ui,
+    entra::{
+        deactivate_directory_role, deactivate_directory_role_set, find_directory_role,
+        list_active_directory_roles, list_eligible_directory_roles, DirectoryRoleAssignment,
+    },
+    graph::{Object, PrincipalType},
+    groups::{
+        activate_group_membership, deactivate_group_membership, deactivate_group_membership_set,
+        extend_group_membership, find_group_membership, list_active_group_memberships,
+        list_eligible_group_memberships, GroupAccess, GroupMembership,
+    },
+    history::{self, HistoryEntry},
+    import::{import_eligible, ImportEntry, ImportResult},
+    interactive::{interactive_ui, Remembered, Selected},
     models::{
         assignments::Assignment,
+        definitions::{Definition, Properties as DefinitionProperties},
+        requests::ScheduleRequest,
         roles::{Role, RoleAssignment, RolesExt},
-        scope::{Scope, ScopeBuilder},
+        scope::{Scope, ScopeBuilder, ScopeError},
     },
-    ListFilter, PimClient,
+    parse_duration,
+    picker::{pick_role, pick_scope},
+    prompt::PromptCache,
+    providers::{list_all_providers, ProviderAssignment},
+    report::{Report, ReportFormat},
+    serve::{serve, ServeSettings},
+    state::{LastActivation, SavedRole},
+    theme::use_theme,
+    time::{format_timestamp, use_utc},
+    tree::ScopeTree,
+    use_non_interactive,
+    watch::{watch, WatchSettings},
+    ActivationOverrides, ActivationRequest, ActivationResult, AuthMethod, ListFilter, PimClient,
+    TicketInfo, DEFAULT_CONDITION_VERSION,
 };
-use clap::{ArgAction, Args, Command, CommandFactory, Parser, Subcommand, ValueHint};
+use clap::{ArgAction, Args, Command, CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
 use clap_complete::{generate, Shell};
-use humantime::Duration as HumanDuration;
-use serde::{Deserialize, Serialize};
+use humantime::parse_rfc3339_weak;
+use jiff::{
+    civil::{DateTime, Time},
+    tz::TimeZone,
+    Timestamp, Zoned,
+};
+use schemars::{schema_for, JsonSchema};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     cmp::min,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
+    env::current_exe,
     error::Error,
-    fs::{read, File},
-    io::{stderr, stdout},
-    path::PathBuf,
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs::{read, read_to_string, write, File},
+    io::{stderr, stdin, stdout, Read},
+    iter::once,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::{exit, Command as ProcessCommand, Stdio},
     str::FromStr,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use tracing_subscriber::filter::LevelFilter;
+use uuid::Uuid;
 
 // empirical testing shows we need to keep under 5 concurrent requests to keep
 // from rate limiting.  In the future, we may move to a model where we go as
@@ -33,16 +78,151 @@ const DEFAULT_CONCURRENCY: usize = 4;
 
 const DEFAULT_DURATION: &str = "8 hours";
 
+const DEFAULT_LISTEN: &str = "127.0.0.1:8765";
+
+// environment variables used as defaults for the corresponding arguments when
+// activating or extending roles
+const ENV_DURATION: &str = "AZ_PIM_DURATION";
+const ENV_JUSTIFICATION: &str = "AZ_PIM_JUSTIFICATION";
+const ENV_CONCURRENCY: &str = "AZ_PIM_CONCURRENCY";
+
+// environment variable used for the token required by `az-pim serve`
+const ENV_SERVE_TOKEN: &str = "AZ_PIM_SERVE_TOKEN";
+
+// exit code used when some, but not all, of the requested roles fail
+const PARTIAL_FAILURE_EXIT_CODE: i32 = 2;
+
+/// Some, but not all, of the entries of a set operation failed, which exits
+/// with `PARTIAL_FAILURE_EXIT_CODE`
+#[derive(Debug, thiserror::Error)]
+#[error("{failed} of {total} {kind} failed")]
+struct PartialFailure {
+    failed: usize,
+    total: usize,
+    kind: String,
+}
+
 #[derive(Parser)]
 #[command(version, disable_help_subcommand = true, name = "az-pim")]
+#[allow(clippy::struct_excessive_bools)]
 struct Cmd {
     #[command(flatten)]
     verbose: Verbosity,
 
+    /// Always request the current role assignments
+    ///
+    /// By default, listings of active and eligible role assignments are
+    /// reused for up to a minute within a command, until a change is made.
+    #[clap(long, global = true)]
+    no_cache: bool,
+
+    /// Use the specified tenant, rather than the tenant of the Azure CLI's
+    /// default subscription
+    ///
+    /// Specify multiple times, or separate tenants with commas, to combine
+    /// the results of `list` from multiple tenants.  Use `all` for every
+    /// tenant you have subscriptions in.
+    #[clap(long, global = true, value_delimiter = ',')]
+    tenant: Vec<String>,
+
+    /// Record the HTTP requests and responses to a HAR-like file
+    ///
+    /// Bearer tokens and principal ids are redacted, such that the file can
+    /// be shared when troubleshooting.
+    #[clap(long, global = true, value_hint = ValueHint::FilePath)]
+    capture: Option<PathBuf>,
+
+    /// Maximum number of times to retry requests that fail with transient
+    /// errors, such as being rate limited
+    ///
+    /// Use 0 to fail immediately.  If not provided, the `max_retries` from
+    /// the config file is used, otherwise 10.
+    #[clap(long, global = true)]
+    max_retries: Option<usize>,
+
+    /// How tokens are obtained from the Azure CLI
+    #[clap(long, global = true, default_value_t = AuthMethod::AzureCli)]
+    auth_method: AuthMethod,
+
+    /// Display timestamps in UTC rather than the local timezone
+    #[clap(long, global = true)]
+    utc: bool,
+
+    /// Activate dangerous roles without confirmation
+    ///
+    /// By default, activating `Owner` or `User Access Administrator` at a
+    /// subscription or management group requires an interactive
+    /// confirmation.  The roles and scopes can be configured in the `policy`
+    /// section of the config file.
+    #[clap(long, global = true)]
+    i_know_what_im_doing: bool,
+
+    /// Fail rather than prompting for input, such as confirmations or
+    /// interactive selections
+    ///
+    /// This is enabled automatically when stdin is not a terminal, such that
+    /// pipelines fail rather than waiting for input that will never arrive.
+    #[clap(long, global = true)]
+    non_interactive: bool,
+
     #[clap(subcommand)]
     command: SubCommand,
 }
 
+// commands without an example in the README
+const WITHOUT_EXAMPLE: &[&str] = &[
+    "az-pim",
+    "az-pim activate interactive",
+    "az-pim activate last",
+    "az-pim activate",
+    "az-pim cleanup all",
+    "az-pim cleanup auto",
+    "az-pim cleanup orphaned-assignments",
+    "az-pim cleanup orphaned-eligible-assignments",
+    "az-pim cleanup",
+    "az-pim interactive",
+    "az-pim history",
+    "az-pim export",
+    "az-pim who-has",
+    "az-pim can-i",
+    "az-pim report",
+    "az-pim import <PATH>",
+    "az-pim audit",
+    "az-pim audit local",
+    "az-pim prompt",
+    "az-pim serve",
+    "az-pim watch <JUSTIFICATION>",
+    "az-pim deactivate interactive",
+    "az-pim deactivate",
+    "az-pim delete interactive",
+    "az-pim delete orphaned-entries",
+    "az-pim delete role <ROLE> <SCOPE>",
+    "az-pim delete set",
+    "az-pim delete",
+    "az-pim role assignment",
+    "az-pim role definition",
+    "az-pim role eligible",
+    "az-pim role resources",
+    "az-pim role",
+    "az-pim group activate <GROUP> <JUSTIFICATION>",
+    "az-pim group deactivate <GROUP>",
+    "az-pim group list",
+    "az-pim group extend <GROUP> <JUSTIFICATION>",
+    "az-pim group",
+    "az-pim entra deactivate <ROLE>",
+    "az-pim entra list",
+    "az-pim entra",
+    "az-pim deactivate all",
+    "az-pim approval approve-all <JUSTIFICATION>",
+    "az-pim approval list",
+    "az-pim approval",
+    "az-pim scope pick",
+    "az-pim config schema <KIND>",
+    "az-pim config validate <PATH>",
+    "az-pim config",
+    "az-pim scope",
+];
+
 impl Cmd {
     fn shell_completion(shell: Shell) {
         let mut cmd = Self::command();
@@ -51,26 +231,10 @@ impl Cmd {
     }
 
     fn example(cmd: &str) -> Option<&'static str> {
+        if WITHOUT_EXAMPLE.contains(&cmd) {
+            return None;
+        }
         match cmd {
-            "az-pim"
-            | "az-pim activate interactive"
-            | "az-pim activate"
-            | "az-pim cleanup all"
-            | "az-pim cleanup auto"
-            | "az-pim cleanup orphaned-assignments"
-            | "az-pim cleanup orphaned-eligible-assignments"
-            | "az-pim cleanup"
-            | "az-pim deactivate interactive"
-            | "az-pim deactivate"
-            | "az-pim delete interactive"
-            | "az-pim delete orphaned-entries"
-            | "az-pim delete role <ROLE> <SCOPE>"
-            | "az-pim delete set"
-            | "az-pim delete"
-            | "az-pim role assignment"
-            | "az-pim role definition"
-            | "az-pim role resources"
-            | "az-pim role" => None,
             "az-pim activate role <ROLE> <JUSTIFICATION>" => {
                 Some(include_str!("../help/az-pim-activate-role.txt"))
             }
@@ -89,12 +253,42 @@ impl Cmd {
             "az-pim role assignment delete-set <CONFIG>" => Some(include_str!(
                 "../help/az-pim-role-assignment-delete-set.txt"
             )),
+            "az-pim role assignment create <ROLE> <PRINCIPAL_ID>" => {
+                Some(include_str!("../help/az-pim-role-assignment-create.txt"))
+            }
+            "az-pim role assignment copy" => {
+                Some(include_str!("../help/az-pim-role-assignment-copy.txt"))
+            }
             "az-pim role assignment delete <ASSIGNMENT_NAME>" => {
                 Some(include_str!("../help/az-pim-role-assignment-delete.txt"))
             }
             "az-pim role assignment list" => {
                 Some(include_str!("../help/az-pim-role-assignment-list.txt"))
             }
+            "az-pim role eligible create <ROLE> <PRINCIPAL_ID>" => {
+                Some(include_str!("../help/az-pim-role-eligible-create.txt"))
+            }
+            "az-pim role eligible delete" => {
+                Some(include_str!("../help/az-pim-role-eligible-delete.txt"))
+            }
+            "az-pim role eligible move" => {
+                Some(include_str!("../help/az-pim-role-eligible-move.txt"))
+            }
+            "az-pim role eligible import <FILE>" => {
+                Some(include_str!("../help/az-pim-role-eligible-import.txt"))
+            }
+            "az-pim role definition create" => {
+                Some(include_str!("../help/az-pim-role-definition-create.txt"))
+            }
+            "az-pim role definition update" => {
+                Some(include_str!("../help/az-pim-role-definition-update.txt"))
+            }
+            "az-pim role definition clone <SOURCE>" => {
+                Some(include_str!("../help/az-pim-role-definition-clone.txt"))
+            }
+            "az-pim role definition delete <DEFINITION>" => {
+                Some(include_str!("../help/az-pim-role-definition-delete.txt"))
+            }
             "az-pim role definition list" => {
                 Some(include_str!("../help/az-pim-role-definition-list.txt"))
             }
@@ -109,22 +303,7 @@ impl Cmd {
 #[derive(Subcommand)]
 enum SubCommand {
     /// List active or eligible assignments
-    List {
-        /// List active assignments
-        #[clap(long)]
-        active: bool,
-
-        /// Filter to apply on the operation
-        ///
-        /// Specifying `as-target` will return results for the current user.
-        ///
-        /// Specifying `at-scope` will return results at or above the specified scope.
-        #[clap(long, default_value_t = ListFilter::AsTarget)]
-        filter: ListFilter,
-
-        #[clap(flatten)]
-        scope: ScopeBuilder,
-    },
+    List(ListArgs),
 
     /// Activate eligible role assignments
     Activate {
@@ -144,11 +323,336 @@ enum SubCommand {
         cmd: RoleSubCommand,
     },
 
+    /// Manage Entra ID directory roles
+    Entra {
+        #[clap(subcommand)]
+        cmd: EntraSubCommand,
+    },
+
+    /// Manage PIM group memberships
+    Group {
+        #[clap(subcommand)]
+        cmd: GroupSubCommand,
+    },
+
+    /// Manage activation requests pending your approval
+    Approval {
+        #[clap(subcommand)]
+        cmd: ApprovalSubCommand,
+    },
+
+    /// Select a scope interactively
+    Scope {
+        #[clap(subcommand)]
+        cmd: ScopeSubCommand,
+    },
+
     Cleanup {
         #[clap(subcommand)]
         cmd: CleanupSubCommand,
     },
 
+    /// Work with `az-pim` config files
+    Config {
+        #[clap(subcommand)]
+        cmd: ConfigSubCommand,
+    },
+
+    /// Manage active and eligible roles interactively
+    ///
+    /// This shows the currently active roles, with the time remaining, and
+    /// the eligible roles in a single view.  Active roles can be deactivated
+    /// or extended and eligible roles can be activated.
+    Interactive {
+        #[clap(long, env = ENV_JUSTIFICATION)]
+        /// Justification used when activating or extending roles
+        ///
+        /// If not provided, the `default_justification` from the config file
+        /// is used.
+        justification: Option<String>,
+
+        #[clap(long, env = ENV_DURATION, default_value = DEFAULT_DURATION)]
+        /// Duration for roles to be active
+        ///
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        duration: DurationArg,
+
+        #[clap(long, default_value = "5m")]
+        /// How often to refresh the active and eligible roles
+        refresh: DurationArg,
+    },
+
+    /// Keep a set of roles active
+    ///
+    /// Roles that are not active are activated and active roles that are about
+    /// to expire are extended.  This runs until interrupted or until the time
+    /// specified by `--until`.
+    Watch {
+        /// Justification used when activating or extending roles
+        ///
+        /// If not provided, the `default_justification` from the config file
+        /// is used.
+        #[clap(env = ENV_JUSTIFICATION)]
+        justification: Option<String>,
+
+        #[clap(long, env = ENV_DURATION, default_value = DEFAULT_DURATION)]
+        /// Duration for the roles to be active
+        ///
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        duration: DurationArg,
+
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        /// Path to a JSON, YAML, or TOML config file containing a set of roles
+        /// to keep active
+        ///
+        /// The format is the same as `az-pim activate set`
+        config: Option<PathBuf>,
+
+        #[clap(
+            long,
+            conflicts_with = "config",
+            value_name = "ROLE=SCOPE[@DURATION]",
+            value_parser = parse_key_val::<Role, RoleTarget>,
+            action = clap::ArgAction::Append
+        )]
+        /// Specify a role to keep active
+        ///
+        /// Specify multiple times to include multiple key/value pairs.
+        role: Option<Vec<(Role, RoleTarget)>>,
+
+        #[clap(long, default_value = "10m")]
+        /// Renew roles that expire within this duration
+        renew_before: DurationArg,
+
+        #[clap(long, default_value = "1m")]
+        /// How often to check the active roles
+        interval: DurationArg,
+
+        #[clap(long, value_parser = parse_until)]
+        /// Stop watching at the specified time
+        ///
+        /// This can be a duration, such as '9h', a time of day in the local
+        /// timezone, such as '18:00', or a timestamp, such as
+        /// '2024-01-02T17:00:00Z'.  Roles are not activated past this time.
+        until: Option<SystemTime>,
+
+        #[clap(long)]
+        /// Do not suggest similar roles and scopes
+        ///
+        /// By default, if a role cannot be found, the closest eligible roles
+        /// and scopes are included in the error.
+        exact: bool,
+    },
+
+    /// Serve a local HTTP API for listing, activating, and deactivating roles
+    ///
+    /// This allows editors, dashboards, and scripts to reuse a single client,
+    /// including its tokens and caches, rather than running `az-pim` for each
+    /// request.  Requests must include the header
+    /// `Authorization: Bearer <TOKEN>`.
+    ///
+    /// The API includes `GET /status`, `GET /roles/active`,
+    /// `GET /roles/eligible`, `POST /roles/activate`, and
+    /// `POST /roles/deactivate`.  The body of activate and deactivate requests
+    /// is JSON, such as
+    /// `{"role": "Owner", "scope": "/subscriptions/...", "justification": "..."}`.
+    Serve {
+        #[clap(long, default_value = DEFAULT_LISTEN)]
+        /// Address to listen on
+        listen: SocketAddr,
+
+        #[clap(long, env = ENV_SERVE_TOKEN, hide_env_values = true)]
+        /// Token that requests must include
+        ///
+        /// If not provided, a random token is generated and logged at startup.
+        token: Option<String>,
+
+        #[clap(long, env = ENV_DURATION, default_value = DEFAULT_DURATION)]
+        /// Duration for roles to be active, if the request does not include
+        /// one
+        ///
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        duration: DurationArg,
+    },
+
+    /// Print a compact summary of the active roles for use in shell prompts
+    ///
+    /// The active roles are read from a local cache, such that this never
+    /// waits on Azure.  If the cache is older than `--max-age`, it is refreshed
+    /// in the background.
+    ///
+    /// Example output: `PIM:Owner@prod(2h13m)`
+    Prompt {
+        #[clap(long, default_value = "5m")]
+        /// Refresh the cache in the background if it is older than this
+        max_age: DurationArg,
+
+        #[clap(long, hide = true)]
+        /// Refresh the cache rather than printing the summary
+        refresh: bool,
+    },
+
+    /// List the requests to activate, deactivate, or extend roles at a scope
+    ///
+    /// This includes requests made by all users, such as who activated which
+    /// role, when, and with what justification.
+    #[command(args_conflicts_with_subcommands = true)]
+    Audit {
+        #[clap(subcommand)]
+        cmd: Option<AuditSubCommand>,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        #[clap(long, value_parser = parse_since)]
+        /// Only show requests made since the specified time
+        ///
+        /// This can be either a duration, such as '7d' or '1 week', or a
+        /// timestamp, such as '2024-01-02T03:04:05Z'.
+        since: Option<SystemTime>,
+
+        #[clap(long, value_parser = parse_since)]
+        /// Only show requests made before the specified time
+        ///
+        /// This can be either a duration before now, such as '1d', or a
+        /// timestamp, such as '2024-01-02T03:04:05Z'.
+        until: Option<SystemTime>,
+
+        #[clap(long)]
+        /// Only show requests for the specified principal
+        ///
+        /// This can be the object id, display name, or email of the principal.
+        principal: Option<String>,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
+    },
+
+    /// Show the activations, deactivations, and extensions made using `az-pim`
+    ///
+    /// Each request is recorded in `$HOME/.cache/az-pim-cli/history.jsonl`.
+    History {
+        #[clap(long, value_parser = parse_since)]
+        /// Only show requests made since the specified time
+        ///
+        /// This can be either a duration, such as '7d' or '1 week', or a
+        /// timestamp, such as '2024-01-02T03:04:05Z'.
+        since: Option<SystemTime>,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
+    },
+
+    /// List the principals that hold, or are able to activate, a role
+    ///
+    /// This includes active and eligible assignments at the specified scope
+    /// and the scopes above it.
+    WhoHas {
+        /// Name of the role
+        #[clap(long)]
+        role: Role,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        /// Include assignments at scopes below the specified scope
+        #[clap(long)]
+        nested: bool,
+
+        /// Include the members of groups that hold the role
+        #[clap(long)]
+        expand_groups: bool,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
+    },
+
+    /// Check if the current user has a role, or is able to activate it
+    ///
+    /// This reports if the role is already active, if it can be activated
+    /// through an eligible assignment to the user or one of their groups, or
+    /// if there is no assignment that would grant it.  This is useful when
+    /// debugging authorization failures.
+    CanI {
+        /// Name of the role
+        #[clap(long)]
+        role: Role,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
+    },
+
+    /// Generate a human readable report of the access granted at a scope
+    ///
+    /// The report includes the active and eligible assignments grouped by
+    /// scope, the activation policies of the eligible roles, custom roles,
+    /// and orphaned assignments.  This is intended for periodic access
+    /// certifications.
+    Report {
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        /// Include assignments at scopes below the specified scope
+        #[clap(long)]
+        nested: bool,
+
+        /// Path to write the report
+        ///
+        /// If not provided, the report is written to stdout.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+
+        /// Report format
+        ///
+        /// If not provided, HTML is used when the output path ends in `.html`
+        /// and markdown is used otherwise.
+        #[clap(long)]
+        format: Option<ReportFormat>,
+    },
+
+    /// Export the assignments and custom roles at a scope
+    ///
+    /// This captures the active assignments, eligible assignments, and custom
+    /// role definitions in a versioned JSON document, such that they can be
+    /// reviewed or restored after a bulk cleanup or subscription move.
+    Export {
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        /// Include assignments at scopes below the specified scope
+        #[clap(long)]
+        nested: bool,
+
+        /// Path to write the export to, rather than stdout
+        #[clap(long, short, value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
+
+    /// Restore assignments and custom roles from an export
+    ///
+    /// Custom roles and assignments that already exist are skipped, as are
+    /// eligible assignments that have since expired.
+    Import {
+        /// Path to a file created by `az-pim export`
+        #[clap(value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+
+        /// Show what would be created without making any changes
+        #[clap(long)]
+        dry_run: bool,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
+    },
+
     /// Setup shell tab completions
     ///
     /// This command will generate shell completions for the specified shell.
@@ -159,27 +663,108 @@ enum SubCommand {
     Readme,
 }
 
+#[derive(Args)]
+struct ListArgs {
+    /// List active assignments
+    #[clap(long)]
+    active: bool,
+
+    /// Filter to apply on the operation
+    ///
+    /// Specifying `as-target` will return results for the current user.
+    ///
+    /// Specifying `at-scope` will return results at or above the specified scope.
+    #[clap(long, default_value_t = ListFilter::AsTarget)]
+    filter: ListFilter,
+
+    #[clap(flatten)]
+    scope: ScopeBuilder,
+
+    /// Include Entra ID directory roles and PIM group memberships
+    ///
+    /// The results for the current user from each provider are merged into
+    /// a single list with the provider, role, target, state, and expiry.
+    #[clap(long, conflicts_with_all = ["filter", "scope", "subscription", "resource_group", "provider"])]
+    all_providers: bool,
+
+    #[clap(flatten)]
+    sort: SortArgs,
+
+    /// Group the results, such as listing the roles beneath each scope
+    #[clap(long)]
+    group_by: Option<GroupBy>,
+
+    /// Arrange the results into a tree of scopes
+    ///
+    /// Each scope is nested beneath the narrowest scope that contains it,
+    /// showing where roles at broad scopes are inherited.  Resource groups
+    /// are nested beneath their subscription, even if you have no roles at
+    /// the subscription.
+    #[clap(long, conflicts_with_all = ["all_providers", "group_by"])]
+    tree: bool,
+
+    #[clap(long, default_value_t = OutputFormat::Json)]
+    /// Output format
+    output: OutputFormat,
+}
+
 #[derive(Subcommand)]
 enum ActivateSubCommand {
     /// Activate a specific role
+    ///
+    /// If only the justification is provided, the role is selected
+    /// interactively from the eligible roles at the scope.
     Role {
         /// Name of the role to activate
-        role: Role,
+        role: Option<Role>,
 
         /// Justification for the request
-        justification: String,
+        ///
+        /// If not provided, the `default_justification` from the config file
+        /// is used.  When a default justification is configured, a single
+        /// positional argument is the role rather than the justification.
+        #[clap(env = ENV_JUSTIFICATION)]
+        justification: Option<String>,
 
-        #[clap(long, default_value = DEFAULT_DURATION)]
+        #[clap(long, env = ENV_DURATION, default_value = DEFAULT_DURATION)]
         /// Duration for the role to be active
         ///
-        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', '1h30m'
-        duration: HumanDuration,
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        ///
+        /// Use 'max' for the maximum duration allowed by each role's policy.
+        duration: ActivationDuration,
+
+        #[clap(long, value_parser = parse_until)]
+        /// Activate the role until the specified time, rather than for
+        /// `--duration`
+        ///
+        /// This can be a time of day in the local timezone, such as '18:00', or
+        /// a timestamp, such as '2024-06-01T17:00Z'.  This takes precedence
+        /// over `--duration`.
+        until: Option<SystemTime>,
 
         #[clap(long)]
         /// Duration to wait for the roles to be activated
         ///
-        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', '1h30m'
-        wait: Option<HumanDuration>,
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        wait: Option<DurationArg>,
+
+        #[clap(long)]
+        /// Duration to wait for the activation to be approved, if the role
+        /// requires approval
+        ///
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        wait_approval: Option<DurationArg>,
+
+        #[clap(long)]
+        /// Do not suggest similar roles and scopes
+        ///
+        /// By default, if a role cannot be found, the closest eligible roles
+        /// and scopes are included in the error.
+        exact: bool,
+
+        #[clap(flatten)]
+        ticket: TicketArgs,
 
         #[clap(flatten)]
         scope: ScopeBuilder,
@@ -191,17 +776,41 @@ enum ActivateSubCommand {
     /// used with a config file or by specifying roles on the command line.
     Set {
         /// Justification for the request
-        justification: String,
+        ///
+        /// If not provided, the `default_justification` from the config file
+        /// is used.
+        #[clap(env = ENV_JUSTIFICATION)]
+        justification: Option<String>,
 
-        #[clap(long, default_value = DEFAULT_DURATION)]
+        #[clap(long, env = ENV_DURATION, default_value = DEFAULT_DURATION)]
         /// Duration for the role to be active
         ///
-        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', '1h30m'
-        duration: HumanDuration,
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        ///
+        /// Use 'max' for the maximum duration allowed by each role's policy.
+        duration: ActivationDuration,
+
+        #[clap(long, value_parser = parse_until)]
+        /// Activate the role until the specified time, rather than for
+        /// `--duration`
+        ///
+        /// This can be a time of day in the local timezone, such as '18:00', or
+        /// a timestamp, such as '2024-06-01T17:00Z'.  This takes precedence
+        /// over `--duration`.
+        until: Option<SystemTime>,
 
         #[clap(long, value_hint = ValueHint::FilePath)]
         /// Path to a JSON config file containing a set of roles to activate
         ///
+        /// Each entry may optionally include a `duration` or `justification`
+        /// that overrides the `--duration` or justification for that role.
+        ///
+        /// Use `-` to read the config from stdin.
+        ///
+        /// Files ending in `.yaml`, `.yml`, or `.toml` are read as YAML or
+        /// TOML, otherwise the format is detected from the contents.  TOML
+        /// files list the roles as an array of tables, such as `[[role]]`.
+        ///
         /// Example config file:
         /// `
         ///     [
@@ -211,7 +820,9 @@ enum ActivateSubCommand {
         ///         },
         ///         {
         ///             "role": "Owner",
-        ///             "scope": "/subscriptions/00000000-0000-0000-0000-000000000001"
+        ///             "scope": "/subscriptions/00000000-0000-0000-0000-000000000001",
+        ///             "duration": "4h",
+        ///             "justification": "deploying release INC-1234"
         ///         }
         ///     ]
         /// `
@@ -220,16 +831,19 @@ enum ActivateSubCommand {
         #[clap(
             long,
             conflicts_with = "config",
-            value_name = "ROLE=SCOPE",
-            value_parser = parse_key_val::<Role, Scope>,
+            value_name = "ROLE=SCOPE[@DURATION]",
+            value_parser = parse_key_val::<Role, RoleTarget>,
             action = clap::ArgAction::Append
         )]
         /// Specify a role to activate
         ///
-        /// Specify multiple times to include multiple key/value pairs
-        role: Option<Vec<(Role, Scope)>>,
+        /// Specify multiple times to include multiple key/value pairs.
+        ///
+        /// A duration can be specified for an individual role by appending
+        /// `@DURATION` to the scope, such as `Owner=/subscriptions/...@4h`
+        role: Option<Vec<(Role, RoleTarget)>>,
 
-        #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+        #[clap(long, env = ENV_CONCURRENCY, default_value_t = DEFAULT_CONCURRENCY)]
         /// Concurrency rate
         ///
         /// Specify how many roles to activate concurrently.  This can be used to
@@ -239,111 +853,197 @@ enum ActivateSubCommand {
         #[clap(long)]
         /// Duration to wait for the roles to be activated
         ///
-        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', '1h30m'
-        wait: Option<HumanDuration>,
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        wait: Option<DurationArg>,
+
+        #[clap(long)]
+        /// Do not suggest similar roles and scopes
+        ///
+        /// By default, if a role cannot be found, the closest eligible roles
+        /// and scopes are included in the error.
+        exact: bool,
+
+        #[clap(long)]
+        /// Continue activating the remaining roles if any role fails
+        ///
+        /// A summary of the result for each role is printed.  If some, but not
+        /// all, of the roles fail, the exit code is 2.
+        continue_on_error: bool,
+
+        #[clap(flatten)]
+        ticket: TicketArgs,
     },
 
     /// Activate roles interactively
     Interactive {
-        #[clap(long)]
+        #[clap(long, env = ENV_JUSTIFICATION)]
         /// Justification for the request
+        ///
+        /// If not provided, the `default_justification` from the config file,
+        /// or the justification from the last activation, is used.
         justification: Option<String>,
 
-        #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+        #[clap(long, env = ENV_CONCURRENCY, default_value_t = DEFAULT_CONCURRENCY)]
         /// Concurrency rate
         ///
         /// Specify how many roles to activate concurrently.  This can be used to
         /// speed up activation of roles.
         concurrency: usize,
 
-        #[clap(long, default_value = DEFAULT_DURATION)]
+        #[clap(long, env = ENV_DURATION)]
         /// Duration for the role to be active
         ///
-        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', '1h30m'
-        duration: HumanDuration,
+        /// If not provided, the duration from the last activation is used,
+        /// otherwise 8 hours.
+        ///
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        ///
+        /// Use 'max' for the maximum duration allowed by each role's policy.
+        duration: Option<ActivationDuration>,
 
         #[clap(long)]
         /// Duration to wait for the roles to be activated
         ///
-        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', '1h30m'
-        wait: Option<HumanDuration>,
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        wait: Option<DurationArg>,
     },
-}
 
-impl ActivateSubCommand {
-    fn run(self, client: &PimClient) -> Result<()> {
-        match self {
-            Self::Role {
-                role,
-                justification,
-                duration,
-                wait,
-                scope,
-            } => {
-                let roles = client
-                    .list_eligible_role_assignments(None, Some(ListFilter::AsTarget))
-                    .context("unable to list eligible assignments")?;
-                let scope = scope.build().context("valid scope must be provided")?;
-                let entry = roles
-                    .find_role(&role, &scope)
-                    .with_context(|| format!("role not found ({role:?} {scope:?})"))?;
-                client.activate_role_assignment(&entry, &justification, duration.into())?;
-
-                if let Some(wait) = wait {
-                    let assignments = [entry].into();
-                    client.wait_for_role_activation(&assignments, wait.into())?;
-                }
-            }
-            Self::Set {
-                config,
-                role,
-                justification,
+    /// Activate the same roles as the last successful activation
+    ///
+    /// The roles, justification, and duration of the most recent activation are
+    /// recorded in `$HOME/.cache/az-pim-cli`.
+    Last {
+        #[clap(long)]
+        /// Justification for the request
+        ///
+        /// If not provided, the justification from the last activation is used.
+        justification: Option<String>,
+
+        #[clap(long)]
+        /// Duration for the roles to be active
+        ///
+        /// If not provided, the duration from the last activation is used.
+        ///
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        duration: Option<DurationArg>,
+
+        #[clap(long, env = ENV_CONCURRENCY, default_value_t = DEFAULT_CONCURRENCY)]
+        /// Concurrency rate
+        ///
+        /// Specify how many roles to activate concurrently.  This can be used to
+        /// speed up activation of roles.
+        concurrency: usize,
+
+        #[clap(long)]
+        /// Duration to wait for the roles to be activated
+        ///
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        wait: Option<DurationArg>,
+
+        #[clap(long)]
+        /// Continue activating the remaining roles if any role fails
+        ///
+        /// A summary of the result for each role is printed.  If some, but not
+        /// all, of the roles fail, the exit code is 2.
+        continue_on_error: bool,
+    },
+}
+
+impl ActivateSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::Role {
+                role,
+                justification,
+                duration,
+                until,
+                wait,
+                wait_approval,
+                exact,
+                ticket,
+                scope,
+            } => {
+                let (role, justification) = role_and_justification(role, justification)?;
+                let roles = client
+                    .list_eligible_role_assignments(None, Some(ListFilter::AsTarget))
+                    .context("unable to list eligible assignments")?;
+                let scope = scope.resolve_or_default(client)?;
+                let entry = if let Some(role) = role {
+                    roles.resolve_role(&role, &scope, exact)?
+                } else {
+                    pick_role("Select a role to activate", &roles, &scope)?
+                        .context("no role selected")?
+                };
+                let duration = role_duration(client, &entry, until, duration)?;
+                let ticket = ticket.build();
+                client.check_activation_policy(&entry, &justification, ticket.as_ref())?;
+                let request = client.activate_role_assignment_request(
+                    &entry,
+                    &justification,
+                    duration,
+                    ticket.as_ref(),
+                )?;
+                record_activation(
+                    [SavedRole::new(&entry, &justification, duration)],
+                    &justification,
+                    duration,
+                );
+                wait_for_request(client, entry, &request, wait, wait_approval)?;
+            }
+            Self::Set {
+                config,
+                role,
+                justification,
                 duration,
+                until,
                 concurrency,
                 wait,
+                exact,
+                continue_on_error,
+                ticket,
             } => {
-                let set = build_set(client, config, role, false)?;
+                let justification = justification_or_default(justification)?;
+                let mut set = build_set(client, config, role_targets(role), false, exact)?;
                 ensure!(!set.is_empty(), "no roles to activate");
-                client.activate_role_assignment_set(
+                let duration = set_duration(client, &mut set, until, duration)?;
+                check_set_policy(client, &mut set, &justification, ticket.build().as_ref())?;
+                activate_set(
+                    client,
                     &set,
                     &justification,
-                    duration.into(),
+                    duration,
                     concurrency,
+                    wait.map(Into::into),
+                    continue_on_error,
                 )?;
-
-                if let Some(wait) = wait {
-                    client.wait_for_role_activation(&set, wait.into())?;
-                }
             }
             Self::Interactive {
                 justification,
                 concurrency,
                 duration,
                 wait,
+            } => activate_interactive(client, justification, concurrency, duration, wait)?,
+            Self::Last {
+                justification,
+                duration,
+                concurrency,
+                wait,
+                continue_on_error,
             } => {
-                let roles =
-                    client.list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?;
-                if let Some(Selected {
-                    assignments,
-                    justification,
-                    duration,
-                }) = interactive_ui(
-                    roles,
-                    Some(justification.unwrap_or_default()),
-                    Some(duration.as_secs() / 60),
-                )? {
-                    let duration = Duration::from_secs(duration * 60);
-                    client.activate_role_assignment_set(
-                        &assignments,
-                        &justification,
-                        duration,
-                        concurrency,
-                    )?;
-
-                    if let Some(wait) = wait {
-                        client.wait_for_role_activation(&assignments, wait.into())?;
-                    }
-                }
+                let last = LastActivation::load()?.context("no previous activation recorded")?;
+                let duration = duration.map(Into::into);
+                let set =
+                    build_last_set(client, &last, justification.is_none(), duration.is_none())?;
+                ensure!(!set.is_empty(), "no roles to activate");
+                activate_set(
+                    client,
+                    &set,
+                    &justification.unwrap_or(last.justification),
+                    duration.unwrap_or(last.duration),
+                    concurrency,
+                    wait.map(Into::into),
+                    continue_on_error,
+                )?;
             }
         }
         Ok(())
@@ -353,9 +1053,19 @@ impl ActivateSubCommand {
 #[derive(Subcommand)]
 enum DeactivateSubCommand {
     /// Deactivate a specific role
+    ///
+    /// If the role is not provided, it is selected interactively from the
+    /// active roles at the scope.
     Role {
         /// Name of the role to deactivate
-        role: Role,
+        role: Option<Role>,
+
+        #[clap(long)]
+        /// Do not suggest similar roles and scopes
+        ///
+        /// By default, if a role cannot be found, the closest eligible roles
+        /// and scopes are included in the error.
+        exact: bool,
 
         #[clap(flatten)]
         scope: ScopeBuilder,
@@ -365,6 +1075,11 @@ enum DeactivateSubCommand {
         #[clap(long, value_hint = ValueHint::FilePath)]
         /// Path to a JSON config file containing a set of roles to deactivate
         ///
+        /// Use `-` to read the config from stdin.
+        ///
+        /// Files ending in `.yaml`, `.yml`, or `.toml` are read as YAML or
+        /// TOML, otherwise the format is detected from the contents.
+        ///
         /// Example config file:
         /// `
         ///     [
@@ -398,6 +1113,20 @@ enum DeactivateSubCommand {
         /// Specify how many roles to deactivate concurrently.  This can be used to
         /// speed up activation of roles.
         concurrency: usize,
+
+        #[clap(long)]
+        /// Do not suggest similar roles and scopes
+        ///
+        /// By default, if a role cannot be found, the closest eligible roles
+        /// and scopes are included in the error.
+        exact: bool,
+
+        #[clap(long)]
+        /// Continue deactivating the remaining roles if any role fails
+        ///
+        /// A summary of the result for each role is printed.  If some, but not
+        /// all, of the roles fail, the exit code is 2.
+        continue_on_error: bool,
     },
     /// Deactivate roles interactively
     Interactive {
@@ -408,34 +1137,115 @@ enum DeactivateSubCommand {
         /// speed up deactivation of roles.
         concurrency: usize,
     },
+    /// Deactivate all active Azure roles, Entra ID directory roles, and
+    /// activated PIM group memberships
+    ///
+    /// A summary of the result for each role and group membership is
+    /// printed.  If some, but not all, of them fail, the exit code is 2.
+    All {
+        #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+        /// Concurrency rate
+        ///
+        /// Specify how many roles to deactivate concurrently.  This can be used to
+        /// speed up deactivation of roles.
+        concurrency: usize,
+    },
 }
 
 impl DeactivateSubCommand {
     fn run(self, client: &PimClient) -> Result<()> {
         match self {
-            Self::Role { role, scope } => {
-                let scope = scope.build().context("valid scope must be provided")?;
+            Self::Role { role, exact, scope } => {
+                let scope = scope.resolve_or_default(client)?;
                 let roles = client
                     .list_active_role_assignments(None, Some(ListFilter::AsTarget))
                     .context("unable to list active assignments")?;
-                let entry = roles.find_role(&role, &scope).context("role not found")?;
+                let entry = if let Some(role) = role {
+                    roles.resolve_role(&role, &scope, exact)?
+                } else {
+                    pick_role("Select a role to deactivate", &roles, &scope)?
+                        .context("no role selected")?
+                };
                 client.deactivate_role_assignment(&entry)?;
             }
             Self::Set {
                 config,
                 role,
                 concurrency,
+                exact,
+                continue_on_error,
             } => {
-                let set = build_set(client, config, role, true)?;
+                let role = role.map(|x| {
+                    x.into_iter()
+                        .map(|(role, scope)| (role, scope, ActivationOverrides::default()))
+                        .collect()
+                });
+                let set = build_set(client, config, role, true, exact)?
+                    .into_keys()
+                    .collect();
+                if continue_on_error {
+                    let results =
+                        client.deactivate_role_assignment_set_results(&set, concurrency)?;
+                    return summarize(&results);
+                }
                 client.deactivate_role_assignment_set(&set, concurrency)?;
             }
             Self::Interactive { concurrency } => {
                 let roles =
                     client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
-                if let Some(Selected { assignments, .. }) = interactive_ui(roles, None, None)? {
+                if let Some(Selected { assignments, .. }) =
+                    interactive_ui(roles, None, None, false, Remembered::default(), None)?
+                {
                     client.deactivate_role_assignment_set(&assignments, concurrency)?;
                 }
             }
+            Self::All { concurrency } => {
+                let roles =
+                    client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
+                let memberships = list_active_group_memberships(client)?
+                    .into_iter()
+                    .filter(GroupMembership::is_activated)
+                    .collect::<BTreeSet<_>>();
+                let directory_roles = list_active_directory_roles(client, false)?
+                    .into_iter()
+                    .filter(DirectoryRoleAssignment::is_activated)
+                    .collect::<BTreeSet<_>>();
+                if roles.is_empty() && memberships.is_empty() && directory_roles.is_empty() {
+                    info!("no active roles or group memberships");
+                    return Ok(());
+                }
+
+                let roles = if roles.is_empty() {
+                    BTreeMap::new()
+                } else {
+                    client.deactivate_role_assignment_set_results(&roles, concurrency)?
+                };
+                let memberships = if memberships.is_empty() {
+                    BTreeMap::new()
+                } else {
+                    deactivate_group_membership_set(client, &memberships, concurrency)?
+                };
+                let directory_roles = if directory_roles.is_empty() {
+                    BTreeMap::new()
+                } else {
+                    deactivate_directory_role_set(client, &directory_roles, concurrency)?
+                };
+                let rows = roles
+                    .iter()
+                    .map(|(entry, result)| (role_row(entry), result))
+                    .chain(
+                        memberships
+                            .iter()
+                            .map(|(entry, result)| (group_row(entry), result)),
+                    )
+                    .chain(
+                        directory_roles
+                            .iter()
+                            .map(|(entry, result)| (directory_role_row(entry), result)),
+                    )
+                    .collect();
+                return summarize_rows(&["Role", "Scope"], rows, "deactivations");
+            }
         }
         Ok(())
     }
@@ -449,6 +1259,12 @@ enum RoleSubCommand {
         cmd: AssignmentSubCommand,
     },
 
+    /// Manage eligible role assignments
+    Eligible {
+        #[clap(subcommand)]
+        cmd: EligibleSubCommand,
+    },
+
     /// Manage role definitions
     Definition {
         #[clap(subcommand)]
@@ -462,27 +1278,150 @@ enum RoleSubCommand {
     },
 }
 
+impl RoleSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::Assignment { cmd } => cmd.run(client),
+            Self::Eligible { cmd } => cmd.run(client),
+            Self::Definition { cmd } => cmd.run(client),
+            Self::Resources { cmd } => cmd.run(client),
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum AssignmentSubCommand {
     /// List assignments
     List {
         #[clap(flatten)]
         scope: ScopeBuilder,
+
+        /// Include the assignments at each of the eligible child resources of
+        /// the scope
+        ///
+        /// Each assignment includes the scope it was found at.
+        #[clap(long)]
+        nested: bool,
+
+        /// Only include assignments of the principal, by object ID or user
+        /// principal name
+        #[clap(long)]
+        principal: Option<String>,
+
+        /// Only include assignments of the role
+        #[clap(long)]
+        role: Option<Role>,
+
+        /// Include the members of groups with assignments
+        ///
+        /// Members of nested groups are included, with `via_group` set to the
+        /// group with the assignment.
+        #[clap(long)]
+        expand_groups: bool,
+
+        #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+        /// Concurrency rate
+        ///
+        /// Specify how many requests to make concurrently when listing the
+        /// assignments at nested resources.
+        concurrency: usize,
+
+        #[clap(flatten)]
+        sort: SortArgs,
+
+        #[clap(long, default_value_t = OutputFormat::Json)]
+        /// Output format
+        output: OutputFormat,
+    },
+
+    /// Create an assignment
+    Create {
+        /// Name of the role to assign
+        role: Role,
+
+        /// Object ID of the principal to assign the role to
+        principal_id: String,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        /// ABAC condition to further constrain the assignment
+        #[clap(long)]
+        condition: Option<String>,
+
+        /// Version of the ABAC condition
+        #[clap(long, requires = "condition", default_value = DEFAULT_CONDITION_VERSION)]
+        condition_version: String,
+    },
+
+    /// Copy the assignments of one principal to another
+    ///
+    /// Assignments the target principal already has are skipped.  Eligible
+    /// assignments are copied for their remaining duration.
+    Copy {
+        /// Object ID or user principal name to copy assignments from
+        #[clap(long)]
+        from: String,
+
+        /// Object ID or user principal name to copy assignments to
+        #[clap(long)]
+        to: String,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        /// Include assignments at scopes below the specified scope
+        #[clap(long)]
+        nested: bool,
+
+        /// Copy eligible assignments rather than active assignments
+        #[clap(long)]
+        eligible: bool,
+
+        /// Show what would be created without making any changes
+        #[clap(long)]
+        dry_run: bool,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
     },
 
     /// Delete an assignment
+    ///
+    /// Specify either the assignment name, or the role and principal of the
+    /// assignment.  Assignments found by role and principal are confirmed
+    /// before they are deleted.
     Delete {
         /// Assignment name
-        assignment_name: String,
+        #[clap(required_unless_present_all = ["role", "principal"])]
+        assignment_name: Option<String>,
+
+        /// Name of the assigned role
+        #[clap(long, conflicts_with = "assignment_name", requires = "principal")]
+        role: Option<Role>,
+
+        /// Object ID or user principal name of the assigned principal
+        #[clap(long, conflicts_with = "assignment_name", requires = "role")]
+        principal: Option<String>,
 
         #[clap(flatten)]
         scope: ScopeBuilder,
+
+        /// Always respond yes to confirmations
+        #[arg(long)]
+        yes: bool,
     },
 
     /// Delete a set of assignments
     DeleteSet {
         #[clap(value_hint = ValueHint::FilePath)]
         /// Path to a JSON config file containing a set of assignments to delete
+        ///
+        /// Use `-` to read the config from stdin.
+        ///
+        /// Files ending in `.yaml`, `.yml`, or `.toml` are read as YAML or
+        /// TOML, otherwise the format is detected from the contents.
         config: PathBuf,
     },
 }
@@ -490,26 +1429,96 @@ enum AssignmentSubCommand {
 impl AssignmentSubCommand {
     fn run(self, client: &PimClient) -> Result<()> {
         match self {
-            Self::List { scope } => {
-                let scope = scope.build().context("valid scope must be provided")?;
-                let objects = client
-                    .role_assignments(&scope)
-                    .context("unable to list active assignments")?;
-                output(&objects)?;
+            Self::List {
+                scope,
+                nested,
+                principal,
+                role,
+                expand_groups,
+                concurrency,
+                sort,
+                output: format,
+            } => {
+                let scope = scope.resolve_or_default(client)?;
+                let mut objects =
+                    list_assignments(client, &scope, nested, principal.as_deref(), concurrency)?;
+                // role names are only needed for display, or to filter or
+                // sort by role
+                let definitions = match (format, sort.sort, &role) {
+                    (OutputFormat::Table, _, _) | (_, Some(SortKey::Role), _) | (_, _, Some(_)) => {
+                        client.role_definitions(&scope)?
+                    }
+                    _ => Vec::new(),
+                };
+                if let Some(role) = &role {
+                    retain_role(&mut objects, &definitions, role, &scope)?;
+                }
+                let objects = if expand_groups {
+                    expand_group_assignments(client, objects)?
+                } else {
+                    objects.into_iter().map(|(s, x)| (s, x, None)).collect()
+                };
+                print_assignments(objects, &definitions, &sort, format, nested, expand_groups)?;
+            }
+            Self::Create {
+                role,
+                principal_id,
+                scope,
+                condition,
+                condition_version,
+            } => {
+                let scope = scope.resolve_or_default(client)?;
+                let condition_version = condition.as_ref().map(|_| condition_version.as_str());
+                let assignment = client
+                    .create_role_assignment(
+                        &scope,
+                        &role,
+                        &principal_id,
+                        condition.as_deref(),
+                        condition_version,
+                    )
+                    .context("unable to create assignment")?;
+                output(&assignment)?;
+            }
+            Self::Copy {
+                from,
+                to,
+                scope,
+                nested,
+                eligible,
+                dry_run,
+                output: format,
+            } => {
+                let scope = scope.resolve_or_default(client)?;
+                let from = client.resolve_principal(&from)?;
+                let to = client.resolve_principal(&to)?;
+                let mut backup = Backup::collect(client, &scope, nested)?.reassign(&from.id, &to);
+                if eligible {
+                    backup.active.clear();
+                } else {
+                    backup.eligible.clear();
+                }
+                let results = backup.restore(client, dry_run)?;
+                format.print(&results)?;
+                ensure!(
+                    !results
+                        .iter()
+                        .any(|x| matches!(x.status, RestoreStatus::Failed(_))),
+                    "unable to copy all assignments"
+                );
             }
             Self::Delete {
                 assignment_name,
+                role,
+                principal,
                 scope,
+                yes,
             } => {
-                let scope = scope.build().context("valid scope must be provided")?;
-                client
-                    .delete_role_assignment(&scope, &assignment_name)
-                    .context("unable to delete assignment")?;
+                let scope = scope.resolve_or_default(client)?;
+                delete_assignments(client, &scope, assignment_name, role, principal, yes)?;
             }
             Self::DeleteSet { config } => {
-                let data = read(config)?;
-                let entries = serde_json::from_slice::<Vec<Assignment>>(&data)
-                    .context("unable to parse config file")?;
+                let entries = read_config::<Vec<Assignment>>(&config)?;
                 for entry in entries {
                     client
                         .delete_role_assignment(&entry.properties.scope, &entry.name)
@@ -522,74 +1531,592 @@ impl AssignmentSubCommand {
 }
 
 #[derive(Subcommand)]
-enum CleanupSubCommand {
-    /// Delete orphaned role assignments and orphaned eligibile role assignments for all available scopes
-    All {
-        /// Always respond yes to confirmations
-        #[arg(long)]
-        yes: bool,
-    },
-
-    /// Delete orphaned role assignments and orphaned eligibile role assignments
-    Auto {
-        #[clap(flatten)]
-        scope: ScopeBuilder,
+enum EntraSubCommand {
+    /// List eligible or active directory roles
+    List {
+        #[clap(long)]
+        /// List active roles, rather than eligible roles
+        active: bool,
 
-        #[arg(long)]
-        /// Do not check for nested assignments
-        skip_nested: bool,
+        #[clap(long)]
+        /// List the roles of all principals, rather than only your own
+        all: bool,
 
-        #[arg(long)]
-        /// Always respond yes to confirmations
-        yes: bool,
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
     },
 
-    /// Delete orphaned role assignments
-    OrphanedAssignments {
-        #[clap(flatten)]
-        scope: ScopeBuilder,
-
-        #[arg(long)]
-        /// Do not check for nested assignments
-        skip_nested: bool,
+    /// Deactivate an active directory role
+    Deactivate {
+        /// Name or id of the role to deactivate
+        role: Role,
 
-        #[arg(long)]
-        /// Always respond yes to confirmations
-        yes: bool,
+        #[clap(long, default_value = "/")]
+        /// The directory scope of the role, such as `/` for the whole
+        /// directory or `/administrativeUnits/<ID>`
+        directory_scope: String,
     },
+}
 
-    /// Delete orphaned eligible role assignments
-    OrphanedEligibleAssignments {
-        #[clap(flatten)]
-        scope: ScopeBuilder,
+impl EntraSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::List {
+                active,
+                all,
+                output: format,
+            } => {
+                let roles = if active {
+                    list_active_directory_roles(client, all)?
+                } else {
+                    list_eligible_directory_roles(client, all)?
+                };
+                format.print(&roles.into_iter().collect::<Vec<_>>())
+            }
+            Self::Deactivate {
+                role,
+                directory_scope,
+            } => {
+                let roles = list_active_directory_roles(client, false)?;
+                let entry = find_directory_role(&roles, &role, &directory_scope)?;
+                ensure!(
+                    entry.is_activated(),
+                    "{} is assigned directly and cannot be deactivated",
+                    entry.friendly()
+                );
+                deactivate_directory_role(client, &entry)
+            }
+        }
+    }
+}
 
-        #[arg(long)]
-        /// Do not check for nested assignments
-        skip_nested: bool,
+#[derive(Subcommand)]
+enum GroupSubCommand {
+    /// List eligible or active group memberships
+    List {
+        #[clap(long)]
+        /// List active memberships, rather than eligible memberships
+        active: bool,
 
-        #[arg(long)]
-        /// Always respond yes to confirmations
-        yes: bool,
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
     },
-}
 
-impl CleanupSubCommand {
-    fn run(self, client: &PimClient) -> Result<()> {
-        match self {
-            Self::All { yes } => {
-                let active =
-                    client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
-                let mut total =
-                    client.list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?;
-                total.extend(active.clone());
+    /// Activate an eligible group membership
+    Activate {
+        /// Name or id of the group
+        group: String,
 
-                let mut to_activate = BTreeSet::new();
+        /// Justification for the request
+        ///
+        /// If not provided, the `default_justification` from the config file
+        /// is used.
+        #[clap(env = ENV_JUSTIFICATION)]
+        justification: Option<String>,
 
-                let mut scopes = BTreeSet::new();
-                for role_assignment in total {
-                    if role_assignment.scope.subscription().is_none() {
-                        continue;
-                    }
+        #[clap(long, default_value_t = GroupAccess::Member)]
+        /// The access to activate
+        access: GroupAccess,
+
+        #[clap(long, env = ENV_DURATION, default_value = DEFAULT_DURATION)]
+        /// Duration for the membership to be active
+        ///
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        duration: DurationArg,
+
+        #[clap(long, value_parser = parse_until)]
+        /// Activate the membership until the specified time, rather than for
+        /// `--duration`
+        ///
+        /// This can be a time of day in the local timezone, such as '18:00', or
+        /// a timestamp, such as '2024-06-01T17:00Z'.  This takes precedence
+        /// over `--duration`.
+        until: Option<SystemTime>,
+    },
+
+    /// Deactivate an active group membership
+    Deactivate {
+        /// Name or id of the group
+        group: String,
+
+        #[clap(long, default_value_t = GroupAccess::Member)]
+        /// The access to deactivate
+        access: GroupAccess,
+    },
+
+    /// Request extending an active group membership
+    Extend {
+        /// Name or id of the group
+        group: String,
+
+        /// Justification for the request
+        ///
+        /// If not provided, the `default_justification` from the config file
+        /// is used.
+        #[clap(env = ENV_JUSTIFICATION)]
+        justification: Option<String>,
+
+        #[clap(long, default_value_t = GroupAccess::Member)]
+        /// The access to extend
+        access: GroupAccess,
+
+        #[clap(long, env = ENV_DURATION, default_value = DEFAULT_DURATION)]
+        /// Duration to extend the membership by
+        ///
+        /// Examples include '8h', '8 hours', '1h30m', '1 hour 30 minutes', 'PT8H'
+        duration: DurationArg,
+
+        #[clap(long, value_parser = parse_until)]
+        /// Extend the membership until the specified time, rather than by
+        /// `--duration`
+        ///
+        /// This can be a time of day in the local timezone, such as '18:00', or
+        /// a timestamp, such as '2024-06-01T17:00Z'.  This takes precedence
+        /// over `--duration`.
+        until: Option<SystemTime>,
+    },
+}
+
+impl GroupSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::List {
+                active,
+                output: format,
+            } => {
+                let memberships = if active {
+                    list_active_group_memberships(client)?
+                } else {
+                    list_eligible_group_memberships(client)?
+                };
+                format.print(&memberships.into_iter().collect::<Vec<_>>())?;
+            }
+            Self::Activate {
+                group,
+                justification,
+                access,
+                duration,
+                until,
+            } => {
+                let justification = justification_or_default(justification)?;
+                let duration = until.map_or_else(|| Ok(duration.into()), duration_until)?;
+                let memberships = list_eligible_group_memberships(client)?;
+                let entry = find_group_membership(&memberships, &group, access)?;
+                let result = activate_group_membership(client, &entry, &justification, duration)?;
+                if result == ActivationResult::PendingApproval {
+                    info!("activation of {} is pending approval", entry.friendly());
+                }
+            }
+            Self::Deactivate { group, access } => {
+                let memberships = list_active_group_memberships(client)?;
+                let entry = find_group_membership(&memberships, &group, access)?;
+                ensure!(
+                    entry.is_activated(),
+                    "{} is assigned directly and cannot be deactivated",
+                    entry.friendly()
+                );
+                deactivate_group_membership(client, &entry)?;
+            }
+            Self::Extend {
+                group,
+                justification,
+                access,
+                duration,
+                until,
+            } => {
+                let justification = justification_or_default(justification)?;
+                let duration = until.map_or_else(|| Ok(duration.into()), duration_until)?;
+                let memberships = list_active_group_memberships(client)?;
+                let entry = find_group_membership(&memberships, &group, access)?;
+                let result = extend_group_membership(client, &entry, &justification, duration)?;
+                if result == ActivationResult::PendingApproval {
+                    info!("extension of {} is pending approval", entry.friendly());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand)]
+enum ApprovalSubCommand {
+    /// List the activation requests pending your approval
+    ///
+    /// Only requests at or below the specified scope are included, such that
+    /// resource owners can review the requests for the resources they manage.
+    List {
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
+    },
+
+    /// Approve all of the activation requests pending your approval that
+    /// match the filters
+    ///
+    /// A summary of the matching requests is shown for confirmation before
+    /// any are approved.
+    ApproveAll {
+        /// Justification for the approval
+        justification: String,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        #[clap(long, action = clap::ArgAction::Append)]
+        /// Only approve requests where the role, scope, or principal contains
+        /// the value, ignoring case
+        ///
+        /// Specify multiple times to require all of the filters to match.
+        filter: Vec<String>,
+
+        #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+        /// Concurrency rate
+        ///
+        /// Specify how many requests to approve concurrently.
+        concurrency: usize,
+
+        #[arg(long)]
+        /// Always respond yes to confirmations
+        yes: bool,
+    },
+}
+
+impl ApprovalSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::List {
+                scope,
+                output: format,
+            } => {
+                let scope = scope.resolve_or_default(client)?;
+                format.print(&client.list_pending_approvals(&scope)?)
+            }
+            Self::ApproveAll {
+                justification,
+                scope,
+                filter,
+                concurrency,
+                yes,
+            } => {
+                let scope = scope.resolve_or_default(client)?;
+                let requests = client
+                    .list_pending_approvals(&scope)?
+                    .into_iter()
+                    .filter(|x| x.matches_filters(&filter))
+                    .collect::<Vec<_>>();
+                if requests.is_empty() {
+                    info!("no requests pending approval");
+                    return Ok(());
+                }
+                OutputFormat::Table.print(&requests)?;
+                if !yes && !confirm(&format!("approve {} requests", requests.len()))? {
+                    info!("skipping approvals");
+                    return Ok(());
+                }
+                let results = client.approve_requests(&requests, &justification, concurrency)?;
+                summarize_approvals(&results)
+            }
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ScopeSubCommand {
+    /// Select one of the scopes where you have eligible assignments, or
+    /// their child resources, using a fuzzy finder
+    ///
+    /// The selected scope is written to stdout, such that it can be used
+    /// with other commands.  Any command that accepts `--scope` also accepts
+    /// `--scope interactive` to select the scope in the same way.
+    Pick {
+        /// Include all nested child resources, rather than only the direct
+        /// children of each scope
+        #[clap(long)]
+        nested: bool,
+    },
+}
+
+impl ScopeSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::Pick { nested } => {
+                let scope = pick_scope(client, nested)?.context("no scope selected")?;
+                println!("{scope}");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ConfigSubCommand {
+    /// Print the JSON Schema for a config file format
+    ///
+    /// Editors can use the schema to validate and autocomplete config files.
+    Schema {
+        /// The config file format
+        kind: ConfigKind,
+    },
+
+    /// Check a config file against your current roles
+    ///
+    /// Each entry is checked for a valid scope and duration, and that the
+    /// role can be found in your eligible roles, or your active roles for
+    /// `deactivate` configs, without activating or deactivating anything.
+    /// The global config file is only checked for unknown settings.
+    Validate {
+        /// Path to the config file
+        #[clap(value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+
+        /// The config file format
+        #[clap(long, default_value = "activate")]
+        kind: ConfigKind,
+
+        #[clap(long)]
+        /// Do not suggest similar roles and scopes
+        ///
+        /// By default, if a role cannot be found, the closest eligible roles
+        /// and scopes are included in the error.
+        exact: bool,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
+    },
+}
+
+/// The config file formats
+#[derive(Clone, Copy, ValueEnum)]
+enum ConfigKind {
+    /// The `--config` file for `activate set` and `watch`
+    Activate,
+    /// The `--config` file for `deactivate set`
+    Deactivate,
+    /// The config file for `role assignment delete-set`
+    Delete,
+    /// The global config file, `$HOME/.config/az-pim-cli/config.toml`
+    Global,
+}
+
+impl ConfigSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::Schema { kind } => {
+                let schema = match kind {
+                    ConfigKind::Activate | ConfigKind::Deactivate => schema_for!(Roles),
+                    ConfigKind::Delete => schema_for!(Vec<Assignment>),
+                    ConfigKind::Global => schema_for!(Config),
+                };
+                output(&schema)
+            }
+            Self::Validate {
+                path,
+                kind,
+                exact,
+                output,
+            } => {
+                let results = validate_config(client, &path, kind, exact)?;
+                output.print(&results)?;
+                let failed = results.iter().filter(|x| x.error.is_some()).count();
+                ensure!(
+                    failed == 0,
+                    "{failed} of {} entries are invalid",
+                    results.len()
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The result of checking a single config file entry
+#[derive(Serialize)]
+struct ValidationResult {
+    role: String,
+    scope: String,
+    /// The eligible or active role the entry resolves to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl TableRow for ValidationResult {
+    const HEADER: &'static [&'static str] = &["Role", "Scope", "Resolved", "Error"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.role.clone(),
+            self.scope.clone(),
+            self.resolved.clone().unwrap_or_default(),
+            self.error.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+fn validate_config(
+    client: &PimClient,
+    path: &Path,
+    kind: ConfigKind,
+    exact: bool,
+) -> Result<Vec<ValidationResult>> {
+    if matches!(kind, ConfigKind::Global) {
+        let data = read_to_string(path)
+            .with_context(|| format!("unable to read config file {}", path.display()))?;
+        Config::parse(&data)
+            .with_context(|| format!("unable to parse config file {}", path.display()))?;
+        return Ok(Vec::new());
+    }
+
+    if matches!(kind, ConfigKind::Delete) {
+        let entries = read_config::<Vec<Assignment>>(path)?;
+        return Ok(entries
+            .into_iter()
+            .map(|entry| {
+                let scope = entry.properties.scope.to_string();
+                ValidationResult {
+                    role: entry.properties.role_definition_id,
+                    error: Scope::new(scope.clone()).err().map(|x| x.to_string()),
+                    scope,
+                    resolved: None,
+                }
+            })
+            .collect());
+    }
+
+    let entries = read_roles(path)?;
+    let assignments = if matches!(kind, ConfigKind::Deactivate) {
+        client
+            .list_active_role_assignments(None, Some(ListFilter::AsTarget))
+            .context("unable to list active assignments in PIM")?
+    } else {
+        client
+            .list_eligible_role_assignments(None, Some(ListFilter::AsTarget))
+            .context("unable to list available assignments in PIM")?
+    };
+
+    let check = |entry: &ElevateEntry| -> Result<String> {
+        let scope = Scope::new(entry.scope.to_string())?;
+        if let Some(duration) = &entry.duration {
+            parse_duration(duration).context("invalid duration")?;
+        }
+        let resolved = assignments.resolve_role(&entry.role, &scope, exact)?;
+        Ok(format!("{} in {}", resolved.role, resolved.scope))
+    };
+
+    Ok(entries
+        .iter()
+        .map(|entry| {
+            let (resolved, error) = match check(entry) {
+                Ok(resolved) => (Some(resolved), None),
+                Err(err) => (None, Some(format!("{err:#}"))),
+            };
+            ValidationResult {
+                role: entry.role.to_string(),
+                scope: entry.scope.to_string(),
+                resolved,
+                error,
+            }
+        })
+        .collect())
+}
+
+#[derive(Subcommand)]
+enum CleanupSubCommand {
+    /// Delete orphaned role assignments and orphaned eligibile role assignments for all available scopes
+    All {
+        /// Always respond yes to confirmations
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Delete orphaned role assignments and orphaned eligibile role assignments
+    Auto {
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        #[arg(long)]
+        /// Do not check for nested assignments
+        skip_nested: bool,
+
+        #[arg(long)]
+        /// Always respond yes to confirmations
+        yes: bool,
+
+        #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+        /// Concurrency rate
+        ///
+        /// Specify how many requests to make concurrently when checking
+        /// nested resources.
+        concurrency: usize,
+    },
+
+    /// Delete orphaned role assignments
+    OrphanedAssignments {
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        #[arg(long)]
+        /// Do not check for nested assignments
+        skip_nested: bool,
+
+        #[arg(long)]
+        /// Always respond yes to confirmations
+        yes: bool,
+
+        #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+        /// Concurrency rate
+        ///
+        /// Specify how many requests to make concurrently when checking
+        /// nested resources.
+        concurrency: usize,
+    },
+
+    /// Delete orphaned eligible role assignments
+    OrphanedEligibleAssignments {
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        #[arg(long)]
+        /// Do not check for nested assignments
+        skip_nested: bool,
+
+        #[arg(long)]
+        /// Always respond yes to confirmations
+        yes: bool,
+
+        #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+        /// Concurrency rate
+        ///
+        /// Specify how many requests to make concurrently when checking
+        /// nested resources.
+        concurrency: usize,
+    },
+}
+
+impl CleanupSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::All { yes } => {
+                let active =
+                    client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
+                let mut total =
+                    client.list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?;
+                total.extend(active.clone());
+
+                let mut to_activate = BTreeSet::new();
+
+                let mut scopes = BTreeSet::new();
+                for role_assignment in total {
+                    if role_assignment.scope.subscription().is_none() {
+                        continue;
+                    }
 
                     if !["Owner", "Role Based Access Control Administrator"]
                         .contains(&role_assignment.role.0.as_str())
@@ -631,8 +2158,10 @@ impl CleanupSubCommand {
                 scope,
                 skip_nested,
                 yes,
+                concurrency,
             } => {
-                let scope = scope.build().context("valid scope must be provided")?;
+                PimClient::thread_builder(concurrency);
+                let scope = scope.resolve_or_default(client)?;
                 client.activate_role_admin(
                     &scope,
                     "cleaning up orphaned assignments",
@@ -645,16 +2174,20 @@ impl CleanupSubCommand {
                 scope,
                 skip_nested,
                 yes,
+                concurrency,
             } => {
-                let scope = scope.build().context("valid scope must be provided")?;
+                PimClient::thread_builder(concurrency);
+                let scope = scope.resolve_or_default(client)?;
                 client.delete_orphaned_role_assignments(&scope, yes, !skip_nested)?;
             }
             Self::OrphanedEligibleAssignments {
                 scope,
                 skip_nested,
                 yes,
+                concurrency,
             } => {
-                let scope = scope.build().context("valid scope must be provided")?;
+                PimClient::thread_builder(concurrency);
+                let scope = scope.resolve_or_default(client)?;
                 client.delete_orphaned_eligible_role_assignments(&scope, yes, !skip_nested)?;
             }
         }
@@ -663,143 +2196,1938 @@ impl CleanupSubCommand {
 }
 
 #[derive(Subcommand)]
-enum DefinitionSubCommand {
-    /// List the definitions for the specific scope
-    List {
-        #[clap(flatten)]
-        scope: ScopeBuilder,
+enum AuditSubCommand {
+    /// Show the changes made using `az-pim` on this machine
+    ///
+    /// Each request that activates, deactivates, extends, creates, or deletes
+    /// roles, role assignments, or role definitions is recorded in
+    /// `$HOME/.cache/az-pim-cli/audit.jsonl`, including the command, the
+    /// request, the result, and the user that made it.
+    ///
+    /// Each entry includes a hash of the previous entry, such that modifying
+    /// or removing entries can be detected using `--verify`.
+    Local {
+        #[clap(long, value_parser = parse_since)]
+        /// Only show changes made since the specified time
+        ///
+        /// This can be either a duration, such as '7d' or '1 week', or a
+        /// timestamp, such as '2024-01-02T03:04:05Z'.
+        since: Option<SystemTime>,
+
+        #[clap(long, value_parser = parse_since)]
+        /// Only show changes made before the specified time
+        ///
+        /// This can be either a duration before now, such as '1d', or a
+        /// timestamp, such as '2024-01-02T03:04:05Z'.
+        until: Option<SystemTime>,
+
+        #[clap(long)]
+        /// Check that no entries have been modified or removed
+        verify: bool,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
     },
 }
-impl DefinitionSubCommand {
-    fn run(self, client: &PimClient) -> Result<()> {
+
+impl AuditSubCommand {
+    fn run(self) -> Result<()> {
         match self {
-            Self::List { scope } => {
-                let scope = scope.build().context("valid scope must be provided")?;
+            Self::Local {
+                since,
+                until,
+                verify,
+                output: format,
+            } => {
+                if verify {
+                    let count = audit_log::verify().context("audit log verification failed")?;
+                    info!("verified {count} audit log entries");
+                }
+                format.print(&audit_log::load(since, until)?)
+            }
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum EligibleSubCommand {
+    /// Make a principal eligible for a role
+    Create {
+        /// Name of the role
+        role: Role,
+
+        /// Object ID of the principal to make eligible
+        principal_id: String,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        /// Justification for the request
+        #[clap(long)]
+        justification: Option<String>,
+
+        /// Duration for the principal to be eligible
+        ///
+        /// If not provided, the principal is eligible permanently.
+        ///
+        /// Examples include '30d', '30 days', '1y', '1 year'
+        #[clap(long)]
+        duration: Option<DurationArg>,
+    },
+
+    /// Remove a principal's eligibility for a role
+    ///
+    /// Only eligible assignments made directly at the scope are removed.  Each
+    /// assignment is confirmed before it is removed.
+    Delete {
+        /// Name of the role
+        #[clap(long)]
+        role: Role,
+
+        /// Object ID or user principal name of the eligible principal
+        #[clap(long)]
+        principal: String,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        /// Always respond yes to confirmations
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Move a principal's eligibility for a role to a narrower scope
+    ///
+    /// The principal is made eligible at the new scope for the remaining
+    /// duration of the original eligibility, which is then removed.  If the
+    /// original eligibility cannot be removed, the new one is removed.
+    Move {
+        /// Name of the role
+        #[clap(long)]
+        role: Role,
+
+        /// Object ID or user principal name of the eligible principal
+        #[clap(long)]
+        principal: String,
+
+        /// Scope of the existing eligibility
+        #[clap(long)]
+        from_scope: Scope,
+
+        /// Scope to move the eligibility to, which must be within `from-scope`
+        #[clap(long)]
+        to_scope: Scope,
+
+        /// Justification for the request
+        #[clap(long)]
+        justification: Option<String>,
+
+        /// Always respond yes to confirmations
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Make principals eligible for roles from a CSV file
+    ///
+    /// The file has a header with the columns `principal`, `role`, `scope`,
+    /// and optionally `expiration`.  Principals are object IDs or user
+    /// principal names.  Expirations are either a duration, such as `90d`,
+    /// or a timestamp, such as `2025-01-01T00:00:00Z`.  Rows without an
+    /// expiration are eligible permanently.
+    ///
+    /// Rows that are already eligible are skipped.  The result of each row is
+    /// reported, and rows that fail do not stop the import.
+    Import {
+        /// Path to the CSV file
+        ///
+        /// Use `-` to read the file from stdin.
+        #[clap(value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+
+        /// Justification for the requests
+        #[clap(long)]
+        justification: Option<String>,
+
+        /// Show what would be created without making any changes
+        #[clap(long)]
+        dry_run: bool,
+
+        #[clap(long, default_value_t = OutputFormat::Table)]
+        /// Output format
+        output: OutputFormat,
+    },
+}
+
+impl EligibleSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::Create {
+                role,
+                principal_id,
+                scope,
+                justification,
+                duration,
+            } => {
+                let scope = scope.resolve_or_default(client)?;
+                client
+                    .create_eligible_role_assignment(
+                        &scope,
+                        &role,
+                        &principal_id,
+                        justification.as_deref(),
+                        duration.map(Into::into),
+                    )
+                    .context("unable to create eligible assignment")?;
+            }
+            Self::Delete {
+                role,
+                principal,
+                scope,
+                yes,
+            } => {
+                let scope = scope.resolve_or_default(client)?;
+                let principal = client.resolve_principal(&principal)?;
+                let principal_name = principal.upn.as_deref().unwrap_or(&principal.display_name);
+                let found = find_eligible_assignments(client, &role, &principal, &scope)?;
+                for assignment in found {
+                    let value = format!("eligibility of {principal_name} for {role} at {scope}");
+                    if !yes && !confirm(&format!("delete {value}"))? {
+                        info!("skipping {value}");
+                        continue;
+                    }
+                    client
+                        .delete_eligible_role_assignment(&assignment)
+                        .context("unable to delete eligible assignment")?;
+                }
+            }
+            Self::Move {
+                role,
+                principal,
+                from_scope,
+                to_scope,
+                justification,
+                yes,
+            } => {
+                ensure!(
+                    from_scope.contains(&to_scope) && from_scope != to_scope,
+                    "{to_scope} is not within {from_scope}"
+                );
+                let principal = client.resolve_principal(&principal)?;
+                let principal_name = principal.upn.as_deref().unwrap_or(&principal.display_name);
+                let found = find_eligible_assignments(client, &role, &principal, &from_scope)?;
+                for assignment in found {
+                    let value = format!(
+                        "eligibility of {principal_name} for {role} from {from_scope} to {to_scope}"
+                    );
+                    if !yes && !confirm(&format!("move {value}"))? {
+                        info!("skipping {value}");
+                        continue;
+                    }
+                    client
+                        .move_eligible_role_assignment(
+                            &assignment,
+                            &to_scope,
+                            justification.as_deref(),
+                        )
+                        .context("unable to move eligible assignment")?;
+                }
+            }
+            Self::Import {
+                file,
+                justification,
+                dry_run,
+                output: format,
+            } => {
+                let data = read_input(&file)?;
+                let entries = ImportEntry::parse_csv(&data)
+                    .with_context(|| format!("unable to parse {}", file.display()))?;
+                let results = import_eligible(client, &entries, justification.as_deref(), dry_run);
+                format.print(&results)?;
+                ensure!(
+                    !results
+                        .iter()
+                        .any(|x| matches!(x.status, RestoreStatus::Failed(_))),
+                    "unable to import all eligible assignments"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find the eligible assignments of `role` to a principal made directly at
+/// `scope`
+fn find_eligible_assignments(
+    client: &PimClient,
+    role: &Role,
+    principal: &Object,
+    scope: &Scope,
+) -> Result<Vec<RoleAssignment>> {
+    let found = client
+        .list_eligible_role_assignments(Some(scope.clone()), Some(ListFilter::AtScope))?
+        .into_iter()
+        .filter(|x| {
+            x.role.0.eq_ignore_ascii_case(&role.0)
+                && x.scope.to_string().eq_ignore_ascii_case(&scope.to_string())
+                && x.principal_id.as_deref() == Some(principal.id.as_str())
+        })
+        .collect::<Vec<_>>();
+    let principal_name = principal.upn.as_deref().unwrap_or(&principal.display_name);
+    ensure!(
+        !found.is_empty(),
+        "no eligible assignments of {role} to {principal_name} found at {scope}"
+    );
+    Ok(found)
+}
+
+#[derive(Subcommand)]
+enum DefinitionSubCommand {
+    /// List the definitions for the specific scope
+    List {
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+    },
+
+    /// Create a custom role definition
+    ///
+    /// The file contains the `properties` of a role definition, as shown by
+    /// `az-pim role definition list`.  If a scope is not provided, the first
+    /// assignable scope is used.
+    Create {
+        /// Path to a JSON file containing the role definition
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+    },
+
+    /// Update a custom role definition
+    ///
+    /// The file contains the `properties` of a role definition, as shown by
+    /// `az-pim role definition list`.  The definition to update is identified
+    /// by `roleName`.  If a scope is not provided, the first assignable scope
+    /// is used.
+    Update {
+        /// Path to a JSON file containing the role definition
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+    },
+
+    /// Create a custom role based on an existing role
+    ///
+    /// The new role is assignable at the specified scope.  Actions removed
+    /// with `--remove-action` that are not granted explicitly, such as those
+    /// covered by a wildcard, are added to `notActions`.
+    Clone {
+        /// Name of the existing role
+        source: Role,
+
+        /// Name of the new role
+        #[clap(long)]
+        name: String,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        /// Action to grant in the new role
+        ///
+        /// Specify multiple times to grant multiple actions.
+        #[clap(long)]
+        add_action: Vec<String>,
+
+        /// Action to remove from the new role
+        ///
+        /// Specify multiple times to remove multiple actions.
+        #[clap(long)]
+        remove_action: Vec<String>,
+
+        /// Description of the new role
+        #[clap(long)]
+        description: Option<String>,
+    },
+
+    /// Delete a custom role definition
+    ///
+    /// Built-in roles cannot be deleted.
+    Delete {
+        /// Role name, or the name or ID of the role definition
+        definition: String,
+
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        /// Always respond yes to confirmations
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+impl DefinitionSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::List { scope } => {
+                let scope = scope.resolve_or_default(client)?;
                 output(&client.role_definitions(&scope)?)?;
             }
+            Self::Create { file, scope } => {
+                let (scope, properties) = read_definition(client, &file, scope)?;
+                output(&client.create_role_definition(&scope, &properties)?)?;
+            }
+            Self::Update { file, scope } => {
+                let (scope, properties) = read_definition(client, &file, scope)?;
+                output(&client.update_role_definition(&scope, &properties)?)?;
+            }
+            Self::Clone {
+                source,
+                name,
+                scope,
+                add_action,
+                remove_action,
+                description,
+            } => {
+                let scope = scope.resolve_or_default(client)?;
+                let found = client
+                    .role_definitions(&scope)?
+                    .into_iter()
+                    .find(|x| x.properties.role_name.eq_ignore_ascii_case(&source.0))
+                    .with_context(|| format!("role definition {source} not found"))?;
+                let mut properties =
+                    found
+                        .properties
+                        .clone_as(&name, &scope, &add_action, &remove_action);
+                if let Some(description) = description {
+                    properties.description = description;
+                }
+                output(&client.create_role_definition(&scope, &properties)?)?;
+            }
+            Self::Delete {
+                definition,
+                scope,
+                yes,
+            } => {
+                let scope = scope.resolve_or_default(client)?;
+                let found = client
+                    .role_definitions(&scope)?
+                    .into_iter()
+                    .find(|x| {
+                        x.properties.role_name.eq_ignore_ascii_case(&definition)
+                            || x.name.eq_ignore_ascii_case(&definition)
+                            || x.id.eq_ignore_ascii_case(&definition)
+                    })
+                    .with_context(|| format!("role definition {definition} not found"))?;
+                let value = format!(
+                    "role definition \"{}\" ({}) at {scope}",
+                    found.properties.role_name, found.name
+                );
+                if !yes && !confirm(&format!("delete {value}"))? {
+                    info!("skipping {value}");
+                    return Ok(());
+                }
+                client.delete_role_definition(&scope, &found.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read and validate a role definition, determining the scope to write it to
+fn read_definition(
+    client: &PimClient,
+    path: &Path,
+    scope: ScopeBuilder,
+) -> Result<(Scope, DefinitionProperties)> {
+    let data = read(path).with_context(|| format!("unable to read {}", path.display()))?;
+    let properties = serde_json::from_slice::<DefinitionProperties>(&data)
+        .with_context(|| format!("unable to parse role definition {}", path.display()))?;
+    properties
+        .validate()
+        .with_context(|| format!("invalid role definition {}", path.display()))?;
+    let scope = match scope.resolve(client)? {
+        Some(scope) => scope,
+        None => properties
+            .assignable_scopes
+            .first()
+            .context("valid scope must be provided")?
+            .parse()?,
+    };
+    Ok((scope, properties))
+}
+
+#[derive(Subcommand)]
+enum ResourcesSubCommand {
+    /// List the child resources of a resource which you have eligible access
+    List {
+        #[clap(flatten)]
+        scope: ScopeBuilder,
+
+        #[arg(long)]
+        /// Do not check for nested assignments
+        skip_nested: bool,
+
+        #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+        /// Concurrency rate
+        ///
+        /// Specify how many requests to make concurrently when checking
+        /// nested resources.
+        concurrency: usize,
+
+        /// Only include resources of this type, such as
+        /// `Microsoft.KeyVault/vaults`
+        ///
+        /// This can be specified multiple times.  Resources that cannot
+        /// contain any of the types are not checked for nested resources.
+        #[arg(long = "type", value_name = "TYPE")]
+        types: Vec<String>,
+
+        /// Show the resources as a tree, with each resource beneath its parent
+        #[arg(long)]
+        tree: bool,
+
+        /// Only show resources nested up to this many levels beneath the top
+        /// of the tree
+        #[arg(long, requires = "tree")]
+        depth: Option<usize>,
+    },
+}
+
+impl ResourcesSubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::List {
+                scope,
+                skip_nested,
+                concurrency,
+                types,
+                tree,
+                depth,
+            } => {
+                PimClient::thread_builder(concurrency);
+                let scope = scope.resolve_or_default(client)?;
+                let resources =
+                    client.eligible_child_resources_of_types(&scope, !skip_nested, &types)?;
+                if !tree {
+                    return output(&resources);
+                }
+
+                let mut trees =
+                    ScopeTree::build(resources.into_iter().map(|x| (x.id, Some(x.name), x.type_)));
+                if let Some(depth) = depth {
+                    ScopeTree::truncate(&mut trees, depth);
+                }
+                let rendered = ScopeTree::render(&trees, |node, parent| {
+                    let name = node.display_name(parent);
+                    match node.values.first() {
+                        Some(type_) => format!("{name} ({type_})"),
+                        None => name,
+                    }
+                });
+                println!("{rendered}");
+            }
         }
         Ok(())
     }
 }
 
-#[derive(Subcommand)]
-enum ResourcesSubCommand {
-    /// List the child resources of a resource which you have eligible access
-    List {
-        #[clap(flatten)]
-        scope: ScopeBuilder,
+/// Parse a single key-value pair of `X=Y` into a typed tuple of `(X, Y)`.
+///
+/// # Errors
+/// Returns an `Err` if any of the keys or values cannot be parsed or if no `=` is found.
+pub fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
+where
+    T: FromStr,
+    T::Err: Error + Send + Sync + 'static,
+    U: FromStr,
+    U::Err: Error + Send + Sync + 'static,
+{
+    if let Some((key, value)) = s.split_once('=') {
+        Ok((key.parse()?, value.parse()?))
+    } else {
+        Err(format!("invalid KEY=value: no `=` found in `{s}`").into())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+enum RoleTargetError {
+    #[error(transparent)]
+    Scope(#[from] ScopeError),
+    #[error(transparent)]
+    Duration(#[from] anyhow::Error),
+}
+
+/// A scope with an optional duration, in the form of `SCOPE[@DURATION]`
+#[derive(Clone)]
+struct RoleTarget {
+    scope: Scope,
+    duration: Option<Duration>,
+}
+
+impl FromStr for RoleTarget {
+    type Err = RoleTargetError;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Some((scope, duration)) = s.rsplit_once('@') {
+            Ok(Self {
+                scope: scope.parse()?,
+                duration: Some(parse_duration(duration)?),
+            })
+        } else {
+            Ok(Self {
+                scope: s.parse()?,
+                duration: None,
+            })
+        }
+    }
+}
+
+fn build_readme_entry(cmd: &mut Command, mut names: Vec<String>) -> String {
+    let mut readme = String::new();
+    let current = cmd.get_name().to_string();
+
+    names.push(current);
+
+    // add positions to the display name if there are any
+    for positional in cmd.get_positionals() {
+        names.push(format!("<{}>", positional.get_id().as_str().to_uppercase()));
+    }
+
+    let name = names.join(" ");
+
+    // once we're at 6 levels of nesting, don't nest anymore.  This is the max
+    // that shows up on crates.io and GitHub.
+    let depth = min(names.iter().filter(|f| !f.starts_with('<')).count(), 5);
+    for _ in 0..depth {
+        readme.push('#');
+    }
+
+    let long_help = cmd.render_long_help().to_string().replace("```", "\n```\n");
+    readme.push_str(&format!(" {name}\n\n```\n{long_help}\n```\n",));
+
+    if let Some(example) = Cmd::example(&name) {
+        for _ in 0..=depth {
+            readme.push('#');
+        }
+        readme.push_str(&format!(
+            " Example Usage\n\n```\n{}\n```\n\n",
+            example.trim()
+        ));
+    }
+
+    for cmd in cmd.get_subcommands_mut() {
+        if cmd.get_name() == "readme" {
+            continue;
+        }
+        readme.push_str(&build_readme_entry(cmd, names.clone()));
+    }
+    readme
+}
+
+fn build_readme() {
+    let mut cmd = Cmd::command();
+    let readme = build_readme_entry(&mut cmd, Vec::new())
+        .replacen(
+            "# az-pim",
+            &format!("# Azure PIM CLI\n\n{}", env!("CARGO_PKG_DESCRIPTION")),
+            1,
+        )
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .replace("\n\n\n", "\n");
+    print!("{readme}");
+}
+
+pub(crate) fn output<T>(value: &T) -> Result<()>
+where
+    T: ?Sized + Serialize,
+{
+    serde_json::to_writer_pretty(stdout(), value).context("unable to serialize results")
+}
+
+/// A duration, either in the `humantime` format, such as `8h`, or as an ISO
+/// 8601 duration, such as `PT8H`
+#[derive(Clone, Copy)]
+struct DurationArg(Duration);
+
+impl FromStr for DurationArg {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_duration(s).map(Self)
+    }
+}
+
+impl From<DurationArg> for Duration {
+    fn from(value: DurationArg) -> Self {
+        value.0
+    }
+}
+
+/// The duration for roles to be active
+#[derive(Clone, Copy)]
+enum ActivationDuration {
+    Fixed(Duration),
+    /// The maximum duration allowed by each role's policy
+    Max,
+}
+
+impl FromStr for ActivationDuration {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("max") {
+            Ok(Self::Max)
+        } else {
+            parse_duration(s).map(Self::Fixed)
+        }
+    }
+}
+
+/// Determine the role and justification from the positional arguments
+///
+/// With a single positional argument, it is the justification, unless a
+/// default justification is configured.
+fn role_and_justification(
+    role: Option<Role>,
+    justification: Option<String>,
+) -> Result<(Option<Role>, String)> {
+    Ok(match (role, justification) {
+        (Some(role), Some(justification)) => (Some(role), justification),
+        (Some(single), None) => match Config::load()?.default_justification {
+            Some(justification) => (Some(single), justification),
+            None => (None, single.0),
+        },
+        (None, _) => (None, justification_or_default(None)?),
+    })
+}
+
+/// Wait for an activation request to be approved and the role to become
+/// active, as requested
+fn wait_for_request(
+    client: &PimClient,
+    entry: RoleAssignment,
+    request: &ActivationRequest,
+    wait: Option<DurationArg>,
+    wait_approval: Option<DurationArg>,
+) -> Result<()> {
+    if request.status == ActivationResult::PendingApproval {
+        match client.approvers(&entry) {
+            Ok(approvers) if !approvers.is_empty() => {
+                info!(
+                    "activation requires approval from: {}",
+                    approvers.join(", ")
+                );
+            }
+            Ok(_) => info!("activation requires approval"),
+            Err(err) => {
+                info!("activation requires approval");
+                warn!("unable to determine approvers: {err:#}");
+            }
+        }
+        let Some(wait_approval) = wait_approval else {
+            if wait.is_some() {
+                warn!("not waiting for activation while pending approval; use --wait-approval to wait for the approval");
+            }
+            return Ok(());
+        };
+        client.wait_for_role_approval(&entry, &request.name, wait_approval.into())?;
+    }
+
+    if let Some(wait) = wait {
+        let assignments = [entry].into();
+        client.wait_for_role_activation(&assignments, wait.into())?;
+    }
+    Ok(())
+}
+
+/// Determine the duration to activate a role for
+fn role_duration(
+    client: &PimClient,
+    entry: &RoleAssignment,
+    until: Option<SystemTime>,
+    duration: ActivationDuration,
+) -> Result<Duration> {
+    match (until, duration) {
+        (Some(until), _) => duration_until(until),
+        (None, ActivationDuration::Fixed(duration)) => Ok(duration),
+        (None, ActivationDuration::Max) => client
+            .max_activation_durations(&BTreeSet::from([entry.clone()]))?
+            .into_values()
+            .next()
+            .context("no maximum activation duration"),
+    }
+}
+
+/// Determine the duration to activate a set of roles for
+///
+/// With `--duration max`, each role is activated for the maximum allowed by
+/// its policy, unless a duration was given for the role.
+fn set_duration(
+    client: &PimClient,
+    set: &mut BTreeMap<RoleAssignment, ActivationOverrides>,
+    until: Option<SystemTime>,
+    duration: ActivationDuration,
+) -> Result<Duration> {
+    match (until, duration) {
+        (Some(until), _) => duration_until(until),
+        (None, ActivationDuration::Fixed(duration)) => Ok(duration),
+        (None, ActivationDuration::Max) => {
+            // durations given for individual roles take precedence
+            let max = client.max_activation_durations(&set.keys().cloned().collect())?;
+            for (entry, overrides) in set {
+                if overrides.duration.is_none() {
+                    overrides.duration = max.get(entry).copied();
+                }
+            }
+            parse_duration(DEFAULT_DURATION)
+        }
+    }
+}
+
+/// Add the ticket to each role of the set, checking the justification and
+/// ticket meet each role's policy before any are activated
+fn check_set_policy(
+    client: &PimClient,
+    set: &mut BTreeMap<RoleAssignment, ActivationOverrides>,
+    justification: &str,
+    ticket: Option<&TicketInfo>,
+) -> Result<()> {
+    let mut violations = Vec::new();
+    for (entry, overrides) in set {
+        overrides.ticket = ticket.cloned();
+        let justification = overrides.justification.as_deref().unwrap_or(justification);
+        if let Err(err) = client.check_activation_policy(entry, justification, ticket) {
+            violations.push(format!("{err:#}"));
+        }
+    }
+    ensure!(violations.is_empty(), violations.join("\n"));
+    Ok(())
+}
+
+/// Set the duration of each role to the maximum allowed by its policy
+fn max_duration_overrides(
+    client: &PimClient,
+    assignments: &BTreeSet<RoleAssignment>,
+) -> Result<BTreeMap<RoleAssignment, ActivationOverrides>> {
+    Ok(client
+        .max_activation_durations(assignments)?
+        .into_iter()
+        .map(|(entry, duration)| {
+            let overrides = ActivationOverrides {
+                duration: Some(duration),
+                ..ActivationOverrides::default()
+            };
+            (entry, overrides)
+        })
+        .collect())
+}
+
+#[allow(clippy::manual_assert, clippy::panic)]
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SortKey {
+    Role,
+    Scope,
+    /// When the assignment ends, with permanent assignments last
+    Expiry,
+    Principal,
+}
+
+/// Ordering of listed results
+#[derive(Args)]
+struct SortArgs {
+    /// Sort the results by the specified field
+    #[clap(long)]
+    sort: Option<SortKey>,
+
+    /// Reverse the order of the results
+    #[clap(long)]
+    reverse: bool,
+}
+
+impl SortArgs {
+    fn apply<T: SortFields>(&self, rows: &mut [T]) {
+        self.apply_by(rows, T::sort_field);
+    }
+
+    /// Sort using the value of each field, where rows without a value are
+    /// last
+    fn apply_by<T, F>(&self, rows: &mut [T], field: F)
+    where
+        F: Fn(&T, SortKey) -> Option<String>,
+    {
+        if let Some(key) = self.sort {
+            rows.sort_by_cached_key(|x| {
+                let value = field(x, key).map(|x| x.to_lowercase());
+                (value.is_none(), value)
+            });
+        }
+        if self.reverse {
+            rows.reverse();
+        }
+    }
+}
+
+/// The values used to sort listed results
+trait SortFields {
+    fn sort_field(&self, key: SortKey) -> Option<String>;
+}
+
+impl SortFields for RoleAssignment {
+    fn sort_field(&self, key: SortKey) -> Option<String> {
+        match key {
+            SortKey::Role => Some(self.role.to_string()),
+            SortKey::Scope => Some(self.scope.to_string()),
+            SortKey::Expiry => self.end_date_time.clone(),
+            SortKey::Principal => self
+                .object
+                .as_ref()
+                .map(|x| x.upn.clone().unwrap_or_else(|| x.display_name.clone()))
+                .or_else(|| self.principal_id.clone()),
+        }
+    }
+}
+
+impl SortFields for ProviderAssignment {
+    fn sort_field(&self, key: SortKey) -> Option<String> {
+        match key {
+            SortKey::Role => Some(self.role.clone()),
+            SortKey::Scope => Some(self.target.clone()),
+            SortKey::Expiry => self.end_date_time.clone(),
+            SortKey::Principal => None,
+        }
+    }
+}
+
+impl SortFields for AssignmentRow {
+    fn sort_field(&self, key: SortKey) -> Option<String> {
+        match key {
+            SortKey::Role => Some(self.role.clone()),
+            SortKey::Scope => Some(self.scope.to_string()),
+            SortKey::Expiry => None,
+            SortKey::Principal => Some(self.principal.clone()),
+        }
+    }
+}
+
+impl<T: SortFields> SortFields for TenantRow<T> {
+    fn sort_field(&self, key: SortKey) -> Option<String> {
+        self.value.sort_field(key)
+    }
+}
+
+/// Ticket information for roles whose policy requires it
+#[derive(Args)]
+struct TicketArgs {
+    /// Ticket number to include with the request
+    ///
+    /// This is required for roles whose activation policy requires ticket
+    /// information.
+    #[clap(long)]
+    ticket_number: Option<String>,
+
+    /// Ticket system to include with the request
+    #[clap(long, requires = "ticket_number")]
+    ticket_system: Option<String>,
+}
+
+impl TicketArgs {
+    fn build(self) -> Option<TicketInfo> {
+        let Self {
+            ticket_number,
+            ticket_system,
+        } = self;
+        ticket_number.map(|number| TicketInfo {
+            number,
+            system: ticket_system,
+        })
+    }
+}
+
+/// Use the justification if provided, otherwise the default justification
+/// from the config file
+fn justification_or_default(justification: Option<String>) -> Result<String> {
+    match justification {
+        Some(justification) => Ok(justification),
+        None => Config::load()?.justification(None),
+    }
+}
+
+/// Select roles to activate using the interactive UI
+fn activate_interactive(
+    client: &PimClient,
+    justification: Option<String>,
+    concurrency: usize,
+    duration: Option<ActivationDuration>,
+    wait: Option<DurationArg>,
+) -> Result<()> {
+    let roles = client.list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?;
+    let justification = justification.or(Config::load()?.default_justification);
+    let remembered = remembered_selection(justification.is_none(), duration.is_none());
+    let (duration, max_duration) = match duration {
+        Some(ActivationDuration::Fixed(duration)) => (duration, false),
+        Some(ActivationDuration::Max) => (parse_duration(DEFAULT_DURATION)?, true),
+        None => (parse_duration(DEFAULT_DURATION)?, false),
+    };
+
+    if let Some(Selected {
+        assignments,
+        justification,
+        duration,
+        max_duration,
+    }) = interactive_ui(
+        roles,
+        Some(justification.unwrap_or_default()),
+        Some(duration.as_secs() / 60),
+        max_duration,
+        remembered,
+        Some(&|assignments| client.roles_requiring_approval(assignments)),
+    )? {
+        let duration = Duration::from_secs(duration * 60);
+        if max_duration {
+            let set = max_duration_overrides(client, &assignments)?;
+            activate_set(
+                client,
+                &set,
+                &justification,
+                duration,
+                concurrency,
+                None,
+                false,
+            )?;
+        } else {
+            client.activate_role_assignment_set(
+                &assignments,
+                &justification,
+                duration,
+                concurrency,
+            )?;
+            record_activation(
+                assignments
+                    .iter()
+                    .map(|x| SavedRole::new(x, &justification, duration)),
+                &justification,
+                duration,
+            );
+        }
+
+        if let Some(wait) = wait {
+            client.wait_for_role_activation(&assignments, wait.into())?;
+        }
+    }
+    Ok(())
+}
+
+/// List the requests at a scope that match the specified filters
+fn audit(
+    client: &PimClient,
+    scope: ScopeBuilder,
+    since: Option<SystemTime>,
+    until: Option<SystemTime>,
+    principal: Option<&str>,
+) -> Result<Vec<ScheduleRequest>> {
+    let scope = scope.resolve_or_default(client)?;
+    let requests = client.list_role_assignment_schedule_requests(scope)?;
+    Ok(requests
+        .into_iter()
+        .filter(|x| {
+            let time = x.time();
+            since.is_none_or(|since| time.is_some_and(|time| time >= since))
+                && until.is_none_or(|until| time.is_some_and(|time| time <= until))
+                && principal.is_none_or(|principal| x.is_principal(principal))
+        })
+        .collect())
+}
+
+/// Serve requests to activate and deactivate roles, generating a token if one
+/// is not provided
+fn serve_requests(
+    client: &PimClient,
+    listen: SocketAddr,
+    token: Option<String>,
+    duration: Duration,
+) -> Result<()> {
+    let token = token.unwrap_or_else(|| {
+        let token = Uuid::new_v4().simple().to_string();
+        info!("requests must include the header `Authorization: Bearer {token}`");
+        token
+    });
+    let settings = ServeSettings {
+        listen,
+        token,
+        justification: Config::load()?.default_justification,
+        duration,
+    };
+    serve(client, &settings)
+}
+
+/// Refresh the prompt cache with the currently active roles
+fn refresh_prompt(client: &PimClient) -> Result<()> {
+    let roles = client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
+    PromptCache::from_assignments(&roles).save()
+}
+
+/// Print the prompt summary, refreshing the cache in the background if needed
+fn prompt(max_age: Duration) -> Result<()> {
+    let cache = PromptCache::load().unwrap_or_else(|err| {
+        debug!("unable to load prompt cache: {err:?}");
+        None
+    });
+
+    let cache = match cache {
+        Some((cache, age)) if age <= max_age => cache,
+        cache => {
+            let cache = cache.map(|(cache, _)| cache).unwrap_or_default();
+            // re-save the current cache before refreshing to keep prompts
+            // rendered during the refresh from starting additional refreshes
+            cache.save()?;
+            ProcessCommand::new(current_exe()?)
+                .args(["prompt", "--refresh"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .context("unable to refresh prompt cache")?;
+            cache
+        }
+    };
+
+    if let Some(segment) = cache.segment() {
+        println!("{segment}");
+    }
+    Ok(())
+}
+
+/// Find the eligible assignments for the roles of the last activation
+///
+/// The justification and duration saved for each role are only used if they
+/// were not provided on the command line.
+fn build_last_set(
+    client: &PimClient,
+    last: &LastActivation,
+    use_justification: bool,
+    use_duration: bool,
+) -> Result<BTreeMap<RoleAssignment, ActivationOverrides>> {
+    let roles = client
+        .list_eligible_role_assignments(None, Some(ListFilter::AsTarget))
+        .context("unable to list eligible assignments")?;
+    last.roles
+        .iter()
+        .map(|saved| {
+            let entry = roles.resolve_role(&saved.role, &saved.scope, true)?;
+            let overrides = ActivationOverrides {
+                justification: saved.justification.clone().filter(|_| use_justification),
+                duration: saved.duration.filter(|_| use_duration),
+                ..ActivationOverrides::default()
+            };
+            Ok((entry, overrides))
+        })
+        .collect()
+}
+
+/// Record the roles that were activated for use with `activate last`
+///
+/// Failing to record the activation only logs a warning, as the roles have
+/// already been activated.
+fn record_activation(
+    roles: impl IntoIterator<Item = SavedRole>,
+    justification: &str,
+    duration: Duration,
+) {
+    let last = LastActivation {
+        roles: roles.into_iter().collect(),
+        justification: justification.to_string(),
+        duration,
+    };
+    if last.roles.is_empty() {
+        return;
+    }
+    if let Err(err) = last.save() {
+        warn!("unable to save the last activation: {err:?}");
+    }
+}
+
+/// Load the selection from the last activation
+///
+/// The remembered justification and duration are only used if they were not
+/// provided on the command line.
+fn remembered_selection(use_justification: bool, use_duration: bool) -> Remembered {
+    let last = LastActivation::load().unwrap_or_else(|err| {
+        warn!("unable to load the last activation: {err:?}");
+        None
+    });
+    last.map(|last| Remembered {
+        roles: last.roles,
+        justification: use_justification.then_some(last.justification),
+        duration: use_duration.then_some(last.duration.as_secs() / 60),
+    })
+    .unwrap_or_default()
+}
+
+/// Activate a set of roles, optionally waiting for them to become active
+///
+/// If `continue_on_error` is set, a summary of the results is printed rather
+/// than failing on the first error.
+fn activate_set(
+    client: &PimClient,
+    set: &BTreeMap<RoleAssignment, ActivationOverrides>,
+    justification: &str,
+    duration: Duration,
+    concurrency: usize,
+    wait: Option<Duration>,
+    continue_on_error: bool,
+) -> Result<()> {
+    let results =
+        client.activate_role_assignment_set_results(set, justification, duration, concurrency)?;
+
+    // record the justification and duration each role was activated with,
+    // including those from per-role overrides
+    let activated = results
+        .iter()
+        .filter(|(_, result)| !result.is_failed())
+        .map(|(entry, _)| {
+            let overrides = set.get(entry);
+            SavedRole::new(
+                entry,
+                overrides
+                    .and_then(|x| x.justification.as_deref())
+                    .unwrap_or(justification),
+                overrides.and_then(|x| x.duration).unwrap_or(duration),
+            )
+        });
+    record_activation(activated, justification, duration);
+
+    if !continue_on_error {
+        let failed = results
+            .iter()
+            .filter(|(_, result)| result.is_failed())
+            .map(|(entry, _)| entry.clone())
+            .collect::<BTreeSet<_>>();
+        ensure!(
+            failed.is_empty(),
+            "failed to activate the following roles:\n{}",
+            failed.friendly()
+        );
+    }
+
+    let waited = if let Some(wait) = wait {
+        let succeeded = results
+            .iter()
+            .filter(|(_, result)| **result == ActivationResult::Succeeded)
+            .map(|(entry, _)| entry.clone())
+            .collect();
+        client.wait_for_role_activation(&succeeded, wait)
+    } else {
+        Ok(())
+    };
+
+    if continue_on_error {
+        // summarize even if waiting fails, such that the roles that were
+        // activated, failed, or are pending approval are always shown
+        let summary = summarize(&results);
+        waited?;
+        summary
+    } else {
+        waited
+    }
+}
+
+/// Print a summary table of the results of a set operation
+///
+/// If every role failed, this returns an error.  If only some of the roles
+/// failed, this returns a `PartialFailure`.
+fn summarize(results: &BTreeMap<RoleAssignment, ActivationResult>) -> Result<()> {
+    let rows = results
+        .iter()
+        .map(|(entry, result)| (role_row(entry), result))
+        .collect();
+    summarize_rows(&["Role", "Scope"], rows, "roles")
+}
+
+fn summarize_approvals(results: &[(ScheduleRequest, ActivationResult)]) -> Result<()> {
+    let rows = results
+        .iter()
+        .map(|(request, result)| {
+            let row = vec![
+                request.role.to_string(),
+                request.principal_display(),
+                request
+                    .scope_name
+                    .clone()
+                    .unwrap_or_else(|| request.scope.to_string()),
+            ];
+            (row, result)
+        })
+        .collect();
+    summarize_rows(&["Role", "Principal", "Scope"], rows, "approvals")
+}
+
+fn role_row(entry: &RoleAssignment) -> Vec<String> {
+    vec![
+        entry.role.to_string(),
+        entry
+            .scope_name
+            .clone()
+            .unwrap_or_else(|| entry.scope.to_string()),
+    ]
+}
+
+fn directory_role_row(entry: &DirectoryRoleAssignment) -> Vec<String> {
+    vec![
+        entry.role.to_string(),
+        format!("directory {}", entry.directory_scope_id),
+    ]
+}
+
+fn group_row(entry: &GroupMembership) -> Vec<String> {
+    vec![
+        format!("group {}", entry.access),
+        entry
+            .group_name
+            .clone()
+            .unwrap_or_else(|| entry.group_id.clone()),
+    ]
+}
+
+/// Print the status of each entry, returning a `PartialFailure` if some, but
+/// not all, of the entries failed
+fn summarize_rows(
+    header: &[&str],
+    rows: Vec<(Vec<String>, &ActivationResult)>,
+    kind: &str,
+) -> Result<()> {
+    let total = rows.len();
+    let failed = rows.iter().filter(|(_, x)| x.is_failed()).count();
+
+    let rows = rows
+        .into_iter()
+        .map(|(mut row, result)| {
+            row.push(result.to_string());
+            row.push(if let ActivationResult::Failed(reason) = result {
+                reason.lines().next().unwrap_or_default().to_string()
+            } else {
+                String::new()
+            });
+            row
+        })
+        .collect::<Vec<_>>();
+    let header = header
+        .iter()
+        .chain(&["Status", "Reason"])
+        .copied()
+        .collect::<Vec<_>>();
+    print_table(&header, rows);
+
+    if failed == 0 {
+        Ok(())
+    } else if failed == total {
+        bail!("all {failed} {kind} failed");
+    } else {
+        Err(PartialFailure {
+            failed,
+            total,
+            kind: kind.to_string(),
+        }
+        .into())
+    }
+}
+
+/// Print rows as a table with left-aligned columns
+fn print_table(header: &[&str], rows: Vec<Vec<String>>) {
+    let header = header.iter().map(ToString::to_string).collect::<Vec<_>>();
+    let mut widths = header.iter().map(String::len).collect::<Vec<_>>();
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    for row in once(header).chain(rows) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(column, width)| format!("{column:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
+
+/// A value that can be displayed as a row in a table
+trait TableRow {
+    const HEADER: &'static [&'static str];
+
+    fn row(&self) -> Vec<String>;
+}
+
+/// An active role assignment, with the role name resolved for display
+#[derive(Serialize)]
+struct AssignmentRow {
+    role: String,
+    principal: String,
+    principal_type: String,
+    scope: Scope,
+    condition: Option<String>,
+}
+
+impl AssignmentRow {
+    fn new(assignment: &Assignment, definitions: &[Definition]) -> Self {
+        let properties = &assignment.properties;
+        Self {
+            role: definitions
+                .iter()
+                .find(|x| x.id.eq_ignore_ascii_case(&properties.role_definition_id))
+                .map_or_else(
+                    || properties.role_definition_id.clone(),
+                    |x| x.properties.role_name.clone(),
+                ),
+            principal: assignment.object.as_ref().map_or_else(
+                || properties.principal_id.clone(),
+                |x| x.upn.clone().unwrap_or_else(|| x.display_name.clone()),
+            ),
+            principal_type: properties.principal_type.clone(),
+            scope: properties.scope.clone(),
+            condition: properties.condition.clone(),
+        }
+    }
+}
+
+/// A listed role assignment, along with the scope it was found at and the
+/// group that grants it to the principal
+#[derive(Serialize)]
+struct ListedAssignment<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    found_at: Option<&'a Scope>,
+    #[serde(flatten)]
+    value: &'a Assignment,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    via_group: Option<&'a str>,
+}
+
+impl ListedAssignment<'_> {
+    fn row(&self, definitions: &[Definition], expand_groups: bool) -> Vec<String> {
+        let mut row = AssignmentRow::new(self.value, definitions).row();
+        row.extend(self.found_at.map(ToString::to_string));
+        if expand_groups {
+            row.push(self.via_group.unwrap_or_default().to_string());
+        }
+        row
+    }
+}
+
+/// List the assignments at the scope, optionally including those at nested
+/// scopes, along with the scope each was found at
+fn list_assignments(
+    client: &PimClient,
+    scope: &Scope,
+    nested: bool,
+    principal: Option<&str>,
+    concurrency: usize,
+) -> Result<Vec<(Scope, Assignment)>> {
+    let principal_id = principal
+        .map(|x| client.resolve_principal(x).map(|x| x.id))
+        .transpose()?;
+    if nested {
+        PimClient::thread_builder(concurrency);
+        return client.nested_role_assignments(scope, principal_id.as_deref());
+    }
+    Ok(client
+        .role_assignments_of_principal(scope, principal_id.as_deref())
+        .context("unable to list active assignments")?
+        .into_iter()
+        .map(|x| (scope.clone(), x))
+        .collect())
+}
+
+/// Print the sorted assignments, along with the scope each was found at if
+/// `nested` is set, and the group that grants it if `expand_groups` is set
+fn print_assignments(
+    mut objects: Vec<(Scope, Assignment, Option<String>)>,
+    definitions: &[Definition],
+    sort: &SortArgs,
+    format: OutputFormat,
+    nested: bool,
+    expand_groups: bool,
+) -> Result<()> {
+    sort.apply_by(&mut objects, |(_, x, _), key| {
+        AssignmentRow::new(x, definitions).sort_field(key)
+    });
+    let rows = objects
+        .iter()
+        .map(|(found_at, value, via_group)| ListedAssignment {
+            found_at: nested.then_some(found_at),
+            value,
+            via_group: via_group.as_deref(),
+        })
+        .collect::<Vec<_>>();
+    match format {
+        OutputFormat::Json => output(&rows)?,
+        OutputFormat::Table => {
+            let mut header = AssignmentRow::HEADER.to_vec();
+            if nested {
+                header.push("Found At");
+            }
+            if expand_groups {
+                header.push("Via Group");
+            }
+            print_table(
+                &header,
+                rows.iter()
+                    .map(|x| x.row(definitions, expand_groups))
+                    .collect(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Only keep the assignments of the role
+fn retain_role(
+    objects: &mut Vec<(Scope, Assignment)>,
+    definitions: &[Definition],
+    role: &Role,
+    scope: &Scope,
+) -> Result<()> {
+    let definition = definitions
+        .iter()
+        .find(|x| x.properties.role_name.eq_ignore_ascii_case(&role.0))
+        .with_context(|| format!("unable to find role definition for {role} at {scope}"))?;
+    objects.retain(|(_, x)| {
+        x.properties
+            .role_definition_id
+            .eq_ignore_ascii_case(&definition.id)
+    });
+    Ok(())
+}
+
+/// Delete the named assignment, or the assignments of the role to the
+/// principal, confirming each unless `yes` is set
+fn delete_assignments(
+    client: &PimClient,
+    scope: &Scope,
+    assignment_name: Option<String>,
+    role: Option<Role>,
+    principal: Option<String>,
+    yes: bool,
+) -> Result<()> {
+    if let Some(assignment_name) = assignment_name {
+        return client
+            .delete_role_assignment(scope, &assignment_name)
+            .context("unable to delete assignment");
+    }
+    let (Some(role), Some(principal)) = (role, principal) else {
+        bail!("either the assignment name or the role and principal must be specified");
+    };
+    let principal = client.resolve_principal(&principal)?;
+    let principal_name = principal.upn.as_deref().unwrap_or(&principal.display_name);
+    let found = client.find_role_assignments(scope, &role, &principal.id)?;
+    ensure!(
+        !found.is_empty(),
+        "no assignments of {role} to {principal_name} found at {scope}"
+    );
+    for assignment in found {
+        let value = format!(
+            "assignment of {role} to {principal_name} ({})",
+            assignment.name
+        );
+        if !yes && !confirm(&format!("delete {value} at {scope}"))? {
+            info!("skipping {value}");
+            continue;
+        }
+        client
+            .delete_role_assignment(scope, &assignment.name)
+            .context("unable to delete assignment")?;
+    }
+    Ok(())
+}
+
+/// Add an assignment for each member of the groups with assignments
+///
+/// Each member's assignment is a copy of the group's assignment for the
+/// member, along with the name of the group.  Members of nested groups are
+/// included.
+fn expand_group_assignments(
+    client: &PimClient,
+    objects: Vec<(Scope, Assignment)>,
+) -> Result<Vec<(Scope, Assignment, Option<String>)>> {
+    let group = PrincipalType::Group.to_string();
+    let mut members = BTreeMap::<String, BTreeSet<Object>>::new();
+    let mut results = Vec::new();
+    for (found_at, assignment) in objects {
+        let properties = &assignment.properties;
+        if properties.principal_type.eq_ignore_ascii_case(&group) {
+            let group_name = assignment.object.as_ref().map_or_else(
+                || properties.principal_id.clone(),
+                |x| x.display_name.clone(),
+            );
+            if !members.contains_key(&properties.principal_id) {
+                let found = client
+                    .group_members(&properties.principal_id, true)
+                    .with_context(|| format!("unable to list members of {group_name}"))?;
+                members.insert(properties.principal_id.clone(), found);
+            }
+            for member in members.get(&properties.principal_id).into_iter().flatten() {
+                let mut expanded = assignment.clone();
+                expanded.properties.principal_id.clone_from(&member.id);
+                expanded.properties.principal_type = member.object_type.to_string();
+                expanded.object = Some(member.clone());
+                results.push((found_at.clone(), expanded, Some(group_name.clone())));
+            }
+        }
+        results.push((found_at, assignment, None));
+    }
+    Ok(results)
+}
+
+impl TableRow for AssignmentRow {
+    const HEADER: &'static [&'static str] = &["Role", "Principal", "Type", "Scope", "Condition"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.role.clone(),
+            self.principal.clone(),
+            self.principal_type.clone(),
+            self.scope.to_string(),
+            self.condition.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl TableRow for RoleAssignment {
+    const HEADER: &'static [&'static str] = &["Role", "Scope", "Principal", "Expires"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.role.to_string(),
+            self.scope_name
+                .clone()
+                .unwrap_or_else(|| self.scope.to_string()),
+            self.object
+                .as_ref()
+                .map(|x| x.upn.clone().unwrap_or_else(|| x.display_name.clone()))
+                .or_else(|| self.principal_id.clone())
+                .unwrap_or_default(),
+            self.end_date_time
+                .as_deref()
+                .map(format_timestamp)
+                .unwrap_or_default(),
+        ]
+    }
+}
+
+impl TableRow for ProviderAssignment {
+    const HEADER: &'static [&'static str] = &["Provider", "Role", "Target", "State", "Expires"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.provider.to_string(),
+            self.role.clone(),
+            self.target.clone(),
+            self.state.clone(),
+            self.end_date_time
+                .as_deref()
+                .map(format_timestamp)
+                .unwrap_or_default(),
+        ]
+    }
+}
+
+impl TableRow for DirectoryRoleAssignment {
+    const HEADER: &'static [&'static str] =
+        &["Role", "Principal", "Directory Scope", "Type", "Expires"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.role.to_string(),
+            self.object.as_ref().map_or_else(
+                || self.principal_id.clone(),
+                |x| x.upn.clone().unwrap_or_else(|| x.display_name.clone()),
+            ),
+            self.directory_scope_id.clone(),
+            self.assignment_type.clone().unwrap_or_default(),
+            self.end_date_time
+                .as_deref()
+                .map_or_else(|| "permanent".to_string(), format_timestamp),
+        ]
+    }
+}
+
+impl TableRow for GroupMembership {
+    const HEADER: &'static [&'static str] = &["Group", "Access", "Type", "Expires"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.group_name
+                .clone()
+                .unwrap_or_else(|| self.group_id.clone()),
+            self.access.to_string(),
+            self.assignment_type.clone().unwrap_or_default(),
+            self.end_date_time
+                .as_deref()
+                .map_or_else(|| "permanent".to_string(), format_timestamp),
+        ]
+    }
+}
+
+impl TableRow for ScheduleRequest {
+    const HEADER: &'static [&'static str] = &[
+        "Created",
+        "Principal",
+        "Request",
+        "Role",
+        "Scope",
+        "Status",
+        "Justification",
+        "Ticket",
+    ];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.created_on.clone(),
+            self.principal_display(),
+            self.request_type.clone(),
+            self.role.to_string(),
+            self.scope_name
+                .clone()
+                .unwrap_or_else(|| self.scope.to_string()),
+            self.status.clone(),
+            self.justification.clone().unwrap_or_default(),
+            self.ticket_number.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl TableRow for AuditEntry {
+    const HEADER: &'static [&'static str] = &["Timestamp", "Principal", "Method", "URL", "Result"];
 
-        #[arg(long)]
-        /// Do not check for nested assignments
-        skip_nested: bool,
-    },
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.timestamp.clone(),
+            self.principal.clone().unwrap_or_default(),
+            self.method.clone(),
+            self.url.clone(),
+            self.error
+                .clone()
+                .unwrap_or_else(|| "Succeeded".to_string()),
+        ]
+    }
 }
 
-impl ResourcesSubCommand {
-    fn run(self, client: &PimClient) -> Result<()> {
-        match self {
-            Self::List { scope, skip_nested } => {
-                let scope = scope.build().context("valid scope must be provided")?;
-                output(&client.eligible_child_resources(&scope, !skip_nested)?)?;
-            }
-        }
-        Ok(())
+impl TableRow for HistoryEntry {
+    const HEADER: &'static [&'static str] = &[
+        "Timestamp",
+        "Action",
+        "Role",
+        "Scope",
+        "Duration",
+        "Status",
+        "Justification",
+    ];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.timestamp.clone(),
+            self.action.to_string(),
+            self.role.to_string(),
+            self.scope_name
+                .clone()
+                .unwrap_or_else(|| self.scope.to_string()),
+            self.duration.clone().unwrap_or_default(),
+            self.result.to_string(),
+            self.justification.clone().unwrap_or_default(),
+        ]
     }
 }
 
-/// Parse a single key-value pair of `X=Y` into a typed tuple of `(X, Y)`.
-///
-/// # Errors
-/// Returns an `Err` if any of the keys or values cannot be parsed or if no `=` is found.
-pub fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
-where
-    T: FromStr,
-    T::Err: Error + Send + Sync + 'static,
-    U: FromStr,
-    U::Err: Error + Send + Sync + 'static,
-{
-    if let Some((key, value)) = s.split_once('=') {
-        Ok((key.parse()?, value.parse()?))
-    } else {
-        Err(format!("invalid KEY=value: no `=` found in `{s}`").into())
+impl TableRow for RoleHolder {
+    const HEADER: &'static [&'static str] =
+        &["Principal", "Type", "State", "Role", "Scope", "Via Group"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.upn
+                .clone()
+                .unwrap_or_else(|| self.display_name.clone()),
+            self.principal_type.to_string(),
+            self.state.to_string(),
+            self.role.to_string(),
+            self.scope.to_string(),
+            self.via_group.clone().unwrap_or_default(),
+        ]
     }
 }
 
-fn build_readme_entry(cmd: &mut Command, mut names: Vec<String>) -> String {
-    let mut readme = String::new();
-    let current = cmd.get_name().to_string();
-
-    names.push(current);
+impl TableRow for ImportResult {
+    const HEADER: &'static [&'static str] = &["Line", "Principal", "Role", "Scope", "Status"];
 
-    // add positions to the display name if there are any
-    for positional in cmd.get_positionals() {
-        names.push(format!("<{}>", positional.get_id().as_str().to_uppercase()));
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.line.to_string(),
+            self.principal.clone(),
+            self.role.to_string(),
+            self.scope.to_string(),
+            self.status.to_string(),
+        ]
     }
+}
 
-    let name = names.join(" ");
+impl TableRow for RestoreResult {
+    const HEADER: &'static [&'static str] = &["Kind", "Role", "Scope", "Principal", "Status"];
 
-    // once we're at 6 levels of nesting, don't nest anymore.  This is the max
-    // that shows up on crates.io and GitHub.
-    let depth = min(names.iter().filter(|f| !f.starts_with('<')).count(), 5);
-    for _ in 0..depth {
-        readme.push('#');
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.kind.to_string(),
+            self.role.to_string(),
+            self.scope.to_string(),
+            self.principal_id.clone().unwrap_or_default(),
+            self.status.to_string(),
+        ]
     }
+}
 
-    let long_help = cmd.render_long_help().to_string().replace("```", "\n```\n");
-    readme.push_str(&format!(" {name}\n\n```\n{long_help}\n```\n",));
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Json,
+    Table,
+}
 
-    if let Some(example) = Cmd::example(&name) {
-        for _ in 0..=depth {
-            readme.push('#');
+impl OutputFormat {
+    fn print<T>(self, values: &[T]) -> Result<()>
+    where
+        T: Serialize + TableRow,
+    {
+        match self {
+            Self::Json => output(values),
+            Self::Table => {
+                print_table(T::HEADER, values.iter().map(TableRow::row).collect());
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Table => write!(f, "table"),
         }
-        readme.push_str(&format!(
-            " Example Usage\n\n```\n{}\n```\n\n",
-            example.trim()
-        ));
     }
+}
 
-    for cmd in cmd.get_subcommands_mut() {
-        if cmd.get_name() == "readme" {
-            continue;
+/// Parse either a duration before now or a timestamp
+fn parse_since(value: &str) -> Result<SystemTime> {
+    if let Ok(duration) = parse_duration(value) {
+        return SystemTime::now()
+            .checked_sub(duration)
+            .context("duration is too large");
+    }
+    parse_rfc3339_weak(value).context("expected a duration or timestamp")
+}
+
+/// Parse either a duration after now, a time of day, or a timestamp
+///
+/// Times of day, and timestamps without an offset, are in the local timezone.
+/// If the time of day has already passed today, the next day is used.
+fn parse_until(value: &str) -> Result<SystemTime> {
+    if let Ok(duration) = parse_duration(value) {
+        return SystemTime::now()
+            .checked_add(duration)
+            .context("duration is too large");
+    }
+    if let Ok(timestamp) = value.parse::<Timestamp>() {
+        return Ok(timestamp.into());
+    }
+    if let Ok(datetime) = value.parse::<DateTime>() {
+        return Ok(datetime.to_zoned(TimeZone::system())?.timestamp().into());
+    }
+    if let Ok(time) = value.parse::<Time>() {
+        let now = Zoned::now();
+        let mut end = now.with().time(time).build()?;
+        if end <= now {
+            end = end.tomorrow()?;
         }
-        readme.push_str(&build_readme_entry(cmd, names.clone()));
+        return Ok(end.timestamp().into());
     }
-    readme
+    parse_rfc3339_weak(value).context("expected a duration, time of day, or timestamp")
 }
 
-fn build_readme() {
-    let mut cmd = Cmd::command();
-    let readme = build_readme_entry(&mut cmd, Vec::new())
-        .replacen(
-            "# az-pim",
-            &format!("# Azure PIM CLI\n\n{}", env!("CARGO_PKG_DESCRIPTION")),
-            1,
-        )
-        .lines()
-        .map(str::trim_end)
-        .collect::<Vec<_>>()
-        .join("\n")
-        .replace("\n\n\n", "\n");
-    print!("{readme}");
+/// The duration from now until the specified time
+fn duration_until(until: SystemTime) -> Result<Duration> {
+    until
+        .duration_since(SystemTime::now())
+        .ok()
+        .filter(|x| x.as_secs() > 0)
+        .context("--until must be in the future")
 }
 
-pub(crate) fn output<T>(value: &T) -> Result<()>
-where
-    T: ?Sized + Serialize,
-{
-    serde_json::to_writer_pretty(stdout(), value).context("unable to serialize results")
+/// Convert roles specified on the command line into entries for `build_set`
+fn role_targets(
+    role: Option<Vec<(Role, RoleTarget)>>,
+) -> Option<Vec<(Role, Scope, ActivationOverrides)>> {
+    role.map(|x| {
+        x.into_iter()
+            .map(|(role, target)| {
+                let overrides = ActivationOverrides {
+                    duration: target.duration,
+                    ..ActivationOverrides::default()
+                };
+                (role, target.scope, overrides)
+            })
+            .collect()
+    })
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct ElevateEntry {
+    /// The name of the role
     role: Role,
+    /// The scope of the role
     scope: Scope,
+    /// How long the role should be active, overriding `--duration`
+    #[serde(default)]
+    duration: Option<String>,
+    /// The justification for the role, overriding the command line
+    #[serde(default)]
+    justification: Option<String>,
 }
 
-#[derive(Deserialize)]
+/// A set of roles to activate or deactivate
+#[derive(Deserialize, JsonSchema)]
 struct Roles(Vec<ElevateEntry>);
 
 fn main() -> Result<()> {
+    match run() {
+        Err(err) if err.chain().any(<dyn Error>::is::<PartialFailure>) => {
+            warn!("{err:#}");
+            exit(PARTIAL_FAILURE_EXIT_CODE);
+        }
+        result => result,
+    }
+}
+
+/// Load the config file
+///
+/// An invalid config file must not disable its policy or hooks, so only the
+/// commands used to inspect and fix it continue without it.
+fn load_config(command: &SubCommand) -> Result<Config> {
+    match Config::load() {
+        Ok(config) => Ok(config),
+        Err(err)
+            if matches!(
+                command,
+                SubCommand::Config { .. } | SubCommand::Init { .. } | SubCommand::Readme
+            ) =>
+        {
+            warn!("ignoring the config file: {err:#}");
+            Ok(Config::default())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn run() -> Result<()> {
     let args = Cmd::parse();
 
     let filter = if let Ok(x) = tracing_subscriber::EnvFilter::try_from_default_env() {
@@ -816,59 +4144,579 @@ fn main() -> Result<()> {
         .try_init()
         .ok();
 
-    if let Err(err) = check_latest_version() {
-        debug!("unable to check latest version: {err}");
+    use_utc(args.utc);
+    use_non_interactive(args.non_interactive);
+
+    let tenants = if args.tenant.iter().any(|x| x.eq_ignore_ascii_case("all")) {
+        PimClient::list_tenants()?
+    } else {
+        args.tenant
+    };
+
+    let mut client = PimClient::new()?.with_history().with_audit_log();
+    if args.no_cache {
+        client = client.without_listing_cache();
+    }
+    client = client.with_auth_method(args.auth_method);
+    if let Some(capture) = args.capture {
+        client = client.with_capture(capture);
     }
+    let config = load_config(&args.command)?;
 
-    let client = PimClient::new()?;
+    // the prompt must not wait on the network
+    if !matches!(args.command, SubCommand::Prompt { .. }) {
+        if let Err(err) = check_latest_version(config.install_channel) {
+            debug!("unable to check latest version: {err}");
+        }
+    }
 
-    match args.command {
-        SubCommand::List {
-            active,
-            filter,
-            scope,
-        } => {
+    client = client.with_retry_policy(config.retry_policy(args.max_retries)?);
+    if let Some(user_agent) = &config.user_agent {
+        client = client.with_user_agent(user_agent)?;
+    }
+    if let Some(theme) = &config.theme {
+        use_theme(theme.build()?);
+    }
+    if let Some(command_name) = config.command_name {
+        client = client.with_command_name(command_name);
+    }
+    client = client
+        .with_policy(config.policy.unwrap_or_default())
+        .with_dangerous_acknowledged(args.i_know_what_im_doing);
+    if let Some(hooks) = config.hooks {
+        client = client.with_hooks(hooks);
+    }
+    match (tenants.as_slice(), args.command) {
+        ([], command) => command.run(&client),
+        ([tenant], command) => command.run(&client.with_tenant(tenant)),
+        (
+            tenants,
+            SubCommand::List(ListArgs {
+                active,
+                filter,
+                scope,
+                all_providers,
+                sort,
+                group_by,
+                tree,
+                output: format,
+            }),
+        ) => {
+            ensure!(
+                group_by.is_none() && !tree,
+                "--group-by and --tree are not supported with multiple tenants"
+            );
             let scope = scope.build();
-            let roles = if active {
-                client.list_active_role_assignments(scope, Some(filter))?
+            if all_providers {
+                let mut rows = for_each_tenant(&client, tenants, |client| {
+                    list_all_providers(client, active)
+                });
+                sort.apply(&mut rows);
+                print_tenant_rows(format, &rows)
             } else {
-                client.list_eligible_role_assignments(scope, Some(filter))?
-            };
-            output(&roles)
-        }
-        SubCommand::Activate { cmd } => cmd.run(&client),
-        SubCommand::Deactivate { cmd } => cmd.run(&client),
-        SubCommand::Role { cmd } => match cmd {
-            RoleSubCommand::Assignment { cmd } => cmd.run(&client),
-            RoleSubCommand::Definition { cmd } => cmd.run(&client),
-            RoleSubCommand::Resources { cmd } => cmd.run(&client),
-        },
-        SubCommand::Cleanup { cmd } => cmd.run(&client),
-        SubCommand::Readme => {
-            build_readme();
+                let mut rows = for_each_tenant(&client, tenants, |client| {
+                    let roles = if active {
+                        client.list_active_role_assignments(scope.clone(), Some(filter.clone()))?
+                    } else {
+                        client
+                            .list_eligible_role_assignments(scope.clone(), Some(filter.clone()))?
+                    };
+                    Ok(roles.into_iter().collect())
+                });
+                sort.apply(&mut rows);
+                print_tenant_rows(format, &rows)
+            }
+        }
+        (_, _) => bail!("multiple tenants are only supported by `list`"),
+    }
+}
+
+/// A result from one of several tenants
+#[derive(Serialize)]
+struct TenantRow<T> {
+    tenant: String,
+    #[serde(flatten)]
+    value: T,
+}
+
+/// Collect results from each tenant, skipping tenants that fail
+fn for_each_tenant<T, F>(client: &PimClient, tenants: &[String], func: F) -> Vec<TenantRow<T>>
+where
+    F: Fn(&PimClient) -> Result<Vec<T>>,
+{
+    let mut results = Vec::new();
+    for tenant in tenants {
+        let result = client.for_tenant(tenant).and_then(|client| func(&client));
+        match result {
+            Ok(values) => results.extend(values.into_iter().map(|value| TenantRow {
+                tenant: tenant.clone(),
+                value,
+            })),
+            Err(err) => warn!("skipping tenant {tenant}: {err:#}"),
+        }
+    }
+    results
+}
+
+#[allow(clippy::manual_assert, clippy::panic)]
+#[derive(Clone, Copy, ValueEnum)]
+enum GroupBy {
+    /// One group for each scope, with its roles beneath
+    Scope,
+}
+
+/// Results that share the same scope
+#[derive(Serialize)]
+struct ScopeGroup<'a, T> {
+    scope: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope_name: Option<String>,
+    assignments: Vec<&'a T>,
+}
+
+/// The scope used to group results
+trait GroupFields {
+    /// The scope, and its name if known
+    fn group_scope(&self) -> (String, Option<String>);
+}
+
+impl GroupFields for RoleAssignment {
+    fn group_scope(&self) -> (String, Option<String>) {
+        (self.scope.to_string(), self.scope_name.clone())
+    }
+}
+
+impl GroupFields for ProviderAssignment {
+    fn group_scope(&self) -> (String, Option<String>) {
+        (self.target.clone(), None)
+    }
+}
+
+/// Print the results, in groups if requested
+///
+/// Groups are ordered by scope, and the results within each group keep their
+/// original order.
+fn print_grouped<T>(format: OutputFormat, group_by: Option<GroupBy>, rows: &[T]) -> Result<()>
+where
+    T: Serialize + TableRow + GroupFields,
+{
+    let Some(GroupBy::Scope) = group_by else {
+        return format.print(rows);
+    };
+
+    let mut groups = BTreeMap::<String, ScopeGroup<T>>::new();
+    for row in rows {
+        let (scope, scope_name) = row.group_scope();
+        groups
+            .entry(scope.to_lowercase())
+            .or_insert_with(|| ScopeGroup {
+                scope,
+                scope_name,
+                assignments: Vec::new(),
+            })
+            .assignments
+            .push(row);
+    }
+    let groups = groups.into_values().collect::<Vec<_>>();
+
+    match format {
+        OutputFormat::Json => output(&groups),
+        OutputFormat::Table => {
+            for (i, group) in groups.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                match &group.scope_name {
+                    Some(name) => println!("{name} ({})", group.scope),
+                    None => println!("{}", group.scope),
+                }
+                print_table(
+                    T::HEADER,
+                    group.assignments.iter().map(|x| x.row()).collect(),
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print the roles arranged into a tree of scopes
+fn print_tree(format: OutputFormat, roles: Vec<RoleAssignment>) -> Result<()> {
+    let trees = ScopeTree::build(
+        roles
+            .into_iter()
+            .map(|x| (x.scope.clone(), x.scope_name.clone(), x)),
+    );
+    match format {
+        OutputFormat::Json => output(&trees),
+        OutputFormat::Table => {
+            let rendered = ScopeTree::render(&trees, |node, parent| {
+                let name = node.display_name(parent);
+                if node.values.is_empty() {
+                    return name;
+                }
+                let roles = node
+                    .values
+                    .iter()
+                    .map(|x| x.role.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{name} [{roles}]")
+            });
+            println!("{rendered}");
+            Ok(())
+        }
+    }
+}
+
+fn print_tenant_rows<T>(format: OutputFormat, rows: &[TenantRow<T>]) -> Result<()>
+where
+    T: Serialize + TableRow,
+{
+    match format {
+        OutputFormat::Json => output(rows),
+        OutputFormat::Table => {
+            let header = ["Tenant"]
+                .iter()
+                .chain(T::HEADER)
+                .copied()
+                .collect::<Vec<_>>();
+            let rows = rows
+                .iter()
+                .map(|x| {
+                    let mut row = vec![x.tenant.clone()];
+                    row.extend(x.value.row());
+                    row
+                })
+                .collect();
+            print_table(&header, rows);
             Ok(())
         }
-        SubCommand::Init { shell } => {
-            Cmd::shell_completion(shell);
+    }
+}
+
+fn list_holders(
+    client: &PimClient,
+    role: &Role,
+    scope: ScopeBuilder,
+    nested: bool,
+    expand_groups: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let scope = scope.resolve_or_default(client)?;
+    let holders = who_has(client, role, &scope, nested, expand_groups)?;
+    format.print(&holders.into_iter().collect::<Vec<_>>())
+}
+
+fn check_access(
+    client: &PimClient,
+    role: &Role,
+    scope: ScopeBuilder,
+    format: OutputFormat,
+) -> Result<()> {
+    let scope = scope.resolve_or_default(client)?;
+    let access = can_i(client, role, &scope)?;
+    match format {
+        OutputFormat::Json => output(&access),
+        OutputFormat::Table => {
+            println!("{access}");
             Ok(())
         }
     }
 }
 
+fn report(
+    client: &PimClient,
+    scope: ScopeBuilder,
+    nested: bool,
+    path: Option<PathBuf>,
+    format: Option<ReportFormat>,
+) -> Result<()> {
+    let scope = scope.resolve_or_default(client)?;
+    let report = Report::collect(client, &scope, nested)?;
+    if let Some(path) = path {
+        let format = format.unwrap_or_else(|| ReportFormat::from_path(&path));
+        write(&path, report.render(format)?)
+            .with_context(|| format!("unable to write {}", path.display()))
+    } else {
+        print!(
+            "{}",
+            report.render(format.unwrap_or(ReportFormat::Markdown))?
+        );
+        Ok(())
+    }
+}
+
+fn export(
+    client: &PimClient,
+    scope: ScopeBuilder,
+    nested: bool,
+    path: Option<PathBuf>,
+) -> Result<()> {
+    let scope = scope.resolve_or_default(client)?;
+    let backup = Backup::collect(client, &scope, nested)?;
+    match path {
+        Some(path) => {
+            let handle = File::create(&path)
+                .with_context(|| format!("unable to create {}", path.display()))?;
+            serde_json::to_writer_pretty(handle, &backup).context("unable to serialize export")
+        }
+        None => output(&backup),
+    }
+}
+
+fn import(client: &PimClient, path: &Path, dry_run: bool, format: OutputFormat) -> Result<()> {
+    let data = read(path).with_context(|| format!("unable to read {}", path.display()))?;
+    let results = Backup::parse(&data)?.restore(client, dry_run)?;
+    format.print(&results)?;
+    ensure!(
+        !results
+            .iter()
+            .any(|x| matches!(x.status, RestoreStatus::Failed(_))),
+        "unable to restore all entries"
+    );
+    Ok(())
+}
+
+impl ListArgs {
+    fn run(self, client: &PimClient) -> Result<()> {
+        let Self {
+            active,
+            filter,
+            scope,
+            all_providers,
+            sort,
+            group_by,
+            tree,
+            output: format,
+        } = self;
+        if all_providers {
+            let mut rows = list_all_providers(client, active)?;
+            sort.apply(&mut rows);
+            return print_grouped(format, group_by, &rows);
+        }
+        let scope = scope.resolve(client)?;
+        let roles = if active {
+            client.list_active_role_assignments(scope, Some(filter))?
+        } else {
+            client.list_eligible_role_assignments(scope, Some(filter))?
+        };
+        let mut rows = roles.into_iter().collect::<Vec<_>>();
+        sort.apply(&mut rows);
+        if tree {
+            return print_tree(format, rows);
+        }
+        print_grouped(format, group_by, &rows)
+    }
+}
+
+impl SubCommand {
+    fn run(self, client: &PimClient) -> Result<()> {
+        match self {
+            Self::List(args) => args.run(client),
+            Self::Activate { cmd } => cmd.run(client),
+            Self::Deactivate { cmd } => cmd.run(client),
+            Self::Role { cmd } => cmd.run(client),
+            Self::Entra { cmd } => cmd.run(client),
+            Self::Group { cmd } => cmd.run(client),
+            Self::Approval { cmd } => cmd.run(client),
+            Self::Scope { cmd } => cmd.run(client),
+            Self::Cleanup { cmd } => cmd.run(client),
+            Self::Config { cmd } => cmd.run(client),
+            Self::Interactive {
+                justification,
+                duration,
+                refresh,
+            } => {
+                let justification = justification.or(Config::load()?.default_justification);
+                dashboard_ui(client, justification, duration.into(), refresh.into())
+            }
+            Self::Watch {
+                justification,
+                duration,
+                config,
+                role,
+                renew_before,
+                interval,
+                until,
+                exact,
+            } => {
+                let set = build_set(client, config, role_targets(role), false, exact)?;
+                ensure!(!set.is_empty(), "no roles to watch");
+                let settings = WatchSettings {
+                    justification: justification_or_default(justification)?,
+                    duration: duration.into(),
+                    renew_before: renew_before.into(),
+                    interval: interval.into(),
+                    until,
+                };
+                watch(client, &set, &settings)
+            }
+            Self::Serve {
+                listen,
+                token,
+                duration,
+            } => serve_requests(client, listen, token, duration.into()),
+            Self::Prompt { refresh: true, .. } => refresh_prompt(client),
+            Self::Prompt { max_age, .. } => prompt(max_age.into()),
+            Self::Audit { cmd: Some(cmd), .. } => cmd.run(),
+            Self::Audit {
+                cmd: None,
+                scope,
+                since,
+                until,
+                principal,
+                output: format,
+            } => format.print(&audit(client, scope, since, until, principal.as_deref())?),
+            Self::History {
+                since,
+                output: format,
+            } => format.print(&history::load(since)?),
+            Self::WhoHas {
+                role,
+                scope,
+                nested,
+                expand_groups,
+                output: format,
+            } => list_holders(client, &role, scope, nested, expand_groups, format),
+            Self::CanI {
+                role,
+                scope,
+                output: format,
+            } => check_access(client, &role, scope, format),
+            Self::Report {
+                scope,
+                nested,
+                output: path,
+                format,
+            } => report(client, scope, nested, path, format),
+            Self::Export {
+                scope,
+                nested,
+                output: path,
+            } => export(client, scope, nested, path),
+            Self::Import {
+                path,
+                dry_run,
+                output: format,
+            } => import(client, &path, dry_run, format),
+            Self::Readme => {
+                build_readme();
+                Ok(())
+            }
+            Self::Init { shell } => {
+                Cmd::shell_completion(shell);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The formats supported for config files
+#[derive(Clone, Copy)]
+enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Determine the format from the extension, or from the contents if the
+    /// extension is not recognized
+    fn detect(path: &Path, data: &str) -> Self {
+        match path
+            .extension()
+            .and_then(|x| x.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("json") => Self::Json,
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("toml") => Self::Toml,
+            _ => Self::sniff(data),
+        }
+    }
+
+    fn sniff(data: &str) -> Self {
+        if serde_json::from_str::<serde_json::Value>(data).is_ok() {
+            Self::Json
+        } else if toml::from_str::<toml::Table>(data).is_ok() {
+            Self::Toml
+        } else {
+            Self::Yaml
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(self, data: &str) -> Result<T> {
+        Ok(match self {
+            Self::Json => serde_json::from_str(data)?,
+            Self::Yaml => serde_norway::from_str(data)?,
+            Self::Toml => {
+                // TOML documents must be a table, so the entries are read from
+                // its only key, such as `[[role]]`
+                let table = toml::from_str::<toml::Table>(data)?;
+                let mut values = table.into_iter().map(|(_, value)| value);
+                let (Some(value), None) = (values.next(), values.next()) else {
+                    bail!("expected a single array of tables");
+                };
+                value.try_into()?
+            }
+        })
+    }
+}
+
+/// Read a file, or stdin if the path is `-`
+fn read_input(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut data = String::new();
+        stdin()
+            .read_to_string(&mut data)
+            .context("unable to read from stdin")?;
+        Ok(data)
+    } else {
+        read_to_string(path).with_context(|| format!("unable to read {}", path.display()))
+    }
+}
+
+/// Read a config file, or stdin if the path is `-`
+fn read_config<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let data = read_input(path)?;
+    ConfigFormat::detect(path, &data)
+        .parse(&data)
+        .with_context(|| format!("unable to parse config file {}", path.display()))
+}
+
+/// Read a set of roles from an `activate set` or `deactivate set` config file
+fn read_roles(path: &Path) -> Result<Vec<ElevateEntry>> {
+    let Roles(roles) = read_config(path)?;
+    Ok(roles)
+}
+
 fn build_set(
     client: &PimClient,
     config: Option<PathBuf>,
-    role: Option<Vec<(Role, Scope)>>,
+    role: Option<Vec<(Role, Scope, ActivationOverrides)>>,
     active: bool,
-) -> Result<BTreeSet<RoleAssignment>> {
+    exact: bool,
+) -> Result<BTreeMap<RoleAssignment, ActivationOverrides>> {
     let mut desired_roles = role.unwrap_or_default();
 
     if let Some(path) = config {
-        let handle = File::open(path).context("unable to open activate-set config file")?;
-        let Roles(roles) =
-            serde_json::from_reader(handle).context("unable to parse config file")?;
-        for entry in roles {
-            desired_roles.push((entry.role, entry.scope));
+        for entry in read_roles(&path)? {
+            let duration = entry
+                .duration
+                .as_deref()
+                .map(parse_duration)
+                .transpose()
+                .with_context(|| {
+                    format!("invalid duration for {} in {}", entry.role, entry.scope)
+                })?;
+            let overrides = ActivationOverrides {
+                duration,
+                justification: entry.justification,
+                ticket: None,
+            };
+            desired_roles.push((entry.role, entry.scope, overrides));
         }
     }
 
@@ -882,12 +4730,10 @@ fn build_set(
             .context("unable to list available assignments in PIM")?
     };
 
-    let mut to_add = BTreeSet::new();
-    for (role, scope) in desired_roles {
-        let entry = assignments
-            .find_role(&role, &scope)
-            .with_context(|| format!("role not found.  role:{role} scope:{scope}"))?;
-        to_add.insert(entry);
+    let mut to_add = BTreeMap::new();
+    for (role, scope, overrides) in desired_roles {
+        let entry = assignments.resolve_role(&role, &scope, exact)?;
+        to_add.insert(entry, overrides);
     }
 
     Ok(to_add)
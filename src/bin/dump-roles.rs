@@ -1,23 +1,39 @@
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 use azure_pim_cli::{
     check_latest_version,
-    graph::PrincipalType,
+    graph::{NestedMember, PrincipalType},
     models::{
+        policies::ActivationPolicy,
         roles::{Role, RoleAssignment},
-        scope::{Scope, ScopeBuilder},
+        scope::{Scope, ScopeBuilder, ScopeLevel},
     },
     ListFilter, PimClient,
 };
-use clap::{ArgAction, Args, CommandFactory, Parser};
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use humantime::format_duration;
+use parking_lot::Mutex;
 use rayon::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::BTreeSet,
-    io::{stderr, stdout},
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{stderr, stdout, BufReader, Write},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
 };
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 use tracing_subscriber::filter::LevelFilter;
 
+// keep in sync with `az-pim`, which has found that more than 4 concurrent
+// requests are likely to be rate limited
+const DEFAULT_CONCURRENCY: usize = 4;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+// the number of scopes listed at once, which are combined into batch requests
+const SCAN_CHUNK: usize = 100;
+const GROUP_CHAIN_SEPARATOR: &str = " -> ";
+
 /// A CLI to dump all the roles in a given scope
 #[derive(Parser)]
 #[command(version, disable_help_subcommand = true, name = "dump-roles")]
@@ -33,8 +49,149 @@ struct Cmd {
     eligible: bool,
 
     /// Expand groups to include their members
+    ///
+    /// Members of nested groups are included, with `via_group` set to the chain
+    /// of groups that grants the role, such as `TeamA -> SubTeamB`.
     #[clap(long)]
     expand_groups: bool,
+
+    /// Expand nested groups using a single request per group
+    ///
+    /// This is faster for deeply nested groups, but `via_group` only includes
+    /// the group with the assignment rather than the chain of nested groups.
+    #[clap(long, requires = "expand_groups")]
+    transitive: bool,
+
+    #[clap(flatten)]
+    filters: Filters,
+
+    /// Concurrency rate
+    ///
+    /// Specify how many requests to make concurrently.  Higher values may be
+    /// faster, but are more likely to be rate limited by Azure.
+    #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    /// Use the specified tenant, rather than the tenant of the Azure CLI's
+    /// default subscription
+    #[clap(long)]
+    tenant: Option<String>,
+
+    #[clap(flatten)]
+    columns: Columns,
+
+    /// Output format
+    ///
+    /// The `csv` and `tsv` formats include the columns `role`, `scope`,
+    /// `principal_type`, `display_name`, `upn`, and `via_group`.  If
+    /// `--include-permissions` is specified, the columns `actions`,
+    /// `not_actions`, `data_actions`, and `not_data_actions` are included, with
+    /// multiple values separated by `;`.  If `--include-policies` is specified,
+    /// the columns `max_duration`, `approval_required`, `mfa_required`,
+    /// `justification_required`, and `ticket_required` are included.
+    #[clap(long, value_enum, default_value_t = OutputFormat::Json)]
+    output: OutputFormat,
+
+    #[clap(subcommand)]
+    command: Option<SubCommand>,
+}
+
+#[derive(Subcommand)]
+enum SubCommand {
+    /// Compare a previous dump against the current state or another dump
+    ///
+    /// The scope and filters are specified before `diff`, such as
+    /// `dump-roles --subscription <SUBSCRIPTION> diff old.json`.  The filters
+    /// are also applied to the entries read from the files.
+    Diff {
+        /// Path to a previous JSON dump
+        old: PathBuf,
+
+        /// Path to another JSON dump to compare against
+        ///
+        /// If not provided, the current state of the specified scope is used.
+        new: Option<PathBuf>,
+
+        /// Exit with a status of 1 if there are any differences
+        #[clap(long)]
+        exit_code: bool,
+    },
+}
+
+#[derive(Args)]
+#[command(about = None)]
+struct Filters {
+    /// Only include assignments of the specified role
+    ///
+    /// Specify multiple times to include multiple roles.
+    #[clap(long)]
+    role: Vec<Role>,
+
+    /// Only include principals of the specified type
+    ///
+    /// Specify multiple times to include multiple types.
+    #[clap(long, value_enum)]
+    principal_type: Vec<PrincipalType>,
+
+    /// Only include principals whose UPN contains the specified text
+    #[clap(long)]
+    upn_contains: Option<String>,
+
+    /// Only include assignments at the specified level or broader
+    ///
+    /// For example, `subscription` excludes assignments made at resource groups
+    /// or individual resources.  Scopes narrower than the specified level are
+    /// not queried.
+    #[clap(long, value_enum)]
+    min_scope_level: Option<ScopeLevel>,
+}
+
+impl Filters {
+    fn includes_scope(&self, scope: &Scope) -> bool {
+        self.min_scope_level
+            .is_none_or(|level| scope.level() <= level)
+    }
+
+    fn includes_role(&self, role: &Role) -> bool {
+        self.role.is_empty() || self.role.iter().any(|x| x.0.eq_ignore_ascii_case(&role.0))
+    }
+
+    fn includes(&self, entry: &Entry) -> bool {
+        let upn_contains = self.upn_contains.as_ref().map(|x| x.to_lowercase());
+        self.includes_scope(&entry.scope)
+            && self.includes_role(&entry.role)
+            && (self.principal_type.is_empty()
+                || self.principal_type.contains(&entry.principal_type))
+            && upn_contains.is_none_or(|text| {
+                entry
+                    .upn
+                    .as_ref()
+                    .is_some_and(|upn| upn.to_lowercase().contains(&text))
+            })
+    }
+}
+
+/// Optional details to include for each entry
+#[derive(Args, Clone, Copy, Default)]
+#[command(about = None)]
+struct Columns {
+    /// Include the actions and data actions of each role
+    #[clap(long = "include-permissions")]
+    permissions: bool,
+
+    /// Include the conditions for activating each eligible role
+    ///
+    /// This includes the maximum activation duration and if approval,
+    /// multi-factor authentication, a justification, or a ticket is required.
+    #[clap(long = "include-policies", requires = "eligible")]
+    policies: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum, PartialEq, Eq)]
+enum OutputFormat {
+    Json,
+    Csv,
+    Tsv,
 }
 
 impl Cmd {
@@ -57,7 +214,7 @@ $ dump-roles --subscription 00000000-0000-0000-0000-000000000000 --expand-groups
     }
 }
 
-#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 struct Entry {
     role: Role,
     scope: Scope,
@@ -68,9 +225,143 @@ struct Entry {
     principal_type: PrincipalType,
     #[serde(skip_serializing_if = "Option::is_none")]
     via_group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permissions: Option<Permissions>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    policy: Option<ActivationPolicy>,
+}
+
+/// The combined permissions of a role definition
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Default)]
+struct Permissions {
+    actions: BTreeSet<String>,
+    not_actions: BTreeSet<String>,
+    data_actions: BTreeSet<String>,
+    not_data_actions: BTreeSet<String>,
+}
+
+impl Permissions {
+    const COLUMNS: [&'static str; 4] =
+        ["actions", "not_actions", "data_actions", "not_data_actions"];
+
+    fn columns(&self) -> [String; 4] {
+        [
+            &self.actions,
+            &self.not_actions,
+            &self.data_actions,
+            &self.not_data_actions,
+        ]
+        .map(|x| x.iter().map(String::as_str).collect::<Vec<_>>().join(";"))
+    }
+}
+
+const POLICY_COLUMNS: [&str; 5] = [
+    "max_duration",
+    "approval_required",
+    "mfa_required",
+    "justification_required",
+    "ticket_required",
+];
+
+fn policy_columns(policy: Option<&ActivationPolicy>) -> [String; 5] {
+    let Some(policy) = policy else {
+        return Default::default();
+    };
+    [
+        policy.max_duration.clone().unwrap_or_default(),
+        policy.approval_required.to_string(),
+        policy.mfa_required.to_string(),
+        policy.justification_required.to_string(),
+        policy.ticket_required.to_string(),
+    ]
+}
+
+/// The differences between two dumps
+///
+/// Entries are matched by role, scope, principal, and group.  Entries that
+/// match but differ otherwise, such as a renamed principal, are changed.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct Diff {
+    added: Vec<Entry>,
+    removed: Vec<Entry>,
+    changed: Vec<Change>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct Change {
+    old: Entry,
+    new: Entry,
+}
+
+impl Diff {
+    fn new(old: BTreeSet<Entry>, new: BTreeSet<Entry>) -> Self {
+        let mut old = old
+            .into_iter()
+            .map(|x| (x.key(), x))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for entry in new {
+            match old.remove(&entry.key()) {
+                None => added.push(entry),
+                Some(previous) if previous != entry => changed.push(Change {
+                    old: previous,
+                    new: entry,
+                }),
+                Some(_) => {}
+            }
+        }
+
+        Self {
+            added,
+            removed: old.into_values().collect(),
+            changed,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
 }
 
 impl Entry {
+    fn key(&self) -> (Role, Scope, String, Option<String>) {
+        (
+            self.role.clone(),
+            self.scope.clone(),
+            self.id.clone(),
+            self.via_group.clone(),
+        )
+    }
+
+    const COLUMNS: [&'static str; 6] = [
+        "role",
+        "scope",
+        "principal_type",
+        "display_name",
+        "upn",
+        "via_group",
+    ];
+
+    fn columns(&self, include: Columns) -> Vec<String> {
+        let mut columns = vec![
+            self.role.to_string(),
+            self.scope.to_string(),
+            self.principal_type.to_string(),
+            self.display_name.clone(),
+            self.upn.clone().unwrap_or_default(),
+            self.via_group.clone().unwrap_or_default(),
+        ];
+        if include.permissions {
+            columns.extend(self.permissions.clone().unwrap_or_default().columns());
+        }
+        if include.policies {
+            columns.extend(policy_columns(self.policy.as_ref()));
+        }
+        columns
+    }
+
     fn is_dominated(&self, other: &Self) -> bool {
         self.id == other.id && self.role == other.role && other.scope.contains(&self.scope)
     }
@@ -82,6 +373,13 @@ fn main() -> Result<()> {
         scope,
         eligible,
         expand_groups,
+        transitive,
+        filters,
+        concurrency,
+        tenant,
+        columns,
+        output,
+        command,
     } = Cmd::build()?;
 
     let filter = if let Ok(x) = tracing_subscriber::EnvFilter::try_from_default_env() {
@@ -98,31 +396,102 @@ fn main() -> Result<()> {
         .try_init()
         .ok();
 
-    if let Err(err) = check_latest_version() {
+    if let Err(err) = check_latest_version(None) {
         debug!("unable to check latest version: {err}");
     }
 
-    let scope = scope.build().context("scope required")?;
-    let client = PimClient::new()?;
+    PimClient::thread_builder(concurrency);
+    let mut client = PimClient::new()?;
+    if let Some(tenant) = tenant {
+        client = client.with_tenant(tenant);
+    }
+    let current = || -> Result<BTreeSet<Entry>> {
+        let scope = scope.resolve_or_default(&client)?;
+        let mut results = dump(
+            &client,
+            scope,
+            eligible,
+            expand_groups,
+            transitive,
+            &filters,
+        )?;
+        if columns.permissions {
+            results = add_permissions(&client, results);
+        }
+        if columns.policies {
+            results = add_policies(&client, results);
+        }
+        Ok(results)
+    };
+
+    match command {
+        Some(SubCommand::Diff {
+            old,
+            new,
+            exit_code,
+        }) => {
+            ensure!(
+                output == OutputFormat::Json,
+                "diff only supports json output"
+            );
+            let old = read_dump(&old, &filters)?;
+            let new = match new {
+                Some(new) => read_dump(&new, &filters)?,
+                None => current()?,
+            };
+            let diff = Diff::new(old, new);
+            serde_json::to_writer_pretty(stdout(), &diff)?;
+            if exit_code && !diff.is_empty() {
+                exit(1);
+            }
+        }
+        None => {
+            let results = current()?;
+            match output {
+                OutputFormat::Json => serde_json::to_writer_pretty(stdout(), &results)?,
+                OutputFormat::Csv => {
+                    write_delimited(&mut stdout(), &results, ',', columns)?;
+                }
+                OutputFormat::Tsv => {
+                    write_delimited(&mut stdout(), &results, '\t', columns)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
+/// Collect the role assignments at and below the specified scope
+fn dump(
+    client: &PimClient,
+    scope: Scope,
+    eligible: bool,
+    expand_groups: bool,
+    transitive: bool,
+    filters: &Filters,
+) -> Result<BTreeSet<Entry>> {
     let mut scopes = client
         .eligible_child_resources(&scope, true)?
         .into_iter()
         .map(|x| x.id)
+        .filter(|x| filters.includes_scope(x))
         .collect::<BTreeSet<_>>();
     scopes.insert(scope);
+    info!("discovered {} scopes", scopes.len());
 
+    let progress = Progress::new("scopes scanned", scopes.len());
     let mut results = BTreeSet::new();
+    let scopes = scopes.into_iter().collect::<Vec<_>>();
     let result: Vec<(Scope, Result<BTreeSet<RoleAssignment>>)> = scopes
-        .into_par_iter()
-        .map(|scope| {
+        .par_chunks(SCAN_CHUNK)
+        .flat_map_iter(|chunk| {
             let entries = if eligible {
-                client
-                    .list_eligible_role_assignments(Some(scope.clone()), Some(ListFilter::AtScope))
+                client.list_eligible_role_assignments_at_scopes(chunk, Some(&ListFilter::AtScope))
             } else {
-                client.list_active_role_assignments(Some(scope.clone()), Some(ListFilter::AtScope))
+                client.list_active_role_assignments_at_scopes(chunk, Some(&ListFilter::AtScope))
             };
-            (scope.clone(), entries)
+            progress.add(chunk.len());
+            entries
         })
         .collect();
 
@@ -130,6 +499,9 @@ fn main() -> Result<()> {
         match assignments {
             Ok(assignments) => {
                 for entry in assignments {
+                    if !filters.includes_role(&entry.role) {
+                        continue;
+                    }
                     let Some(object) = entry.object else { continue };
                     results.insert(Entry {
                         role: entry.role,
@@ -139,6 +511,8 @@ fn main() -> Result<()> {
                         principal_type: object.object_type,
                         scope: scope.clone(),
                         via_group: None,
+                        permissions: None,
+                        policy: None,
                     });
                 }
             }
@@ -149,31 +523,267 @@ fn main() -> Result<()> {
     }
 
     if expand_groups {
-        let mut expanded = BTreeSet::new();
-        for entry in &results {
-            if entry.principal_type != PrincipalType::Group {
-                continue;
-            }
+        let expanded = expand_group_members(client, &results, transitive)?;
+        results.extend(expanded);
+    }
 
-            let members = client.group_members(&entry.id, true)?;
-            for member in members {
-                expanded.insert(Entry {
-                    role: entry.role.clone(),
-                    id: member.id,
-                    display_name: member.display_name,
-                    upn: member.upn,
-                    principal_type: member.object_type,
-                    scope: entry.scope.clone(),
-                    via_group: Some(entry.display_name.clone()),
-                });
-            }
+    results.retain(|x| filters.includes(x));
+    Ok(remove_dominated_scopes(results))
+}
+
+/// Read a previous JSON dump
+fn read_dump(path: &Path, filters: &Filters) -> Result<BTreeSet<Entry>> {
+    let handle = File::open(path).with_context(|| format!("unable to open {}", path.display()))?;
+    let mut results: BTreeSet<Entry> = serde_json::from_reader(BufReader::new(handle))
+        .with_context(|| format!("unable to parse {}", path.display()))?;
+    results.retain(|x| filters.includes(x));
+    Ok(results)
+}
+
+/// Periodically report the progress of a long running step to stderr
+///
+/// Progress is logged at the `info` level, so `--quiet` suppresses it.
+struct Progress {
+    label: &'static str,
+    total: usize,
+    done: AtomicUsize,
+    start: Instant,
+    last: Mutex<Instant>,
+}
+
+impl Progress {
+    fn new(label: &'static str, total: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            label,
+            total,
+            done: AtomicUsize::new(0),
+            start: now,
+            last: Mutex::new(now),
+        }
+    }
+
+    fn increment(&self) {
+        self.add(1);
+    }
+
+    fn add(&self, count: usize) {
+        let done = self.done.fetch_add(count, Ordering::Relaxed) + count;
+        let mut last = self.last.lock();
+        if done < self.total && last.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        *last = Instant::now();
+
+        let elapsed = self.start.elapsed();
+        let remaining = self.total.saturating_sub(done);
+        let eta = elapsed
+            .checked_div(u32::try_from(done).unwrap_or(u32::MAX))
+            .and_then(|x| x.checked_mul(u32::try_from(remaining).unwrap_or(u32::MAX)))
+            .unwrap_or_default();
+        info!(
+            "{}: {done}/{} ({}%), eta {}",
+            self.label,
+            self.total,
+            done * 100 / self.total.max(1),
+            format_duration(Duration::from_secs(eta.as_secs()))
+        );
+    }
+}
+
+/// Create entries for the members of the groups with assignments
+///
+/// For members of nested groups, `via_group` records the chain of groups that
+/// grants the role, such as `TeamA -> SubTeamB`, unless `transitive` is set.
+fn expand_group_members(
+    client: &PimClient,
+    results: &BTreeSet<Entry>,
+    transitive: bool,
+) -> Result<BTreeSet<Entry>> {
+    let groups = results
+        .iter()
+        .filter(|x| x.principal_type == PrincipalType::Group)
+        .collect::<Vec<_>>();
+    let progress = Progress::new("groups expanded", groups.len());
+
+    let mut expanded = BTreeSet::new();
+    for entry in groups {
+        let members = if transitive {
+            client
+                .group_members(&entry.id, true)?
+                .into_iter()
+                .map(|object| NestedMember {
+                    object,
+                    via: Vec::new(),
+                })
+                .collect()
+        } else {
+            client.nested_group_members(&entry.id)?
+        };
+        progress.increment();
+        for member in members {
+            let via_group = [entry.display_name.clone()]
+                .into_iter()
+                .chain(member.via)
+                .collect::<Vec<_>>()
+                .join(GROUP_CHAIN_SEPARATOR);
+            expanded.insert(Entry {
+                role: entry.role.clone(),
+                id: member.object.id,
+                display_name: member.object.display_name,
+                upn: member.object.upn,
+                principal_type: member.object.object_type,
+                scope: entry.scope.clone(),
+                via_group: Some(via_group),
+                permissions: None,
+                policy: None,
+            });
         }
-        results.extend(expanded);
     }
+    Ok(expanded)
+}
+
+/// Add the permissions of the role definition to each entry
+///
+/// Role definitions are looked up at the scope of each entry, such that custom
+/// roles defined below the requested scope are included.
+fn add_permissions(client: &PimClient, results: BTreeSet<Entry>) -> BTreeSet<Entry> {
+    let scopes = results
+        .iter()
+        .map(|x| x.scope.clone())
+        .collect::<BTreeSet<_>>();
+
+    let permissions = scopes
+        .into_par_iter()
+        .filter_map(|scope| match client.role_definitions(&scope) {
+            Ok(definitions) => Some((scope, definitions)),
+            Err(err) => {
+                warn!("error listing role definitions for {scope}: {err}");
+                None
+            }
+        })
+        .flat_map_iter(|(scope, definitions)| {
+            definitions.into_iter().map(move |definition| {
+                let mut permissions = Permissions::default();
+                for permission in definition.properties.permissions {
+                    permissions
+                        .actions
+                        .extend(permission.actions.unwrap_or_default());
+                    permissions
+                        .not_actions
+                        .extend(permission.not_actions.unwrap_or_default());
+                    permissions
+                        .data_actions
+                        .extend(permission.data_actions.unwrap_or_default());
+                    permissions
+                        .not_data_actions
+                        .extend(permission.not_data_actions.unwrap_or_default());
+                }
+                (
+                    (scope.clone(), definition.properties.role_name),
+                    permissions,
+                )
+            })
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    results
+        .into_iter()
+        .map(|mut entry| {
+            entry.permissions = permissions
+                .get(&(entry.scope.clone(), entry.role.0.clone()))
+                .cloned();
+            if entry.permissions.is_none() {
+                warn!(
+                    "unable to find permissions for {} in {}",
+                    entry.role, entry.scope
+                );
+            }
+            entry
+        })
+        .collect()
+}
+
+/// Add the activation policy of the role to each entry
+///
+/// Policies are looked up at the scope of each entry, as each scope can have
+/// its own policy for a role.
+fn add_policies(client: &PimClient, results: BTreeSet<Entry>) -> BTreeSet<Entry> {
+    let scopes = results
+        .iter()
+        .map(|x| x.scope.clone())
+        .collect::<BTreeSet<_>>();
 
-    let results = remove_dominated_scopes(results);
+    let policies = scopes
+        .into_par_iter()
+        .filter_map(|scope| match client.role_management_policies(&scope) {
+            Ok(policies) => Some((scope, policies)),
+            Err(err) => {
+                warn!("error listing role management policies for {scope}: {err}");
+                None
+            }
+        })
+        .flat_map_iter(|(scope, policies)| {
+            policies
+                .into_iter()
+                .map(move |policy| ((scope.clone(), policy.role), policy.activation))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    results
+        .into_iter()
+        .map(|mut entry| {
+            entry.policy = policies
+                .get(&(entry.scope.clone(), entry.role.clone()))
+                .cloned();
+            if entry.policy.is_none() {
+                warn!(
+                    "unable to find the policy for {} in {}",
+                    entry.role, entry.scope
+                );
+            }
+            entry
+        })
+        .collect()
+}
+
+/// Escape a value for a delimited file
+///
+/// CSV values are quoted if needed.  TSV does not support quoting, so tabs and
+/// newlines are replaced with spaces.
+fn escape(value: &str, delimiter: char) -> String {
+    if delimiter == '\t' {
+        value.replace(['\t', '\r', '\n'], " ")
+    } else if value.contains([delimiter, '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
 
-    serde_json::to_writer_pretty(stdout(), &results)?;
+fn write_delimited<W: Write>(
+    writer: &mut W,
+    results: &BTreeSet<Entry>,
+    delimiter: char,
+    columns: Columns,
+) -> Result<()> {
+    let separator = delimiter.to_string();
+    let mut header = Entry::COLUMNS.to_vec();
+    if columns.permissions {
+        header.extend(Permissions::COLUMNS);
+    }
+    if columns.policies {
+        header.extend(POLICY_COLUMNS);
+    }
+    writeln!(writer, "{}", header.join(&separator))?;
+    for entry in results {
+        let row = entry
+            .columns(columns)
+            .iter()
+            .map(|x| escape(x, delimiter))
+            .collect::<Vec<_>>();
+        writeln!(writer, "{}", row.join(&separator))?;
+    }
     Ok(())
 }
 
@@ -238,6 +848,8 @@ mod tests {
             upn: Some("wut".to_string()),
             principal_type: PrincipalType::User,
             via_group: None,
+            permissions: None,
+            policy: None,
         };
 
         let mut dominated = base.clone();
@@ -256,4 +868,77 @@ mod tests {
         assert!(results.contains(&other_user));
         assert!(!results.contains(&dominated));
     }
+
+    #[test]
+    fn delimited() -> Result<()> {
+        let entry = Entry {
+            scope: Scope::from_subscription(&Uuid::nil()),
+            role: Role("Contributor".to_string()),
+            id: "1".to_string(),
+            display_name: "Doe, \"Jane\"".to_string(),
+            upn: None,
+            principal_type: PrincipalType::User,
+            via_group: Some("admins\tgroup".to_string()),
+            permissions: None,
+            policy: None,
+        };
+        let entries = [entry].into_iter().collect::<BTreeSet<_>>();
+
+        let mut csv = Vec::new();
+        write_delimited(&mut csv, &entries, ',', Columns::default())?;
+        assert_eq!(
+            String::from_utf8(csv)?,
+            "role,scope,principal_type,display_name,upn,via_group\n\
+             Contributor,/subscriptions/00000000-0000-0000-0000-000000000000,User,\"Doe, \"\"Jane\"\"\",,admins\tgroup\n"
+        );
+
+        let mut tsv = Vec::new();
+        write_delimited(&mut tsv, &entries, '\t', Columns::default())?;
+        assert_eq!(
+            String::from_utf8(tsv)?,
+            "role\tscope\tprincipal_type\tdisplay_name\tupn\tvia_group\n\
+             Contributor\t/subscriptions/00000000-0000-0000-0000-000000000000\tUser\tDoe, \"Jane\"\t\tadmins group\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn diff() {
+        let base = Entry {
+            scope: Scope::from_subscription(&Uuid::nil()),
+            role: Role("Contributor".to_string()),
+            id: "1".to_string(),
+            display_name: "User 1".to_string(),
+            upn: Some("user1@contoso.com".to_string()),
+            principal_type: PrincipalType::User,
+            via_group: None,
+            permissions: None,
+            policy: None,
+        };
+
+        let mut renamed = base.clone();
+        renamed.display_name = "User One".to_string();
+        let mut removed = base.clone();
+        removed.id = "2".to_string();
+        let mut added = base.clone();
+        added.role = Role("Owner".to_string());
+
+        let old = [base.clone(), removed.clone()].into_iter().collect();
+        let new = [renamed.clone(), added.clone()].into_iter().collect();
+        let diff = Diff::new(old, new);
+        assert_eq!(
+            diff,
+            Diff {
+                added: vec![added],
+                removed: vec![removed],
+                changed: vec![Change {
+                    old: base.clone(),
+                    new: renamed
+                }],
+            }
+        );
+
+        let same = [base].into_iter().collect::<BTreeSet<_>>();
+        assert!(Diff::new(same.clone(), same).is_empty());
+    }
 }
@@ -0,0 +1,343 @@
+use crate::az_cli::extract_oid;
+use anyhow::{Context, Result};
+use humantime::format_rfc3339_millis;
+use parking_lot::Mutex;
+use regex::Regex;
+use reqwest::{blocking::Request, StatusCode};
+use serde_json::{json, Value};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::LazyLock,
+    time::{Instant, SystemTime},
+};
+use tracing::warn;
+
+const REDACTED: &str = "REDACTED";
+
+// closes the `entries` array and the enclosing objects, such that the file is
+// valid JSON after each entry is written
+const TRAILER: &str = "\n]}}\n";
+
+// keys whose values identify principals or contain credentials
+const REDACTED_KEYS: &[&str] = &[
+    "access_token",
+    "approverid",
+    "ids",
+    "mail",
+    "objectid",
+    "oid",
+    "principalid",
+    "refresh_token",
+    "requestorid",
+    "userprincipalname",
+];
+
+// the types of directory objects whose `id` identifies a principal
+const PRINCIPAL_TYPES: &[&str] = &[
+    "#microsoft.graph.user",
+    "#microsoft.graph.group",
+    "#microsoft.graph.servicePrincipal",
+];
+
+// principal ids in paths, such as `/users/{id}`, and in filters, such as
+// `principalId eq '{id}'`, including when URL encoded
+static PRINCIPAL_ID: LazyLock<Regex> = LazyLock::new(|| {
+    #[allow(clippy::expect_used)]
+    Regex::new(
+        r"(?i)(/users/|/groups/|/servicePrincipals/|/directoryObjects/|principalId(?:\s|%20|\+)+eq(?:\s|%20|\+)+(?:'|%27))[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}",
+    )
+    .expect("the principal id pattern is valid")
+});
+
+/// Records the HTTP requests and responses to a HAR-like file
+///
+/// Bearer tokens are removed, and principal ids are redacted from the URLs
+/// and bodies.  Each entry is appended as the response is received, such that
+/// the capture is complete even if the process exits early.
+pub(crate) struct Capture {
+    path: PathBuf,
+    file: Mutex<Option<CaptureFile>>,
+}
+
+struct CaptureFile {
+    file: File,
+    entries: usize,
+}
+
+/// A request that has been sent, but whose response has not been recorded
+pub(crate) struct PendingEntry {
+    started: SystemTime,
+    timer: Instant,
+    request: Value,
+    secrets: Vec<String>,
+}
+
+impl Capture {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Record the response to a request, or the error if no response was
+    /// received
+    pub(crate) fn finish(&self, pending: PendingEntry, response: Result<(StatusCode, &str)>) {
+        let PendingEntry {
+            started,
+            timer,
+            request,
+            secrets,
+        } = pending;
+        let response = match response {
+            Ok((status, body)) => json!({
+                "status": status.as_u16(),
+                "content": {"text": redact(body, &secrets)},
+            }),
+            Err(err) => json!({
+                "status": 0,
+                "_error": redact(&format!("{err:#}"), &secrets),
+            }),
+        };
+        let entry = json!({
+            "startedDateTime": format_rfc3339_millis(started).to_string(),
+            "time": timer.elapsed().as_millis(),
+            "request": request,
+            "response": response,
+        });
+
+        if let Err(err) = self.append(&entry) {
+            warn!(
+                "unable to write capture to {}: {err:#}",
+                self.path.display()
+            );
+        }
+    }
+
+    /// Append the entry to the capture, creating the file on the first entry
+    fn append(&self, entry: &Value) -> Result<()> {
+        let mut capture = self.file.lock();
+        let capture = match &mut *capture {
+            Some(capture) => capture,
+            None => capture.insert(CaptureFile::create(&self.path)?),
+        };
+
+        // overwrite the trailer written after the previous entry
+        let trailer = i64::try_from(TRAILER.len())?;
+        capture.file.seek(SeekFrom::End(-trailer))?;
+        if capture.entries > 0 {
+            capture.file.write_all(b",")?;
+        }
+        write!(capture.file, "\n{entry}{TRAILER}")?;
+        capture.file.flush()?;
+        capture.entries += 1;
+        Ok(())
+    }
+}
+
+impl CaptureFile {
+    fn create(path: &PathBuf) -> Result<Self> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // the capture includes the details of the user's roles
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("unable to create {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            // the mode only applies when the file is created
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+
+        let creator = json!({"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")});
+        write!(
+            file,
+            r#"{{"log":{{"version":"1.2","creator":{creator},"entries":[{TRAILER}"#
+        )?;
+        Ok(Self { file, entries: 0 })
+    }
+}
+
+impl PendingEntry {
+    /// Start recording a request, before it is sent
+    pub(crate) fn new(request: &Request) -> Self {
+        // the current user's id is included in many of the URLs and bodies
+        let secrets = request
+            .headers()
+            .get("Authorization")
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.strip_prefix("Bearer "))
+            .and_then(|token| extract_oid(token).ok())
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let headers = request
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if name.as_str().eq_ignore_ascii_case("authorization") {
+                    REDACTED.to_string()
+                } else {
+                    value.to_str().unwrap_or_default().to_string()
+                };
+                json!({"name": name.as_str(), "value": value})
+            })
+            .collect::<Vec<_>>();
+
+        let mut entry = json!({
+            "method": request.method().as_str(),
+            "url": redact(request.url().as_str(), &secrets),
+            "headers": headers,
+        });
+        if let (Some(entry), Some(body)) = (
+            entry.as_object_mut(),
+            request
+                .body()
+                .and_then(|x| x.as_bytes())
+                .map(String::from_utf8_lossy),
+        ) {
+            entry.insert(
+                "postData".to_string(),
+                json!({"text": redact(&body, &secrets)}),
+            );
+        }
+
+        Self {
+            started: SystemTime::now(),
+            timer: Instant::now(),
+            request: entry,
+            secrets,
+        }
+    }
+}
+
+/// Redact the values of sensitive keys in JSON, principal ids in paths and
+/// filters, along with any of the `secrets`
+fn redact(text: &str, secrets: &[String]) -> String {
+    let text = match serde_json::from_str::<Value>(text) {
+        Ok(mut value) if value.is_object() || value.is_array() => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        _ => text.to_string(),
+    };
+    let mut text = PRINCIPAL_ID
+        .replace_all(&text, format!("${{1}}{REDACTED}"))
+        .into_owned();
+    for secret in secrets.iter().filter(|x| !x.is_empty()) {
+        text = text.replace(secret.as_str(), REDACTED);
+    }
+    text
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            // directory objects, such as those returned by `getByIds`, and the
+            // expanded principal of role assignments are identified by `id`
+            let is_principal = map
+                .get("@odata.type")
+                .and_then(Value::as_str)
+                .is_some_and(|x| PRINCIPAL_TYPES.contains(&x));
+            for (key, value) in map.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if REDACTED_KEYS.contains(&key.as_str()) || (is_principal && key == "id") {
+                    *value = Value::String(REDACTED.to_string());
+                } else if key == "principal" {
+                    if let Some(id) = value.get_mut("id") {
+                        *id = Value::String(REDACTED.to_string());
+                    }
+                    redact_value(value);
+                } else {
+                    redact_value(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact, Capture};
+    use anyhow::Result;
+    use serde_json::{json, Value};
+    use std::{env, fs::read_to_string, process};
+
+    #[test]
+    fn test_redact() {
+        let secrets = ["00000000-0000-0000-0000-000000000001".to_string()];
+        let body = r#"{"properties":{"principalId":"00000000-0000-0000-0000-000000000002","justification":"testing","scope":"/users/00000000-0000-0000-0000-000000000001"}}"#;
+        assert_eq!(
+            redact(body, &secrets),
+            r#"{"properties":{"justification":"testing","principalId":"REDACTED","scope":"/users/REDACTED"}}"#
+        );
+
+        assert_eq!(
+            redact(
+                "https://graph.microsoft.com/v1.0/users/00000000-0000-0000-0000-000000000001",
+                &secrets
+            ),
+            "https://graph.microsoft.com/v1.0/users/REDACTED"
+        );
+    }
+
+    #[test]
+    fn test_redact_other_principals() {
+        assert_eq!(
+            redact(
+                "https://graph.microsoft.com/v1.0/users/00000000-0000-0000-0000-000000000002?$select=displayName",
+                &[]
+            ),
+            "https://graph.microsoft.com/v1.0/users/REDACTED?$select=displayName"
+        );
+        assert_eq!(
+            redact(
+                "https://management.azure.com/providers/Microsoft.Authorization/roleAssignments?$filter=principalId%20eq%20%2700000000-0000-0000-0000-000000000002%27",
+                &[]
+            ),
+            "https://management.azure.com/providers/Microsoft.Authorization/roleAssignments?$filter=principalId%20eq%20%27REDACTED%27"
+        );
+
+        let body = r##"{"value":[{"@odata.type":"#microsoft.graph.user","id":"00000000-0000-0000-0000-000000000002","displayName":"user"}]}"##;
+        assert_eq!(
+            redact(body, &[]),
+            r##"{"value":[{"@odata.type":"#microsoft.graph.user","displayName":"user","id":"REDACTED"}]}"##
+        );
+
+        let body = r#"{"properties":{"expandedProperties":{"principal":{"id":"00000000-0000-0000-0000-000000000002","type":"User"}},"roleDefinitionId":"/providers/Microsoft.Authorization/roleDefinitions/00000000-0000-0000-0000-000000000003"}}"#;
+        assert_eq!(
+            redact(body, &[]),
+            r#"{"properties":{"expandedProperties":{"principal":{"id":"REDACTED","type":"User"}},"roleDefinitionId":"/providers/Microsoft.Authorization/roleDefinitions/00000000-0000-0000-0000-000000000003"}}"#
+        );
+    }
+
+    #[test]
+    fn test_append() -> Result<()> {
+        let path = env::temp_dir().join(format!("az-pim-capture-test-{}.har", process::id()));
+        let capture = Capture::new(path.clone());
+        capture.append(&json!({"time": 1}))?;
+        capture.append(&json!({"time": 2}))?;
+
+        let har: Value = serde_json::from_str(&read_to_string(&path)?)?;
+        assert_eq!(
+            har.pointer("/log/entries"),
+            Some(&json!([{"time": 1}, {"time": 2}]))
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(path.metadata()?.permissions().mode() & 0o777, 0o600);
+        }
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::Result;
+use parking_lot::{Condvar, Mutex};
+use std::{collections::HashMap, hash::Hash, sync::Arc};
+
+/// Share the result of a single call between concurrent callers using the
+/// same key
+///
+/// The first caller for a key performs the call, while any callers for the
+/// same key that arrive before it completes wait for, and receive a copy of,
+/// its result.
+///
+/// Only successful results are shared.  If the call fails or panics, the
+/// waiting callers retry the call themselves, such that each caller receives
+/// its own error rather than a copy that can no longer be downcast.
+pub(crate) struct Coalesce<K, V> {
+    in_flight: Mutex<HashMap<K, Arc<Flight<V>>>>,
+}
+
+struct Flight<V> {
+    state: Mutex<State<V>>,
+    done: Condvar,
+}
+
+enum State<V> {
+    Pending,
+    Succeeded(V),
+    Failed,
+}
+
+/// Completes the flight when the leader's call returns or unwinds, such that
+/// waiters are never left blocked
+struct Leader<'a, K: Hash + Eq, V> {
+    coalesce: &'a Coalesce<K, V>,
+    key: &'a K,
+    flight: Arc<Flight<V>>,
+}
+
+impl<K: Hash + Eq, V> Drop for Leader<'_, K, V> {
+    fn drop(&mut self) {
+        {
+            let mut state = self.flight.state.lock();
+            if matches!(*state, State::Pending) {
+                *state = State::Failed;
+            }
+        }
+        self.coalesce.in_flight.lock().remove(self.key);
+        self.flight.done.notify_all();
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Coalesce<K, V> {
+    pub fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn run<F>(&self, key: &K, func: F) -> Result<V>
+    where
+        F: FnOnce() -> Result<V>,
+    {
+        loop {
+            let (flight, leader) = {
+                let mut in_flight = self.in_flight.lock();
+                if let Some(flight) = in_flight.get(key) {
+                    (flight.clone(), false)
+                } else {
+                    let flight = Arc::new(Flight {
+                        state: Mutex::new(State::Pending),
+                        done: Condvar::new(),
+                    });
+                    in_flight.insert(key.clone(), flight.clone());
+                    (flight, true)
+                }
+            };
+
+            if leader {
+                let leader = Leader {
+                    coalesce: self,
+                    key,
+                    flight,
+                };
+                let result = func();
+                if let Ok(value) = &result {
+                    *leader.flight.state.lock() = State::Succeeded(value.clone());
+                }
+                return result;
+            }
+
+            let mut state = flight.state.lock();
+            while matches!(*state, State::Pending) {
+                flight.done.wait(&mut state);
+            }
+            if let State::Succeeded(value) = &*state {
+                return Ok(value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coalesce;
+    use anyhow::Result;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread::{scope, sleep},
+        time::Duration,
+    };
+
+    #[test]
+    fn test_coalesce() -> Result<()> {
+        let coalesce = Coalesce::new();
+        let calls = AtomicUsize::new(0);
+        let results = scope(|s| {
+            let handles = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        coalesce.run(&"key", || {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            sleep(Duration::from_millis(100));
+                            Ok(1)
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|x| x.join().map_err(|_| anyhow::anyhow!("thread panicked")))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        for result in results {
+            assert_eq!(result?, 1);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // once complete, the next call for the key is made again
+        coalesce.run(&"key", || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(2)
+        })?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_coalesce_panic() -> Result<()> {
+        let coalesce = Coalesce::new();
+        let (panicked, waiter) = scope(|s| {
+            let leader = s.spawn(|| {
+                coalesce.run(&"key", || -> Result<usize> {
+                    sleep(Duration::from_millis(100));
+                    #[allow(clippy::panic)]
+                    {
+                        panic!("leader panicked")
+                    }
+                })
+            });
+            sleep(Duration::from_millis(20));
+            let waiter = s.spawn(|| coalesce.run(&"key", || Ok(2)));
+            (leader.join().is_err(), waiter.join())
+        });
+        assert!(panicked);
+        // the waiter retries the call, rather than blocking forever
+        assert_eq!(waiter.map_err(|_| anyhow::anyhow!("thread panicked"))??, 2);
+
+        // the panic completes the flight, rather than blocking later calls
+        assert_eq!(coalesce.run(&"key", || Ok(1))?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_coalesce_error() -> Result<()> {
+        let coalesce = Coalesce::new();
+        let calls = AtomicUsize::new(0);
+        let results = scope(|s| {
+            let handles = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        coalesce.run(&"key", || -> Result<usize> {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            sleep(Duration::from_millis(50));
+                            Err(anyhow::anyhow!("failed"))
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|x| x.join().map_err(|_| anyhow::anyhow!("thread panicked")))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        // errors are not shared, so each caller makes its own call
+        assert!(results.iter().all(Result::is_err));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
+}
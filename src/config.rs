@@ -0,0 +1,197 @@
+use crate::{
+    backend::RetryPolicy, hooks::HooksConfig, latest::InstallChannel, parse_duration,
+    policy::PolicyConfig, theme::ThemeConfig,
+};
+use anyhow::{Context, Result};
+use home::home_dir;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{env, fs::read_to_string, io::ErrorKind, path::PathBuf};
+
+/// Environment variable used to override the path to the config file
+pub const CONFIG_ENV: &str = "AZ_PIM_CONFIG";
+
+/// Settings for `az-pim`, stored in `$HOME/.config/az-pim-cli/config.toml`
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Justification used when activating or extending roles, if one is not
+    /// provided
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_justification: Option<String>,
+
+    /// Maximum number of times to retry requests that fail with transient
+    /// errors, where `0` disables retrying requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<usize>,
+
+    /// Delay before the first retry, such as `500ms`, which doubles for each
+    /// retry after.  If not set, 5 seconds is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_base_delay: Option<String>,
+
+    /// Upper bound on the delay between retries, such as `30s`.  If not set,
+    /// 5 seconds is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_max_delay: Option<String>,
+
+    /// Randomize each delay between retries, which spreads out the retries
+    /// of concurrent requests.  Defaults to `true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_jitter: Option<bool>,
+
+    /// Application identifier appended to the User-Agent of requests, such as
+    /// `my-tool/1.0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+
+    /// Value of the `X-Ms-Command-Name` header sent with ARM requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_name: Option<String>,
+
+    /// Where `az-pim` was installed from (`github` or `crates-io`), used to
+    /// check for new versions.  If not set, this is detected from the
+    /// location of the executable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install_channel: Option<InstallChannel>,
+
+    /// Colors and styles of the interactive UIs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<ThemeConfig>,
+
+    /// Restrictions on activating roles, such as which roles require an
+    /// explicit confirmation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PolicyConfig>,
+
+    /// Commands run before and after activating or deactivating roles, such
+    /// as to send notifications or refresh credentials
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<HooksConfig>,
+}
+
+impl Config {
+    /// The path to the config file, which can be overridden using
+    /// `AZ_PIM_CONFIG`
+    #[must_use]
+    pub fn path() -> Option<PathBuf> {
+        env::var_os(CONFIG_ENV).map(PathBuf::from).or_else(|| {
+            home_dir().map(|x| x.join(".config").join("az-pim-cli").join("config.toml"))
+        })
+    }
+
+    /// Parse the contents of a config file
+    ///
+    /// # Errors
+    /// Will return `Err` if the config is not valid TOML, has unknown
+    /// settings, or has an invalid retry delay, theme, or policy
+    pub fn parse(data: &str) -> Result<Self> {
+        let config: Self = toml::from_str(data)?;
+        config.retry_policy(None)?;
+        if let Some(theme) = &config.theme {
+            theme.build()?;
+        }
+        if let Some(policy) = &config.policy {
+            policy.validate()?;
+        }
+        Ok(config)
+    }
+
+    /// Load the config file
+    ///
+    /// If the config file does not exist, the default settings are used.
+    ///
+    /// # Errors
+    /// Will return `Err` if the config file cannot be read or parsed
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        let data = match read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("unable to read {}", path.display()));
+            }
+        };
+        Self::parse(&data).with_context(|| format!("unable to parse {}", path.display()))
+    }
+
+    /// How requests that fail with transient errors are retried, using
+    /// `max_retries` if specified rather than the configured `max_retries`
+    ///
+    /// # Errors
+    /// Will return `Err` if either retry delay is not a valid duration
+    pub fn retry_policy(&self, max_retries: Option<usize>) -> Result<RetryPolicy> {
+        let delay = |value: &Option<String>, name: &str| {
+            value
+                .as_deref()
+                .map(|x| parse_duration(x).with_context(|| format!("invalid {name} {x:?}")))
+                .transpose()
+        };
+        let default = RetryPolicy::default();
+        Ok(RetryPolicy {
+            max_retries: max_retries
+                .or(self.max_retries)
+                .unwrap_or(default.max_retries),
+            base_delay: delay(&self.retry_base_delay, "retry_base_delay")?
+                .unwrap_or(default.base_delay),
+            max_delay: delay(&self.retry_max_delay, "retry_max_delay")?
+                .unwrap_or(default.max_delay),
+            jitter: self.retry_jitter.unwrap_or(default.jitter),
+        })
+    }
+
+    /// Use the specified justification, or the default justification
+    ///
+    /// # Errors
+    /// Will return `Err` if neither is set
+    pub fn justification(&self, justification: Option<String>) -> Result<String> {
+        justification
+            .or_else(|| self.default_justification.clone())
+            .context("justification must be provided")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::{backend::RetryPolicy, latest::InstallChannel};
+    use anyhow::Result;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let config = Config::parse("default_justification = \"routine maintenance\"")?;
+        assert_eq!(config.justification(None)?, "routine maintenance");
+        assert_eq!(config.justification(Some("incident".into()))?, "incident");
+
+        assert!(Config::parse("")?.justification(None).is_err());
+        assert!(Config::parse("unknown = 1").is_err());
+
+        assert_eq!(Config::parse("max_retries = 0")?.max_retries, Some(0));
+        let retry = Config::parse(
+            "max_retries = 3\nretry_base_delay = \"500ms\"\nretry_max_delay = \"30s\"\nretry_jitter = false",
+        )?;
+        let policy = retry.retry_policy(None)?;
+        assert_eq!(policy.max_retries, 3);
+        assert_eq!(policy.base_delay, Duration::from_millis(500));
+        assert_eq!(policy.max_delay, Duration::from_secs(30));
+        assert!(!policy.jitter);
+        assert_eq!(retry.retry_policy(Some(0))?.max_retries, 0);
+        assert_eq!(
+            Config::parse("")?.retry_policy(None)?,
+            RetryPolicy::default()
+        );
+        assert!(Config::parse("retry_max_delay = \"soon\"").is_err());
+        assert_eq!(
+            Config::parse("install_channel = \"crates-io\"")?.install_channel,
+            Some(InstallChannel::CratesIo)
+        );
+        assert!(Config::parse("[theme]\nbase = \"high-contrast\"").is_ok());
+        assert!(Config::parse("[theme]\nborder = { fg = \"not-a-color\" }").is_err());
+        assert!(Config::parse("[policy]\ndangerous_roles = [\"Contributor\"]").is_ok());
+        assert!(Config::parse("[policy]\ndangerous_scope_levels = [\"tenant\"]").is_err());
+        Ok(())
+    }
+}
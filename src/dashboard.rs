@@ -0,0 +1,514 @@
+use crate::{
+    disable_prompts, ensure_interactive, models::roles::RoleAssignment, policy::PolicyViolation,
+    theme::theme, time::format_time_of_day, use_non_interactive, ListFilter, PimClient,
+};
+use anyhow::Result;
+use ratatui::{
+    crossterm::{
+        event::{
+            self, Event,
+            KeyCode::{BackTab, Char, Down, Enter, Esc, Tab, Up},
+            KeyEventKind,
+        },
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    prelude::*,
+    widgets::{BorderType, HighlightSpacing, Paragraph, Row, Table, TableState},
+};
+use std::{
+    collections::BTreeSet,
+    io::stdout,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::scope,
+    time::{Duration, Instant},
+};
+
+const TITLE_TEXT: &str = "Azure PIM roles";
+const ACTIVE_TEXT: &str = "↑ or ↓ to move | d to deactivate | e to extend";
+const ELIGIBLE_TEXT: &str = "↑ or ↓ to move | Enter to activate";
+const ALL_HELP: &str = "Tab or Shift-Tab to change sections | r to refresh | Esc to quit";
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Requests sent from the UI to the background worker
+enum Request {
+    Refresh,
+    Activate(RoleAssignment),
+    Deactivate(RoleAssignment),
+    Extend(RoleAssignment),
+}
+
+/// Updates sent from the background worker to the UI
+enum Update {
+    Roles {
+        active: BTreeSet<RoleAssignment>,
+        eligible: BTreeSet<RoleAssignment>,
+    },
+    /// The result of an action, after which the roles are refreshed
+    Status(String),
+    /// Refreshing the roles failed, which is retried at the next interval
+    Error(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Active,
+    Eligible,
+}
+
+struct App {
+    active: Vec<RoleAssignment>,
+    eligible: Vec<RoleAssignment>,
+    focus: Focus,
+    active_state: TableState,
+    eligible_state: TableState,
+    justification: Option<String>,
+    status: String,
+    pending: usize,
+    last_refresh: Option<Instant>,
+    refresh: Duration,
+}
+
+impl App {
+    fn new(justification: Option<String>, refresh: Duration) -> Self {
+        Self {
+            active: Vec::new(),
+            eligible: Vec::new(),
+            focus: Focus::Eligible,
+            active_state: TableState::default(),
+            eligible_state: TableState::default(),
+            justification,
+            status: String::new(),
+            pending: 0,
+            last_refresh: None,
+            refresh,
+        }
+    }
+
+    fn current(&self) -> Option<&RoleAssignment> {
+        match self.focus {
+            Focus::Active => self
+                .active_state
+                .selected()
+                .and_then(|i| self.active.get(i)),
+            Focus::Eligible => self
+                .eligible_state
+                .selected()
+                .and_then(|i| self.eligible.get(i)),
+        }
+    }
+
+    fn step(&mut self, forward: bool) {
+        let (state, count) = match self.focus {
+            Focus::Active => (&mut self.active_state, self.active.len()),
+            Focus::Eligible => (&mut self.eligible_state, self.eligible.len()),
+        };
+        if count == 0 {
+            state.select(None);
+            return;
+        }
+        let i = match (state.selected(), forward) {
+            (Some(i), true) if i + 1 < count => i + 1,
+            (Some(i), false) if i > 0 => i - 1,
+            (Some(_), false) => count - 1,
+            _ => 0,
+        };
+        state.select(Some(i));
+    }
+
+    fn update(&mut self, update: Update) {
+        match update {
+            Update::Roles { active, eligible } => {
+                self.active = active.into_iter().collect();
+                self.eligible = eligible.into_iter().collect();
+                for (state, count) in [
+                    (&mut self.active_state, self.active.len()),
+                    (&mut self.eligible_state, self.eligible.len()),
+                ] {
+                    let selected = state.selected().map(|i| i.min(count.saturating_sub(1)));
+                    state.select(if count == 0 {
+                        None
+                    } else {
+                        selected.or(Some(0))
+                    });
+                }
+                self.pending = self.pending.saturating_sub(1);
+                self.last_refresh = Some(Instant::now());
+            }
+            Update::Status(status) => {
+                self.pending = self.pending.saturating_sub(1);
+                self.status = status;
+                self.last_refresh = None;
+            }
+            Update::Error(status) => {
+                self.pending = self.pending.saturating_sub(1);
+                self.status = status;
+                self.last_refresh = Some(Instant::now());
+            }
+        }
+    }
+
+    fn send(&mut self, requests: &Sender<Request>, request: Request) {
+        // the worker only goes away once the UI is closed
+        if requests.send(request).is_ok() {
+            self.pending += 1;
+        }
+    }
+
+    fn draw(&mut self, f: &mut Frame) {
+        let rects = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Percentage(40),
+            Constraint::Min(5),
+            Constraint::Length(3),
+            Constraint::Length(4),
+        ])
+        .split(f.area());
+        let mut rects = rects.iter();
+
+        // from here forward, if the next() call fails, we return early as the
+        // rect is missing
+        let Some(title) = rects.next() else {
+            return;
+        };
+        f.render_widget(
+            Paragraph::new(TITLE_TEXT)
+                .style(Style::default().add_modifier(Modifier::BOLD))
+                .alignment(Alignment::Center),
+            *title,
+        );
+
+        let Some(active) = rects.next() else {
+            return;
+        };
+        self.render_active(f, *active);
+
+        let Some(eligible) = rects.next() else {
+            return;
+        };
+        self.render_eligible(f, *eligible);
+
+        let Some(status) = rects.next() else {
+            return;
+        };
+        self.render_status(f, *status);
+
+        let Some(footer) = rects.next() else {
+            return;
+        };
+        self.render_footer(f, *footer);
+    }
+
+    fn highlight_style(&self, focus: Focus) -> Style {
+        if self.focus == focus {
+            theme().selection
+        } else {
+            Style::default()
+        }
+    }
+
+    fn render_active(&mut self, frame: &mut Frame, area: Rect) {
+        let rows = self.active.iter().map(|entry| {
+            Row::new(vec![
+                entry.role.to_string(),
+                scope_display(entry),
+                format_expiry(entry),
+            ])
+        });
+        frame.render_stateful_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Percentage(35),
+                    Constraint::Percentage(50),
+                    Constraint::Percentage(15),
+                ],
+            )
+            .header(header(["Role", "Scope", "Expires In"]))
+            .row_highlight_style(self.highlight_style(Focus::Active))
+            .highlight_spacing(HighlightSpacing::Always)
+            .block(theme().block().title("Active")),
+            area,
+            &mut self.active_state,
+        );
+    }
+
+    fn render_eligible(&mut self, frame: &mut Frame, area: Rect) {
+        let active = self.active.iter().collect::<BTreeSet<_>>();
+        let rows = self.eligible.iter().map(|entry| {
+            Row::new(vec![
+                entry.role.to_string(),
+                scope_display(entry),
+                if active.contains(entry) {
+                    "active".to_string()
+                } else {
+                    String::new()
+                },
+            ])
+        });
+        frame.render_stateful_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Percentage(35),
+                    Constraint::Percentage(50),
+                    Constraint::Percentage(15),
+                ],
+            )
+            .header(header(["Role", "Scope", "Status"]))
+            .row_highlight_style(self.highlight_style(Focus::Eligible))
+            .highlight_spacing(HighlightSpacing::Always)
+            .block(theme().block().title("Eligible")),
+            area,
+            &mut self.eligible_state,
+        );
+    }
+
+    fn render_status(&self, frame: &mut Frame, area: Rect) {
+        let mut status = self.status.clone();
+        if self.pending > 0 {
+            status = format!("working... {status}");
+        }
+        frame.render_widget(
+            Paragraph::new(status).block(theme().block().title("Status")),
+            area,
+        );
+    }
+
+    fn render_footer(&self, frame: &mut Frame, area: Rect) {
+        frame.render_widget(
+            Paragraph::new(format!(
+                "{}\n{ALL_HELP}",
+                match self.focus {
+                    Focus::Active => ACTIVE_TEXT,
+                    Focus::Eligible => ELIGIBLE_TEXT,
+                }
+            ))
+            .centered()
+            .block(
+                theme()
+                    .block()
+                    .title("Help")
+                    .border_type(BorderType::Double),
+            ),
+            area,
+        );
+    }
+
+    fn run<B: Backend>(
+        mut self,
+        terminal: &mut Terminal<B>,
+        requests: &Sender<Request>,
+        updates: &Receiver<Update>,
+    ) -> Result<()> {
+        loop {
+            while let Ok(update) = updates.try_recv() {
+                self.update(update);
+            }
+
+            if self.pending == 0
+                && self
+                    .last_refresh
+                    .is_none_or(|x| x.elapsed() >= self.refresh)
+            {
+                self.send(requests, Request::Refresh);
+            }
+
+            terminal.draw(|f| self.draw(f))?;
+
+            if !event::poll(POLL_INTERVAL)? {
+                continue;
+            }
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match (self.focus, key.code) {
+                (_, Esc | Char('q')) => return Ok(()),
+                (Focus::Active, Tab | BackTab) => self.focus = Focus::Eligible,
+                (Focus::Eligible, Tab | BackTab) => self.focus = Focus::Active,
+                (_, Down) => self.step(true),
+                (_, Up) => self.step(false),
+                (_, Char('r')) => self.send(requests, Request::Refresh),
+                (Focus::Active, Char('d')) => {
+                    if let Some(entry) = self.current().cloned() {
+                        self.status = format!("deactivating {}", entry.friendly());
+                        self.send(requests, Request::Deactivate(entry));
+                    }
+                }
+                (Focus::Active, Char('e')) | (Focus::Eligible, Enter) => {
+                    let Some(entry) = self.current().cloned() else {
+                        continue;
+                    };
+                    if self.justification.is_none() {
+                        self.status =
+                            "a justification is required (use --justification)".to_string();
+                    } else if self.focus == Focus::Active {
+                        self.status = format!("extending {}", entry.friendly());
+                        self.send(requests, Request::Extend(entry));
+                    } else {
+                        self.status = format!("activating {}", entry.friendly());
+                        self.send(requests, Request::Activate(entry));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn header(columns: [&'static str; 3]) -> Row<'static> {
+    columns
+        .into_iter()
+        .collect::<Row>()
+        .style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED))
+        .height(1)
+}
+
+fn scope_display(entry: &RoleAssignment) -> String {
+    entry
+        .scope_name
+        .clone()
+        .unwrap_or_else(|| entry.scope.to_string())
+}
+
+/// Format the remaining time of an assignment to the minute
+pub(crate) fn format_remaining(remaining: Duration) -> String {
+    let minutes = remaining.as_secs() / 60;
+    if minutes == 0 {
+        return "< 1m".to_string();
+    }
+    humantime::format_duration(Duration::from_secs(minutes * 60)).to_string()
+}
+
+/// Format the remaining time of an assignment, followed by when it ends in the
+/// display timezone, such as `2h 13m (17:00)`
+pub(crate) fn format_expiry(entry: &RoleAssignment) -> String {
+    let Some(remaining) = entry.expires_in().map(format_remaining) else {
+        return String::new();
+    };
+    match entry.end_date_time.as_deref().and_then(format_time_of_day) {
+        Some(end) => format!("{remaining} ({end})"),
+        None => remaining,
+    }
+}
+
+/// Perform the requests from the UI, sending the results back to the UI
+fn worker(
+    client: &PimClient,
+    justification: &str,
+    duration: Duration,
+    requests: &Receiver<Request>,
+    updates: &Sender<Update>,
+) {
+    for request in requests {
+        let result = match request {
+            Request::Refresh => {
+                client.clear_cache();
+                Ok(client
+                    .list_active_role_assignments(None, Some(ListFilter::AsTarget))
+                    .and_then(|active| {
+                        let eligible = client
+                            .list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?;
+                        Ok(Update::Roles { active, eligible })
+                    })
+                    .unwrap_or_else(|err| Update::Error(format!("refresh failed: {err:#}"))))
+            }
+            Request::Activate(entry) => client
+                .activate_role_assignment_with_status(&entry, justification, duration)
+                .map(|result| Update::Status(format!("{}: {result}", entry.friendly()))),
+            Request::Deactivate(entry) => client
+                .deactivate_role_assignment(&entry)
+                .map(|()| Update::Status(format!("deactivated {}", entry.friendly()))),
+            Request::Extend(entry) => client
+                .extend_role_assignment(&entry, justification, duration)
+                .map(|()| Update::Status(format!("extended {}", entry.friendly()))),
+        };
+
+        let update = result.unwrap_or_else(|err| {
+            if PolicyViolation::caused(&err) {
+                Update::Status(format!("not allowed from the dashboard: {err:#}"))
+            } else {
+                Update::Status(format!("error: {err:#}"))
+            }
+        });
+        if updates.send(update).is_err() {
+            break;
+        }
+    }
+}
+
+/// Interactive dashboard showing active and eligible roles
+///
+/// Active roles can be deactivated or extended, and eligible roles can be
+/// activated.  The roles are refreshed every `refresh` interval.
+///
+/// # Errors
+/// Will return `Err` if the terminal cannot be configured
+pub fn dashboard_ui(
+    client: &PimClient,
+    justification: Option<String>,
+    duration: Duration,
+    refresh: Duration,
+) -> Result<()> {
+    ensure_interactive("the dashboard")?;
+    // the UI owns the terminal, so the worker must fail rather than prompt
+    // for confirmations or compliant justifications, which the UI would
+    // otherwise hide and consume as key presses
+    let prompts_disabled = disable_prompts();
+
+    let (request_tx, request_rx) = channel();
+    let (update_tx, update_rx) = channel();
+
+    let result = scope(|s| {
+        let worker_justification = justification.clone().unwrap_or_default();
+        s.spawn(move || {
+            worker(
+                client,
+                &worker_justification,
+                duration,
+                &request_rx,
+                &update_tx,
+            );
+        });
+
+        // setup terminal
+        enable_raw_mode()?;
+        let mut stdout = stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let backend = CrosstermBackend::new(stdout);
+        let mut terminal = Terminal::new(backend)?;
+
+        let app = App::new(justification, refresh);
+        let res = app.run(&mut terminal, &request_tx, &update_rx);
+
+        // stop the worker once any in-flight request finishes
+        drop(request_tx);
+
+        // restore terminal
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+
+        res
+    });
+    use_non_interactive(prompts_disabled);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_remaining;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_remaining() {
+        assert_eq!(format_remaining(Duration::from_secs(59)), "< 1m");
+        assert_eq!(format_remaining(Duration::from_secs(61)), "1m");
+        assert_eq!(format_remaining(Duration::from_secs(7980)), "2h 13m");
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    graph::{get_objects_by_ids, list_pages, submit_schedule_request, Object},
+    hooks::Hook,
+    models::roles::Role,
+    ActivationResult, PimClient,
+};
+use anyhow::{bail, ensure, Context, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    str::FromStr,
+};
+use tracing::{error, info};
+
+const DIRECTORY_PATH: &str = "/v1.0/roleManagement/directory";
+
+/// An eligible or active Entra ID directory role
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DirectoryRoleAssignment {
+    pub role: Role,
+    pub role_definition_id: String,
+    /// The scope of the assignment, `/` for the whole directory or an
+    /// administrative unit
+    pub directory_scope_id: String,
+    pub principal_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object: Option<Object>,
+    /// How an active role was granted, either `Activated` or `Assigned`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignment_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_date_time: Option<String>,
+}
+
+impl DirectoryRoleAssignment {
+    #[must_use]
+    pub fn friendly(&self) -> String {
+        if self.directory_scope_id == "/" {
+            self.role.to_string()
+        } else {
+            format!("{} in {}", self.role, self.directory_scope_id)
+        }
+    }
+
+    /// Check if the role was activated, rather than directly assigned, such
+    /// that it can be deactivated
+    #[must_use]
+    pub fn is_activated(&self) -> bool {
+        self.assignment_type
+            .as_deref()
+            .is_some_and(|x| x.eq_ignore_ascii_case("activated"))
+    }
+
+    // NOTE: serde_json doesn't panic on failed index slicing, it returns a Value
+    // that allows further nested nulls
+    #[allow(clippy::indexing_slicing)]
+    fn parse(body: &Value) -> Result<Vec<Self>> {
+        let Some(values) = body["value"].as_array() else {
+            bail!("unable to parse response: missing value array: {body:#?}");
+        };
+
+        let string = |value: &Value| value.as_str().map(ToString::to_string);
+
+        let mut results = Vec::new();
+        for entry in values {
+            let Some(role) = entry["roleDefinition"]["displayName"]
+                .as_str()
+                .and_then(|x| Role::from_str(x).ok())
+            else {
+                bail!("no role name: {entry:#?}");
+            };
+            let Some(role_definition_id) = string(&entry["roleDefinitionId"]) else {
+                bail!("no role definition id: {entry:#?}");
+            };
+            let Some(principal_id) = string(&entry["principalId"]) else {
+                bail!("no principal id: {entry:#?}");
+            };
+            results.push(Self {
+                role,
+                role_definition_id,
+                directory_scope_id: string(&entry["directoryScopeId"])
+                    .unwrap_or_else(|| "/".to_string()),
+                principal_id,
+                object: None,
+                assignment_type: string(&entry["assignmentType"]),
+                end_date_time: string(&entry["endDateTime"]),
+            });
+        }
+        Ok(results)
+    }
+}
+
+fn list_directory_roles(
+    client: &PimClient,
+    instances: &str,
+    all: bool,
+) -> Result<BTreeSet<DirectoryRoleAssignment>> {
+    let mut url = client.backend.graph(&format!(
+        "{DIRECTORY_PATH}/{instances}?$expand=roleDefinition"
+    ));
+    if !all {
+        let principal_id = client.current_user()?;
+        write!(url, "&$filter=principalId eq '{principal_id}'")?;
+    }
+
+    let mut results = Vec::new();
+    for page in list_pages(client, url)? {
+        results.extend(DirectoryRoleAssignment::parse(&page)?);
+    }
+
+    let ids = results
+        .iter()
+        .map(|x| x.principal_id.as_str())
+        .collect::<BTreeSet<_>>();
+    let objects = get_objects_by_ids(client, ids)?;
+    for entry in &mut results {
+        entry.object = objects.get(&entry.principal_id).cloned();
+    }
+
+    Ok(results.into_iter().collect())
+}
+
+/// List eligible Entra ID directory roles
+///
+/// Only the current user's roles are included, unless `all` is set.
+///
+/// # Errors
+/// Will return `Err` if the request fails or the response is not valid JSON
+pub fn list_eligible_directory_roles(
+    client: &PimClient,
+    all: bool,
+) -> Result<BTreeSet<DirectoryRoleAssignment>> {
+    info!("listing eligible directory roles");
+    list_directory_roles(client, "roleEligibilityScheduleInstances", all)
+        .context("unable to list eligible directory roles")
+}
+
+/// List active Entra ID directory roles
+///
+/// Only the current user's roles are included, unless `all` is set.
+///
+/// # Errors
+/// Will return `Err` if the request fails or the response is not valid JSON
+pub fn list_active_directory_roles(
+    client: &PimClient,
+    all: bool,
+) -> Result<BTreeSet<DirectoryRoleAssignment>> {
+    info!("listing active directory roles");
+    list_directory_roles(client, "roleAssignmentScheduleInstances", all)
+        .context("unable to list active directory roles")
+}
+
+/// Find a directory role by name or definition id, ignoring case
+///
+/// # Errors
+/// Will return `Err` if no role matches
+pub fn find_directory_role(
+    roles: &BTreeSet<DirectoryRoleAssignment>,
+    role: &Role,
+    directory_scope_id: &str,
+) -> Result<DirectoryRoleAssignment> {
+    if let Some(entry) = roles.iter().find(|x| {
+        (x.role.0.eq_ignore_ascii_case(&role.0)
+            || x.role_definition_id.eq_ignore_ascii_case(&role.0))
+            && x.directory_scope_id
+                .eq_ignore_ascii_case(directory_scope_id)
+    }) {
+        return Ok(entry.clone());
+    }
+
+    let available = roles
+        .iter()
+        .map(|x| format!("* {}", x.friendly()))
+        .collect::<Vec<_>>();
+    if available.is_empty() {
+        bail!("directory role not found.  role:{role} scope:{directory_scope_id}");
+    }
+    bail!(
+        "directory role not found.  role:{role} scope:{directory_scope_id}\navailable roles:\n{}",
+        available.join("\n")
+    );
+}
+
+/// Deactivate an active directory role
+///
+/// # Errors
+/// Will return `Err` if the request fails
+pub fn deactivate_directory_role(client: &PimClient, role: &DirectoryRoleAssignment) -> Result<()> {
+    info!("deactivating directory role {}", role.friendly());
+    let body = json!({
+        "action": "selfDeactivate",
+        "principalId": role.principal_id,
+        "roleDefinitionId": role.role_definition_id,
+        "directoryScopeId": role.directory_scope_id,
+    });
+    let result = submit_schedule_request(
+        client,
+        &client
+            .backend
+            .graph(&format!("{DIRECTORY_PATH}/roleAssignmentScheduleRequests")),
+        &body,
+    );
+    client.run_hook_after(Hook::PostDeactivate, role, None, &result);
+    result?;
+    Ok(())
+}
+
+/// Deactivate a set of directory roles, returning the result of each
+///
+/// # Errors
+/// Will return `Err` if no roles are specified
+pub fn deactivate_directory_role_set(
+    client: &PimClient,
+    roles: &BTreeSet<DirectoryRoleAssignment>,
+    concurrency: usize,
+) -> Result<BTreeMap<DirectoryRoleAssignment, ActivationResult>> {
+    ensure!(!roles.is_empty(), "no directory roles specified");
+
+    PimClient::thread_builder(concurrency);
+
+    Ok(roles
+        .into_par_iter()
+        .map(|entry| {
+            let result = match deactivate_directory_role(client, entry) {
+                Ok(()) => ActivationResult::Succeeded,
+                Err(error) => {
+                    error!("directory role: {} error: {error:?}", entry.role);
+                    ActivationResult::Failed(format!("{error:#}"))
+                }
+            };
+            (entry.clone(), result)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DirectoryRoleAssignment;
+    use anyhow::Result;
+    use insta::assert_json_snapshot;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        const DATA: &str = include_str!("../tests/data/directory-assignment-instances.json");
+        let roles = DirectoryRoleAssignment::parse(&serde_json::from_str(DATA)?)?;
+        assert_eq!(roles.iter().filter(|x| x.is_activated()).count(), 1);
+        assert_json_snapshot!(roles);
+        Ok(())
+    }
+}
@@ -49,6 +49,14 @@ impl<K: Hash + Eq, V> ExpiringMap<K, V> {
             .map(Value::value)
     }
 
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord + Hash,
+    {
+        self.data.remove(key).map(|v| v.value)
+    }
+
     pub fn contains_key<Q>(&self, key: &Q) -> bool
     where
         K: Borrow<Q>,
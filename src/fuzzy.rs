@@ -0,0 +1,96 @@
+/// Compute the Levenshtein edit distance between two strings
+pub(crate) fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut row = current.iter_mut();
+        let mut last = i + 1;
+        if let Some(first) = row.next() {
+            *first = last;
+        }
+        for ((cell, b_char), (diagonal, above)) in row
+            .zip(&b)
+            .zip(previous.iter().zip(previous.iter().skip(1)))
+        {
+            let cost = usize::from(a_char != *b_char);
+            last = (diagonal + cost).min(above + 1).min(last + 1);
+            *cell = last;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous.last().copied().unwrap_or_default()
+}
+
+/// Case-insensitive distance between a user provided query and a candidate
+///
+/// An exact match is 0, a prefix match is 1, otherwise the Levenshtein
+/// distance is used.
+pub(crate) fn distance(query: &str, candidate: &str) -> usize {
+    let query = query.to_lowercase();
+    let candidate = candidate.to_lowercase();
+    if query == candidate {
+        0
+    } else if candidate.starts_with(&query) {
+        1
+    } else {
+        levenshtein(&query, &candidate).max(1)
+    }
+}
+
+/// Score how well `query` matches `candidate` as a case-insensitive
+/// subsequence, where lower is better
+///
+/// Returns `None` if the characters of `query` do not all appear in
+/// `candidate` in order.  The score is the distance between the first and
+/// last matched characters, such that contiguous matches rank first.
+pub(crate) fn subsequence(query: &str, candidate: &str) -> Option<usize> {
+    let mut query = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut first = None;
+    let mut last = 0;
+    for (i, c) in candidate.chars().flat_map(char::to_lowercase).enumerate() {
+        let Some(next) = query.peek() else {
+            break;
+        };
+        if *next == c {
+            query.next();
+            first.get_or_insert(i);
+            last = i;
+        }
+    }
+    if query.peek().is_some() {
+        return None;
+    }
+    let Some(first) = first else {
+        return Some(0);
+    };
+    Some(last - first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance, levenshtein, subsequence};
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("abc", ""), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("Owner", "Owenr"), 2);
+        assert_eq!(distance("owner", "Owner"), 0);
+        assert_eq!(distance("Storage Blob", "Storage Blob Data Reader"), 1);
+        assert_eq!(distance("Ownr", "Owner"), 1);
+    }
+
+    #[test]
+    fn test_subsequence() {
+        assert_eq!(subsequence("", "anything"), Some(0));
+        assert_eq!(subsequence("prod", "my-PROD-rg"), Some(3));
+        assert_eq!(subsequence("mprg", "my-prod-rg"), Some(9));
+        assert_eq!(subsequence("gr", "rg"), None);
+        assert!(subsequence("prod", "production") < subsequence("prod", "p-r-o-d"));
+    }
+}
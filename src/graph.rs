@@ -1,13 +1,24 @@
-use crate::{az_cli::TokenScope, PimClient};
-use anyhow::{bail, Context, Result};
+use crate::{activate::check_error_response, az_cli::TokenScope, ActivationResult, PimClient};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::ValueEnum;
 use rayon::prelude::*;
-use reqwest::Method;
+use reqwest::{Method, Url};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Formatter, Result as FmtResult},
+};
 use tracing::info;
+use uuid::Uuid;
 
-#[derive(Deserialize, Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone)]
+// the maximum number of objects Graph returns per page
+const PAGE_SIZE: usize = 999;
+// stop following pages rather than looping forever on a misbehaving response
+const MAX_PAGES: usize = 1000;
+
+#[derive(Deserialize, Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone, JsonSchema)]
 pub struct Object {
     pub id: String,
     pub display_name: String,
@@ -16,13 +27,35 @@ pub struct Object {
     pub object_type: PrincipalType,
 }
 
-#[derive(Deserialize, Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone)]
+#[allow(clippy::manual_assert, clippy::panic)]
+#[derive(
+    Deserialize, Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone, ValueEnum, JsonSchema,
+)]
 pub enum PrincipalType {
     User,
     Group,
     ServicePrincipal,
 }
 
+impl Display for PrincipalType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::User => write!(f, "User"),
+            Self::Group => write!(f, "Group"),
+            Self::ServicePrincipal => write!(f, "ServicePrincipal"),
+        }
+    }
+}
+
+/// A member of a group, along with the nested groups it was found through
+#[derive(Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone)]
+pub struct NestedMember {
+    pub object: Object,
+    /// The display names of the nested groups between the top-level group and
+    /// the member, outermost first.  Empty for direct members.
+    pub via: Vec<String>,
+}
+
 fn parse_objects(value: &Value) -> Result<BTreeSet<Object>> {
     let mut results = BTreeSet::new();
     if let Some(values) = value.get("value").and_then(|x| x.as_array()) {
@@ -73,6 +106,80 @@ fn parse_objects(value: &Value) -> Result<BTreeSet<Object>> {
     Ok(results)
 }
 
+/// List the pages of results from a Graph API endpoint, following
+/// `@odata.nextLink` until all of the pages have been read
+pub(crate) fn list_pages(pim_client: &PimClient, url: String) -> Result<Vec<Value>> {
+    let mut results = Vec::new();
+    let mut next = Some(url);
+
+    while let Some(url) = next.take() {
+        ensure!(
+            results.len() < MAX_PAGES,
+            "more than {MAX_PAGES} pages of results, giving up"
+        );
+
+        let request = pim_client
+            .backend
+            .client
+            .request(Method::GET, &url)
+            .bearer_auth(pim_client.backend.get_token(TokenScope::Graph)?)
+            .build()?;
+        let value = pim_client.backend.retry_request(&request, None)?;
+
+        next = value
+            .get("@odata.nextLink")
+            .and_then(Value::as_str)
+            .map(ToString::to_string);
+        results.push(value);
+        if next.is_some() {
+            info!("listed {} pages, requesting the next page", results.len());
+        }
+    }
+
+    Ok(results)
+}
+
+/// Submit a PIM schedule request, such as activating a group membership or
+/// deactivating a directory role
+///
+/// As these can grant Azure roles, cached listings of role assignments are
+/// treated as out of date afterwards.
+pub(crate) fn submit_schedule_request(
+    pim_client: &PimClient,
+    url: &str,
+    body: &Value,
+) -> Result<ActivationResult> {
+    let request = pim_client
+        .backend
+        .client
+        .request(Method::POST, url)
+        .bearer_auth(pim_client.backend.get_token(TokenScope::Graph)?)
+        .json(body)
+        .build()?;
+    let response = pim_client
+        .backend
+        .send_write(&request, Some(check_error_response))?;
+
+    let pending = response
+        .get("status")
+        .and_then(Value::as_str)
+        .is_some_and(|x| x.starts_with("PendingApproval"));
+    Ok(if pending {
+        ActivationResult::PendingApproval
+    } else {
+        ActivationResult::Succeeded
+    })
+}
+
+/// List the objects from a Graph API endpoint
+fn list_objects(pim_client: &PimClient, url: String) -> Result<BTreeSet<Object>> {
+    let mut results = BTreeSet::new();
+    for page in list_pages(pim_client, url)? {
+        results.extend(parse_objects(&page)?);
+    }
+    Ok(results)
+}
+
 fn get_objects_by_ids_small(pim_client: &PimClient, ids: &[&&str]) -> Result<BTreeSet<Object>> {
     info!("checking {} objects", ids.len());
     let builder = pim_client
@@ -80,7 +187,7 @@ fn get_objects_by_ids_small(pim_client: &PimClient, ids: &[&&str]) -> Result<BTr
         .client
         .request(
             Method::POST,
-            "https://graph.microsoft.com/v1.0/directoryObjects/getByIds",
+            pim_client.backend.graph("/v1.0/directoryObjects/getByIds"),
         )
         .bearer_auth(pim_client.backend.get_token(TokenScope::Graph)?);
 
@@ -127,31 +234,75 @@ pub(crate) fn get_objects_by_ids(
     Ok(result)
 }
 
-pub(crate) fn group_members(pim_client: &PimClient, id: &str) -> Result<BTreeSet<Object>> {
-    let mut group_cache = pim_client.group_cache.lock();
-    if let Some(entries) = group_cache.get(id) {
-        return Ok(entries.clone());
-    }
+/// Find a user, group, or service principal by object ID or user principal name
+pub(crate) fn resolve_principal(pim_client: &PimClient, principal: &str) -> Result<Object> {
+    let id = if Uuid::parse_str(principal).is_ok() {
+        principal.to_string()
+    } else {
+        // user principal names of guests include `#`, so encode the name
+        // rather than formatting it into the URL
+        let mut url = Url::parse(&pim_client.backend.graph("/v1.0/users"))?;
+        url.path_segments_mut()
+            .map_err(|()| anyhow!("invalid graph url"))?
+            .push(principal);
+        url.set_query(Some("$select=id"));
+        let request = pim_client
+            .backend
+            .client
+            .request(Method::GET, url)
+            .bearer_auth(pim_client.backend.get_token(TokenScope::Graph)?)
+            .build()?;
+        let value = pim_client
+            .backend
+            .retry_request(&request, None)
+            .with_context(|| format!("unable to find user {principal}"))?;
+        value
+            .get("id")
+            .and_then(Value::as_str)
+            .with_context(|| format!("unable to find user {principal}"))?
+            .to_string()
+    };
 
-    let mut cache = pim_client.object_cache.lock();
+    get_objects_by_ids(pim_client, [id.as_str()].into_iter().collect())?
+        .remove(&id)
+        .with_context(|| format!("unable to find principal {principal}"))
+}
 
-    let url = format!("https://graph.microsoft.com/v1.0/groups/{id}/members");
-    let request = pim_client
-        .backend
-        .client
-        .request(Method::GET, &url)
-        .bearer_auth(pim_client.backend.get_token(TokenScope::Graph)?)
-        .build()?;
-    let value = pim_client.backend.retry_request(&request, None)?;
-    let results = parse_objects(&value)?;
+/// List the members of a group
+///
+/// If `transitive` is set, members of nested groups are included using a
+/// single request, rather than walking the nested groups.
+pub(crate) fn group_members(
+    pim_client: &PimClient,
+    id: &str,
+    transitive: bool,
+) -> Result<BTreeSet<Object>> {
+    let (group_cache, relationship) = if transitive {
+        (&pim_client.transitive_group_cache, "transitiveMembers")
+    } else {
+        (&pim_client.group_cache, "members")
+    };
 
-    for object in &results {
-        if cache.get(&object.id).is_none() {
-            cache.insert(object.id.clone(), Some(object.clone()));
-        }
+    if let Some(entries) = group_cache.lock().get(id) {
+        return Ok(entries.clone());
     }
 
-    group_cache.insert(id.to_string(), results.clone());
+    let key = (id.to_string(), transitive);
+    pim_client.group_in_flight.run(&key, || {
+        let url = pim_client.backend.graph(&format!(
+            "/v1.0/groups/{id}/{relationship}?$top={PAGE_SIZE}"
+        ));
+        let results = list_objects(pim_client, url)
+            .with_context(|| format!("unable to list members of group {id}"))?;
 
-    Ok(results)
+        let mut cache = pim_client.object_cache.lock();
+        for object in &results {
+            if cache.get(&object.id).is_none() {
+                cache.insert(object.id.clone(), Some(object.clone()));
+            }
+        }
+
+        group_cache.lock().insert(id.to_string(), results.clone());
+        Ok(results)
+    })
 }
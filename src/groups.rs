@@ -0,0 +1,337 @@
+use crate::{
+    format_duration,
+    graph::{get_objects_by_ids, list_pages, submit_schedule_request},
+    hooks::Hook,
+    ActivationResult, PimClient,
+};
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+use humantime::format_rfc3339_seconds;
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Formatter, Result as FmtResult},
+    time::{Duration, SystemTime},
+};
+use tracing::{error, info};
+
+const GROUP_PIM_PATH: &str = "/v1.0/identityGovernance/privilegedAccess/group";
+
+/// The relationship to a group granted by a PIM group membership
+#[allow(clippy::manual_assert, clippy::panic)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupAccess {
+    Member,
+    Owner,
+}
+
+impl GroupAccess {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Member => "member",
+            Self::Owner => "owner",
+        }
+    }
+}
+
+impl Display for GroupAccess {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// An eligible or active membership of a PIM-managed group
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GroupMembership {
+    pub group_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_name: Option<String>,
+    pub access: GroupAccess,
+    pub principal_id: String,
+    /// How an active membership was granted, either `activated` or `assigned`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignment_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_date_time: Option<String>,
+}
+
+impl GroupMembership {
+    /// The group's display name, or its id if the name is not known
+    #[must_use]
+    pub fn friendly(&self) -> String {
+        match &self.group_name {
+            Some(name) => format!("{} of {name} ({})", self.access, self.group_id),
+            None => format!("{} of {}", self.access, self.group_id),
+        }
+    }
+
+    /// Check if the membership was activated, rather than directly assigned,
+    /// such that it can be deactivated
+    #[must_use]
+    pub fn is_activated(&self) -> bool {
+        self.assignment_type
+            .as_deref()
+            .is_some_and(|x| x.eq_ignore_ascii_case("activated"))
+    }
+
+    // NOTE: serde_json doesn't panic on failed index slicing, it returns a Value
+    // that allows further nested nulls
+    #[allow(clippy::indexing_slicing)]
+    fn parse(body: &Value) -> Result<Vec<Self>> {
+        let Some(values) = body["value"].as_array() else {
+            bail!("unable to parse response: missing value array: {body:#?}");
+        };
+
+        let string = |value: &Value| value.as_str().map(ToString::to_string);
+
+        let mut results = Vec::new();
+        for entry in values {
+            let Some(group_id) = string(&entry["groupId"]) else {
+                bail!("no group id: {entry:#?}");
+            };
+            let Some(principal_id) = string(&entry["principalId"]) else {
+                bail!("no principal id: {entry:#?}");
+            };
+            let access = match entry["accessId"].as_str() {
+                Some("member") => GroupAccess::Member,
+                Some("owner") => GroupAccess::Owner,
+                _ => bail!("unknown access: {entry:#?}"),
+            };
+            results.push(Self {
+                group_id,
+                group_name: string(&entry["group"]["displayName"]),
+                access,
+                principal_id,
+                assignment_type: string(&entry["assignmentType"]),
+                end_date_time: string(&entry["endDateTime"]),
+            });
+        }
+        Ok(results)
+    }
+}
+
+fn list_memberships(client: &PimClient, instances: &str) -> Result<BTreeSet<GroupMembership>> {
+    let principal_id = client.current_user()?;
+    let url = client.backend.graph(&format!(
+        "{GROUP_PIM_PATH}/{instances}?$filter=principalId eq '{principal_id}'&$expand=group"
+    ));
+    let mut results = Vec::new();
+    for page in list_pages(client, url)? {
+        results.extend(GroupMembership::parse(&page)?);
+    }
+
+    // resolve the names of any groups that were not expanded in the response
+    let missing = results
+        .iter()
+        .filter(|x| x.group_name.is_none())
+        .map(|x| x.group_id.as_str())
+        .collect::<BTreeSet<_>>();
+    if !missing.is_empty() {
+        let objects = get_objects_by_ids(client, missing)?;
+        for entry in &mut results {
+            if entry.group_name.is_none() {
+                entry.group_name = objects.get(&entry.group_id).map(|x| x.display_name.clone());
+            }
+        }
+    }
+
+    Ok(results.into_iter().collect())
+}
+
+/// List the PIM group memberships the current user is eligible to activate
+///
+/// # Errors
+/// Will return `Err` if the request fails or the response is not valid JSON
+pub fn list_eligible_group_memberships(client: &PimClient) -> Result<BTreeSet<GroupMembership>> {
+    info!("listing eligible group memberships");
+    list_memberships(client, "eligibilityScheduleInstances")
+        .context("unable to list eligible group memberships")
+}
+
+/// List the PIM group memberships that are active for the current user
+///
+/// # Errors
+/// Will return `Err` if the request fails or the response is not valid JSON
+pub fn list_active_group_memberships(client: &PimClient) -> Result<BTreeSet<GroupMembership>> {
+    info!("listing active group memberships");
+    list_memberships(client, "assignmentScheduleInstances")
+        .context("unable to list active group memberships")
+}
+
+/// Find a membership by group name or id, ignoring case
+///
+/// # Errors
+/// Will return `Err` if no membership matches
+pub fn find_group_membership(
+    memberships: &BTreeSet<GroupMembership>,
+    group: &str,
+    access: GroupAccess,
+) -> Result<GroupMembership> {
+    if let Some(entry) = memberships.iter().find(|x| {
+        x.access == access
+            && (x.group_id.eq_ignore_ascii_case(group)
+                || x.group_name
+                    .as_deref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(group)))
+    }) {
+        return Ok(entry.clone());
+    }
+
+    let available = memberships
+        .iter()
+        .map(|x| format!("* {}", x.friendly()))
+        .collect::<Vec<_>>();
+    if available.is_empty() {
+        bail!("group membership not found.  group:{group} access:{access}");
+    }
+    bail!(
+        "group membership not found.  group:{group} access:{access}\navailable memberships:\n{}",
+        available.join("\n")
+    );
+}
+
+/// Submit a schedule request for the current user's membership
+fn schedule_request(
+    client: &PimClient,
+    membership: &GroupMembership,
+    action: &str,
+    justification: Option<&str>,
+    duration: Option<Duration>,
+) -> Result<ActivationResult> {
+    let mut body = Map::new();
+    body.insert("accessId".into(), json!(membership.access.as_str()));
+    body.insert("principalId".into(), json!(membership.principal_id));
+    body.insert("groupId".into(), json!(membership.group_id));
+    body.insert("action".into(), json!(action));
+    if let Some(justification) = justification {
+        body.insert("justification".into(), json!(justification));
+    }
+    if let Some(duration) = duration {
+        body.insert(
+            "scheduleInfo".into(),
+            json!({
+                "startDateTime": format_rfc3339_seconds(SystemTime::now()).to_string(),
+                "expiration": {
+                    "type": "afterDuration",
+                    "duration": format_duration(duration)?,
+                }
+            }),
+        );
+    }
+
+    submit_schedule_request(
+        client,
+        &client
+            .backend
+            .graph(&format!("{GROUP_PIM_PATH}/assignmentScheduleRequests")),
+        &Value::Object(body),
+    )
+}
+
+/// Activate an eligible group membership
+///
+/// # Errors
+/// Will return `Err` if the request fails
+pub fn activate_group_membership(
+    client: &PimClient,
+    membership: &GroupMembership,
+    justification: &str,
+    duration: Duration,
+) -> Result<ActivationResult> {
+    let justification = client.compliant_justification(justification)?;
+    client.check_group_policy(membership, duration)?;
+    client.run_hook(Hook::PreActivate, membership, Some(duration))?;
+    info!("activating {}", membership.friendly());
+    let result = schedule_request(
+        client,
+        membership,
+        "selfActivate",
+        Some(&justification),
+        Some(duration),
+    );
+    client.run_hook_after(Hook::PostActivate, membership, Some(duration), &result);
+    result
+}
+
+/// Deactivate an active group membership
+///
+/// # Errors
+/// Will return `Err` if the request fails
+pub fn deactivate_group_membership(client: &PimClient, membership: &GroupMembership) -> Result<()> {
+    info!("deactivating {}", membership.friendly());
+    let result = schedule_request(client, membership, "selfDeactivate", None, None);
+    client.run_hook_after(Hook::PostDeactivate, membership, None, &result);
+    result?;
+    Ok(())
+}
+
+/// Request extending an active group membership
+///
+/// # Errors
+/// Will return `Err` if the request fails
+pub fn extend_group_membership(
+    client: &PimClient,
+    membership: &GroupMembership,
+    justification: &str,
+    duration: Duration,
+) -> Result<ActivationResult> {
+    let justification = client.compliant_justification(justification)?;
+    client.check_group_policy(membership, duration)?;
+    info!("extending {}", membership.friendly());
+    let result = schedule_request(
+        client,
+        membership,
+        "selfExtend",
+        Some(&justification),
+        Some(duration),
+    );
+    client.run_hook_after(Hook::PostExtend, membership, Some(duration), &result);
+    result
+}
+
+/// Deactivate a set of group memberships, returning the result of each
+///
+/// # Errors
+/// Will return `Err` if no memberships are specified
+pub fn deactivate_group_membership_set(
+    client: &PimClient,
+    memberships: &BTreeSet<GroupMembership>,
+    concurrency: usize,
+) -> Result<BTreeMap<GroupMembership, ActivationResult>> {
+    ensure!(!memberships.is_empty(), "no group memberships specified");
+
+    PimClient::thread_builder(concurrency);
+
+    Ok(memberships
+        .into_par_iter()
+        .map(|entry| {
+            let result = match deactivate_group_membership(client, entry) {
+                Ok(()) => ActivationResult::Succeeded,
+                Err(error) => {
+                    error!("group: {} error: {error:?}", entry.group_id);
+                    ActivationResult::Failed(format!("{error:#}"))
+                }
+            };
+            (entry.clone(), result)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GroupMembership;
+    use anyhow::Result;
+    use insta::assert_json_snapshot;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        const DATA: &str = include_str!("../tests/data/group-assignment-instances.json");
+        let memberships = GroupMembership::parse(&serde_json::from_str(DATA)?)?;
+        assert_eq!(memberships.iter().filter(|x| x.is_activated()).count(), 1);
+        assert_json_snapshot!(memberships);
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use serde_json::Value;
+
+/// Remediation hints for common failures, keyed by a substring of the error
+/// code or message
+///
+/// The more specific entries are listed first, as policy validation failures
+/// include the name of the rule that failed in the message.
+const HINTS: &[(&str, &str)] = &[
+    (
+        "ExpirationRule",
+        "the requested duration is longer than the role's activation policy allows; use a shorter --duration",
+    ),
+    (
+        "JustificationRule",
+        "the role's activation policy requires a justification; provide one that meets the policy",
+    ),
+    (
+        "TicketingRule",
+        "the role's activation policy requires ticket information with the request",
+    ),
+    (
+        "AuthenticationContextRule",
+        "the role requires a Conditional Access authentication context; run `az login` to reauthenticate and try again",
+    ),
+    (
+        "MfaRule",
+        "the role requires multi-factor authentication; run `az login` to reauthenticate with MFA and try again",
+    ),
+    (
+        "RoleAssignmentRequestAcrsValidationFailed",
+        "the role requires additional authentication under Conditional Access; run `az login` to reauthenticate and try again",
+    ),
+    (
+        "RoleAssignmentRequestPolicyValidationFailed",
+        "the request does not meet the role's activation policy, such as the maximum duration or a required justification or ticket",
+    ),
+    (
+        "ActiveDurationTooShort",
+        "roles cannot be deactivated within five minutes of being activated; try again shortly",
+    ),
+    (
+        "RoleAssignmentDoesNotExist",
+        "the role is not currently assigned at this scope; check `az-pim list --active`",
+    ),
+    (
+        "InsufficientPermissions",
+        "you do not have permission to perform this operation at the scope; check that an active role grants it",
+    ),
+    (
+        "AuthorizationFailed",
+        "you do not have permission to perform this operation at the scope; check that an active role grants it",
+    ),
+    (
+        "PrincipalNotFound",
+        "the principal could not be found; newly created principals can take a few minutes to replicate",
+    ),
+    (
+        "AADSTS53003",
+        "access was blocked by a Conditional Access policy; check that you are signing in from a compliant device or location",
+    ),
+    (
+        "AADSTS50076",
+        "Conditional Access requires you to sign in again; run `az login` and try again",
+    ),
+    (
+        "interaction_required",
+        "Conditional Access requires you to sign in again; run `az login` and try again",
+    ),
+    (
+        "AADSTS700082",
+        "the Azure CLI session has expired; run `az login` and try again",
+    ),
+    (
+        "AADSTS70043",
+        "the Azure CLI session has expired; run `az login` and try again",
+    ),
+];
+
+/// Find a remediation hint for an error
+pub(crate) fn hint(text: &str) -> Option<&'static str> {
+    HINTS
+        .iter()
+        .find(|(key, _)| text.contains(key))
+        .map(|(_, hint)| *hint)
+}
+
+/// Summarize an ARM error response, if it is one that has a remediation hint
+// NOTE: serde_json doesn't panic on failed index slicing, it returns a Value
+// that allows further nested nulls
+#[allow(clippy::indexing_slicing)]
+pub(crate) fn friendly_error(body: &Value) -> Option<String> {
+    let code = body["error"]["code"].as_str()?;
+    let message = body["error"]["message"].as_str().unwrap_or_default();
+    let hint = hint(message).or_else(|| hint(code))?;
+    Some(format!("{code}: {message}\nhint: {hint}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_friendly_error() {
+        let body = json!({"error": {
+            "code": "RoleAssignmentRequestPolicyValidationFailed",
+            "message": "The following policy rules failed: [\"ExpirationRule\"]",
+        }});
+        assert_eq!(
+            friendly_error(&body),
+            Some(format!(
+                "RoleAssignmentRequestPolicyValidationFailed: The following policy rules failed: [\"ExpirationRule\"]\nhint: {}",
+                hint("ExpirationRule").unwrap_or_default()
+            ))
+        );
+
+        let body = json!({"error": {"code": "InsufficientPermissions", "message": "denied"}});
+        assert!(friendly_error(&body).is_some_and(|x| x.contains("do not have permission")));
+
+        let body = json!({"error": {"code": "SomethingElse", "message": "unknown"}});
+        assert_eq!(friendly_error(&body), None);
+        assert_eq!(friendly_error(&json!({})), None);
+    }
+
+    #[test]
+    fn test_hint() {
+        let stderr = "ERROR: AADSTS50076: Due to a configuration change made by your administrator ... interaction_required";
+        assert_eq!(hint(stderr), hint("interaction_required"));
+        assert_eq!(hint("unrelated"), None);
+    }
+}
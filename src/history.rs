@@ -0,0 +1,178 @@
+use crate::{
+    latest::cache_path,
+    models::{
+        roles::{Role, RoleAssignment},
+        scope::Scope,
+    },
+    ActivationResult,
+};
+use anyhow::{Context, Result};
+use humantime::{format_rfc3339_seconds, parse_rfc3339_weak};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs::{create_dir_all, read_to_string, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use tracing::warn;
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+// activations run concurrently, serialize appending to the history file
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Activate,
+    Deactivate,
+    Extend,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Activate => write!(f, "activate"),
+            Self::Deactivate => write!(f, "deactivate"),
+            Self::Extend => write!(f, "extend"),
+        }
+    }
+}
+
+/// A single request made through `az-pim`, stored in
+/// `$HOME/.cache/az-pim-cli/history.jsonl`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub timestamp: String,
+    pub action: Action,
+    pub role: Role,
+    pub scope: Scope,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub scope_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub duration: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub justification: Option<String>,
+    pub result: ActivationResult,
+}
+
+impl HistoryEntry {
+    /// The time the request was made
+    ///
+    /// Returns `None` if the timestamp cannot be parsed.
+    #[must_use]
+    pub fn time(&self) -> Option<SystemTime> {
+        parse_rfc3339_weak(&self.timestamp).ok()
+    }
+}
+
+fn history_path() -> Result<PathBuf> {
+    let cache_path = cache_path().context("unable to determine cache path")?;
+    Ok(cache_path.join(HISTORY_FILE))
+}
+
+fn append(entry: &HistoryEntry) -> Result<()> {
+    let path = history_path()?;
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).context("unable to create cache path")?;
+    }
+    let mut line = serde_json::to_string(entry).context("unable to serialize history entry")?;
+    line.push('\n');
+
+    let _lock = HISTORY_LOCK.lock();
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut handle| handle.write_all(line.as_bytes()))
+        .with_context(|| format!("unable to write {}", path.display()))
+}
+
+/// Record the outcome of a request in the local history
+///
+/// Failing to record the request only logs a warning, as the request itself
+/// has already been made.
+pub(crate) fn record(
+    action: Action,
+    assignment: &RoleAssignment,
+    justification: Option<&str>,
+    duration: Option<Duration>,
+    result: &Result<ActivationResult>,
+) {
+    let entry = HistoryEntry {
+        timestamp: format_rfc3339_seconds(SystemTime::now()).to_string(),
+        action,
+        role: assignment.role.clone(),
+        scope: assignment.scope.clone(),
+        scope_name: assignment.scope_name.clone(),
+        duration: duration.map(|x| humantime::format_duration(x).to_string()),
+        justification: justification.map(ToString::to_string),
+        result: match result {
+            Ok(result) => result.clone(),
+            Err(err) => ActivationResult::Failed(format!("{err:#}")),
+        },
+    };
+
+    if let Err(err) = append(&entry) {
+        warn!("unable to record history: {err:?}");
+    }
+}
+
+/// Load the recorded history, optionally limited to entries after `since`
+///
+/// Entries that cannot be parsed are skipped with a warning.
+///
+/// # Errors
+/// Will return `Err` if the history file exists and cannot be read
+pub fn load(since: Option<SystemTime>) -> Result<Vec<HistoryEntry>> {
+    let path = history_path()?;
+    let data = match read_to_string(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("unable to read {}", path.display()));
+        }
+    };
+
+    let mut entries = Vec::new();
+    for (number, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: HistoryEntry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!("skipping line {} of {}: {err}", number + 1, path.display());
+                continue;
+            }
+        };
+        if let Some(since) = since {
+            if entry.time().is_none_or(|time| time < since) {
+                continue;
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, HistoryEntry};
+    use crate::ActivationResult;
+    use anyhow::Result;
+
+    #[test]
+    fn test_history_entry_roundtrip() -> Result<()> {
+        let line = r#"{"timestamp":"2024-01-02T03:04:05Z","action":"activate","role":"Owner","scope":"/subscriptions/00000000-0000-0000-0000-000000000000","duration":"8h","justification":"testing","result":{"status":"failed","reason":"denied"}}"#;
+        let entry: HistoryEntry = serde_json::from_str(line)?;
+        assert_eq!(entry.action, Action::Activate);
+        assert_eq!(entry.result, ActivationResult::Failed("denied".to_string()));
+        assert!(entry.time().is_some());
+        assert_eq!(serde_json::to_string(&entry)?, line);
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{
+    entra::DirectoryRoleAssignment, format_duration, groups::GroupMembership, is_interactive,
+    models::roles::RoleAssignment, ActivationResult,
+};
+use anyhow::{ensure, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    process::{Command, Stdio},
+    time::Duration,
+};
+use tracing::{debug, warn};
+
+#[cfg(target_os = "windows")]
+const SHELL: &[&str] = &["cmd", "/C"];
+#[cfg(not(target_os = "windows"))]
+const SHELL: &[&str] = &["sh", "-c"];
+
+/// Commands run before and after changing roles or group memberships using
+/// `az-pim`
+///
+/// Each command is run using the shell (`sh -c`, or `cmd /C` on Windows),
+/// with the details of the role in the environment:
+///
+/// * `AZ_PIM_HOOK`: `pre_activate`, `post_activate`, `post_extend`, or
+///   `post_deactivate`
+/// * `AZ_PIM_KIND`: `role` for Azure roles, `directory` for Entra ID roles,
+///   or `group` for group memberships
+/// * `AZ_PIM_ROLE`: the name of the role, or `member` or `owner` for group
+///   memberships
+/// * `AZ_PIM_SCOPE`: the scope of the role, the directory scope of the Entra
+///   ID role, or the id of the group
+/// * `AZ_PIM_SCOPE_NAME`: the display name of the scope or group, if known
+/// * `AZ_PIM_DURATION`: the ISO 8601 duration of the activation, such as `PT8H`
+/// * `AZ_PIM_DURATION_SECONDS`: the duration of the activation in seconds
+/// * `AZ_PIM_RESULT`: the result of the request, such as `succeeded` or
+///   `pending-approval`, for hooks run after the request
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Command run before activating a role
+    ///
+    /// If the command fails, the role is not activated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_activate: Option<String>,
+
+    /// Command run after requesting a role be activated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_activate: Option<String>,
+
+    /// Command run after requesting an active role be extended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_extend: Option<String>,
+
+    /// Command run after deactivating a role
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_deactivate: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hook {
+    PreActivate,
+    PostActivate,
+    PostExtend,
+    PostDeactivate,
+}
+
+/// The role or group membership a hook is run for
+pub(crate) struct HookTarget<'a> {
+    kind: &'static str,
+    role: &'a str,
+    scope: &'a str,
+    scope_name: Option<&'a str>,
+}
+
+impl<'a> From<&'a RoleAssignment> for HookTarget<'a> {
+    fn from(assignment: &'a RoleAssignment) -> Self {
+        Self {
+            kind: "role",
+            role: &assignment.role.0,
+            scope: &assignment.scope.0,
+            scope_name: assignment.scope_name.as_deref(),
+        }
+    }
+}
+
+impl<'a> From<&'a DirectoryRoleAssignment> for HookTarget<'a> {
+    fn from(role: &'a DirectoryRoleAssignment) -> Self {
+        Self {
+            kind: "directory",
+            role: &role.role.0,
+            scope: &role.directory_scope_id,
+            scope_name: None,
+        }
+    }
+}
+
+impl<'a> From<&'a GroupMembership> for HookTarget<'a> {
+    fn from(membership: &'a GroupMembership) -> Self {
+        Self {
+            kind: "group",
+            role: membership.access.as_str(),
+            scope: &membership.group_id,
+            scope_name: membership.group_name.as_deref(),
+        }
+    }
+}
+
+impl HookTarget<'_> {
+    fn friendly(&self) -> String {
+        match self.scope_name {
+            Some(scope_name) => format!("{} in {scope_name} ({})", self.role, self.scope),
+            None => format!("{} in {}", self.role, self.scope),
+        }
+    }
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Self::PreActivate => "pre_activate",
+            Self::PostActivate => "post_activate",
+            Self::PostExtend => "post_extend",
+            Self::PostDeactivate => "post_deactivate",
+        }
+    }
+}
+
+impl HooksConfig {
+    fn command(&self, hook: Hook) -> Option<&str> {
+        match hook {
+            Hook::PreActivate => self.pre_activate.as_deref(),
+            Hook::PostActivate => self.post_activate.as_deref(),
+            Hook::PostExtend => self.post_extend.as_deref(),
+            Hook::PostDeactivate => self.post_deactivate.as_deref(),
+        }
+    }
+
+    /// Run the command for the hook, if one is configured
+    ///
+    /// # Errors
+    /// Will return `Err` if the command cannot be launched or fails
+    pub(crate) fn run(
+        &self,
+        hook: Hook,
+        target: &HookTarget,
+        duration: Option<Duration>,
+        result: Option<&ActivationResult>,
+    ) -> Result<()> {
+        let Some(cmd) = self.command(hook) else {
+            return Ok(());
+        };
+        let name = hook.name();
+        debug!("running {name} hook for {}", target.friendly());
+
+        let mut command = Command::new(SHELL.first().copied().unwrap_or_default());
+        command
+            .args(SHELL.get(1..).unwrap_or_default())
+            .arg(cmd)
+            .env("AZ_PIM_HOOK", name)
+            .env("AZ_PIM_KIND", target.kind)
+            .env("AZ_PIM_ROLE", target.role)
+            .env("AZ_PIM_SCOPE", target.scope);
+        if let Some(scope_name) = target.scope_name {
+            command.env("AZ_PIM_SCOPE_NAME", scope_name);
+        }
+        if let Some(duration) = duration {
+            command
+                .env("AZ_PIM_DURATION", format_duration(duration)?)
+                .env("AZ_PIM_DURATION_SECONDS", duration.as_secs().to_string());
+        }
+        if let Some(result) = result {
+            command.env("AZ_PIM_RESULT", result.to_string());
+        }
+
+        if is_interactive() {
+            let status = command
+                .status()
+                .with_context(|| format!("unable to run the {name} hook"))?;
+            ensure!(status.success(), "the {name} hook failed: {status}");
+            return Ok(());
+        }
+
+        // hooks must not wait on input that can never be provided, nor write
+        // to a terminal that may be owned by the dashboard
+        let output = command
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| format!("unable to run the {name} hook"))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        debug!("{name} hook output: {stdout}{stderr}");
+        ensure!(
+            output.status.success(),
+            "the {name} hook failed: {}: {}",
+            output.status,
+            stderr.trim()
+        );
+        Ok(())
+    }
+
+    /// Run the command for a hook that runs after a request, where failures
+    /// are only logged as the request has already been made
+    pub(crate) fn run_after(
+        &self,
+        hook: Hook,
+        target: &HookTarget,
+        duration: Option<Duration>,
+        result: &ActivationResult,
+    ) {
+        if let Err(err) = self.run(hook, target, duration, Some(result)) {
+            warn!("{err:#}");
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::{Hook, HookTarget, HooksConfig};
+    use crate::models::{
+        roles::{Role, RoleAssignment},
+        scope::Scope,
+    };
+    use anyhow::Result;
+    use std::time::Duration;
+
+    #[test]
+    fn test_run() -> Result<()> {
+        let assignment = RoleAssignment {
+            role: Role("Reader".to_string()),
+            scope: Scope::new("/subscriptions/00000000-0000-0000-0000-000000000000")?,
+            scope_name: None,
+            role_definition_id: String::new(),
+            principal_id: None,
+            principal_type: None,
+            object: None,
+            end_date_time: None,
+        };
+        let hooks = HooksConfig {
+            pre_activate: Some(
+                r#"test "$AZ_PIM_HOOK/$AZ_PIM_ROLE/$AZ_PIM_DURATION_SECONDS" = "pre_activate/Reader/3600""#
+                    .to_string(),
+            ),
+            post_activate: Some("exit 1".to_string()),
+            post_extend: Some(r#"test "$AZ_PIM_HOOK/$AZ_PIM_KIND" = "post_extend/role""#.to_string()),
+            post_deactivate: None,
+        };
+        let target = HookTarget::from(&assignment);
+        let hour = Some(Duration::from_secs(60 * 60));
+        hooks.run(Hook::PreActivate, &target, hour, None)?;
+        assert!(hooks.run(Hook::PostActivate, &target, hour, None).is_err());
+        hooks.run(Hook::PostExtend, &target, hour, None)?;
+        hooks.run(Hook::PostDeactivate, &target, None, None)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+use crate::{
+    backup::{assignment_key, RestoreStatus},
+    graph::Object,
+    models::{roles::Role, scope::Scope},
+    parse_duration, PimClient,
+};
+use anyhow::{bail, ensure, Context, Result};
+use humantime::parse_rfc3339_weak;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::SystemTime,
+};
+use tracing::warn;
+
+const COLUMNS: &[&str] = &["principal", "role", "scope", "expiration"];
+
+/// A row of an eligible assignment import file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportEntry {
+    /// The line of the file the entry was read from
+    pub line: usize,
+    /// Object ID or user principal name of the principal
+    pub principal: String,
+    pub role: Role,
+    pub scope: Scope,
+    /// When the eligibility expires, or `None` if it is permanent
+    pub expiration: Option<SystemTime>,
+}
+
+impl ImportEntry {
+    /// Parse a CSV file with the columns `principal`, `role`, `scope`, and
+    /// optionally `expiration`
+    ///
+    /// The first line is a header naming the columns, which may be in any
+    /// order.  Expirations are either a duration from now, such as `90d`, or a
+    /// timestamp, such as `2025-01-01T00:00:00Z`.  An empty expiration makes
+    /// the principal eligible permanently.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file is not valid CSV, is missing a required
+    /// column, or contains an invalid scope or expiration
+    pub fn parse_csv(data: &str) -> Result<Vec<Self>> {
+        let now = SystemTime::now();
+        let mut lines = data
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        let Some((_, header)) = lines.next() else {
+            bail!("missing header");
+        };
+        let header = split_csv_line(header)
+            .context("unable to parse header")?
+            .into_iter()
+            .map(|x| x.to_lowercase())
+            .collect::<Vec<_>>();
+        for name in &header {
+            ensure!(COLUMNS.contains(&name.as_str()), "unknown column {name:?}");
+        }
+        let index = |name: &str| header.iter().position(|x| x == name);
+        let (Some(principal), Some(role), Some(scope)) =
+            (index("principal"), index("role"), index("scope"))
+        else {
+            bail!("the header must include the columns principal, role, and scope");
+        };
+        let expiration = index("expiration");
+
+        let mut entries = Vec::new();
+        for (line, text) in lines {
+            let fields = split_csv_line(text).with_context(|| format!("line {line}"))?;
+            ensure!(
+                fields.len() == header.len(),
+                "line {line}: expected {} fields, found {}",
+                header.len(),
+                fields.len()
+            );
+            let field = |i: usize| fields.get(i).map(String::as_str).unwrap_or_default();
+            let scope = Scope::new(field(scope)).with_context(|| format!("line {line}"))?;
+            let expiration = expiration
+                .map(field)
+                .filter(|x| !x.is_empty())
+                .map(|x| parse_expiration(x, now))
+                .transpose()
+                .with_context(|| format!("line {line}"))?;
+            entries.push(Self {
+                line,
+                principal: field(principal).to_string(),
+                role: Role(field(role).to_string()),
+                scope,
+                expiration,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+/// Parse an expiration, either a duration from `now` or a timestamp
+fn parse_expiration(value: &str, now: SystemTime) -> Result<SystemTime> {
+    if let Ok(timestamp) = parse_rfc3339_weak(value) {
+        return Ok(timestamp);
+    }
+    let duration =
+        parse_duration(value).with_context(|| format!("invalid expiration {value:?}"))?;
+    now.checked_add(duration)
+        .with_context(|| format!("expiration is too far in the future: {value:?}"))
+}
+
+/// Split a line of CSV into its fields
+///
+/// Fields may be quoted, with quotes within quoted fields escaped by doubling
+/// them.  Unquoted fields are trimmed.
+fn split_csv_line(line: &str) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|x| *x == ' ' || *x == '\t').is_some() {}
+        let mut field = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => bail!("unterminated quoted field"),
+                }
+            }
+            while chars.next_if(|x| *x == ' ' || *x == '\t').is_some() {}
+            ensure!(
+                matches!(chars.peek(), None | Some(',')),
+                "unexpected characters after quoted field"
+            );
+        } else {
+            while let Some(c) = chars.next_if(|x| *x != ',') {
+                field.push(c);
+            }
+            field = field.trim().to_string();
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+/// The outcome of importing a single row
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportResult {
+    pub line: usize,
+    pub principal: String,
+    pub role: Role,
+    pub scope: Scope,
+    #[serde(flatten)]
+    pub status: RestoreStatus,
+}
+
+/// Make each of the principals eligible for their roles
+///
+/// Principals are resolved using Graph, and each row's role is validated
+/// against the role definitions at its scope before it is submitted.  Rows that
+/// are already eligible or whose expiration has passed are skipped.  If
+/// `dry_run` is set, nothing is created.
+///
+/// Errors for individual rows are included in the results, rather than
+/// stopping the import.
+pub fn import_eligible(
+    client: &PimClient,
+    entries: &[ImportEntry],
+    justification: Option<&str>,
+    dry_run: bool,
+) -> Vec<ImportResult> {
+    let now = SystemTime::now();
+    let mut principals = BTreeMap::<String, Result<Object, String>>::new();
+    let mut roles = BTreeMap::<Scope, Result<BTreeSet<String>, String>>::new();
+    let mut existing = BTreeMap::<Scope, Result<BTreeSet<_>, String>>::new();
+
+    let mut results = Vec::new();
+    for entry in entries {
+        let principal = principals
+            .entry(entry.principal.to_lowercase())
+            .or_insert_with(|| {
+                client
+                    .resolve_principal(&entry.principal)
+                    .map_err(|err| format!("{err:#}"))
+            });
+        let scope_roles = roles.entry(entry.scope.clone()).or_insert_with(|| {
+            client
+                .role_definitions(&entry.scope)
+                .map(|x| {
+                    x.into_iter()
+                        .map(|x| x.properties.role_name.to_lowercase())
+                        .collect()
+                })
+                .map_err(|err| format!("{err:#}"))
+        });
+        let scope_existing = existing.entry(entry.scope.clone()).or_insert_with(|| {
+            client
+                .list_eligible_role_assignments(Some(entry.scope.clone()), None)
+                .map(|x| {
+                    x.iter()
+                        .filter_map(|x| {
+                            let principal_id = x.principal_id.as_deref()?;
+                            Some(assignment_key(&x.scope, principal_id, &x.role))
+                        })
+                        .collect()
+                })
+                .map_err(|err| format!("{err:#}"))
+        });
+        let duration = entry
+            .expiration
+            .map(|x| x.duration_since(now).unwrap_or_default());
+
+        let status = match (principal, scope_roles, scope_existing) {
+            (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => {
+                RestoreStatus::Failed(err.clone())
+            }
+            (Ok(_), Ok(scope_roles), _) if !scope_roles.contains(&entry.role.0.to_lowercase()) => {
+                RestoreStatus::Failed(format!("role {} not found at {}", entry.role, entry.scope))
+            }
+            (Ok(principal), _, Ok(scope_existing))
+                if scope_existing.contains(&assignment_key(
+                    &entry.scope,
+                    &principal.id,
+                    &entry.role,
+                )) =>
+            {
+                RestoreStatus::AlreadyExists
+            }
+            _ if duration.is_some_and(|x| x.is_zero()) => RestoreStatus::Expired,
+            _ if dry_run => RestoreStatus::WouldCreate,
+            (Ok(principal), _, _) => client
+                .create_eligible_role_assignment(
+                    &entry.scope,
+                    &entry.role,
+                    &principal.id,
+                    justification,
+                    duration,
+                )
+                .map_or_else(
+                    |err| RestoreStatus::Failed(format!("{err:#}")),
+                    |()| RestoreStatus::Created,
+                ),
+        };
+        if let RestoreStatus::Failed(reason) = &status {
+            warn!(
+                "line {}: unable to make {} eligible for {} at {}: {reason}",
+                entry.line, entry.principal, entry.role, entry.scope
+            );
+        }
+        results.push(ImportResult {
+            line: entry.line,
+            principal: entry.principal.clone(),
+            role: entry.role.clone(),
+            scope: entry.scope.clone(),
+            status,
+        });
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_csv_line, ImportEntry};
+    use anyhow::Result;
+
+    #[test]
+    fn test_split_csv_line() -> Result<()> {
+        assert_eq!(
+            split_csv_line(r#"user@contoso.com, "Storage ""Blob"" Reader" ,/subscriptions/x,"#)?,
+            [
+                "user@contoso.com",
+                "Storage \"Blob\" Reader",
+                "/subscriptions/x",
+                ""
+            ]
+        );
+        assert!(split_csv_line(r#""unterminated"#).is_err());
+        assert!(split_csv_line(r#""quoted" trailing"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_csv() -> Result<()> {
+        let data = "scope,Principal,role,expiration\n\
+            /subscriptions/00000000-0000-0000-0000-000000000000,user@contoso.com,Reader,\n\
+            \n\
+            /subscriptions/00000000-0000-0000-0000-000000000000,00000000-0000-0000-0000-000000000001,\"Storage Blob Data Reader\",2030-01-01T00:00:00Z\n";
+        let entries = ImportEntry::parse_csv(data)?;
+        assert_eq!(entries.len(), 2);
+        let (Some(first), Some(second)) = (entries.first(), entries.get(1)) else {
+            anyhow::bail!("missing entries");
+        };
+        assert_eq!(first.line, 2);
+        assert_eq!(first.principal, "user@contoso.com");
+        assert_eq!(first.role.0, "Reader");
+        assert_eq!(first.expiration, None);
+        assert_eq!(second.line, 4);
+        assert_eq!(second.role.0, "Storage Blob Data Reader");
+        assert!(second.expiration.is_some());
+
+        assert!(ImportEntry::parse_csv("principal,role\nuser,Reader").is_err());
+        assert!(ImportEntry::parse_csv("principal,role,scope\nuser,Reader").is_err());
+        assert!(ImportEntry::parse_csv("principal,role,scope\nuser,Reader,not-a-scope").is_err());
+        assert!(ImportEntry::parse_csv(
+            "principal,role,scope,expiration\nuser,Reader,/subscriptions/x,soon"
+        )
+        .is_err());
+        Ok(())
+    }
+}
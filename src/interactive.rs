@@ -1,10 +1,13 @@
-use crate::models::roles::RoleAssignment;
+use crate::{
+    dashboard::format_expiry, ensure_interactive, models::roles::RoleAssignment, state::SavedRole,
+    theme::theme,
+};
 use anyhow::Result;
 use ratatui::{
     crossterm::{
         event::{
             self, Event,
-            KeyCode::{BackTab, Backspace, Char, Down, Enter, Esc, Tab, Up},
+            KeyCode::{BackTab, Backspace, Char, Down, Enter, Esc, Tab, Up, F},
             KeyEventKind,
         },
         execute,
@@ -12,7 +15,8 @@ use ratatui::{
     },
     prelude::*,
     widgets::{
-        Block, BorderType, HighlightSpacing, Paragraph, Row, ScrollbarState, Table, TableState,
+        BorderType, Cell, Clear, HighlightSpacing, Paragraph, Row, ScrollbarState, Table,
+        TableState, Wrap,
     },
 };
 use std::{collections::BTreeSet, io::stdout};
@@ -20,39 +24,172 @@ use std::{collections::BTreeSet, io::stdout};
 const ENABLED: &str = " ✓ ";
 const DISABLED: &str = " ☐ ";
 const TITLE_TEXT: &str = "Activate Azure PIM roles";
-const JUSTIFICATION_TEXT: &str = "Type to enter justification";
-const SCOPE_TEXT: &str = "↑ or ↓ to move | Space to toggle";
-const DURATION_TEXT: &str = "↑ or ↓ to update duration";
-const ALL_HELP: &str = "Tab or Shift-Tab to change sections | Enter to activate | Esc to quit";
+const JUSTIFICATION_TEXT: &str = "Type to enter justification | F1 for help";
+const SCOPE_TEXT: &str = "Space to toggle | / to filter | ? for help";
+const FILTER_TEXT: &str = "Type to filter roles | Enter to keep filter | F1 for help";
+const DURATION_TEXT: &str = "↑ or ↓ to update duration | ? for help";
+const ALL_HELP: &str = "Enter to activate | Esc to quit";
+const HELP_TITLE: &str = "Keys (any key to close)";
+const CONFIRM_TITLE: &str = "Confirm activation";
+const CONFIRM_TEXT: &str = "y to submit | n or Esc to go back";
+// the keys for each section, shown in the help overlay
+const HELP_KEYS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Anywhere",
+        &[
+            ("Tab / Shift-Tab", "change sections"),
+            ("Enter", "review and activate the selected roles"),
+            ("Esc", "quit without activating"),
+            ("? / F1", "show this help, using F1 while typing"),
+        ],
+    ),
+    (
+        "Scopes",
+        &[
+            ("↑ / ↓", "move between roles"),
+            ("Space", "toggle the current role"),
+            ("a", "select all visible roles"),
+            ("n", "select none"),
+            ("i", "invert the selection"),
+            ("/", "filter roles"),
+            ("r", "reset selections, justification, and duration"),
+        ],
+    ),
+    (
+        "Filter",
+        &[("Enter", "keep the filter"), ("Esc", "clear the filter")],
+    ),
+    (
+        "Duration",
+        &[
+            ("↑ / ↓", "update the duration by one minute"),
+            ("m", "toggle the maximum allowed by policy"),
+        ],
+    ),
+];
 const ITEM_HEIGHT: u16 = 2;
+const HELP_WIDTH: u16 = 66;
+const CONFIRM_WIDTH: u16 = 100;
+
+/// Look up which of the selected roles require approval to activate
+pub type ApprovalCheck<'a> =
+    &'a dyn Fn(&BTreeSet<RoleAssignment>) -> Result<BTreeSet<RoleAssignment>>;
+const EXPIRY_WIDTH: u16 = 20;
 
 pub struct Selected {
     pub assignments: BTreeSet<RoleAssignment>,
     pub justification: String,
     pub duration: u64,
+    /// Use the maximum duration allowed by each role's policy, rather than
+    /// `duration`
+    pub max_duration: bool,
+}
+
+/// Selections remembered from a previous session
+///
+/// `justification` and `duration` are only set when they should replace the
+/// values provided on the command line.
+#[derive(Default)]
+pub struct Remembered {
+    pub roles: BTreeSet<SavedRole>,
+    pub justification: Option<String>,
+    pub duration: Option<u64>,
 }
 
 struct Entry {
     value: RoleAssignment,
     enabled: bool,
+    /// The lowercase role, scope, and scope name, used for filtering
+    search: String,
+    /// The role and scope cells, which are built when first shown and
+    /// rebuilt when the filter changes
+    cells: Option<(Line<'static>, Text<'static>)>,
+}
+
+impl Entry {
+    fn new(value: RoleAssignment, enabled: bool) -> Self {
+        let search = [
+            value.role.0.as_str(),
+            value.scope.0.as_str(),
+            value.scope_name.as_deref().unwrap_or_default(),
+        ]
+        .join("\n")
+        .to_ascii_lowercase();
+        Self {
+            value,
+            enabled,
+            search,
+            cells: None,
+        }
+    }
+
+    fn cells(&mut self, filter: &str) -> (Line<'static>, Text<'static>) {
+        self.cells
+            .get_or_insert_with(|| {
+                let role = highlight(&self.value.role.0, filter);
+                let mut scope = Text::default();
+                if let Some(scope_name) = self.value.scope_name.as_deref() {
+                    scope.push_line(highlight(scope_name, filter));
+                }
+                scope.push_line(highlight(&self.value.scope.0, filter));
+                (role, scope)
+            })
+            .clone()
+    }
+}
+
+/// The summary of the selections shown before activating
+struct Confirmation {
+    assignments: BTreeSet<RoleAssignment>,
+    warnings: Vec<String>,
+}
+
+impl Confirmation {
+    fn new(assignments: BTreeSet<RoleAssignment>, approval_check: ApprovalCheck) -> Self {
+        let warnings = match approval_check(&assignments) {
+            Ok(approval) => approval
+                .iter()
+                .map(|x| format!("{} requires approval before it is active", x.friendly()))
+                .collect(),
+            Err(err) => vec![format!(
+                "unable to check which roles require approval: {err:#}"
+            )],
+        };
+        Self {
+            assignments,
+            warnings,
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum InputState {
     Duration,
+    Filter,
     Justification,
     Scopes,
 }
 
 struct App {
     duration: Option<u64>,
+    max_duration: bool,
     input_state: InputState,
     table_state: TableState,
+    /// Indexes of the items that match the current filter
+    visible: Vec<usize>,
+    /// Index within `visible` of the first row shown
+    offset: usize,
     justification: Option<String>,
+    filter: String,
     items: Vec<Entry>,
     longest_item_lens: (u16, u16),
     scroll_state: ScrollbarState,
     warnings: Vec<String>,
+    initial_justification: Option<String>,
+    initial_duration: Option<u64>,
+    initial_max_duration: bool,
+    show_help: bool,
+    confirmation: Option<Confirmation>,
 }
 
 impl App {
@@ -60,9 +197,15 @@ impl App {
         assignments: BTreeSet<RoleAssignment>,
         justification: Option<String>,
         duration: Option<u64>,
+        max_duration: bool,
+        remembered: Remembered,
     ) -> Result<Self> {
-        Ok(Self {
+        let mut app = Self {
+            initial_justification: justification.clone(),
+            initial_duration: duration,
+            initial_max_duration: max_duration,
             duration,
+            max_duration,
             input_state: if justification.is_none() {
                 InputState::Scopes
             } else {
@@ -70,31 +213,120 @@ impl App {
             },
             table_state: TableState::default().with_selected(0),
             justification,
+            filter: String::new(),
             longest_item_lens: column_widths(&assignments)?,
             scroll_state: ScrollbarState::new((assignments.len() - 1) * usize::from(ITEM_HEIGHT)),
             items: assignments
                 .into_iter()
-                .map(|value| Entry {
-                    value,
-                    enabled: false,
+                .map(|value| {
+                    let enabled = remembered.roles.iter().any(|x| x.matches(&value));
+                    Entry::new(value, enabled)
                 })
                 .collect(),
+            visible: Vec::new(),
+            offset: 0,
             warnings: Vec::new(),
-        })
+            show_help: false,
+            confirmation: None,
+        };
+
+        if app.justification.is_some() {
+            if let Some(justification) = remembered.justification {
+                app.justification = Some(justification);
+            }
+        }
+        if app.duration.is_some() {
+            if let Some(duration) = remembered.duration {
+                app.duration = Some(duration);
+            }
+        }
+
+        app.visible = app.matching();
+        Ok(app)
+    }
+
+    /// Clear the selections and restore the justification and duration
+    /// provided on the command line
+    fn reset(&mut self) {
+        for entry in &mut self.items {
+            entry.enabled = false;
+        }
+        self.justification.clone_from(&self.initial_justification);
+        self.duration = self.initial_duration;
+        self.max_duration = self.initial_max_duration;
+    }
+
+    /// Indexes of the items that match the current filter
+    fn matching(&self) -> Vec<usize> {
+        let filter = self.filter.to_ascii_lowercase();
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.search.contains(&filter))
+            .map(|(i, _)| i)
+            .collect()
     }
 
     fn toggle_current(&mut self) {
-        if let Some(i) = self.table_state.selected() {
-            if let Some(item) = self.items.get_mut(i) {
+        if let Some(i) = self
+            .table_state
+            .selected()
+            .and_then(|i| self.visible.get(i))
+        {
+            if let Some(item) = self.items.get_mut(*i) {
                 item.enabled = !item.enabled;
             }
         }
     }
 
+    /// Select all of the visible items
+    fn select_all(&mut self) {
+        for i in &self.visible {
+            if let Some(item) = self.items.get_mut(*i) {
+                item.enabled = true;
+            }
+        }
+    }
+
+    /// Clear all selections, including items hidden by the filter
+    fn select_none(&mut self) {
+        for item in &mut self.items {
+            item.enabled = false;
+        }
+    }
+
+    /// Invert the selection of the visible items
+    fn invert_selection(&mut self) {
+        for i in &self.visible {
+            if let Some(item) = self.items.get_mut(*i) {
+                item.enabled = !item.enabled;
+            }
+        }
+    }
+
+    fn update_filter(&mut self) {
+        self.visible = self.matching();
+        for entry in &mut self.items {
+            entry.cells = None;
+        }
+        self.offset = 0;
+        let selected = if self.visible.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        self.table_state.select(selected);
+        self.scroll_state = self.scroll_state.position(0);
+    }
+
     pub fn next(&mut self) {
+        let count = self.visible.len();
+        if count == 0 {
+            return;
+        }
         let i = match self.table_state.selected() {
             Some(i) => {
-                if i >= self.items.len() - 1 {
+                if i >= count - 1 {
                     0
                 } else {
                     i + 1
@@ -107,10 +339,14 @@ impl App {
     }
 
     pub fn previous(&mut self) {
+        let count = self.visible.len();
+        if count == 0 {
+            return;
+        }
         let i = match self.table_state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.items.len() - 1
+                    count - 1
                 } else {
                     i - 1
                 }
@@ -144,6 +380,11 @@ impl App {
             sections.push(Constraint::Length(3));
         }
 
+        // filter
+        if self.show_filter() {
+            sections.push(Constraint::Length(3));
+        }
+
         // roles
         sections.push(Constraint::Min(5));
 
@@ -153,7 +394,7 @@ impl App {
         }
 
         // footer
-        sections.push(Constraint::Length(4));
+        sections.push(Constraint::Length(3));
 
         if !self.warnings.is_empty() {
             sections.push(Constraint::Length(
@@ -178,6 +419,13 @@ impl App {
             self.render_justification(f, *justification);
         }
 
+        if self.show_filter() {
+            let Some(filter) = rects.next() else {
+                return;
+            };
+            self.render_filter(f, *filter);
+        }
+
         let Some(scopes) = rects.next() else {
             return;
         };
@@ -201,14 +449,131 @@ impl App {
             };
             self.render_warnings(f, *warnings);
         }
+
+        if let Some(confirmation) = &self.confirmation {
+            self.render_confirmation(f, confirmation);
+        } else if self.show_help {
+            Self::render_help(f);
+        }
+    }
+
+    /// Render the summary of what will be activated as a popup over the rest
+    /// of the UI
+    fn render_confirmation(&self, frame: &mut Frame, confirmation: &Confirmation) {
+        let mut lines = vec![Line::from(format!(
+            "Activate {} role(s):",
+            confirmation.assignments.len()
+        ))];
+        lines.extend(
+            confirmation
+                .assignments
+                .iter()
+                .map(|x| Line::from(format!("  {}", x.friendly()))),
+        );
+        lines.push(Line::default());
+        lines.push(Line::from(if self.max_duration {
+            "Duration: maximum allowed by policy".to_string()
+        } else {
+            format!("Duration: {} minutes", self.duration.unwrap_or_default())
+        }));
+        lines.push(Line::from(format!(
+            "Justification: {}",
+            self.justification.as_deref().unwrap_or_default()
+        )));
+        if !confirmation.warnings.is_empty() {
+            lines.push(Line::default());
+            lines.extend(
+                confirmation
+                    .warnings
+                    .iter()
+                    .map(|x| Line::from(x.as_str()).style(theme().warning)),
+            );
+        }
+        lines.push(Line::default());
+        lines.push(Line::from(CONFIRM_TEXT).centered());
+
+        let height = u16::try_from(lines.len())
+            .unwrap_or(u16::MAX)
+            .saturating_add(2);
+        let [area] = Layout::vertical([Constraint::Length(height)])
+            .flex(layout::Flex::Center)
+            .areas(frame.area());
+        let [area] = Layout::horizontal([Constraint::Length(CONFIRM_WIDTH)])
+            .flex(layout::Flex::Center)
+            .areas(area);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+                theme()
+                    .block()
+                    .title(CONFIRM_TITLE)
+                    .border_type(BorderType::Double),
+            ),
+            area,
+        );
+    }
+
+    /// Render the list of keys as a popup over the rest of the UI
+    fn render_help(frame: &mut Frame) {
+        let mut rows = Vec::new();
+        for (section, keys) in HELP_KEYS {
+            if !rows.is_empty() {
+                rows.push(Row::new([""]));
+            }
+            rows.push(
+                Row::new([Cell::from(*section)])
+                    .style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED)),
+            );
+            rows.extend(
+                keys.iter()
+                    .map(|(key, description)| Row::new([*key, *description])),
+            );
+        }
+
+        let height = u16::try_from(rows.len())
+            .unwrap_or(u16::MAX)
+            .saturating_add(2);
+        let [area] = Layout::vertical([Constraint::Length(height)])
+            .flex(layout::Flex::Center)
+            .areas(frame.area());
+        let [area] = Layout::horizontal([Constraint::Length(HELP_WIDTH)])
+            .flex(layout::Flex::Center)
+            .areas(area);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Table::new(rows, [Constraint::Length(17), Constraint::Fill(1)]).block(
+                theme()
+                    .block()
+                    .title(HELP_TITLE)
+                    .border_type(BorderType::Double),
+            ),
+            area,
+        );
+    }
+
+    fn show_filter(&self) -> bool {
+        self.input_state == InputState::Filter || !self.filter.is_empty()
+    }
+
+    fn render_filter(&self, frame: &mut Frame, area: Rect) {
+        frame.render_widget(
+            Paragraph::new(format!("/{}", self.filter)).block(theme().block().title("Filter")),
+            area,
+        );
+        if self.input_state == InputState::Filter {
+            #[allow(clippy::cast_possible_truncation)]
+            frame.set_cursor_position((area.x + self.filter.len() as u16 + 2, area.y + 1));
+        }
     }
 
     fn render_warnings(&self, frame: &mut Frame, area: Rect) {
         frame.render_widget(
             Paragraph::new(self.warnings.join("\n"))
-                .style(Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED))
+                .style(theme().warning)
                 .alignment(Alignment::Center)
-                .block(Block::bordered().title("Warnings!")),
+                .block(theme().block().title("Warnings!")),
             area,
         );
     }
@@ -223,15 +588,18 @@ impl App {
     }
 
     fn render_duration(&mut self, frame: &mut Frame, area: Rect) {
-        // Style::default().add_modifier(Modifier::REVERSED)
         frame.render_widget(
-            Paragraph::new(format!("{} minutes", self.duration.unwrap_or_default()))
-                .style(if self.input_state == InputState::Duration {
-                    Style::default().add_modifier(Modifier::REVERSED)
-                } else {
-                    Style::default()
-                })
-                .block(Block::bordered().title("Duration")),
+            Paragraph::new(if self.max_duration {
+                "maximum allowed by policy".to_string()
+            } else {
+                format!("{} minutes", self.duration.unwrap_or_default())
+            })
+            .style(if self.input_state == InputState::Duration {
+                theme().selection
+            } else {
+                Style::default()
+            })
+            .block(theme().block().title("Duration")),
             area,
         );
     }
@@ -239,7 +607,7 @@ impl App {
     fn render_justification(&mut self, frame: &mut Frame, area: Rect) {
         let justification = self.justification.clone().unwrap_or_default();
         frame.render_widget(
-            Paragraph::new(justification.clone()).block(Block::bordered().title("Justification")),
+            Paragraph::new(justification.clone()).block(theme().block().title("Justification")),
             area,
         );
         if self.input_state == InputState::Justification {
@@ -249,53 +617,85 @@ impl App {
     }
 
     fn render_scopes(&mut self, frame: &mut Frame, area: Rect) {
+        // roles being deactivated do not have a duration, show when they
+        // expire instead
+        let show_expiry = self.duration.is_none();
+
+        // only the rows that fit within the area are built, which keeps the
+        // UI responsive with thousands of roles
+        let capacity = usize::from(area.height.saturating_sub(3) / ITEM_HEIGHT).max(1);
+        let selected = self.table_state.selected();
+        if let Some(selected) = selected {
+            if selected < self.offset {
+                self.offset = selected;
+            } else if selected >= self.offset + capacity {
+                self.offset = selected + 1 - capacity;
+            }
+        }
+        let mut state = TableState::default().with_selected(selected.map(|x| x - self.offset));
+
+        let mut rows = Vec::new();
+        for i in self.visible.iter().skip(self.offset).take(capacity) {
+            let Some(entry) = self.items.get_mut(*i) else {
+                continue;
+            };
+            let (mut role, scope) = entry.cells(&self.filter);
+            role.spans.insert(
+                0,
+                Span::raw(format!(
+                    "{} ",
+                    if entry.enabled { ENABLED } else { DISABLED }
+                )),
+            );
+            let mut cells = vec![Cell::from(role), Cell::from(scope)];
+            if show_expiry {
+                cells.push(Cell::from(format_expiry(&entry.value)));
+            }
+            rows.push(Row::new(cells).height(ITEM_HEIGHT));
+        }
+
+        let mut widths = vec![
+            Constraint::Length(self.longest_item_lens.0 + 4),
+            Constraint::Min(self.longest_item_lens.1 + 1),
+        ];
+        let mut header = vec!["Role", "Scope"];
+        if show_expiry {
+            widths.push(Constraint::Length(EXPIRY_WIDTH));
+            header.push("Expires In");
+        }
+
         frame.render_stateful_widget(
-            Table::new(
-                self.items.iter().map(|data| {
-                    Row::new(vec![
-                        format!(
-                            "{} {}",
-                            if data.enabled { ENABLED } else { DISABLED },
-                            data.value.role
-                        ),
-                        if let Some(scope_name) = data.value.scope_name.as_deref() {
-                            format!("{scope_name}\n{}", data.value.scope)
-                        } else {
-                            data.value.scope.to_string()
-                        },
-                    ])
-                    .height(ITEM_HEIGHT)
-                }),
-                [
-                    Constraint::Length(self.longest_item_lens.0 + 4),
-                    Constraint::Min(self.longest_item_lens.1 + 1),
-                ],
-            )
-            .header(
-                ["Role", "Scope"]
-                    .into_iter()
-                    .collect::<Row>()
-                    .style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED))
-                    .height(1),
-            )
-            .row_highlight_style(if self.input_state == InputState::Scopes {
-                Style::default().add_modifier(Modifier::REVERSED)
-            } else {
-                Style::default()
-            })
-            .highlight_spacing(HighlightSpacing::Always)
-            .block(Block::bordered().title("Scopes")),
+            Table::new(rows, widths)
+                .header(
+                    header
+                        .into_iter()
+                        .collect::<Row>()
+                        .style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED))
+                        .height(1),
+                )
+                .row_highlight_style(if self.input_state == InputState::Scopes {
+                    theme().selection
+                } else {
+                    Style::default()
+                })
+                .highlight_spacing(HighlightSpacing::Always)
+                .block(theme().block().title(if self.filter.is_empty() {
+                    "Scopes".to_string()
+                } else {
+                    format!("Scopes ({} of {})", self.visible.len(), self.items.len())
+                })),
             area,
-            &mut self.table_state,
+            &mut state,
         );
     }
 
     fn render_footer(&self, f: &mut Frame, area: Rect) {
         f.render_widget(
             Paragraph::new(format!(
-                "{}\n{ALL_HELP}",
+                "{} | {ALL_HELP}",
                 match self.input_state {
                     InputState::Duration => DURATION_TEXT,
+                    InputState::Filter => FILTER_TEXT,
                     InputState::Justification => JUSTIFICATION_TEXT,
                     InputState::Scopes => SCOPE_TEXT,
                 }
@@ -303,24 +703,73 @@ impl App {
             .style(Style::new())
             .centered()
             .block(
-                Block::bordered()
+                theme()
+                    .block()
                     .title("Help")
-                    .border_type(BorderType::Double)
-                    .border_style(Style::new()),
+                    .border_type(BorderType::Double),
             ),
             area,
         );
     }
 
-    fn run<B: Backend>(mut self, terminal: &mut Terminal<B>) -> Result<Option<Selected>> {
+    /// Finish with the enabled items
+    fn selected(self) -> Selected {
+        let assignments = self
+            .items
+            .into_iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.value)
+            .collect();
+        Selected {
+            assignments,
+            justification: self.justification.unwrap_or_default(),
+            duration: self.duration.unwrap_or_default(),
+            max_duration: self.max_duration,
+        }
+    }
+
+    fn run<B: Backend>(
+        mut self,
+        terminal: &mut Terminal<B>,
+        approval_check: Option<ApprovalCheck>,
+    ) -> Result<Option<Selected>> {
         self.check();
         loop {
             terminal.draw(|f| self.draw(f))?;
 
             if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
+                if key.kind == KeyEventKind::Press && self.confirmation.is_some() {
+                    match key.code {
+                        Char('y' | 'Y') => return Ok(Some(self.selected())),
+                        Char('n' | 'N') | Esc => self.confirmation = None,
+                        _ => {}
+                    }
+                } else if key.kind == KeyEventKind::Press && self.show_help {
+                    self.show_help = false;
+                } else if key.kind == KeyEventKind::Press {
                     match (self.input_state, key.code) {
-                        (InputState::Justification, Tab) | (InputState::Duration, BackTab) => {
+                        (_, F(1)) | (InputState::Scopes | InputState::Duration, Char('?')) => {
+                            self.show_help = true;
+                        }
+                        (InputState::Filter, Char(c)) => {
+                            self.filter.push(c);
+                            self.update_filter();
+                        }
+                        (InputState::Filter, Backspace) => {
+                            self.filter.pop();
+                            self.update_filter();
+                        }
+                        (InputState::Filter, Esc) => {
+                            self.filter.clear();
+                            self.update_filter();
+                            self.input_state = InputState::Scopes;
+                        }
+                        (InputState::Scopes, Char('/')) => {
+                            self.input_state = InputState::Filter;
+                        }
+                        (InputState::Filter, Enter | Tab | BackTab | Up | Down)
+                        | (InputState::Justification, Tab)
+                        | (InputState::Duration, BackTab) => {
                             self.input_state = InputState::Scopes;
                         }
                         (InputState::Scopes, Tab) | (InputState::Justification, BackTab) => {
@@ -340,27 +789,36 @@ impl App {
                             }
                         }
                         (InputState::Duration, Down) => {
+                            self.max_duration = false;
                             self.duration = self.duration.map(|x| x.saturating_sub(1).max(1));
                         }
                         (InputState::Duration, Up) => {
+                            self.max_duration = false;
                             self.duration = self.duration.map(|x| x.saturating_add(1).min(480));
                         }
+                        (InputState::Duration, Char('m')) => {
+                            self.max_duration = !self.max_duration;
+                        }
                         (InputState::Scopes, Char(' ')) => self.toggle_current(),
+                        (InputState::Scopes, Char('a')) => self.select_all(),
+                        (InputState::Scopes, Char('n')) => self.select_none(),
+                        (InputState::Scopes, Char('i')) => self.invert_selection(),
+                        (InputState::Scopes, Char('r')) => self.reset(),
                         (InputState::Scopes, Down) => self.next(),
                         (InputState::Scopes, Up) => self.previous(),
                         (_, Esc) => return Ok(None),
                         (_, Enter) if self.warnings.is_empty() => {
+                            let Some(approval_check) = approval_check else {
+                                return Ok(Some(self.selected()));
+                            };
                             let assignments = self
                                 .items
-                                .into_iter()
+                                .iter()
                                 .filter(|entry| entry.enabled)
-                                .map(|entry| entry.value)
+                                .map(|entry| entry.value.clone())
                                 .collect();
-                            return Ok(Some(Selected {
-                                assignments,
-                                justification: self.justification.unwrap_or_default(),
-                                duration: self.duration.unwrap_or_default(),
-                            }));
+                            self.confirmation =
+                                Some(Confirmation::new(assignments, approval_check));
                         }
                         _ => {}
                     }
@@ -371,11 +829,20 @@ impl App {
     }
 }
 
+/// Select roles using the interactive UI
+///
+/// If `approval_check` is provided, a summary of the selections, including
+/// which roles require approval, must be confirmed before returning.
 pub fn interactive_ui(
     items: BTreeSet<RoleAssignment>,
     justification: Option<String>,
     duration: Option<u64>,
+    max_duration: bool,
+    remembered: Remembered,
+    approval_check: Option<ApprovalCheck>,
 ) -> Result<Option<Selected>> {
+    ensure_interactive("the interactive UI")?;
+
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = stdout();
@@ -384,8 +851,8 @@ pub fn interactive_ui(
     let mut terminal = Terminal::new(backend)?;
 
     // create app and run it
-    let app = App::new(items, justification, duration)?;
-    let res = app.run(&mut terminal);
+    let app = App::new(items, justification, duration, max_duration, remembered)?;
+    let res = app.run(&mut terminal, approval_check);
 
     // restore terminal
     disable_raw_mode()?;
@@ -395,6 +862,36 @@ pub fn interactive_ui(
     res
 }
 
+/// Highlight the case-insensitive matches of `filter` within `text`
+fn highlight(text: &str, filter: &str) -> Line<'static> {
+    if filter.is_empty() {
+        return Line::from(text.to_string());
+    }
+
+    // ASCII lowercasing keeps the byte offsets the same as the original text
+    let haystack = text.to_ascii_lowercase();
+    let needle = filter.to_ascii_lowercase();
+    let style = Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while let Some(offset) = haystack.get(start..).and_then(|x| x.find(&needle)) {
+        let begin = start + offset;
+        let end = begin + needle.len();
+        spans.push(Span::raw(
+            text.get(start..begin).unwrap_or_default().to_string(),
+        ));
+        spans.push(Span::styled(
+            text.get(begin..end).unwrap_or_default().to_string(),
+            style,
+        ));
+        start = end;
+    }
+    spans.push(Span::raw(text.get(start..).unwrap_or_default().to_string()));
+
+    Line::from(spans)
+}
+
 fn column_widths(items: &BTreeSet<RoleAssignment>) -> Result<(u16, u16)> {
     let (scope_name_len, role_len, scope_len) =
         items
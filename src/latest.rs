@@ -1,10 +1,14 @@
 use anyhow::{ensure, Context, Result};
 use home::home_dir;
 use reqwest::{blocking::Client, header::USER_AGENT};
+use schemars::JsonSchema;
 use semver::Version;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
     cmp::Ordering,
+    env::{current_exe, var_os},
+    fmt::{Display, Formatter, Result as FmtResult},
     fs::{create_dir_all, metadata, read, write},
     path::{Path, PathBuf},
     time::{Duration, SystemTime, UNIX_EPOCH},
@@ -15,8 +19,93 @@ use tracing::{debug, info, trace};
 const CACHE_EXPIRE: Duration = Duration::from_secs(60 * 60 * 24);
 const RELEASES_API_URL: &str = "https://api.github.com/repos/demoray/azure-pim-cli/releases/latest";
 const RELEASES_URL: &str = "https://github.com/demoray/azure-pim-cli/releases";
+const CRATES_API_URL: &str = "https://crates.io/api/v1/crates/azure-pim-cli";
 
-fn cache_path() -> Option<PathBuf> {
+/// Where `az-pim` was installed from, which determines where to check for
+/// new versions
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallChannel {
+    /// Binaries from the GitHub releases
+    Github,
+    /// `cargo install azure-pim-cli`
+    CratesIo,
+}
+
+impl InstallChannel {
+    /// Guess the install channel based on the location of the executable
+    ///
+    /// Executables within `$CARGO_HOME/bin` (`$HOME/.cargo/bin` by default)
+    /// were installed using `cargo install`.
+    #[must_use]
+    pub fn detect() -> Self {
+        let cargo_bin = var_os("CARGO_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|x| x.join(".cargo")))
+            .map(|x| x.join("bin"));
+        match (current_exe(), cargo_bin) {
+            (Ok(exe), Some(cargo_bin)) if exe.starts_with(&cargo_bin) => Self::CratesIo,
+            _ => Self::Github,
+        }
+    }
+
+    fn cache_file(self) -> &'static str {
+        match self {
+            Self::Github => "latest.version",
+            Self::CratesIo => "latest-crates-io.version",
+        }
+    }
+
+    fn fetch_latest(self) -> Result<Version> {
+        let url = match self {
+            Self::Github => RELEASES_API_URL,
+            Self::CratesIo => CRATES_API_URL,
+        };
+        let text = Client::new()
+            .get(url)
+            .header(USER_AGENT, "az-pim-cli")
+            .send()
+            .with_context(|| format!("unable to send request to {self}"))?
+            .text()
+            .with_context(|| format!("unable to receive response from {self}"))?;
+        trace!("response: {text:?}");
+        let response: Value =
+            serde_json::from_str(&text).context("unable to deserialize response")?;
+
+        let version = match self {
+            Self::Github => response
+                .get("tag_name")
+                .context("missing field tag_name")?
+                .as_str()
+                .context("tag_name is not a string")?,
+            Self::CratesIo => response
+                .get("crate")
+                .and_then(|x| x.get("max_stable_version"))
+                .context("missing field crate.max_stable_version")?
+                .as_str()
+                .context("max_stable_version is not a string")?,
+        };
+        Version::parse(version).context("unable to parse latest version")
+    }
+
+    fn upgrade_instructions(self) -> String {
+        match self {
+            Self::Github => format!("is available at {RELEASES_URL}"),
+            Self::CratesIo => "can be installed using `cargo install azure-pim-cli`".to_string(),
+        }
+    }
+}
+
+impl Display for InstallChannel {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Github => write!(f, "GitHub"),
+            Self::CratesIo => write!(f, "crates.io"),
+        }
+    }
+}
+
+pub(crate) fn cache_path() -> Option<PathBuf> {
     home_dir().map(|x| x.join(".cache").join("az-pim-cli"))
 }
 
@@ -33,19 +122,23 @@ fn read_cached_latest(path: &Path) -> Result<Version> {
     Version::parse(&as_str).context("unable to parse cache file")
 }
 
-/// Check if the cache file is older than 1 hour
+/// Check if a newer version of `az-pim` is available from the install
+/// channel, which is detected if not specified
+///
+/// The latest version is cached for a day.
 ///
 /// # Returns
 /// * Does not check if `$HOME` cannot be determined
 /// * Does not check if `$HOME/.cache/az-pim-cli` cannot be created
-pub fn check_latest_version() -> Result<()> {
+pub fn check_latest_version(channel: Option<InstallChannel>) -> Result<()> {
+    let channel = channel.unwrap_or_else(InstallChannel::detect);
     let current =
         Version::parse(env!("CARGO_PKG_VERSION")).context("unable to parse current version")?;
 
     let cache_path = cache_path().context("unable to determine cache path")?;
     create_dir_all(&cache_path).context("unable to create cache path")?;
 
-    let cache_file_path = cache_path.join("latest.version");
+    let cache_file_path = cache_path.join(channel.cache_file());
 
     match read_cached_latest(&cache_file_path) {
         Ok(cached_latest) => match cached_latest.cmp(&current) {
@@ -53,7 +146,10 @@ pub fn check_latest_version() -> Result<()> {
                 debug!("cached latest is older than current: {cached_latest} < {current}");
             }
             Ordering::Greater => {
-                info!("a new version of az-pim ({cached_latest}) is available at {RELEASES_URL} (using {current})");
+                info!(
+                    "a new version of az-pim ({cached_latest}) {} (using {current})",
+                    channel.upgrade_instructions()
+                );
                 return Ok(());
             }
             Ordering::Equal => {
@@ -66,32 +162,17 @@ pub fn check_latest_version() -> Result<()> {
         }
     }
 
-    let text = Client::new()
-        .get(RELEASES_API_URL)
-        .header(USER_AGENT, "az-pim-cli")
-        .send()
-        .context("unable to send request to GitHub")?
-        .text()
-        .context("unable to receive response from GitHub")?;
-    trace!("response: {text:?}");
-    let response: Value = serde_json::from_str(&text).context("unable to deserialize response")?;
-
-    let tag = response
-        .get("tag_name")
-        .context("missing field tag_name")?
-        .as_str()
-        .context("tag_name is not a string")?;
-
-    let latest = Version::parse(tag).context("unable to parse tag_name")?;
+    let latest = channel.fetch_latest()?;
     if latest > current {
         info!(
-            "a new version of az-pim ({latest}) is available at {RELEASES_URL} (using {current})"
+            "a new version of az-pim ({latest}) {} (using {current})",
+            channel.upgrade_instructions()
         );
     } else {
-        debug!("from live az-pim is up-to-date: {current} >= {latest}");
+        debug!("from {channel} az-pim is up-to-date: {current} >= {latest}");
     }
 
-    write(cache_file_path, tag).context("unable to write cache file")?;
+    write(cache_file_path, latest.to_string()).context("unable to write cache file")?;
 
     Ok(())
 }
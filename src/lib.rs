@@ -8,57 +8,160 @@
 )]
 #![allow(clippy::module_name_repetitions)]
 
+pub mod access;
 mod activate;
+pub mod audit_log;
 mod az_cli;
 mod backend;
+pub mod backup;
+mod capture;
+mod coalesce;
+pub mod config;
+pub mod dashboard;
+pub mod entra;
 mod expiring;
+mod fuzzy;
 pub mod graph;
+pub mod groups;
+mod hints;
+pub mod history;
+pub mod hooks;
+pub mod import;
 pub mod interactive;
 mod latest;
 pub mod models;
+mod pages;
+pub mod picker;
+pub mod policy;
+pub mod prompt;
+pub mod providers;
+#[cfg(feature = "python")]
+mod python;
+pub mod report;
+pub mod serve;
+pub mod state;
+pub mod theme;
+pub mod time;
+pub mod tree;
+pub mod watch;
 
-pub use crate::latest::check_latest_version;
 use crate::{
-    activate::check_error_response,
+    activate::{activation_status, approval_status, check_error_response, ApprovalStatus},
     backend::Backend,
+    capture::Capture,
+    coalesce::Coalesce,
     expiring::ExpiringMap,
-    graph::{get_objects_by_ids, group_members, Object, PrincipalType},
+    graph::{
+        get_objects_by_ids, group_members, resolve_principal, NestedMember, Object, PrincipalType,
+    },
+    groups::GroupMembership,
+    history::Action,
+    hooks::{Hook, HookTarget, HooksConfig},
     models::{
         assignments::{Assignment, Assignments},
-        definitions::{Definition, Definitions},
+        definitions::{Definition, Definitions, Properties as DefinitionProperties},
+        policies::RolePolicy,
+        requests::ScheduleRequest,
         resources::ChildResource,
-        roles::{RoleAssignment, RolesExt},
+        roles::{Role, RoleAssignment, RolesExt},
         scope::Scope,
     },
+    policy::{PolicyConfig, PolicyViolation},
+};
+pub use crate::{
+    az_cli::AuthMethod,
+    backend::RetryPolicy,
+    latest::{check_latest_version, InstallChannel},
+    pages::RoleAssignmentPages,
 };
 use anyhow::{bail, ensure, Context, Result};
 use backend::Operation;
 use clap::ValueEnum;
+use humantime::parse_rfc3339_weak;
 use parking_lot::Mutex;
 use rayon::{prelude::*, ThreadPoolBuilder};
+use regex::Regex;
 use reqwest::Method;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::BTreeSet,
-    fmt::{Display, Formatter, Result as FmtResult},
-    io::stdin,
-    sync::Once,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::{Display, Formatter, Result as FmtResult, Write as _},
+    io::{stdin, IsTerminal},
+    iter::once,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Once,
+    },
     thread::sleep,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 const WAIT_DELAY: Duration = Duration::from_secs(5);
+const APPROVAL_POLL_DELAY: Duration = Duration::from_secs(30);
+// how long listings of role assignments are reused within a command
+const LISTING_CACHE_TTL: Duration = Duration::from_secs(60);
 const RBAC_ADMIN_ROLES: &[&str] = &["Owner", "Role Based Access Control Administrator"];
 
-#[allow(clippy::large_enum_variant)]
+/// The ABAC condition version used when one is not specified
+pub const DEFAULT_CONDITION_VERSION: &str = "2.0";
+
+/// The outcome of activating or deactivating a single role
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", tag = "status", content = "reason")]
 pub enum ActivationResult {
-    Success,
-    Failed(RoleAssignment),
+    Succeeded,
+    AlreadyActive,
+    PendingApproval,
+    Failed(String),
+}
+
+/// An activation request that has been submitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivationRequest {
+    /// The name of the role assignment schedule request
+    pub name: String,
+    pub status: ActivationResult,
+}
+
+/// Per-role settings that override the defaults when activating a set of roles
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActivationOverrides {
+    pub duration: Option<Duration>,
+    pub justification: Option<String>,
+    pub ticket: Option<TicketInfo>,
+}
+
+/// Ticket information included with an activation, for roles whose policy
+/// requires it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TicketInfo {
+    pub number: String,
+    pub system: Option<String>,
+}
+
+impl ActivationResult {
+    #[must_use]
+    pub fn is_failed(&self) -> bool {
+        matches!(self, Self::Failed(_))
+    }
+}
+
+impl Display for ActivationResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Succeeded => write!(f, "succeeded"),
+            Self::AlreadyActive => write!(f, "already-active"),
+            Self::PendingApproval => write!(f, "pending-approval"),
+            Self::Failed(_) => write!(f, "failed"),
+        }
+    }
 }
 
 #[allow(clippy::manual_assert, clippy::panic)]
-#[derive(Clone, ValueEnum, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, ValueEnum, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum ListFilter {
     AtScope,
     AsTarget,
@@ -82,37 +185,268 @@ impl ListFilter {
     }
 }
 
+/// Client for the Azure PIM APIs
+///
+/// The requests are made using blocking HTTP calls, so the client can be used
+/// from synchronous code without an async runtime.  Concurrent requests use
+/// the `rayon` thread pool, which can be sized with
+/// [`PimClient::thread_builder`].
 pub struct PimClient {
     backend: Backend,
     object_cache: Mutex<ExpiringMap<String, Option<Object>>>,
     group_cache: Mutex<ExpiringMap<String, BTreeSet<Object>>>,
+    transitive_group_cache: Mutex<ExpiringMap<String, BTreeSet<Object>>>,
     role_definitions_cache: Mutex<ExpiringMap<Scope, Vec<Definition>>>,
+    role_definitions_in_flight: Coalesce<Scope, Vec<Definition>>,
+    group_in_flight: Coalesce<(String, bool), BTreeSet<Object>>,
+    listing_cache: Option<Mutex<ExpiringMap<ListingKey, Listing>>>,
+    policy: Option<PolicyConfig>,
+    dangerous_acknowledged: bool,
+    hooks: Option<HooksConfig>,
+    history: bool,
 }
 
+type ListingKey = (Operation, Option<Scope>, Option<ListFilter>);
+// a listing of role assignments, along with the backend write count when it
+// was requested
+type Listing = (u64, BTreeSet<RoleAssignment>);
+
 impl PimClient {
     pub fn new() -> Result<Self> {
-        let backend = Backend::new();
+        let backend = Backend::new()?;
         let object_cache = Mutex::new(ExpiringMap::new(Duration::from_secs(60 * 10)));
         let group_cache = Mutex::new(ExpiringMap::new(Duration::from_secs(60 * 10)));
+        let transitive_group_cache = Mutex::new(ExpiringMap::new(Duration::from_secs(60 * 10)));
         let role_definitions_cache = Mutex::new(ExpiringMap::new(Duration::from_secs(60 * 10)));
         Ok(Self {
             backend,
             object_cache,
             group_cache,
+            transitive_group_cache,
             role_definitions_cache,
+            role_definitions_in_flight: Coalesce::new(),
+            group_in_flight: Coalesce::new(),
+            listing_cache: Some(Mutex::new(ExpiringMap::new(LISTING_CACHE_TTL))),
+            policy: None,
+            dangerous_acknowledged: false,
+            hooks: None,
+            history: false,
         })
     }
 
+    /// Use the specified tenant, rather than the tenant of the Azure CLI's
+    /// default subscription
+    #[must_use]
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.backend.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Record the HTTP requests and responses to a HAR-like file
+    ///
+    /// Bearer tokens and principal ids are redacted from the capture.
+    #[must_use]
+    pub fn with_capture(mut self, path: impl Into<PathBuf>) -> Self {
+        self.backend.capture = Some(Arc::new(Capture::new(path.into())));
+        self
+    }
+
+    /// Set how requests that fail with transient errors are retried
+    #[must_use]
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.backend.retry_policy = retry_policy;
+        self
+    }
+
+    /// Append an application identifier, such as `my-tool/1.0`, to the
+    /// User-Agent of requests
+    ///
+    /// # Errors
+    /// Will return `Err` if the HTTP client cannot be created
+    pub fn with_user_agent(mut self, application: &str) -> Result<Self> {
+        self.backend.client = Backend::build_client(Some(application))?;
+        Ok(self)
+    }
+
+    /// Override the `X-Ms-Command-Name` header sent with ARM requests
+    #[must_use]
+    pub fn with_command_name(mut self, command_name: impl Into<String>) -> Self {
+        self.backend.command_name = command_name.into();
+        self
+    }
+
+    /// Set how tokens are obtained from the Azure CLI
+    #[must_use]
+    pub fn with_auth_method(mut self, auth_method: AuthMethod) -> Self {
+        self.backend.auth_method = auth_method;
+        self
+    }
+
+    /// Send ARM requests to the specified endpoint, rather than
+    /// `https://management.azure.com`
+    ///
+    /// This can also be set using the `AZ_PIM_ARM_URL` environment variable.
+    #[must_use]
+    pub fn with_arm_url(mut self, url: &str) -> Self {
+        self.backend.arm_url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Restrict activations using the policy
+    ///
+    /// Activating the policy's dangerous roles requires an interactive
+    /// confirmation, unless they have been acknowledged using
+    /// [`PimClient::with_dangerous_acknowledged`].
+    #[must_use]
+    pub fn with_policy(mut self, policy: PolicyConfig) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Activate the policy's dangerous roles without confirmation
+    #[must_use]
+    pub fn with_dangerous_acknowledged(mut self, acknowledged: bool) -> Self {
+        self.dangerous_acknowledged = acknowledged;
+        self
+    }
+
+    /// Run the commands from the hooks before and after changing roles
+    #[must_use]
+    pub fn with_hooks(mut self, hooks: HooksConfig) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Record the outcome of activating, extending, and deactivating roles in
+    /// the local history, as shown by `az-pim history`
+    ///
+    /// The history summarizes each change to the user's own roles, including
+    /// its justification and duration.  This differs from the
+    /// [`audit_log`], which records every request that modifies Azure as it
+    /// was sent, such that tampering can be detected.
+    #[must_use]
+    pub fn with_history(mut self) -> Self {
+        self.history = true;
+        self
+    }
+
+    /// Record each request that modifies Azure in the local [`audit_log`],
+    /// as shown by `az-pim audit local`
+    ///
+    /// The entries include the full request bodies, such as justifications.
+    #[must_use]
+    pub fn with_audit_log(mut self) -> Self {
+        self.backend.audit_log = true;
+        self
+    }
+
+    /// Send Graph requests to the specified endpoint, rather than
+    /// `https://graph.microsoft.com`
+    ///
+    /// This can also be set using the `AZ_PIM_GRAPH_URL` environment variable.
+    #[must_use]
+    pub fn with_graph_url(mut self, url: &str) -> Self {
+        self.backend.graph_url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Create a client for the specified tenant, using the same settings as
+    /// this client
+    ///
+    /// # Errors
+    /// Will return `Err` if the client cannot be created
+    pub fn for_tenant(&self, tenant: impl Into<String>) -> Result<Self> {
+        let mut client = Self::new()?.with_tenant(tenant);
+        client.backend.capture.clone_from(&self.backend.capture);
+        client.backend.retry_policy = self.backend.retry_policy;
+        client.backend.client = self.backend.client.clone();
+        client
+            .backend
+            .command_name
+            .clone_from(&self.backend.command_name);
+        client.backend.auth_method = self.backend.auth_method;
+        client.backend.arm_url.clone_from(&self.backend.arm_url);
+        client.backend.graph_url.clone_from(&self.backend.graph_url);
+        client.backend.audit_log = self.backend.audit_log;
+        if self.listing_cache.is_none() {
+            client = client.without_listing_cache();
+        }
+        client.policy.clone_from(&self.policy);
+        client.dangerous_acknowledged = self.dangerous_acknowledged;
+        client.hooks.clone_from(&self.hooks);
+        client.history = self.history;
+        Ok(client)
+    }
+
+    /// The tenant used by the client, if one was specified
+    #[must_use]
+    pub fn tenant(&self) -> Option<&str> {
+        self.backend.tenant.as_deref()
+    }
+
+    /// List the ids of the tenants the current user has subscriptions in
+    ///
+    /// # Errors
+    /// Will return `Err` if the Azure CLI fails
+    pub fn list_tenants() -> Result<Vec<String>> {
+        az_cli::list_tenants()
+    }
+
+    /// Disable reusing listings of active and eligible role assignments
+    /// within the lifetime of the client
+    #[must_use]
+    pub fn without_listing_cache(mut self) -> Self {
+        self.listing_cache = None;
+        self
+    }
+
     pub fn clear_cache(&self) {
         self.object_cache.lock().clear();
         self.role_definitions_cache.lock().clear();
+        if let Some(listing_cache) = &self.listing_cache {
+            listing_cache.lock().clear();
+        }
+    }
+
+    /// Reuse a recent listing of role assignments, unless caching is disabled
+    /// or a request that may have modified assignments has been sent since
+    fn cached_listing<F>(
+        &self,
+        operation: Operation,
+        scope: Option<Scope>,
+        filter: Option<ListFilter>,
+        fetch: F,
+    ) -> Result<BTreeSet<RoleAssignment>>
+    where
+        F: FnOnce(Option<Scope>, Option<ListFilter>) -> Result<BTreeSet<RoleAssignment>>,
+    {
+        let Some(listing_cache) = &self.listing_cache else {
+            return fetch(scope, filter);
+        };
+
+        let key = (operation, scope, filter);
+        let writes = self.backend.writes();
+        if let Some((cached_writes, results)) = listing_cache.lock().get(&key) {
+            if *cached_writes == writes {
+                debug!("using cached {operation:?} listing");
+                return Ok(results.clone());
+            }
+        }
+
+        let results = fetch(key.1.clone(), key.2.clone())?;
+        listing_cache.lock().insert(key, (writes, results.clone()));
+        Ok(results)
     }
 
     pub fn current_user(&self) -> Result<String> {
         self.backend.principal_id()
     }
 
-    fn thread_builder(concurrency: usize) {
+    /// Limit the number of requests made concurrently
+    ///
+    /// This configures the global thread pool used for concurrent requests,
+    /// so only the first call takes effect.
+    pub fn thread_builder(concurrency: usize) {
         static ONCE: Once = Once::new();
         ONCE.call_once(|| {
             if let Err(err) = ThreadPoolBuilder::new()
@@ -133,6 +467,38 @@ impl PimClient {
         scope: Option<Scope>,
         filter: Option<ListFilter>,
     ) -> Result<BTreeSet<RoleAssignment>> {
+        self.cached_listing(
+            Operation::RoleEligibilityScheduleInstances,
+            scope,
+            filter,
+            |scope, filter| self.fetch_eligible_role_assignments(scope, filter),
+        )
+    }
+
+    fn fetch_eligible_role_assignments(
+        &self,
+        scope: Option<Scope>,
+        filter: Option<ListFilter>,
+    ) -> Result<BTreeSet<RoleAssignment>> {
+        let mut results = BTreeSet::new();
+        for page in self.stream_eligible_role_assignments(scope, filter) {
+            results.extend(page?);
+        }
+        Ok(results)
+    }
+
+    /// List the roles available to the current user, page by page
+    ///
+    /// Each page of results is requested as the iterator is advanced, rather
+    /// than waiting for the entire listing.  Unlike
+    /// [`PimClient::list_eligible_role_assignments`], the results are not
+    /// cached.
+    #[must_use]
+    pub fn stream_eligible_role_assignments(
+        &self,
+        scope: Option<Scope>,
+        filter: Option<ListFilter>,
+    ) -> RoleAssignmentPages<'_> {
         let with_principal = filter.as_ref() != Some(&ListFilter::AsTarget);
         if let Some(scope) = &scope {
             info!("listing eligible assignments for {scope}");
@@ -151,31 +517,7 @@ impl PimClient {
             builder = builder.query("$filter", filter.as_str());
         }
 
-        let response = builder
-            .send()
-            .context("unable to list eligible assignments")?;
-        let mut results = RoleAssignment::parse(&response, with_principal)
-            .context("unable to parse eligible assignments")?;
-
-        if with_principal {
-            let ids = results
-                .iter()
-                .filter_map(|x| x.principal_id.as_deref())
-                .collect::<BTreeSet<_>>();
-
-            let objects = get_objects_by_ids(self, ids).context("getting objects by id")?;
-            results = results
-                .into_iter()
-                .map(|mut x| {
-                    if let Some(principal_id) = x.principal_id.as_ref() {
-                        x.object = objects.get(principal_id).cloned();
-                    }
-                    x
-                })
-                .collect();
-        }
-
-        Ok(results)
+        RoleAssignmentPages::new(self, builder, with_principal, "eligible assignments")
     }
 
     /// List the roles active role assignments for the current user
@@ -187,6 +529,38 @@ impl PimClient {
         scope: Option<Scope>,
         filter: Option<ListFilter>,
     ) -> Result<BTreeSet<RoleAssignment>> {
+        self.cached_listing(
+            Operation::RoleAssignmentScheduleInstances,
+            scope,
+            filter,
+            |scope, filter| self.fetch_active_role_assignments(scope, filter),
+        )
+    }
+
+    fn fetch_active_role_assignments(
+        &self,
+        scope: Option<Scope>,
+        filter: Option<ListFilter>,
+    ) -> Result<BTreeSet<RoleAssignment>> {
+        let mut results = BTreeSet::new();
+        for page in self.stream_active_role_assignments(scope, filter) {
+            results.extend(page?);
+        }
+        Ok(results)
+    }
+
+    /// List the active role assignments for the current user, page by page
+    ///
+    /// Each page of results is requested as the iterator is advanced, rather
+    /// than waiting for the entire listing.  Unlike
+    /// [`PimClient::list_active_role_assignments`], the results are not
+    /// cached.
+    #[must_use]
+    pub fn stream_active_role_assignments(
+        &self,
+        scope: Option<Scope>,
+        filter: Option<ListFilter>,
+    ) -> RoleAssignmentPages<'_> {
         let with_principal = filter.as_ref() != Some(&ListFilter::AsTarget);
 
         if let Some(scope) = &scope {
@@ -207,30 +581,235 @@ impl PimClient {
             builder = builder.query("$filter", filter.as_str());
         }
 
-        let response = builder
+        RoleAssignmentPages::new(self, builder, with_principal, "active role assignments")
+    }
+
+    /// Look up the principals of role assignments
+    pub(crate) fn add_principals(
+        &self,
+        results: BTreeSet<RoleAssignment>,
+    ) -> Result<BTreeSet<RoleAssignment>> {
+        let ids = results
+            .iter()
+            .filter_map(|x| x.principal_id.as_deref())
+            .collect::<BTreeSet<_>>();
+
+        let objects = get_objects_by_ids(self, ids).context("getting objects by id")?;
+        Ok(results
+            .into_iter()
+            .map(|mut x| {
+                if let Some(principal_id) = x.principal_id.as_ref() {
+                    x.object = objects.get(principal_id).cloned();
+                }
+                x
+            })
+            .collect())
+    }
+
+    /// List the eligible role assignments at each of the scopes
+    ///
+    /// The requests are combined using the ARM batch API, which reduces the
+    /// number of round trips when scanning many scopes.
+    pub fn list_eligible_role_assignments_at_scopes(
+        &self,
+        scopes: &[Scope],
+        filter: Option<&ListFilter>,
+    ) -> Vec<(Scope, Result<BTreeSet<RoleAssignment>>)> {
+        info!("listing eligible assignments for {} scopes", scopes.len());
+        self.list_role_assignments_at_scopes(
+            Operation::RoleEligibilityScheduleInstances,
+            scopes,
+            filter,
+        )
+    }
+
+    /// List the active role assignments at each of the scopes
+    ///
+    /// The requests are combined using the ARM batch API, which reduces the
+    /// number of round trips when scanning many scopes.
+    pub fn list_active_role_assignments_at_scopes(
+        &self,
+        scopes: &[Scope],
+        filter: Option<&ListFilter>,
+    ) -> Vec<(Scope, Result<BTreeSet<RoleAssignment>>)> {
+        info!(
+            "listing active role assignments for {} scopes",
+            scopes.len()
+        );
+        self.list_role_assignments_at_scopes(
+            Operation::RoleAssignmentScheduleInstances,
+            scopes,
+            filter,
+        )
+    }
+
+    fn list_role_assignments_at_scopes(
+        &self,
+        operation: Operation,
+        scopes: &[Scope],
+        filter: Option<&ListFilter>,
+    ) -> Vec<(Scope, Result<BTreeSet<RoleAssignment>>)> {
+        let with_principal = filter != Some(&ListFilter::AsTarget);
+
+        let requests = scopes
+            .iter()
+            .map(|scope| {
+                let builder = self
+                    .backend
+                    .request(Method::GET, operation)
+                    .scope(scope.clone());
+                match filter {
+                    Some(filter) => builder.query("$filter", filter.as_str()),
+                    None => builder,
+                }
+            })
+            .collect();
+
+        let results = scopes
+            .iter()
+            .cloned()
+            .zip(self.backend.send_batch(requests))
+            .map(|(scope, response)| {
+                // each batch response is the first page of the listing, and
+                // any later pages are fetched individually using `nextLink`
+                let results = response
+                    .and_then(|x| {
+                        RoleAssignmentPages::from_response(
+                            self,
+                            x,
+                            with_principal,
+                            "role assignments",
+                        )
+                        .without_principal_lookup()
+                        .collect::<Result<Vec<_>>>()
+                    })
+                    .map(|pages| pages.into_iter().flatten().collect())
+                    .with_context(|| format!("unable to list role assignments at {scope}"));
+                (scope, results)
+            })
+            .collect::<Vec<_>>();
+
+        if !with_principal {
+            return results;
+        }
+
+        // look up all of the principals at once, such that looking up the
+        // principals of each scope uses the cached objects
+        let ids = results
+            .iter()
+            .filter_map(|(_, x)| x.as_ref().ok())
+            .flatten()
+            .filter_map(|x| x.principal_id.as_deref())
+            .collect::<BTreeSet<_>>();
+        if let Err(err) = get_objects_by_ids(self, ids) {
+            warn!("unable to look up principals: {err:#}");
+        }
+
+        results
+            .into_iter()
+            .map(|(scope, x)| (scope, x.and_then(|x| self.add_principals(x))))
+            .collect()
+    }
+
+    /// List the requests to activate, deactivate, or extend roles at a scope
+    ///
+    /// # Errors
+    /// Will return `Err` if the request fails or the response is not valid JSON
+    pub fn list_role_assignment_schedule_requests(
+        &self,
+        scope: Scope,
+    ) -> Result<Vec<ScheduleRequest>> {
+        info!("listing role assignment requests in {scope}");
+        let response = self
+            .backend
+            .request(Method::GET, Operation::RoleAssignmentScheduleRequests)
+            .scope(scope)
+            .query("$filter", ListFilter::AtScope.as_str())
             .send()
-            .context("unable to list active role assignments")?;
-        let mut results = RoleAssignment::parse(&response, with_principal)
-            .context("unable to parse active role assignments")?;
+            .context("unable to list role assignment requests")?;
+        ScheduleRequest::parse(&response).context("unable to parse role assignment requests")
+    }
 
-        if with_principal {
-            let ids = results
-                .iter()
-                .filter_map(|x| x.principal_id.as_deref())
-                .collect::<BTreeSet<_>>();
+    /// List the activation requests at or below a scope that are waiting
+    /// for the current user to approve them
+    ///
+    /// # Errors
+    /// Will return `Err` if the request fails or the response is not valid JSON
+    pub fn list_pending_approvals(&self, scope: &Scope) -> Result<Vec<ScheduleRequest>> {
+        info!("listing requests pending approval in {scope}");
+        let response = self
+            .backend
+            .request(Method::GET, Operation::RoleAssignmentScheduleRequests)
+            .scope(scope.clone())
+            .query("$filter", "asApprover()")
+            .send()
+            .context("unable to list requests pending approval")?;
+        let requests = ScheduleRequest::parse(&response)
+            .context("unable to parse requests pending approval")?;
+        Ok(requests
+            .into_iter()
+            .filter(|x| x.is_pending_approval() && scope.contains(&x.scope))
+            .collect())
+    }
 
-            let objects = get_objects_by_ids(self, ids).context("getting objects by id")?;
-            results = results
-                .into_iter()
-                .map(|mut x| {
-                    if let Some(principal_id) = x.principal_id.as_ref() {
-                        x.object = objects.get(principal_id).cloned();
+    /// Approve an activation request that is pending approval
+    ///
+    /// # Errors
+    /// Will return `Err` if the request does not require approval or the
+    /// approval fails
+    pub fn approve_request(&self, request: &ScheduleRequest, justification: &str) -> Result<()> {
+        let approval = request
+            .approval_id
+            .as_deref()
+            .and_then(|x| x.rsplit('/').next())
+            .context("request does not have an approval")?;
+        info!(
+            "approving {} for {} in {}",
+            request.role,
+            request.principal_display(),
+            request.scope
+        );
+        self.backend
+            .request(Method::PUT, Operation::RoleAssignmentApprovals)
+            .extra(format!("/{approval}/stages/{approval}"))
+            .json(serde_json::json!({
+                "properties": {
+                    "reviewResult": "Approve",
+                    "justification": justification,
+                }
+            }))
+            .validate(check_error_response)
+            .send()?;
+        Ok(())
+    }
+
+    /// Approve a set of activation requests, returning the result of each
+    ///
+    /// # Errors
+    /// Will return `Err` if no requests are specified
+    pub fn approve_requests(
+        &self,
+        requests: &[ScheduleRequest],
+        justification: &str,
+        concurrency: usize,
+    ) -> Result<Vec<(ScheduleRequest, ActivationResult)>> {
+        ensure!(!requests.is_empty(), "no requests specified");
+
+        Self::thread_builder(concurrency);
+
+        Ok(requests
+            .par_iter()
+            .map(|request| {
+                let result = match self.approve_request(request, justification) {
+                    Ok(()) => ActivationResult::Succeeded,
+                    Err(error) => {
+                        error!("request: {} error: {error:?}", request.name);
+                        ActivationResult::Failed(format!("{error:#}"))
                     }
-                    x
-                })
-                .collect();
-        }
-        Ok(results)
+                };
+                (request.clone(), result)
+            })
+            .collect())
     }
 
     /// Request extending the specified role eligibility
@@ -251,7 +830,11 @@ impl PimClient {
             principal_id: _,
             principal_type: _,
             object: _,
+            end_date_time: _,
         } = assignment;
+        let justification = &self.compliant_justification(justification)?;
+        self.check_local_policy(assignment, duration, None)?;
+        self.confirm_dangerous_activations([assignment])?;
         if let Some(scope_name) = scope_name {
             info!("extending {role} in {scope_name} ({scope})");
         } else {
@@ -273,13 +856,24 @@ impl PimClient {
             }
         });
 
-        self.backend
+        let result = self
+            .backend
             .request(Method::PUT, Operation::RoleAssignmentScheduleRequests)
             .extra(format!("/{request_id}"))
             .scope(scope.clone())
             .json(body)
             .validate(check_error_response)
-            .send()?;
+            .send()
+            .map(|response| activation_status(&response));
+        self.record_history(
+            Action::Extend,
+            assignment,
+            Some(justification),
+            Some(duration),
+            &result,
+        );
+        self.run_hook_after(Hook::PostExtend, assignment, Some(duration), &result);
+        result?;
         Ok(())
     }
 
@@ -293,6 +887,253 @@ impl PimClient {
         justification: &str,
         duration: Duration,
     ) -> Result<()> {
+        self.activate_role_assignment_with_status(assignment, justification, duration)?;
+        Ok(())
+    }
+
+    /// Activates the specified role, returning the status of the request
+    ///
+    /// # Errors
+    /// Will return `Err` if the request fails or the response is not valid JSON
+    pub fn activate_role_assignment_with_status(
+        &self,
+        assignment: &RoleAssignment,
+        justification: &str,
+        duration: Duration,
+    ) -> Result<ActivationResult> {
+        self.activate_role_assignment_request(assignment, justification, duration, None)
+            .map(|request| request.status)
+    }
+
+    /// Confirm the activation of the roles the policy requires confirmation
+    /// for
+    ///
+    /// Each of the roles is logged prominently.  Unless the dangerous roles
+    /// have been acknowledged, the activation must be confirmed
+    /// interactively.
+    ///
+    /// # Errors
+    /// Will return `Err` if the activation is not confirmed, or cannot be
+    /// confirmed as stdin is not a terminal
+    fn confirm_dangerous_activations<'a>(
+        &self,
+        assignments: impl IntoIterator<Item = &'a RoleAssignment>,
+    ) -> Result<()> {
+        let Some(policy) = &self.policy else {
+            return Ok(());
+        };
+        let dangerous = assignments
+            .into_iter()
+            .filter(|x| policy.requires_confirmation(x))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        if dangerous.is_empty() {
+            return Ok(());
+        }
+        for entry in &dangerous {
+            if policy.is_dangerous(entry) {
+                warn!(
+                    "!!! DANGEROUS ACTIVATION: {} grants control over everything in {} !!!",
+                    entry.role,
+                    entry.scope_name.as_deref().unwrap_or(&entry.scope.0)
+                );
+            } else {
+                warn!(
+                    "!!! the policy requires confirmation to activate {} !!!",
+                    entry.friendly()
+                );
+            }
+        }
+        self.require_confirmation(&format!(
+            "activate the following roles:\n{}\n",
+            dangerous.friendly()
+        ))
+    }
+
+    /// Require confirmation for an action the policy requires confirmation
+    /// for, unless the dangerous roles have been acknowledged
+    ///
+    /// # Errors
+    /// Will return `Err` if the action is not confirmed, or cannot be
+    /// confirmed as prompting for input is not possible
+    fn require_confirmation(&self, action: &str) -> Result<()> {
+        if self.dangerous_acknowledged {
+            warn!("activating dangerous roles without confirmation, as they were acknowledged");
+            return Ok(());
+        }
+        ensure_interactive("confirming the activation of dangerous roles").map_err(|err| {
+            PolicyViolation(format!(
+                "unable to {action}: confirmation is required (use --i-know-what-im-doing): {err:#}"
+            ))
+        })?;
+        if !confirm(action)? {
+            return Err(PolicyViolation(
+                "activation of dangerous roles was not confirmed".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Check that the policy from the config file allows activating or
+    /// extending the group membership, confirming it if the policy requires
+    ///
+    /// # Errors
+    /// Will return `Err` describing each restriction the activation does not
+    /// meet, or if it is not confirmed
+    pub(crate) fn check_group_policy(
+        &self,
+        membership: &GroupMembership,
+        duration: Duration,
+    ) -> Result<()> {
+        let Some(policy) = &self.policy else {
+            return Ok(());
+        };
+        policy
+            .check_group(membership, duration)
+            .with_context(|| format!("unable to activate {}", membership.friendly()))?;
+        if !policy.group_requires_confirmation(membership) {
+            return Ok(());
+        }
+        warn!(
+            "!!! the policy requires confirmation to activate {} !!!",
+            membership.friendly()
+        );
+        self.require_confirmation(&format!("activate {}", membership.friendly()))
+    }
+
+    /// Record the outcome of a request in the local history, if enabled
+    fn record_history(
+        &self,
+        action: Action,
+        assignment: &RoleAssignment,
+        justification: Option<&str>,
+        duration: Option<Duration>,
+        result: &Result<ActivationResult>,
+    ) {
+        if self.history {
+            history::record(action, assignment, justification, duration, result);
+        }
+    }
+
+    /// Run the hook for a role or group membership, if one is configured
+    ///
+    /// # Errors
+    /// Will return `Err` if the hook cannot be launched or fails
+    pub(crate) fn run_hook<'a>(
+        &self,
+        hook: Hook,
+        target: impl Into<HookTarget<'a>>,
+        duration: Option<Duration>,
+    ) -> Result<()> {
+        match &self.hooks {
+            Some(hooks) => hooks.run(hook, &target.into(), duration, None),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the hook after a request for a role or group membership, if the
+    /// request was made
+    pub(crate) fn run_hook_after<'a>(
+        &self,
+        hook: Hook,
+        target: impl Into<HookTarget<'a>>,
+        duration: Option<Duration>,
+        result: &Result<ActivationResult>,
+    ) {
+        if let (Some(hooks), Ok(status)) = (&self.hooks, result) {
+            hooks.run_after(hook, &target.into(), duration, status);
+        }
+    }
+
+    /// Ensure the justification matches the pattern from the config file
+    ///
+    /// If it does not match and stdin is a terminal, a compliant
+    /// justification is requested interactively.
+    ///
+    /// # Errors
+    /// Will return `Err` if the justification does not match and a compliant
+    /// one cannot be requested
+    pub fn compliant_justification(&self, justification: &str) -> Result<String> {
+        let Some(policy) = &self.policy else {
+            return Ok(justification.to_string());
+        };
+        match policy.justification_pattern()? {
+            Some(pattern) => require_match("justification", justification, &pattern),
+            None => Ok(justification.to_string()),
+        }
+    }
+
+    /// Ensure the ticket number matches the pattern from the config file
+    ///
+    /// If it does not match and stdin is a terminal, a compliant ticket
+    /// number is requested interactively.
+    ///
+    /// # Errors
+    /// Will return `Err` if the ticket number does not match and a compliant
+    /// one cannot be requested
+    pub fn compliant_ticket(&self, ticket: Option<&TicketInfo>) -> Result<Option<TicketInfo>> {
+        let (Some(policy), Some(ticket)) = (&self.policy, ticket) else {
+            return Ok(ticket.cloned());
+        };
+        let Some(pattern) = policy.ticket_number_pattern()? else {
+            return Ok(Some(ticket.clone()));
+        };
+        Ok(Some(TicketInfo {
+            number: require_match("ticket number", &ticket.number, &pattern)?,
+            system: ticket.system.clone(),
+        }))
+    }
+
+    /// Activates the specified role, returning the submitted request
+    ///
+    /// The request name can be used with [`PimClient::wait_for_role_approval`]
+    /// if the activation is pending approval.
+    ///
+    /// # Errors
+    /// Will return `Err` if the activation is not allowed or not confirmed,
+    /// the request fails, or the response is not valid JSON
+    pub fn activate_role_assignment_request(
+        &self,
+        assignment: &RoleAssignment,
+        justification: &str,
+        duration: Duration,
+        ticket: Option<&TicketInfo>,
+    ) -> Result<ActivationRequest> {
+        let justification = self.compliant_justification(justification)?;
+        let ticket = self.compliant_ticket(ticket)?;
+        self.check_local_policy(assignment, duration, ticket.as_ref())?;
+        self.confirm_dangerous_activations([assignment])?;
+        self.submit_activation(assignment, &justification, duration, ticket.as_ref())
+    }
+
+    /// Check that the policy from the config file allows the activation
+    ///
+    /// # Errors
+    /// Will return `Err` describing each restriction the activation does not
+    /// meet
+    fn check_local_policy(
+        &self,
+        assignment: &RoleAssignment,
+        duration: Duration,
+        ticket: Option<&TicketInfo>,
+    ) -> Result<()> {
+        let Some(policy) = &self.policy else {
+            return Ok(());
+        };
+        policy
+            .check(assignment, duration, ticket.is_some())
+            .with_context(|| format!("unable to activate {}", assignment.friendly()))
+    }
+
+    /// Submit the request to activate a role
+    fn submit_activation(
+        &self,
+        assignment: &RoleAssignment,
+        justification: &str,
+        duration: Duration,
+        ticket: Option<&TicketInfo>,
+    ) -> Result<ActivationRequest> {
         let RoleAssignment {
             scope,
             role_definition_id,
@@ -301,14 +1142,16 @@ impl PimClient {
             principal_id: _,
             principal_type: _,
             object: _,
+            end_date_time: _,
         } = assignment;
+        self.run_hook(Hook::PreActivate, assignment, Some(duration))?;
         if let Some(scope_name) = scope_name {
             info!("activating {role} in {scope_name} ({scope})");
         } else {
             info!("activating {role} in {scope}");
         }
         let request_id = Uuid::now_v7();
-        let body = serde_json::json!({
+        let mut body = serde_json::json!({
             "properties": {
                 "principalId": self.backend.principal_id()?,
                 "roleDefinitionId": role_definition_id,
@@ -322,56 +1165,226 @@ impl PimClient {
                 }
             }
         });
+        if let (Some(ticket), Some(properties)) = (
+            ticket,
+            body.get_mut("properties")
+                .and_then(serde_json::Value::as_object_mut),
+        ) {
+            properties.insert(
+                "ticketInfo".to_string(),
+                serde_json::json!({
+                    "ticketNumber": ticket.number,
+                    "ticketSystem": ticket.system,
+                }),
+            );
+        }
 
-        self.backend
+        let result = self
+            .backend
             .request(Method::PUT, Operation::RoleAssignmentScheduleRequests)
             .extra(format!("/{request_id}"))
             .scope(scope.clone())
             .json(body)
             .validate(check_error_response)
-            .send()?;
-
-        Ok(())
+            .send()
+            .map(|response| activation_status(&response));
+        self.record_history(
+            Action::Activate,
+            assignment,
+            Some(justification),
+            Some(duration),
+            &result,
+        );
+        self.run_hook_after(Hook::PostActivate, assignment, Some(duration), &result);
+        result.map(|status| ActivationRequest {
+            name: request_id.to_string(),
+            status,
+        })
     }
 
-    pub fn activate_role_assignment_set(
+    /// Check that an activation meets the justification and ticket
+    /// requirements of the role's policy, before submitting it
+    ///
+    /// If the policy cannot be read, the activation is not checked.
+    ///
+    /// # Errors
+    /// Will return `Err` describing each requirement that is not met
+    pub fn check_activation_policy(
         &self,
-        assignments: &BTreeSet<RoleAssignment>,
+        assignment: &RoleAssignment,
         justification: &str,
-        duration: Duration,
-        concurrency: usize,
+        ticket: Option<&TicketInfo>,
     ) -> Result<()> {
-        ensure!(!assignments.is_empty(), "no roles specified");
+        let policies = match self.role_management_policies(&assignment.scope) {
+            Ok(policies) => policies,
+            Err(err) => {
+                warn!("unable to check the activation policy: {err:#}");
+                return Ok(());
+            }
+        };
+        let Some(policy) = policies
+            .into_iter()
+            .find(|x| x.role.0.eq_ignore_ascii_case(&assignment.role.0))
+        else {
+            return Ok(());
+        };
+        policy
+            .activation
+            .check(justification, ticket.is_some())
+            .with_context(|| format!("unable to activate {}", assignment.friendly()))
+    }
 
-        Self::thread_builder(concurrency);
+    /// Look up the maximum activation duration allowed by the policy of each
+    /// role
+    ///
+    /// # Errors
+    /// Will return `Err` if the policies cannot be read, or a role's policy
+    /// does not have a maximum activation duration
+    pub fn max_activation_durations(
+        &self,
+        assignments: &BTreeSet<RoleAssignment>,
+    ) -> Result<BTreeMap<RoleAssignment, Duration>> {
+        let scopes = assignments
+            .iter()
+            .map(|x| &x.scope)
+            .collect::<BTreeSet<_>>();
+        let mut policies = BTreeMap::new();
+        for scope in scopes {
+            policies.insert(scope, self.role_management_policies(scope)?);
+        }
 
-        let results = assignments
-            .into_par_iter()
-            .map(
-                |entry| match self.activate_role_assignment(entry, justification, duration) {
-                    Ok(()) => ActivationResult::Success,
-                    Err(error) => {
-                        error!(
-                            "scope: {} definition: {} error: {error:?}",
-                            entry.scope, entry.role_definition_id
-                        );
-                        ActivationResult::Failed(entry.clone())
-                    }
-                },
-            )
-            .collect::<Vec<_>>();
+        let mut results = BTreeMap::new();
+        for assignment in assignments {
+            let duration = policies
+                .get(&assignment.scope)
+                .into_iter()
+                .flatten()
+                .find(|x| x.role.0.eq_ignore_ascii_case(&assignment.role.0))
+                .and_then(|x| x.activation.max_duration.as_deref())
+                .with_context(|| {
+                    format!(
+                        "no maximum activation duration for {}",
+                        assignment.friendly()
+                    )
+                })?;
+            let duration = parse_iso_duration(duration).with_context(|| {
+                format!(
+                    "invalid maximum activation duration for {}",
+                    assignment.friendly()
+                )
+            })?;
+            results.insert(assignment.clone(), duration);
+        }
+        Ok(results)
+    }
 
-        let mut failed = BTreeSet::new();
+    /// Find the roles whose policy requires approval to activate
+    ///
+    /// # Errors
+    /// Will return `Err` if the role management policies cannot be listed
+    pub fn roles_requiring_approval(
+        &self,
+        assignments: &BTreeSet<RoleAssignment>,
+    ) -> Result<BTreeSet<RoleAssignment>> {
+        let scopes = assignments
+            .iter()
+            .map(|x| &x.scope)
+            .collect::<BTreeSet<_>>();
+        let mut policies = BTreeMap::new();
+        for scope in scopes {
+            policies.insert(scope, self.role_management_policies(scope)?);
+        }
 
-        for result in results {
-            match result {
-                ActivationResult::Failed(entry) => {
-                    failed.insert(entry);
+        Ok(assignments
+            .iter()
+            .filter(|assignment| {
+                policies
+                    .get(&assignment.scope)
+                    .into_iter()
+                    .flatten()
+                    .find(|x| x.role.0.eq_ignore_ascii_case(&assignment.role.0))
+                    .is_some_and(|x| x.activation.approval_required)
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// List the users and groups that can approve activating the role
+    ///
+    /// # Errors
+    /// Will return `Err` if the role management policies cannot be listed
+    pub fn approvers(&self, assignment: &RoleAssignment) -> Result<Vec<String>> {
+        Ok(self
+            .role_management_policies(&assignment.scope)?
+            .into_iter()
+            .find(|x| x.role.0.eq_ignore_ascii_case(&assignment.role.0))
+            .map(|x| x.activation.approvers)
+            .unwrap_or_default())
+    }
+
+    /// Wait for an activation request that is pending approval to be approved
+    ///
+    /// # Errors
+    /// Will return `Err` if the request is denied, expires, or is not approved
+    /// within `wait_timeout`
+    pub fn wait_for_role_approval(
+        &self,
+        assignment: &RoleAssignment,
+        request_name: &str,
+        wait_timeout: Duration,
+    ) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            let response = self
+                .backend
+                .request(Method::GET, Operation::RoleAssignmentScheduleRequests)
+                .extra(format!("/{request_name}"))
+                .scope(assignment.scope.clone())
+                .send()?;
+            match approval_status(&response) {
+                ApprovalStatus::Approved => {
+                    info!("activation of {} was approved", assignment.role);
+                    return Ok(());
                 }
-                ActivationResult::Success => {}
+                ApprovalStatus::Closed(status) => {
+                    bail!(
+                        "activation of {} was not approved: {status}",
+                        assignment.role
+                    );
+                }
+                ApprovalStatus::Pending => {}
             }
+
+            if start.elapsed() > wait_timeout {
+                bail!(
+                    "timed out waiting for approval to activate {}",
+                    assignment.friendly()
+                );
+            }
+            debug!("waiting {APPROVAL_POLL_DELAY:?} for approval");
+            sleep(APPROVAL_POLL_DELAY);
         }
+    }
 
+    pub fn activate_role_assignment_set(
+        &self,
+        assignments: &BTreeSet<RoleAssignment>,
+        justification: &str,
+        duration: Duration,
+        concurrency: usize,
+    ) -> Result<()> {
+        let assignments = assignments
+            .iter()
+            .map(|x| (x.clone(), ActivationOverrides::default()))
+            .collect();
+        let results = self.activate_role_assignment_set_results(
+            &assignments,
+            justification,
+            duration,
+            concurrency,
+        )?;
+
+        let failed = failed_entries(results);
         if !failed.is_empty() {
             bail!(
                 "failed to activate the following roles:\n{}",
@@ -382,6 +1395,97 @@ impl PimClient {
         Ok(())
     }
 
+    /// Activate a set of roles, continuing on error
+    ///
+    /// Rather than failing if any role fails to activate, this returns the
+    /// result for each of the requested roles.  Settings in the per-role
+    /// overrides take precedence over the provided defaults.
+    ///
+    /// # Errors
+    /// Will return `Err` if no roles are specified, or the activation of
+    /// dangerous roles is not confirmed
+    pub fn activate_role_assignment_set_results(
+        &self,
+        assignments: &BTreeMap<RoleAssignment, ActivationOverrides>,
+        justification: &str,
+        duration: Duration,
+        concurrency: usize,
+    ) -> Result<BTreeMap<RoleAssignment, ActivationResult>> {
+        ensure!(!assignments.is_empty(), "no roles specified");
+        // justifications and ticket numbers are made compliant one at a time,
+        // as doing so may prompt for new values
+        let justification = self.compliant_justification(justification)?;
+        let assignments = assignments
+            .iter()
+            .map(|(entry, overrides)| {
+                let overrides = ActivationOverrides {
+                    duration: overrides.duration,
+                    justification: overrides
+                        .justification
+                        .as_deref()
+                        .map(|x| self.compliant_justification(x))
+                        .transpose()?,
+                    ticket: self.compliant_ticket(overrides.ticket.as_ref())?,
+                };
+                Ok((entry, overrides))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        // roles the policy does not allow fail without being submitted, and
+        // the rest are confirmed all at once, before any are activated
+        let denied = assignments
+            .iter()
+            .filter_map(|(entry, overrides)| {
+                let duration = overrides.duration.unwrap_or(duration);
+                self.check_local_policy(entry, duration, overrides.ticket.as_ref())
+                    .err()
+                    .map(|err| {
+                        (
+                            (*entry).clone(),
+                            ActivationResult::Failed(format!("{err:#}")),
+                        )
+                    })
+            })
+            .collect::<BTreeMap<_, _>>();
+        self.confirm_dangerous_activations(
+            assignments
+                .keys()
+                .copied()
+                .filter(|x| !denied.contains_key(*x)),
+        )?;
+
+        Self::thread_builder(concurrency);
+
+        let mut results = assignments
+            .into_par_iter()
+            .filter(|(entry, _)| !denied.contains_key(*entry))
+            .map(|(entry, overrides)| {
+                let duration = overrides.duration.unwrap_or(duration);
+                let justification = overrides.justification.as_deref().unwrap_or(&justification);
+                let result = self
+                    .submit_activation(entry, justification, duration, overrides.ticket.as_ref())
+                    .map_or_else(
+                        |error| {
+                            error!(
+                                "scope: {} definition: {} error: {error:?}",
+                                entry.scope, entry.role_definition_id
+                            );
+                            ActivationResult::Failed(format!("{error:#}"))
+                        },
+                        |request| request.status,
+                    );
+                (entry.clone(), result)
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (entry, result) in denied {
+            if let ActivationResult::Failed(reason) = &result {
+                error!("{reason}");
+            }
+            results.insert(entry, result);
+        }
+        Ok(results)
+    }
+
     /// Deactivate the specified role
     ///
     /// # Errors
@@ -395,6 +1499,7 @@ impl PimClient {
             principal_id: _,
             principal_type: _,
             object: _,
+            end_date_time: _,
         } = assignment;
         if let Some(scope_name) = scope_name {
             info!("deactivating {role} in {scope_name} ({scope})");
@@ -411,13 +1516,18 @@ impl PimClient {
             }
         });
 
-        self.backend
+        let result = self
+            .backend
             .request(Method::PUT, Operation::RoleAssignmentScheduleRequests)
             .extra(format!("/{request_id}"))
             .scope(scope.clone())
             .json(body)
             .validate(check_error_response)
-            .send()?;
+            .send()
+            .map(|_| ActivationResult::Succeeded);
+        self.record_history(Action::Deactivate, assignment, None, None, &result);
+        self.run_hook_after(Hook::PostDeactivate, assignment, None, &result);
+        result?;
         Ok(())
     }
 
@@ -426,35 +1536,9 @@ impl PimClient {
         assignments: &BTreeSet<RoleAssignment>,
         concurrency: usize,
     ) -> Result<()> {
-        ensure!(!assignments.is_empty(), "no roles specified");
-
-        Self::thread_builder(concurrency);
-
-        let results = assignments
-            .into_par_iter()
-            .map(|entry| match self.deactivate_role_assignment(entry) {
-                Ok(()) => ActivationResult::Success,
-                Err(error) => {
-                    error!(
-                        "scope: {} definition: {} error: {error:?}",
-                        entry.scope, entry.role_definition_id
-                    );
-                    ActivationResult::Failed(entry.clone())
-                }
-            })
-            .collect::<Vec<_>>();
-
-        let mut failed = BTreeSet::new();
-
-        for result in results {
-            match result {
-                ActivationResult::Failed(entry) => {
-                    failed.insert(entry);
-                }
-                ActivationResult::Success => {}
-            }
-        }
+        let results = self.deactivate_role_assignment_set_results(assignments, concurrency)?;
 
+        let failed = failed_entries(results);
         if !failed.is_empty() {
             bail!(
                 "failed to deactivate the following roles:\n{}",
@@ -465,6 +1549,40 @@ impl PimClient {
         Ok(())
     }
 
+    /// Deactivate a set of roles, continuing on error
+    ///
+    /// Rather than failing if any role fails to deactivate, this returns the
+    /// result for each of the requested roles.
+    ///
+    /// # Errors
+    /// Will return `Err` if no roles are specified
+    pub fn deactivate_role_assignment_set_results(
+        &self,
+        assignments: &BTreeSet<RoleAssignment>,
+        concurrency: usize,
+    ) -> Result<BTreeMap<RoleAssignment, ActivationResult>> {
+        ensure!(!assignments.is_empty(), "no roles specified");
+
+        Self::thread_builder(concurrency);
+
+        Ok(assignments
+            .into_par_iter()
+            .map(|entry| {
+                let result = match self.deactivate_role_assignment(entry) {
+                    Ok(()) => ActivationResult::Succeeded,
+                    Err(error) => {
+                        error!(
+                            "scope: {} definition: {} error: {error:?}",
+                            entry.scope, entry.role_definition_id
+                        );
+                        ActivationResult::Failed(format!("{error:#}"))
+                    }
+                };
+                (entry.clone(), result)
+            })
+            .collect())
+    }
+
     pub fn wait_for_role_activation(
         &self,
         assignments: &BTreeSet<RoleAssignment>,
@@ -498,7 +1616,7 @@ impl PimClient {
             }
             last = Some(current);
 
-            let active = self.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
+            let active = self.fetch_active_role_assignments(None, Some(ListFilter::AsTarget))?;
             debug!("active assignments: {active:#?}");
             waiting.retain(|entry| !active.contains(entry));
             debug!("still waiting: {waiting:#?}");
@@ -518,12 +1636,32 @@ impl PimClient {
     ///
     /// # Errors
     /// Will return `Err` if the request fails or the response is not valid JSON
-    pub fn role_assignments(&self, scope: &Scope) -> Result<Vec<Assignment>> {
+    pub fn role_assignments(&self, scope: &Scope) -> Result<Vec<Assignment>> {
+        self.role_assignments_of_principal(scope, None)
+    }
+
+    /// List role assignments, optionally only those of the specified
+    /// principal id
+    ///
+    /// The principal is filtered by ARM, rather than after listing all of the
+    /// assignments.
+    ///
+    /// # Errors
+    /// Will return `Err` if the request fails or the response is not valid JSON
+    pub fn role_assignments_of_principal(
+        &self,
+        scope: &Scope,
+        principal_id: Option<&str>,
+    ) -> Result<Vec<Assignment>> {
         info!("listing assignments for {scope}");
-        let value = self
+        let mut builder = self
             .backend
             .request(Method::GET, Operation::RoleAssignments)
-            .scope(scope.clone())
+            .scope(scope.clone());
+        if let Some(principal_id) = principal_id {
+            builder = builder.query("$filter", format!("principalId eq '{principal_id}'"));
+        }
+        let value = builder
             .send()
             .with_context(|| format!("unable to list role assignments at {scope}"))?;
         let assignments: Assignments = serde_json::from_value(value)
@@ -541,52 +1679,230 @@ impl PimClient {
         Ok(assignments)
     }
 
+    /// Find the assignments of `role` to a principal made directly at `scope`
+    ///
+    /// Assignments inherited from above `scope`, or made at scopes below it,
+    /// are not included.
+    ///
+    /// # Errors
+    /// Will return `Err` if the role cannot be found or the assignments
+    /// cannot be listed
+    pub fn find_role_assignments(
+        &self,
+        scope: &Scope,
+        role: &Role,
+        principal_id: &str,
+    ) -> Result<Vec<Assignment>> {
+        let definition_id = self.role_definition_id(scope, role)?;
+        let mut assignments = self.role_assignments_of_principal(scope, Some(principal_id))?;
+        assignments.retain(|x| {
+            x.properties
+                .role_definition_id
+                .eq_ignore_ascii_case(&definition_id)
+                && x.properties.scope.0.eq_ignore_ascii_case(&scope.0)
+        });
+        Ok(assignments)
+    }
+
+    /// List the role assignments at a scope and at each of its eligible child
+    /// resources, along with the scope each assignment was listed at
+    ///
+    /// Each assignment is only included once, and assignments inherited from
+    /// above `scope` are excluded.  Child resources whose assignments cannot
+    /// be listed are skipped with a warning.  If `principal_id` is provided,
+    /// only the assignments of that principal are listed.
+    ///
+    /// # Errors
+    /// Will return `Err` if the child resources or the assignments at `scope`
+    /// cannot be listed
+    pub fn nested_role_assignments(
+        &self,
+        scope: &Scope,
+        principal_id: Option<&str>,
+    ) -> Result<Vec<(Scope, Assignment)>> {
+        let scopes = once(scope.clone())
+            .chain(
+                self.eligible_child_resources(scope, true)?
+                    .into_iter()
+                    .map(|x| x.id),
+            )
+            .collect::<Vec<_>>();
+        let results = scopes
+            .par_iter()
+            .map(|x| self.role_assignments_of_principal(x, principal_id))
+            .collect::<Vec<_>>();
+
+        let within = Scope(scope.0.to_lowercase());
+        let mut seen = BTreeSet::new();
+        let mut found = Vec::new();
+        for (at, result) in scopes.into_iter().zip(results) {
+            let assignments = match result {
+                Ok(assignments) => assignments,
+                Err(err) if &at == scope => return Err(err),
+                Err(err) => {
+                    warn!("skipping role assignments at {at}: {err:#}");
+                    continue;
+                }
+            };
+            for assignment in assignments {
+                let assigned = Scope(assignment.properties.scope.0.to_lowercase());
+                if within.contains(&assigned) && seen.insert(assignment.id.to_lowercase()) {
+                    found.push((at.clone(), assignment));
+                }
+            }
+        }
+        Ok(found)
+    }
+
     /// List eligible child resources for the specified scope
     ///
+    /// If `nested` is set, the child resources of each child resource are
+    /// listed as they are discovered, limited to the number of threads
+    /// configured with [`PimClient::thread_builder`].  Requests are retried on
+    /// transient failures, and child resources that still cannot be listed
+    /// are skipped with a warning.
+    ///
     /// # Errors
-    /// Will return `Err` if the request fails or the response is not valid JSON
+    /// Will return `Err` if the request for `scope` fails or the response is
+    /// not valid JSON
     pub fn eligible_child_resources(
         &self,
         scope: &Scope,
         nested: bool,
     ) -> Result<BTreeSet<ChildResource>> {
-        let mut todo = [scope.clone()].into_iter().collect::<BTreeSet<_>>();
-        let mut seen = BTreeSet::new();
-        let mut result = BTreeSet::new();
-
-        while !todo.is_empty() {
-            seen.extend(todo.clone());
-            let iteration: Vec<Result<Result<BTreeSet<ChildResource>>>> = todo
-                .into_par_iter()
-                .map(|scope| {
-                    info!("listing eligible child resources for {scope}");
-                    self.backend
-                        .request(Method::GET, Operation::EligibleChildResources)
-                        .scope(scope.clone())
-                        .send()
-                        .with_context(|| {
-                            format!("unable to list eligible child resources for {scope}")
-                        })
-                        .map(|x| {
-                            ChildResource::parse(&x).with_context(|| {
-                                format!("unable to parse eligible child resources for {scope}")
-                            })
-                        })
-                })
-                .collect();
-
-            todo = BTreeSet::new();
-            for entry in iteration {
-                for child in entry?? {
-                    if nested && !seen.contains(&child.id) {
-                        todo.insert(child.id.clone());
-                    }
-                    result.insert(child);
+        self.eligible_child_resources_of_types(scope, nested, &[])
+    }
+
+    /// List eligible child resources of the specified resource types, such as
+    /// `Microsoft.KeyVault/vaults`, for the specified scope
+    ///
+    /// If `types` is empty, all child resources are listed.  If `nested` is
+    /// set, only the child resources that could contain one of `types` are
+    /// expanded, such that unrelated resources are not listed.
+    ///
+    /// # Errors
+    /// Will return `Err` if the request for `scope` fails or the response is
+    /// not valid JSON
+    pub fn eligible_child_resources_of_types(
+        &self,
+        scope: &Scope,
+        nested: bool,
+        types: &[String],
+    ) -> Result<BTreeSet<ChildResource>> {
+        let children = self.list_child_resources(scope)?;
+        if !nested {
+            return Ok(children
+                .into_iter()
+                .filter(|x| x.is_any_type(types))
+                .collect());
+        }
+
+        let ids = children
+            .iter()
+            .filter(|x| x.may_contain_any_type(types))
+            .map(|x| x.id.clone())
+            .collect::<Vec<_>>();
+        let seen = Mutex::new(
+            children
+                .iter()
+                .map(|x| x.id.clone())
+                .chain([scope.clone()])
+                .collect::<BTreeSet<_>>(),
+        );
+        let found = Mutex::new(
+            children
+                .into_iter()
+                .filter(|x| x.is_any_type(types))
+                .collect(),
+        );
+        rayon::scope(|s| {
+            for id in ids {
+                self.discover_child_resources(s, id, types, &seen, &found);
+            }
+        });
+
+        Ok(found.into_inner())
+    }
+
+    /// Queue listing the child resources of `scope`, which in turn queues
+    /// listing any newly discovered child resources
+    fn discover_child_resources<'a>(
+        &'a self,
+        s: &rayon::Scope<'a>,
+        scope: Scope,
+        types: &'a [String],
+        seen: &'a Mutex<BTreeSet<Scope>>,
+        found: &'a Mutex<BTreeSet<ChildResource>>,
+    ) {
+        s.spawn(move |s| {
+            let children = match self.list_child_resources(&scope) {
+                Ok(children) => children,
+                Err(err) => {
+                    warn!("skipping child resources of {scope}: {err:#}");
+                    return;
+                }
+            };
+            for child in children {
+                if seen.lock().insert(child.id.clone()) && child.may_contain_any_type(types) {
+                    self.discover_child_resources(s, child.id.clone(), types, seen, found);
+                }
+                if child.is_any_type(types) {
+                    found.lock().insert(child);
                 }
             }
+        });
+    }
+
+    fn list_child_resources(&self, scope: &Scope) -> Result<BTreeSet<ChildResource>> {
+        info!("listing eligible child resources for {scope}");
+        let value = self
+            .backend
+            .request(Method::GET, Operation::EligibleChildResources)
+            .scope(scope.clone())
+            .send()
+            .with_context(|| format!("unable to list eligible child resources for {scope}"))?;
+        ChildResource::parse(&value)
+            .with_context(|| format!("unable to parse eligible child resources for {scope}"))
+    }
+
+    /// List the scopes where the current user has eligible assignments, along
+    /// with their child resources, and the display name of each scope
+    ///
+    /// If `nested` is set, all of the nested child resources are included
+    /// rather than only the direct children.  Child resources that cannot be
+    /// listed are skipped with a warning.
+    ///
+    /// # Errors
+    /// Will return `Err` if the eligible assignments cannot be listed
+    pub fn eligible_scopes(&self, nested: bool) -> Result<BTreeMap<Scope, Option<String>>> {
+        let mut results = self
+            .list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?
+            .into_iter()
+            .map(|x| (x.scope, x.scope_name))
+            .collect::<BTreeMap<_, _>>();
+
+        let children = results
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .filter_map(
+                |scope| match self.eligible_child_resources(&scope, nested) {
+                    Ok(children) => Some(children),
+                    Err(err) => {
+                        warn!("unable to list child resources of {scope}: {err:#}");
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        for child in children.into_iter().flatten() {
+            results
+                .entry(child.id)
+                .or_insert_with(|| Some(format!("{} ({})", child.name, child.type_)));
         }
 
-        Ok(result)
+        Ok(results)
     }
 
     /// List role definitions available at the target scope
@@ -596,24 +1912,205 @@ impl PimClient {
     /// # Errors
     /// Will return `Err` if the request fails or the response is not valid JSON
     pub fn role_definitions(&self, scope: &Scope) -> Result<Vec<Definition>> {
-        let mut cache = self.role_definitions_cache.lock();
-
-        if let Some(cached) = cache.get(scope) {
+        if let Some(cached) = self.role_definitions_cache.lock().get(scope) {
             return Ok(cached.clone());
         }
 
-        info!("listing role definitions for {scope}");
-        let definitions = self
+        self.role_definitions_in_flight.run(scope, || {
+            info!("listing role definitions for {scope}");
+            let definitions = self
+                .backend
+                .request(Method::GET, Operation::RoleDefinitions)
+                .scope(scope.clone())
+                .send()
+                .with_context(|| format!("unable to list role definitions at {scope}"))?;
+            let definitions: Definitions = serde_json::from_value(definitions)
+                .with_context(|| format!("unable to parse role definitions at {scope}"))?;
+            self.role_definitions_cache
+                .lock()
+                .insert(scope.clone(), definitions.value.clone());
+            Ok(definitions.value)
+        })
+    }
+
+    /// Create a custom role definition
+    ///
+    /// # Errors
+    /// Will return `Err` if the definition is not valid, a role with the same
+    /// name already exists, or the request fails
+    pub fn create_role_definition(
+        &self,
+        scope: &Scope,
+        properties: &DefinitionProperties,
+    ) -> Result<Definition> {
+        ensure!(
+            self.find_role_definition(scope, &properties.role_name)?
+                .is_none(),
+            "role definition {} already exists at {scope}",
+            properties.role_name
+        );
+        self.put_role_definition(scope, &Uuid::now_v7().to_string(), properties)
+    }
+
+    /// Update an existing custom role definition, identified by its role name
+    ///
+    /// # Errors
+    /// Will return `Err` if the definition is not valid, the role does not
+    /// exist, or the request fails
+    pub fn update_role_definition(
+        &self,
+        scope: &Scope,
+        properties: &DefinitionProperties,
+    ) -> Result<Definition> {
+        let existing = self
+            .find_role_definition(scope, &properties.role_name)?
+            .with_context(|| {
+                format!(
+                    "role definition {} does not exist at {scope}",
+                    properties.role_name
+                )
+            })?;
+        self.put_role_definition(scope, &existing.name, properties)
+    }
+
+    /// Delete a custom role definition
+    ///
+    /// `id` is either the name (a GUID) or the full ID of the definition.
+    ///
+    /// # Errors
+    /// Will return `Err` if the definition does not exist, is a built-in role,
+    /// or the request fails
+    pub fn delete_role_definition(&self, scope: &Scope, id: &str) -> Result<()> {
+        let definition = self
+            .role_definitions(scope)?
+            .into_iter()
+            .find(|x| x.name.eq_ignore_ascii_case(id) || x.id.eq_ignore_ascii_case(id))
+            .with_context(|| format!("role definition {id} does not exist at {scope}"))?;
+        let role_name = &definition.properties.role_name;
+        ensure!(
+            definition.properties.type_ == "CustomRole",
+            "refusing to delete {role_name}, only custom roles can be deleted"
+        );
+
+        info!("deleting role definition {role_name} at {scope}");
+        self.backend
+            .request(Method::DELETE, Operation::RoleDefinitions)
+            .extra(format!("/{}", definition.name))
+            .scope(scope.clone())
+            .send()
+            .with_context(|| format!("unable to delete role definition {role_name} at {scope}"))?;
+        self.role_definitions_cache.lock().remove(scope);
+        Ok(())
+    }
+
+    fn find_role_definition(&self, scope: &Scope, role_name: &str) -> Result<Option<Definition>> {
+        Ok(self
+            .role_definitions(scope)?
+            .into_iter()
+            .find(|x| x.properties.role_name.eq_ignore_ascii_case(role_name)))
+    }
+
+    fn put_role_definition(
+        &self,
+        scope: &Scope,
+        name: &str,
+        properties: &DefinitionProperties,
+    ) -> Result<Definition> {
+        properties.validate()?;
+        let role_name = &properties.role_name;
+        info!("writing role definition {role_name} at {scope}");
+
+        // the audit fields are set by Azure
+        let properties = DefinitionProperties {
+            created_on: None,
+            created_by: None,
+            updated_on: None,
+            updated_by: None,
+            ..properties.clone()
+        };
+        let value = self
+            .backend
+            .request(Method::PUT, Operation::RoleDefinitions)
+            .extra(format!("/{name}"))
+            .scope(scope.clone())
+            .json(serde_json::json!({ "properties": properties }))
+            .send()
+            .with_context(|| format!("unable to write role definition {role_name} at {scope}"))?;
+        self.role_definitions_cache.lock().remove(scope);
+        serde_json::from_value(value)
+            .with_context(|| format!("unable to parse role definition {role_name} at {scope}"))
+    }
+
+    /// List the role management policies that apply to each role at a scope
+    ///
+    /// # Errors
+    /// Will return `Err` if the request fails or the response is not valid JSON
+    pub fn role_management_policies(&self, scope: &Scope) -> Result<Vec<RolePolicy>> {
+        info!("listing role management policies for {scope}");
+        let response = self
             .backend
-            .request(Method::GET, Operation::RoleDefinitions)
+            .request(Method::GET, Operation::RoleManagementPolicyAssignments)
             .scope(scope.clone())
             .send()
-            .with_context(|| format!("unable to list role definitions at {scope}"))?;
-        let definitions: Definitions = serde_json::from_value(definitions)
-            .with_context(|| format!("unable to parse role definitions at {scope}"))?;
-        cache.insert(scope.clone(), definitions.value.clone());
+            .with_context(|| format!("unable to list role management policies at {scope}"))?;
+        RolePolicy::parse(&response)
+            .with_context(|| format!("unable to parse role management policies at {scope}"))
+    }
+
+    /// Find the ID of the role definition with the specified name at a scope
+    fn role_definition_id(&self, scope: &Scope, role: &Role) -> Result<String> {
+        let definitions = self.role_definitions(scope)?;
+        definitions
+            .into_iter()
+            .find(|x| x.properties.role_name.eq_ignore_ascii_case(&role.0))
+            .map(|x| x.id)
+            .with_context(|| format!("unable to find role definition for {role} at {scope}"))
+    }
+
+    /// Create a role assignment
+    ///
+    /// If provided, `condition` is an ABAC condition that further constrains
+    /// the assignment.  `condition_version` defaults to
+    /// [`DEFAULT_CONDITION_VERSION`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the role cannot be found, the request fails, or
+    /// the response is not valid JSON
+    pub fn create_role_assignment(
+        &self,
+        scope: &Scope,
+        role: &Role,
+        principal_id: &str,
+        condition: Option<&str>,
+        condition_version: Option<&str>,
+    ) -> Result<Assignment> {
+        ensure!(
+            condition.is_some() || condition_version.is_none(),
+            "a condition version requires a condition"
+        );
+        let role_definition_id = self.role_definition_id(scope, role)?;
+        let assignment_name = Uuid::now_v7();
+        info!("assigning {role} to {principal_id} in {scope}");
+
+        let mut properties = serde_json::Map::new();
+        properties.insert("roleDefinitionId".into(), role_definition_id.into());
+        properties.insert("principalId".into(), principal_id.into());
+        if let Some(condition) = condition {
+            properties.insert("condition".into(), condition.into());
+            let version = condition_version.unwrap_or(DEFAULT_CONDITION_VERSION);
+            properties.insert("conditionVersion".into(), version.into());
+        }
 
-        Ok(definitions.value)
+        let value = self
+            .backend
+            .request(Method::PUT, Operation::RoleAssignments)
+            .extra(format!("/{assignment_name}"))
+            .scope(scope.clone())
+            .json(serde_json::json!({ "properties": properties }))
+            .send()
+            .with_context(|| format!("unable to assign {role} to {principal_id} at {scope}"))?;
+        serde_json::from_value(value)
+            .with_context(|| format!("unable to parse role assignment response at {scope}"))
     }
 
     /// Delete a role assignment
@@ -631,6 +2128,56 @@ impl PimClient {
         Ok(())
     }
 
+    /// Create an eligible role assignment
+    ///
+    /// The assignment is eligible for `duration`, or permanently if `duration`
+    /// is not provided.
+    ///
+    /// # Errors
+    /// Will return `Err` if the role cannot be found or the request fails
+    pub fn create_eligible_role_assignment(
+        &self,
+        scope: &Scope,
+        role: &Role,
+        principal_id: &str,
+        justification: Option<&str>,
+        duration: Option<Duration>,
+    ) -> Result<()> {
+        let role_definition_id = self.role_definition_id(scope, role)?;
+        info!("making {principal_id} eligible for {role} in {scope}");
+        let expiration = match duration {
+            Some(duration) => serde_json::json!({
+                "type": "AfterDuration",
+                "duration": format_duration(duration)?,
+            }),
+            None => serde_json::json!({ "type": "NoExpiration" }),
+        };
+        let request_id = Uuid::now_v7();
+        let body = serde_json::json!({
+            "properties": {
+                "principalId": principal_id,
+                "roleDefinitionId": role_definition_id,
+                "requestType": "AdminAssign",
+                "justification": justification,
+                "scheduleInfo": {
+                    "expiration": expiration,
+                }
+            }
+        });
+
+        self.backend
+            .request(Method::PUT, Operation::RoleEligibilityScheduleRequests)
+            .extra(format!("/{request_id}"))
+            .scope(scope.clone())
+            .json(body)
+            .validate(check_error_response)
+            .send()
+            .with_context(|| {
+                format!("unable to make {principal_id} eligible for {role} at {scope}")
+            })?;
+        Ok(())
+    }
+
     /// Delete eligibile role assignment
     ///
     /// This removes role assignments that are available via PIM.
@@ -646,6 +2193,7 @@ impl PimClient {
             principal_id,
             principal_type: _,
             object: _,
+            end_date_time: _,
         } = assignment;
 
         let principal_id = principal_id.as_deref().context("missing principal id")?;
@@ -677,6 +2225,61 @@ impl PimClient {
         Ok(())
     }
 
+    /// Move an eligible role assignment to another scope
+    ///
+    /// The principal is made eligible at `scope` for the remaining duration
+    /// of the assignment, then the original assignment is removed.  If the
+    /// original assignment cannot be removed, the new assignment is removed
+    /// such that the principal is left with only the original eligibility.
+    ///
+    /// # Errors
+    /// Will return `Err` if the assignment has expired, or if either the new
+    /// assignment cannot be created or the original cannot be removed
+    pub fn move_eligible_role_assignment(
+        &self,
+        assignment: &RoleAssignment,
+        scope: &Scope,
+        justification: Option<&str>,
+    ) -> Result<()> {
+        let role = &assignment.role;
+        let principal_id = assignment
+            .principal_id
+            .as_deref()
+            .context("missing principal id")?;
+        let duration = assignment
+            .end_date_time
+            .as_deref()
+            .map(|end| {
+                let end = parse_rfc3339_weak(end)
+                    .with_context(|| format!("unable to parse end date {end}"))?;
+                let remaining = end.duration_since(SystemTime::now()).unwrap_or_default();
+                ensure!(!remaining.is_zero(), "eligibility for {role} has expired");
+                Ok(remaining)
+            })
+            .transpose()?;
+
+        let moved = RoleAssignment {
+            scope: scope.clone(),
+            scope_name: None,
+            role_definition_id: self.role_definition_id(scope, role)?,
+            ..assignment.clone()
+        };
+        move_with_rollback(
+            &format!("{role} from {} to {scope}", assignment.scope),
+            || {
+                self.create_eligible_role_assignment(
+                    scope,
+                    role,
+                    principal_id,
+                    justification,
+                    duration,
+                )
+            },
+            || self.delete_eligible_role_assignment(assignment),
+            || self.delete_eligible_role_assignment(&moved),
+        )
+    }
+
     pub fn delete_orphaned_role_assignments(
         &self,
         scope: &Scope,
@@ -712,7 +2315,7 @@ impl PimClient {
                     entry.properties.principal_type,
                     entry.properties.scope
                 );
-                if !answer_yes && !confirm(&format!("delete {value}")) {
+                if !answer_yes && !confirm(&format!("delete {value}"))? {
                     info!("skipping {value}");
                     continue;
                 }
@@ -738,9 +2341,10 @@ impl PimClient {
         } else {
             [scope.clone()].into_iter().collect()
         };
-        for scope in scopes {
+        let scopes = scopes.into_iter().collect::<Vec<_>>();
+        for (scope, entries) in self.list_eligible_role_assignments_at_scopes(&scopes, None) {
             let definitions = self.role_definitions(&scope)?;
-            for entry in self.list_eligible_role_assignments(Some(scope), None)? {
+            for entry in entries? {
                 if entry.object.is_some() {
                     continue;
                 }
@@ -762,7 +2366,7 @@ impl PimClient {
                         .clone()
                         .unwrap_or_else(|| entry.scope.to_string())
                 );
-                if !answer_yes && !confirm(&format!("delete {value}")) {
+                if !answer_yes && !confirm(&format!("delete {value}"))? {
                     info!("skipping {value}");
                     continue;
                 }
@@ -800,37 +2404,69 @@ impl PimClient {
         bail!("unable to find role to administrate RBAC for {scope}");
     }
 
+    /// Find a principal by object ID or user principal name
+    ///
+    /// # Errors
+    /// Will return `Err` if the principal cannot be found
+    pub fn resolve_principal(&self, principal: &str) -> Result<Object> {
+        resolve_principal(self, principal)
+    }
+
+    /// List the members of a group
+    ///
+    /// If `nested` is set, the members of nested groups are included.  This
+    /// uses Graph's `transitiveMembers`, which requires a single request
+    /// regardless of the depth of nesting.  Use `nested_group_members` to
+    /// determine which nested groups grant membership.
+    ///
+    /// # Errors
+    /// Will return `Err` if the members of the group cannot be listed
     pub fn group_members(&self, id: &str, nested: bool) -> Result<BTreeSet<Object>> {
-        if !nested {
-            return group_members(self, id);
-        }
+        group_members(self, id, nested)
+    }
 
+    /// List the members of a group, including the members of nested groups
+    ///
+    /// Each nested group is only expanded once.  Principals that are members
+    /// through multiple nested groups are included once per group.
+    ///
+    /// # Errors
+    /// Will return `Err` if the members of any of the groups cannot be listed
+    pub fn nested_group_members(&self, id: &str) -> Result<BTreeSet<NestedMember>> {
         let mut results = BTreeSet::new();
-        let mut todo = [id.to_string()].into_iter().collect::<BTreeSet<_>>();
-        let mut done = BTreeSet::new();
-
-        while let Some(id) = todo.pop_first() {
-            if done.contains(&id) {
-                continue;
+        let mut todo = VecDeque::from([(id.to_string(), Vec::new())]);
+        let mut done = [id.to_string()].into_iter().collect::<BTreeSet<_>>();
+
+        while let Some((id, via)) = todo.pop_front() {
+            for object in group_members(self, &id, false)? {
+                if object.object_type == PrincipalType::Group && done.insert(object.id.clone()) {
+                    let mut nested = via.clone();
+                    nested.push(object.display_name.clone());
+                    todo.push_back((object.id.clone(), nested));
+                }
+                results.insert(NestedMember {
+                    object,
+                    via: via.clone(),
+                });
             }
-            done.insert(id.clone());
-
-            let group_results = group_members(self, &id)?;
-            todo.extend(
-                group_results
-                    .iter()
-                    .filter(|x| matches!(x.object_type, PrincipalType::Group))
-                    .map(|x| x.id.clone()),
-            );
-            results.extend(group_results);
         }
         Ok(results)
     }
 }
 
+fn failed_entries(results: BTreeMap<RoleAssignment, ActivationResult>) -> BTreeSet<RoleAssignment> {
+    results
+        .into_iter()
+        .filter_map(|(entry, result)| result.is_failed().then_some(entry))
+        .collect()
+}
+
 fn format_duration(duration: Duration) -> Result<String> {
     let mut as_secs = duration.as_secs();
 
+    let days = as_secs / 86400;
+    as_secs %= 86400;
+
     let hours = as_secs / 3600;
     as_secs %= 3600;
 
@@ -848,20 +2484,188 @@ fn format_duration(duration: Duration) -> Result<String> {
         data.push(format!("{seconds}S"));
     }
 
-    ensure!(!data.is_empty(), "duration must be at least 1 second");
-    Ok(format!("PT{}", data.join("")))
+    ensure!(
+        days > 0 || !data.is_empty(),
+        "duration must be at least 1 second"
+    );
+
+    let mut result = "P".to_string();
+    if days > 0 {
+        write!(result, "{days}D")?;
+    }
+    if !data.is_empty() {
+        write!(result, "T{}", data.join(""))?;
+    }
+    Ok(result)
+}
+
+/// Parse a duration, either in the `humantime` format, such as `8h` or
+/// `1 hour 30 minutes`, or as an ISO 8601 duration, such as `PT8H`
+///
+/// # Errors
+/// Will return `Err` if the value is not a valid duration in either format
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    if value.trim_start().starts_with(['P', 'p']) {
+        parse_iso_duration(value)
+    } else {
+        Ok(humantime::parse_duration(value)?)
+    }
+}
+
+/// Parse an ISO 8601 duration, such as `PT8H` or `P1DT12H`
+///
+/// Years and months are not supported, as their length varies.
+///
+/// # Errors
+/// Will return `Err` if the duration is not a valid ISO 8601 duration
+pub fn parse_iso_duration(value: &str) -> Result<Duration> {
+    let Some(rest) = value.trim().strip_prefix(['P', 'p']) else {
+        bail!("ISO 8601 durations must start with P: {value}");
+    };
+
+    let mut secs = 0u64;
+    let mut number = String::new();
+    let mut time = false;
+    let mut found = false;
+    for c in rest.chars() {
+        match c.to_ascii_uppercase() {
+            'T' if !time && number.is_empty() => time = true,
+            '0'..='9' => number.push(c),
+            unit => {
+                let multiplier = match (time, unit) {
+                    (false, 'W') => 7 * 86400,
+                    (false, 'D') => 86400,
+                    (true, 'H') => 3600,
+                    (true, 'M') => 60,
+                    (true, 'S') => 1,
+                    _ => bail!("unsupported ISO 8601 duration: {value}"),
+                };
+                ensure!(!number.is_empty(), "invalid ISO 8601 duration: {value}");
+                let count = number.parse::<u64>()?;
+                secs = count
+                    .checked_mul(multiplier)
+                    .and_then(|x| secs.checked_add(x))
+                    .context("duration is too large")?;
+                number.clear();
+                found = true;
+            }
+        }
+    }
+    ensure!(
+        found && number.is_empty(),
+        "invalid ISO 8601 duration: {value}"
+    );
+    Ok(Duration::from_secs(secs))
+}
+
+/// Create the new assignment, then remove the original, removing the new
+/// assignment if the original cannot be removed
+fn move_with_rollback(
+    description: &str,
+    create: impl FnOnce() -> Result<()>,
+    remove_original: impl FnOnce() -> Result<()>,
+    remove_new: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    create()?;
+    if let Err(err) = remove_original() {
+        warn!("unable to remove the original eligibility moving {description}, removing the new eligibility");
+        if let Err(rollback) = remove_new() {
+            return Err(err.context(format!(
+                "unable to remove the new eligibility moving {description}: {rollback:#}"
+            )));
+        }
+        return Err(err.context(format!("the move of {description} was rolled back")));
+    }
+    Ok(())
+}
+
+/// Ensure `value` matches `pattern`, requesting a compliant value
+/// interactively if it does not and stdin is a terminal
+fn require_match(name: &str, value: &str, pattern: &Regex) -> Result<String> {
+    if pattern.is_match(value) {
+        return Ok(value.to_string());
+    }
+    ensure_interactive(&format!("requesting a compliant {name}")).map_err(|err| {
+        PolicyViolation(format!(
+            "the {name} {value:?} does not match the pattern {:?} required by the config file: {err:#}",
+            pattern.as_str()
+        ))
+    })?;
+    warn!(
+        "the {name} {value:?} does not match the pattern {:?} required by the config file",
+        pattern.as_str()
+    );
+    loop {
+        info!("Enter a {name} matching {:?}: ", pattern.as_str());
+        let mut input = String::new();
+        let read = stdin()
+            .read_line(&mut input)
+            .with_context(|| format!("unable to read {name}"))?;
+        ensure!(read > 0, "no compliant {name} provided");
+        let input = input.trim();
+        if pattern.is_match(input) {
+            return Ok(input.to_string());
+        }
+        warn!("{input:?} does not match {:?}", pattern.as_str());
+    }
+}
+
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Fail rather than prompting for input, such as when running in CI
+///
+/// Prompting for input is also disabled when stdin is not a terminal.
+pub fn use_non_interactive(enabled: bool) {
+    NON_INTERACTIVE.store(enabled, Ordering::Relaxed);
 }
 
-pub fn confirm(msg: &str) -> bool {
+/// Disable prompting for input, returning if it was already disabled
+pub(crate) fn disable_prompts() -> bool {
+    NON_INTERACTIVE.swap(true, Ordering::Relaxed)
+}
+
+/// Check if prompting for input is possible
+#[must_use]
+pub fn is_interactive() -> bool {
+    !NON_INTERACTIVE.load(Ordering::Relaxed) && stdin().is_terminal()
+}
+
+/// Ensure prompting for input is possible before doing so
+///
+/// # Errors
+/// Will return `Err` describing the `action` that requires input if
+/// prompting is not possible
+pub fn ensure_interactive(action: &str) -> Result<()> {
+    if NON_INTERACTIVE.load(Ordering::Relaxed) {
+        bail!("{action} requires input, which is disabled by --non-interactive");
+    }
+    ensure!(
+        stdin().is_terminal(),
+        "{action} requires input, which is not possible as stdin is not a terminal"
+    );
+    Ok(())
+}
+
+/// Ask the user to confirm an action
+///
+/// # Errors
+/// Will return `Err` if prompting for input is not possible, or stdin is
+/// closed
+pub fn confirm(msg: &str) -> Result<bool> {
+    ensure_interactive(&format!("confirming whether to {msg}"))?;
     info!("Are you sure you want to {msg}? (y/n): ");
     loop {
         let mut input = String::new();
-        let Ok(_) = stdin().read_line(&mut input) else {
-            continue;
-        };
+        let read = stdin()
+            .read_line(&mut input)
+            .with_context(|| format!("unable to confirm whether to {msg}"))?;
+        ensure!(
+            read > 0,
+            "unable to confirm whether to {msg}: stdin was closed"
+        );
         match input.trim().to_lowercase().as_str() {
-            "y" => break true,
-            "n" => break false,
+            "y" => break Ok(true),
+            "n" => break Ok(false),
             _ => {
                 warn!("Please enter 'y' or 'n': ");
             }
@@ -872,6 +2676,93 @@ pub fn confirm(msg: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::groups::{activate_group_membership, extend_group_membership, GroupAccess};
+
+    #[test]
+    fn test_policy_applies_to_extensions() -> Result<()> {
+        let policy: PolicyConfig = toml::from_str(
+            r#"
+            dangerous_roles = []
+
+            [[rules]]
+            roles = ["Owner"]
+            block = true
+
+            [[rules]]
+            roles = ["Reader"]
+            max_duration = "1h"
+
+            [[rules]]
+            groups = ["admins"]
+            block = true
+            "#,
+        )?;
+        let client = PimClient::new()?.with_policy(policy);
+        let assignment = |role: &str| -> Result<RoleAssignment> {
+            Ok(RoleAssignment {
+                role: Role(role.to_string()),
+                scope: Scope::new("/subscriptions/00000000-0000-0000-0000-000000000000")?,
+                scope_name: None,
+                role_definition_id: String::new(),
+                principal_id: None,
+                principal_type: None,
+                object: None,
+                end_date_time: None,
+            })
+        };
+        let hour = Duration::from_secs(60 * 60);
+
+        let err = client
+            .extend_role_assignment(&assignment("Owner")?, "testing", hour)
+            .err()
+            .context("extending a blocked role succeeded")?;
+        assert!(format!("{err:#}").contains("blocked"), "{err:#}");
+
+        let err = client
+            .extend_role_assignment(&assignment("Reader")?, "testing", hour * 2)
+            .err()
+            .context("extending beyond max_duration succeeded")?;
+        assert!(
+            format!("{err:#}").contains("limits the duration"),
+            "{err:#}"
+        );
+
+        let membership = GroupMembership {
+            group_id: "00000000-0000-0000-0000-000000000001".to_string(),
+            group_name: Some("Admins".to_string()),
+            access: GroupAccess::Member,
+            principal_id: "00000000-0000-0000-0000-000000000002".to_string(),
+            assignment_type: None,
+            end_date_time: None,
+        };
+        assert!(activate_group_membership(&client, &membership, "testing", hour).is_err());
+        assert!(extend_group_membership(&client, &membership, "testing", hour).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iso_duration() -> Result<()> {
+        for (value, secs) in [
+            ("PT8H", 28800),
+            ("PT1H30M", 5400),
+            ("PT90S", 90),
+            ("P1D", 86400),
+            ("P1DT12H", 129_600),
+            ("P1W", 604_800),
+        ] {
+            assert_eq!(parse_iso_duration(value)?, Duration::from_secs(secs));
+        }
+
+        for value in ["", "8h", "P", "PT", "PT8", "P1M", "PTH", "P1H"] {
+            assert!(parse_iso_duration(value).is_err(), "{value}");
+        }
+
+        assert_eq!(parse_duration("8h")?, Duration::from_secs(28800));
+        assert_eq!(parse_duration("pt8h")?, Duration::from_secs(28800));
+        assert!(parse_duration("P1H").is_err());
+
+        Ok(())
+    }
 
     #[test]
     fn test_format_duration() -> Result<()> {
@@ -882,15 +2773,84 @@ mod tests {
             (60, "PT1M"),
             (61, "PT1M1S"),
             (3600, "PT1H"),
-            (86400, "PT24H"),
-            (86401, "PT24H1S"),
-            (86460, "PT24H1M"),
-            (86520, "PT24H2M"),
-            (90061, "PT25H1M1S"),
+            (86400, "P1D"),
+            (86401, "P1DT1S"),
+            (86460, "P1DT1M"),
+            (86520, "P1DT2M"),
+            (90061, "P1DT1H1M1S"),
+            (1_209_600, "P14D"),
         ] {
-            assert_eq!(format_duration(Duration::from_secs(secs))?, parsed);
+            let duration = Duration::from_secs(secs);
+            assert_eq!(format_duration(duration)?, parsed);
+            assert_eq!(parse_iso_duration(parsed)?, duration);
         }
 
         Ok(())
     }
+
+    #[test]
+    fn test_move_with_rollback() {
+        use std::cell::RefCell;
+
+        let calls = RefCell::new(Vec::new());
+        let step = |name: &'static str, ok: bool| {
+            let calls = &calls;
+            move || {
+                calls.borrow_mut().push(name);
+                if ok {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("{name} failed"))
+                }
+            }
+        };
+
+        assert!(move_with_rollback(
+            "Reader",
+            step("create", true),
+            step("remove_original", true),
+            step("remove_new", true)
+        )
+        .is_ok());
+        assert_eq!(calls.take(), ["create", "remove_original"]);
+
+        // nothing is removed if the new assignment cannot be created
+        assert!(move_with_rollback(
+            "Reader",
+            step("create", false),
+            step("remove_original", true),
+            step("remove_new", true)
+        )
+        .is_err());
+        assert_eq!(calls.take(), ["create"]);
+
+        // the new assignment is removed if the original cannot be
+        let err = move_with_rollback(
+            "Reader",
+            step("create", true),
+            step("remove_original", false),
+            step("remove_new", true),
+        )
+        .err()
+        .map(|x| format!("{x:#}"));
+        assert_eq!(calls.take(), ["create", "remove_original", "remove_new"]);
+        assert_eq!(
+            err.as_deref(),
+            Some("the move of Reader was rolled back: remove_original failed")
+        );
+
+        let err = move_with_rollback(
+            "Reader",
+            step("create", true),
+            step("remove_original", false),
+            step("remove_new", false),
+        )
+        .err()
+        .map(|x| format!("{x:#}"));
+        assert_eq!(calls.take(), ["create", "remove_original", "remove_new"]);
+        assert_eq!(
+            err.as_deref(),
+            Some("unable to remove the new eligibility moving Reader: remove_new failed: remove_original failed")
+        );
+    }
 }
@@ -1,4 +1,5 @@
 use crate::{graph::Object, models::scope::Scope};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -6,7 +7,7 @@ pub(crate) struct Assignments {
     pub(crate) value: Vec<Assignment>,
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, JsonSchema, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Assignment {
     pub id: String,
@@ -18,7 +19,7 @@ pub struct Assignment {
     pub object: Option<Object>,
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, JsonSchema, Clone)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct Properties {
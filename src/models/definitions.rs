@@ -1,3 +1,5 @@
+use crate::models::scope::Scope;
+use anyhow::{ensure, Result};
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -35,7 +37,86 @@ pub struct Properties {
     pub type_: String,
 }
 
-#[derive(Deserialize, Debug, Serialize, Clone)]
+impl Properties {
+    /// Check that the definition can be written as a custom role
+    ///
+    /// # Errors
+    /// Will return `Err` if the definition is not a custom role, has no name,
+    /// assignable scopes, or permissions
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.role_name.trim().is_empty(), "role name is required");
+        ensure!(
+            self.type_ == "CustomRole",
+            "only custom roles can be written, found {}",
+            self.type_
+        );
+        ensure!(
+            !self.assignable_scopes.is_empty(),
+            "at least one assignable scope is required"
+        );
+        ensure!(
+            !self.permissions.is_empty(),
+            "at least one permission is required"
+        );
+        Ok(())
+    }
+
+    /// Create a custom role based on this definition
+    ///
+    /// Actions in `add` are granted.  Actions in `remove` are removed if they
+    /// are granted explicitly, otherwise they are added to `notActions`, such
+    /// that removing an action covered by a wildcard works as expected.
+    #[must_use]
+    pub fn clone_as(
+        &self,
+        role_name: &str,
+        scope: &Scope,
+        add: &[String],
+        remove: &[String],
+    ) -> Self {
+        let mut permissions = self.permissions.clone();
+        if permissions.is_empty() {
+            permissions.push(Permission::default());
+        }
+
+        for permission in &mut permissions {
+            let actions = permission.actions.get_or_insert_with(Vec::new);
+            for action in remove {
+                if actions.contains(action) {
+                    actions.retain(|x| x != action);
+                } else {
+                    let not_actions = permission.not_actions.get_or_insert_with(Vec::new);
+                    if !not_actions.contains(action) {
+                        not_actions.push(action.clone());
+                    }
+                }
+            }
+        }
+
+        if let Some(permission) = permissions.first_mut() {
+            let actions = permission.actions.get_or_insert_with(Vec::new);
+            for action in add {
+                if !actions.contains(action) {
+                    actions.push(action.clone());
+                }
+            }
+        }
+
+        Self {
+            assignable_scopes: vec![scope.to_string()],
+            created_on: None,
+            created_by: None,
+            updated_on: None,
+            updated_by: None,
+            description: format!("Based on {}", self.role_name),
+            permissions,
+            role_name: role_name.to_string(),
+            type_: "CustomRole".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct Permission {
@@ -52,8 +133,10 @@ pub struct Permission {
 #[cfg(test)]
 mod tests {
     use super::Definitions;
-    use anyhow::Result;
+    use crate::models::scope::Scope;
+    use anyhow::{Context, Result};
     use insta::assert_json_snapshot;
+    use uuid::Uuid;
 
     #[test]
     fn test_deserialization() -> Result<()> {
@@ -62,4 +145,42 @@ mod tests {
         assert_json_snapshot!(definitions);
         Ok(())
     }
+
+    #[test]
+    fn test_clone_as() -> Result<()> {
+        const ROLES: &str = include_str!("../../tests/data/definitions.json");
+        let definitions: Definitions = serde_json::from_str(ROLES)?;
+        let source = &definitions
+            .value
+            .first()
+            .context("no definitions")?
+            .properties;
+        let scope = Scope::from_subscription(&Uuid::nil());
+
+        let cloned = source.clone_as(
+            "Reader without networks",
+            &scope,
+            &["Microsoft.Storage/*/read".to_string()],
+            &[
+                "Microsoft.Network/*/read".to_string(),
+                "Microsoft.Compute/virtualMachines/read".to_string(),
+            ],
+        );
+        cloned.validate()?;
+        assert_eq!(cloned.role_name, "Reader without networks");
+        assert_eq!(cloned.assignable_scopes, vec![scope.to_string()]);
+        let permission = cloned.permissions.first().context("no permissions")?;
+        assert_eq!(
+            permission.actions,
+            Some(vec![
+                "Microsoft.Compute/*/read".to_string(),
+                "Microsoft.Storage/*/read".to_string()
+            ])
+        );
+        assert_eq!(
+            permission.not_actions,
+            Some(vec!["Microsoft.Compute/virtualMachines/read".to_string()])
+        );
+        Ok(())
+    }
 }
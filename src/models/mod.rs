@@ -1,5 +1,7 @@
 pub mod assignments;
-pub(crate) mod definitions;
+pub mod definitions;
+pub mod policies;
+pub mod requests;
 pub(crate) mod resources;
 pub mod roles;
 pub mod scope;
@@ -0,0 +1,157 @@
+use crate::models::roles::Role;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+
+// rules that apply when an end user activates an eligible role
+const EXPIRATION_RULE: &str = "Expiration_EndUser_Assignment";
+const APPROVAL_RULE: &str = "Approval_EndUser_Assignment";
+const ENABLEMENT_RULE: &str = "Enablement_EndUser_Assignment";
+
+/// The conditions under which an eligible role can be activated
+// each of these are independent settings of the policy
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ActivationPolicy {
+    /// The maximum activation duration, as an ISO 8601 duration such as `PT8H`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub max_duration: Option<String>,
+    pub approval_required: bool,
+    pub mfa_required: bool,
+    pub justification_required: bool,
+    pub ticket_required: bool,
+    /// The users and groups that can approve activations, if approval is
+    /// required
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub approvers: Vec<String>,
+}
+
+impl ActivationPolicy {
+    /// Check that an activation meets the justification and ticket
+    /// requirements of the policy
+    ///
+    /// # Errors
+    /// Will return `Err` describing each requirement that is not met
+    pub fn check(&self, justification: &str, has_ticket: bool) -> Result<()> {
+        let mut missing = Vec::new();
+        if self.justification_required && justification.trim().is_empty() {
+            missing.push("the role's policy requires a justification");
+        }
+        if self.ticket_required && !has_ticket {
+            missing.push("the role's policy requires ticket information (use --ticket-number)");
+        }
+        if !missing.is_empty() {
+            bail!(missing.join("\n"));
+        }
+        Ok(())
+    }
+}
+
+/// The role management policy that applies to a role at a scope
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RolePolicy {
+    pub role: Role,
+    pub activation: ActivationPolicy,
+}
+
+impl RolePolicy {
+    // NOTE: serde_json doesn't panic on failed index slicing, it returns a Value
+    // that allows further nested nulls
+    #[allow(clippy::indexing_slicing)]
+    pub(crate) fn parse(body: &Value) -> Result<Vec<Self>> {
+        let Some(values) = body["value"].as_array() else {
+            bail!("unable to parse response: missing value array: {body:#?}");
+        };
+
+        let mut results = Vec::new();
+        for entry in values {
+            let properties = &entry["properties"];
+
+            let Some(role) = properties["policyAssignmentProperties"]["roleDefinition"]
+                ["displayName"]
+                .as_str()
+                .and_then(|x| Role::from_str(x).ok())
+            else {
+                bail!("no role name: {entry:#?}");
+            };
+
+            let mut activation = ActivationPolicy::default();
+            for rule in properties["effectiveRules"]
+                .as_array()
+                .into_iter()
+                .flatten()
+            {
+                match rule["id"].as_str() {
+                    Some(EXPIRATION_RULE) => {
+                        activation.max_duration =
+                            rule["maximumDuration"].as_str().map(ToString::to_string);
+                    }
+                    Some(APPROVAL_RULE) => {
+                        activation.approval_required = rule["setting"]["isApprovalRequired"]
+                            .as_bool()
+                            .unwrap_or_default();
+                        activation.approvers = rule["setting"]["approvalStages"]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .flat_map(|stage| {
+                                stage["primaryApprovers"].as_array().into_iter().flatten()
+                            })
+                            .filter_map(|approver| {
+                                approver["description"]
+                                    .as_str()
+                                    .or_else(|| approver["id"].as_str())
+                                    .map(ToString::to_string)
+                            })
+                            .collect();
+                    }
+                    Some(ENABLEMENT_RULE) => {
+                        let enabled = rule["enabledRules"]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(Value::as_str)
+                            .collect::<Vec<_>>();
+                        activation.mfa_required = enabled.contains(&"MultiFactorAuthentication");
+                        activation.justification_required = enabled.contains(&"Justification");
+                        activation.ticket_required = enabled.contains(&"Ticketing");
+                    }
+                    _ => {}
+                }
+            }
+
+            results.push(Self { role, activation });
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActivationPolicy, RolePolicy};
+    use anyhow::Result;
+    use insta::assert_json_snapshot;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        const DATA: &str = include_str!("../../tests/data/policy-assignments.json");
+        let policies = RolePolicy::parse(&serde_json::from_str(DATA)?)?;
+        assert_json_snapshot!(policies);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = ActivationPolicy {
+            justification_required: true,
+            ticket_required: true,
+            ..ActivationPolicy::default()
+        };
+        assert!(policy.check("deploying release", true).is_ok());
+        assert!(policy.check("  ", true).is_err());
+        assert!(policy.check("deploying release", false).is_err());
+        assert!(ActivationPolicy::default().check("", false).is_ok());
+    }
+}
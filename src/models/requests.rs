@@ -0,0 +1,187 @@
+use crate::models::{roles::Role, scope::Scope};
+use anyhow::{bail, Result};
+use humantime::parse_rfc3339_weak;
+use serde::Serialize;
+use serde_json::Value;
+use std::{str::FromStr, time::SystemTime};
+
+/// A historical request to activate, deactivate, or extend a role assignment
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleRequest {
+    /// The name of the request
+    pub name: String,
+    pub created_on: String,
+    pub principal_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub principal_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub principal_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub principal_type: Option<String>,
+    pub role: Role,
+    pub scope: Scope,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope_name: Option<String>,
+    pub request_type: String,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub justification: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticket_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticket_system: Option<String>,
+    /// The id of the approval, for requests that require approval
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approval_id: Option<String>,
+}
+
+impl ScheduleRequest {
+    /// The time the request was made
+    ///
+    /// Returns `None` if the timestamp cannot be parsed.
+    #[must_use]
+    pub fn time(&self) -> Option<SystemTime> {
+        parse_rfc3339_weak(&self.created_on).ok()
+    }
+
+    /// The email, display name, or id of the principal, in that order of
+    /// preference
+    #[must_use]
+    pub fn principal_display(&self) -> String {
+        self.principal_email
+            .clone()
+            .or_else(|| self.principal_name.clone())
+            .unwrap_or_else(|| self.principal_id.clone())
+    }
+
+    /// Check if every filter matches the role, scope, or principal of the
+    /// request, ignoring case
+    #[must_use]
+    pub fn matches_filters(&self, filters: &[String]) -> bool {
+        let fields = [
+            Some(self.role.0.as_str()),
+            Some(self.scope.0.as_str()),
+            self.scope_name.as_deref(),
+            Some(self.principal_id.as_str()),
+            self.principal_name.as_deref(),
+            self.principal_email.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+        filters.iter().all(|filter| {
+            let filter = filter.to_lowercase();
+            fields.iter().any(|field| field.contains(&filter))
+        })
+    }
+
+    /// Check if the request is waiting to be approved
+    #[must_use]
+    pub fn is_pending_approval(&self) -> bool {
+        self.status.starts_with("PendingApproval")
+    }
+
+    /// Check if the principal id, name, or email matches `principal`
+    #[must_use]
+    pub fn is_principal(&self, principal: &str) -> bool {
+        self.principal_id.eq_ignore_ascii_case(principal)
+            || [&self.principal_name, &self.principal_email]
+                .into_iter()
+                .flatten()
+                .any(|x| x.eq_ignore_ascii_case(principal))
+    }
+
+    // NOTE: serde_json doesn't panic on failed index slicing, it returns a Value
+    // that allows further nested nulls
+    #[allow(clippy::indexing_slicing)]
+    pub(crate) fn parse(body: &Value) -> Result<Vec<Self>> {
+        let Some(values) = body["value"].as_array() else {
+            bail!("unable to parse response: missing value array: {body:#?}");
+        };
+
+        let string = |value: &Value| value.as_str().map(ToString::to_string);
+
+        let mut results = Vec::new();
+        for entry in values {
+            let properties = &entry["properties"];
+            let expanded = &properties["expandedProperties"];
+
+            let Some(role) = expanded["roleDefinition"]["displayName"]
+                .as_str()
+                .and_then(|x| Role::from_str(x).ok())
+            else {
+                bail!("no role name: {entry:#?}");
+            };
+
+            let Some(scope) = expanded["scope"]["id"]
+                .as_str()
+                .and_then(|x| Scope::from_str(x).ok())
+            else {
+                bail!("no scope id: {entry:#?}");
+            };
+
+            let Some(name) = string(&entry["name"]) else {
+                bail!("no name: {entry:#?}");
+            };
+
+            let Some(created_on) = string(&properties["createdOn"]) else {
+                bail!("no created on: {entry:#?}");
+            };
+
+            let Some(principal_id) = string(&properties["principalId"]) else {
+                bail!("no principal id: {entry:#?}");
+            };
+
+            results.push(Self {
+                name,
+                created_on,
+                principal_id,
+                principal_name: string(&expanded["principal"]["displayName"]),
+                principal_email: string(&expanded["principal"]["email"]),
+                principal_type: string(&properties["principalType"]),
+                role,
+                scope,
+                scope_name: string(&expanded["scope"]["displayName"]),
+                request_type: string(&properties["requestType"]).unwrap_or_default(),
+                status: string(&properties["status"]).unwrap_or_default(),
+                duration: string(&properties["scheduleInfo"]["expiration"]["duration"]),
+                justification: string(&properties["justification"]),
+                ticket_number: string(&properties["ticketInfo"]["ticketNumber"]),
+                ticket_system: string(&properties["ticketInfo"]["ticketSystem"]),
+                approval_id: string(&properties["approvalId"]),
+            });
+        }
+
+        results.sort_by(|a, b| a.created_on.cmp(&b.created_on));
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScheduleRequest;
+    use anyhow::Result;
+    use insta::assert_json_snapshot;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        const DATA: &str = include_str!("../../tests/data/schedule-requests.json");
+        let requests = ScheduleRequest::parse(&serde_json::from_str(DATA)?)?;
+        assert!(requests.iter().all(|x| x.time().is_some()));
+        assert!(requests.iter().all(|x| x.is_principal("USER@contoso.com")));
+        assert_eq!(
+            requests.iter().filter(|x| x.is_pending_approval()).count(),
+            1
+        );
+        let filters = ["custom role".to_string(), "AZURE-SUB".to_string()];
+        assert!(requests.iter().all(|x| x.matches_filters(&filters)));
+        assert!(!requests
+            .iter()
+            .any(|x| x.matches_filters(&["Owner".to_string()])));
+        assert_json_snapshot!(requests);
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::models::scope::Scope;
+use crate::models::scope::{Scope, ScopeLevel};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -27,11 +27,61 @@ impl ChildResource {
 
         Ok(results)
     }
+
+    /// The ARM resource type, such as `Microsoft.KeyVault/vaults`, based on
+    /// the resource's id
+    ///
+    /// Scopes that are not resources, such as resource groups, use the type
+    /// of the child resource, such as `resourcegroup`.
+    #[must_use]
+    pub fn resource_type(&self) -> String {
+        let Some((_, path)) = self.id.0.rsplit_once("/providers/") else {
+            return self.type_.clone();
+        };
+        // the path is the namespace, followed by pairs of type and name
+        let mut parts = path.split('/');
+        let namespace = parts.next().unwrap_or_default();
+        let types = parts.step_by(2).collect::<Vec<_>>();
+        if types.is_empty() {
+            return self.type_.clone();
+        }
+        format!("{namespace}/{}", types.join("/"))
+    }
+
+    /// Check if the child resource is one of `types`, or if `types` is empty
+    #[must_use]
+    pub fn is_any_type(&self, types: &[String]) -> bool {
+        if types.is_empty() {
+            return true;
+        }
+        let resource_type = self.resource_type();
+        types
+            .iter()
+            .any(|x| x.eq_ignore_ascii_case(&resource_type) || x.eq_ignore_ascii_case(&self.type_))
+    }
+
+    /// Check if any of `types` could be nested beneath the child resource
+    ///
+    /// Management groups, subscriptions, and resource groups can contain any
+    /// type of resource, while resources only contain their nested resource
+    /// types, such as `Microsoft.Storage/storageAccounts/blobServices` within
+    /// `Microsoft.Storage/storageAccounts`.
+    #[must_use]
+    pub fn may_contain_any_type(&self, types: &[String]) -> bool {
+        if types.is_empty() || self.id.level() < ScopeLevel::Resource {
+            return true;
+        }
+        let prefix = format!("{}/", self.resource_type().to_ascii_lowercase());
+        types
+            .iter()
+            .any(|x| x.to_ascii_lowercase().starts_with(&prefix))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::ChildResource;
+    use crate::models::scope::Scope;
     use anyhow::Result;
     use insta::assert_json_snapshot;
     use serde_json::{from_str, Value};
@@ -43,4 +93,43 @@ mod tests {
         assert_json_snapshot!(result);
         Ok(())
     }
+
+    #[test]
+    fn test_resource_types() -> Result<()> {
+        let rg = "/subscriptions/00000000-0000-0000-0000-000000000000/resourceGroups/rg";
+        let resource = |id: String, type_: &str| -> Result<ChildResource> {
+            Ok(ChildResource {
+                id: Scope::new(id)?,
+                name: String::new(),
+                type_: type_.to_string(),
+            })
+        };
+        let group = resource(rg.to_string(), "resourcegroup")?;
+        let account = resource(
+            format!("{rg}/providers/Microsoft.Storage/storageAccounts/sa"),
+            "resource",
+        )?;
+        let blobs = resource(
+            format!("{rg}/providers/Microsoft.Storage/storageAccounts/sa/blobServices/default"),
+            "resource",
+        )?;
+        assert_eq!(group.resource_type(), "resourcegroup");
+        assert_eq!(account.resource_type(), "Microsoft.Storage/storageAccounts");
+        assert_eq!(
+            blobs.resource_type(),
+            "Microsoft.Storage/storageAccounts/blobServices"
+        );
+
+        let vaults = ["microsoft.keyvault/vaults".to_string()];
+        assert!(!account.is_any_type(&vaults));
+        assert!(group.may_contain_any_type(&vaults));
+        assert!(!account.may_contain_any_type(&vaults));
+
+        let nested = ["Microsoft.Storage/storageAccounts/blobServices".to_string()];
+        assert!(blobs.is_any_type(&nested));
+        assert!(account.may_contain_any_type(&nested));
+        assert!(group.is_any_type(&["resourcegroup".to_string()]));
+        assert!(account.is_any_type(&[]));
+        Ok(())
+    }
 }
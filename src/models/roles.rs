@@ -1,17 +1,22 @@
 use crate::{
+    fuzzy::{distance, levenshtein},
     graph::Object,
     models::scope::{Scope, ScopeError},
 };
 use anyhow::{bail, Result};
+use humantime::parse_rfc3339_weak;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
+    cmp::Ordering,
     collections::BTreeSet,
     fmt::{Display, Formatter, Result as FmtResult},
     str::FromStr,
+    time::{Duration, SystemTime},
 };
 
-#[derive(Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone, Deserialize)]
+#[derive(Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone, Deserialize, JsonSchema)]
 pub struct Role(pub String);
 impl Display for Role {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
@@ -26,13 +31,30 @@ impl FromStr for Role {
     }
 }
 
+// maximum number of suggestions included when a role cannot be found
+const MAX_SUGGESTIONS: usize = 5;
+
+// maximum edit distance allowed for scopes when fuzzy matching.  Scopes are
+// mostly GUIDs, so only allow for small typos.
+const MAX_SCOPE_DISTANCE: usize = 2;
+
 pub trait RolesExt {
+    #[must_use]
     fn find_role(&self, role: &Role, scope: &Scope) -> Option<RoleAssignment>;
+
+    /// Find a role, ignoring case
+    ///
+    /// If no match can be found, the error includes the closest roles and
+    /// scopes as suggestions, unless `exact` is set.
+    ///
+    /// # Errors
+    /// Will return `Err` if no match can be found
+    fn resolve_role(&self, role: &Role, scope: &Scope, exact: bool) -> Result<RoleAssignment>;
+
     fn friendly(&self) -> String;
 }
 
 impl RolesExt for &BTreeSet<RoleAssignment> {
-    #[must_use]
     fn find_role(&self, role: &Role, scope: &Scope) -> Option<RoleAssignment> {
         let role = role.0.to_lowercase();
         self.iter()
@@ -40,6 +62,48 @@ impl RolesExt for &BTreeSet<RoleAssignment> {
             .cloned()
     }
 
+    fn resolve_role(&self, role: &Role, scope: &Scope, exact: bool) -> Result<RoleAssignment> {
+        if let Some(entry) = self.find_role(role, scope) {
+            return Ok(entry);
+        }
+        if exact {
+            bail!("role not found.  role:{role} scope:{scope}");
+        }
+
+        let scope_lower = scope.0.to_lowercase();
+        let mut candidates = self
+            .iter()
+            .map(|entry| {
+                let role_distance = distance(&role.0, &entry.role.0);
+                let scope_distance = levenshtein(&scope_lower, &entry.scope.0.to_lowercase());
+                (role_distance, scope_distance, entry)
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(role_distance, scope_distance, entry)| {
+            (role_distance + scope_distance, *entry)
+        });
+
+        // only suggest entries where either the role or the scope is close
+        let max_role_distance = (role.0.chars().count() / 4).max(2);
+        let suggestions = candidates
+            .iter()
+            .filter(|(role_distance, scope_distance, _)| {
+                *role_distance <= max_role_distance || *scope_distance <= MAX_SCOPE_DISTANCE
+            })
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, _, entry)| format!("* {}", entry.friendly()))
+            .collect::<Vec<_>>();
+
+        if suggestions.is_empty() {
+            bail!("role not found.  role:{role} scope:{scope}");
+        }
+
+        bail!(
+            "role not found.  role:{role} scope:{scope}\nclosest matches:\n{}",
+            suggestions.join("\n")
+        );
+    }
+
     fn friendly(&self) -> String {
         self.iter()
             .map(|x| format!("* {}", x.friendly()))
@@ -49,17 +113,20 @@ impl RolesExt for &BTreeSet<RoleAssignment> {
 }
 
 impl RolesExt for BTreeSet<RoleAssignment> {
-    #[must_use]
     fn find_role(&self, role: &Role, scope: &Scope) -> Option<RoleAssignment> {
         (&self).find_role(role, scope)
     }
 
+    fn resolve_role(&self, role: &Role, scope: &Scope, exact: bool) -> Result<RoleAssignment> {
+        (&self).resolve_role(role, scope, exact)
+    }
+
     fn friendly(&self) -> String {
         (&self).friendly()
     }
 }
 
-#[derive(Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone)]
+#[derive(Serialize, Debug, Clone)]
 pub struct RoleAssignment {
     pub role: Role,
     pub scope: Scope,
@@ -73,9 +140,67 @@ pub struct RoleAssignment {
     pub principal_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub object: Option<Object>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_date_time: Option<String>,
 }
 
+// `end_date_time` is intentionally excluded from comparisons, such that the
+// eligible and active instances of the same role are considered equal.
 impl RoleAssignment {
+    #[allow(clippy::type_complexity)]
+    fn key(
+        &self,
+    ) -> (
+        &Role,
+        &Scope,
+        &Option<String>,
+        &String,
+        &Option<String>,
+        &Option<String>,
+        &Option<Object>,
+    ) {
+        (
+            &self.role,
+            &self.scope,
+            &self.scope_name,
+            &self.role_definition_id,
+            &self.principal_id,
+            &self.principal_type,
+            &self.object,
+        )
+    }
+}
+
+impl PartialEq for RoleAssignment {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for RoleAssignment {}
+
+impl PartialOrd for RoleAssignment {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RoleAssignment {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl RoleAssignment {
+    /// Time remaining until the assignment ends
+    ///
+    /// Returns `None` if the assignment does not have an end time.
+    #[must_use]
+    pub fn expires_in(&self) -> Option<Duration> {
+        let end = parse_rfc3339_weak(self.end_date_time.as_deref()?).ok()?;
+        Some(end.duration_since(SystemTime::now()).unwrap_or_default())
+    }
+
     pub(crate) fn friendly(&self) -> String {
         if let Some(scope_name) = self.scope_name.as_ref() {
             format!("\"{}\" in \"{}\" ({})", self.role, scope_name, self.scope)
@@ -133,6 +258,10 @@ impl RoleAssignment {
                 (None, None)
             };
 
+            let end_date_time = entry["properties"]["endDateTime"]
+                .as_str()
+                .map(ToString::to_string);
+
             results.insert(Self {
                 role,
                 scope,
@@ -141,6 +270,7 @@ impl RoleAssignment {
                 principal_id,
                 principal_type,
                 object: None,
+                end_date_time,
             });
         }
 
@@ -150,8 +280,8 @@ impl RoleAssignment {
 
 #[cfg(test)]
 mod tests {
-    use super::{RoleAssignment, Scope};
-    use anyhow::Result;
+    use super::{Role, RoleAssignment, RolesExt, Scope};
+    use anyhow::{Context, Result};
     use insta::assert_json_snapshot;
     use uuid::Uuid;
 
@@ -165,6 +295,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn resolve_role() -> Result<()> {
+        const ASSIGNMENTS: &str = include_str!("../../tests/data/role-assignments.json");
+        let assignments = RoleAssignment::parse(&serde_json::from_str(ASSIGNMENTS)?, false)?;
+        let expected = assignments
+            .iter()
+            .next()
+            .cloned()
+            .context("missing test data")?;
+
+        let exact = assignments.resolve_role(&expected.role, &expected.scope, true)?;
+        assert_eq!(exact, expected);
+
+        let typo = Role(expected.role.0.replacen("Role", "Rle", 1));
+        let err = assignments
+            .resolve_role(&typo, &expected.scope, true)
+            .err()
+            .map(|x| x.to_string())
+            .unwrap_or_default();
+        assert!(!err.contains("closest matches"), "{err}");
+        let err = assignments
+            .resolve_role(&typo, &expected.scope, false)
+            .err()
+            .map(|x| x.to_string())
+            .unwrap_or_default();
+        assert!(err.contains(&expected.friendly()), "{err}");
+
+        let err = assignments
+            .resolve_role(&Role("not a real role".to_string()), &expected.scope, false)
+            .err()
+            .map(|x| x.to_string())
+            .unwrap_or_default();
+        assert!(err.contains("closest matches"), "{err}");
+        Ok(())
+    }
+
     #[test]
     fn test_scope() {
         let uuid = Uuid::now_v7();
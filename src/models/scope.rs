@@ -1,10 +1,13 @@
-use anyhow::Result;
-use clap::Args;
+use crate::{az_cli::default_subscription, picker::pick_scope, PimClient};
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::{Display, Formatter, Result as FmtResult},
     str::FromStr,
 };
+use tracing::info;
 use uuid::Uuid;
 
 #[derive(thiserror::Error, Debug)]
@@ -13,8 +16,34 @@ pub enum ScopeError {
     LeadingSlash,
 }
 
-#[derive(Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone, Deserialize, Hash)]
-pub struct Scope(pub(crate) String);
+/// The level of the resource hierarchy for a scope, from broadest to narrowest
+#[allow(clippy::manual_assert, clippy::panic)]
+#[derive(
+    Clone,
+    Copy,
+    ValueEnum,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScopeLevel {
+    Root,
+    ManagementGroup,
+    Subscription,
+    ResourceGroup,
+    Resource,
+}
+
+#[derive(
+    Serialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone, Deserialize, Hash, JsonSchema,
+)]
+pub struct Scope(#[schemars(regex(pattern = "^/"))] pub(crate) String);
 impl Scope {
     pub fn new<S: Into<String>>(value: S) -> Result<Self, ScopeError> {
         let value = value.into();
@@ -48,6 +77,26 @@ impl Scope {
         self.0.starts_with("/subscriptions/") && !self.0.contains("/resourceGroups/")
     }
 
+    #[must_use]
+    pub fn level(&self) -> ScopeLevel {
+        let parts = self
+            .0
+            .split('/')
+            .filter(|x| !x.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect::<Vec<_>>();
+        let parts = parts.iter().map(String::as_str).collect::<Vec<_>>();
+        match parts.as_slice() {
+            [] => ScopeLevel::Root,
+            ["providers", "microsoft.management", "managementgroups", _] => {
+                ScopeLevel::ManagementGroup
+            }
+            ["subscriptions", _] => ScopeLevel::Subscription,
+            ["subscriptions", _, "resourcegroups", _] => ScopeLevel::ResourceGroup,
+            _ => ScopeLevel::Resource,
+        }
+    }
+
     #[must_use]
     pub fn subscription(&self) -> Option<Uuid> {
         let entries = self.0.split('/').collect::<Vec<_>>();
@@ -104,11 +153,67 @@ pub struct ScopeBuilder {
     provider: Option<String>,
 
     /// Specify the full scope directly
+    ///
+    /// Use `interactive` to select from the scopes where you have eligible
+    /// assignments.
     #[arg(long, conflicts_with = "subscription")]
-    scope: Option<Scope>,
+    scope: Option<ScopeArg>,
+}
+
+/// The value used with `--scope` to select a scope interactively
+const INTERACTIVE_SCOPE: &str = "interactive";
+
+#[derive(Clone)]
+enum ScopeArg {
+    Scope(Scope),
+    Interactive,
+}
+
+impl FromStr for ScopeArg {
+    type Err = ScopeError;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case(INTERACTIVE_SCOPE) {
+            Ok(Self::Interactive)
+        } else {
+            Ok(Self::Scope(s.parse()?))
+        }
+    }
 }
 
 impl ScopeBuilder {
+    /// Build the scope, selecting it interactively if `--scope interactive`
+    /// was specified
+    ///
+    /// # Errors
+    /// Will return `Err` if the scope is to be selected interactively and the
+    /// selection fails or is cancelled
+    pub fn resolve(self, client: &PimClient) -> Result<Option<Scope>> {
+        if matches!(self.scope, Some(ScopeArg::Interactive)) {
+            let scope = pick_scope(client, false)?.context("no scope selected")?;
+            return Ok(Some(scope));
+        }
+        Ok(self.build())
+    }
+
+    /// Build the scope, falling back to the Azure CLI's default subscription
+    /// if no scope was specified
+    ///
+    /// # Errors
+    /// Will return `Err` if selecting the scope interactively fails, or if no
+    /// scope was specified and the default subscription cannot be determined
+    pub fn resolve_or_default(self, client: &PimClient) -> Result<Scope> {
+        if let Some(scope) = self.resolve(client)? {
+            return Ok(scope);
+        }
+        let subscription = default_subscription().context("valid scope must be provided")?;
+        info!("no scope specified, using the default subscription {subscription}");
+        Ok(Scope::from_subscription(&subscription))
+    }
+
+    /// Build the scope from the arguments
+    ///
+    /// This returns `None` if `--scope interactive` was specified, use
+    /// [`ScopeBuilder::resolve`] to support selecting the scope.
     #[must_use]
     pub fn build(self) -> Option<Scope> {
         let Self {
@@ -126,8 +231,8 @@ impl ScopeBuilder {
                 Some(Scope::from_resource_group(&subscription, &group))
             }
             (Some(subscription), None, None, None) => Some(Scope::from_subscription(&subscription)),
-            (None, None, None, Some(scope)) => Some(scope),
-            (None, None, None, None) => None,
+            (None, None, None, Some(ScopeArg::Scope(scope))) => Some(scope),
+            (None, None, None, Some(ScopeArg::Interactive) | None) => None,
             _ => {
                 unreachable!("invalid combination of arguments provided");
             }
@@ -137,7 +242,7 @@ impl ScopeBuilder {
 
 #[cfg(test)]
 mod tests {
-    use crate::models::scope::Scope;
+    use crate::models::scope::{Scope, ScopeLevel};
 
     #[test]
     fn test_contains() {
@@ -163,4 +268,30 @@ mod tests {
         assert!(with_sub1.contains(&with_sub1));
         assert!(!with_sub1.contains(&with_sub2));
     }
+
+    #[test]
+    fn test_level() {
+        let cases = [
+            ("/", ScopeLevel::Root),
+            (
+                "/providers/Microsoft.Management/managementGroups/mg",
+                ScopeLevel::ManagementGroup,
+            ),
+            (
+                "/subscriptions/00000000-0000-0000-0000-000000000000",
+                ScopeLevel::Subscription,
+            ),
+            (
+                "/subscriptions/00000000-0000-0000-0000-000000000000/resourceGroups/rg",
+                ScopeLevel::ResourceGroup,
+            ),
+            (
+                "/subscriptions/00000000-0000-0000-0000-000000000000/resourceGroups/rg/providers/Microsoft.KeyVault/vaults/kv",
+                ScopeLevel::Resource,
+            ),
+        ];
+        for (scope, level) in cases {
+            assert_eq!(Scope(scope.to_string()).level(), level, "{scope}");
+        }
+    }
 }
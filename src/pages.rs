@@ -0,0 +1,138 @@
+use crate::{
+    az_cli::TokenScope, backend::RequestBuilder, models::roles::RoleAssignment, PimClient,
+};
+use anyhow::{anyhow, Context, Result};
+use reqwest::Method;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use tracing::info;
+
+const MAX_PAGES: usize = 1000;
+
+enum NextPage<'a> {
+    First(RequestBuilder<'a>),
+    Fetched(Value),
+    Link(String),
+}
+
+/// Role assignments listed page by page, following `nextLink` until all of
+/// the pages have been read
+///
+/// Each page is requested as the iterator is advanced, which allows
+/// processing the results before a large listing completes.  After an error,
+/// no further pages are returned.
+pub struct RoleAssignmentPages<'a> {
+    client: &'a PimClient,
+    next: Option<NextPage<'a>>,
+    with_principal: bool,
+    lookup_principals: bool,
+    pages: usize,
+    description: &'static str,
+}
+
+impl<'a> RoleAssignmentPages<'a> {
+    pub(crate) fn new(
+        client: &'a PimClient,
+        request: RequestBuilder<'a>,
+        with_principal: bool,
+        description: &'static str,
+    ) -> Self {
+        Self::start(
+            client,
+            NextPage::First(request),
+            with_principal,
+            description,
+        )
+    }
+
+    /// Continue a listing from its first page, which has already been
+    /// requested, such as using the batch API
+    pub(crate) fn from_response(
+        client: &'a PimClient,
+        response: Value,
+        with_principal: bool,
+        description: &'static str,
+    ) -> Self {
+        Self::start(
+            client,
+            NextPage::Fetched(response),
+            with_principal,
+            description,
+        )
+    }
+
+    fn start(
+        client: &'a PimClient,
+        next: NextPage<'a>,
+        with_principal: bool,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            client,
+            next: Some(next),
+            with_principal,
+            lookup_principals: with_principal,
+            pages: 0,
+            description,
+        }
+    }
+
+    /// Parse the principals of the role assignments without looking them up,
+    /// such that the principals of many listings can be looked up at once
+    pub(crate) fn without_principal_lookup(mut self) -> Self {
+        self.lookup_principals = false;
+        self
+    }
+
+    fn fetch(&mut self, next: NextPage<'a>) -> Result<BTreeSet<RoleAssignment>> {
+        let description = self.description;
+        let response = match next {
+            NextPage::First(request) => request.send(),
+            NextPage::Fetched(response) => Ok(response),
+            NextPage::Link(url) => {
+                let backend = &self.client.backend;
+                let request = backend
+                    .client
+                    .request(Method::GET, &url)
+                    .bearer_auth(backend.get_token(TokenScope::Management)?)
+                    .build()?;
+                backend.retry_request(&request, None)
+            }
+        }
+        .with_context(|| format!("unable to list {description}"))?;
+        self.pages += 1;
+
+        self.next = response
+            .get("nextLink")
+            .and_then(Value::as_str)
+            .map(|x| NextPage::Link(x.to_string()));
+        if self.next.is_some() {
+            info!("listed {} pages, requesting the next page", self.pages);
+        }
+
+        let results = RoleAssignment::parse(&response, self.with_principal)
+            .with_context(|| format!("unable to parse {description}"))?;
+        if self.lookup_principals {
+            return self.client.add_principals(results);
+        }
+        Ok(results)
+    }
+}
+
+impl Iterator for RoleAssignmentPages<'_> {
+    type Item = Result<BTreeSet<RoleAssignment>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next.take()?;
+        if self.pages >= MAX_PAGES {
+            return Some(Err(anyhow!(
+                "more than {MAX_PAGES} pages of results, giving up"
+            )));
+        }
+        let result = self.fetch(next);
+        if result.is_err() {
+            self.next = None;
+        }
+        Some(result)
+    }
+}
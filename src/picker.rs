@@ -0,0 +1,288 @@
+use crate::{
+    ensure_interactive,
+    fuzzy::subsequence,
+    models::{roles::RoleAssignment, scope::Scope},
+    theme::theme,
+    PimClient,
+};
+use anyhow::{bail, ensure, Result};
+use ratatui::{
+    crossterm::{
+        event::{
+            self, Event,
+            KeyCode::{Backspace, Char, Down, Enter, Esc, Up},
+            KeyEventKind,
+        },
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    prelude::*,
+    widgets::{BorderType, HighlightSpacing, Paragraph, Row, Table, TableState},
+};
+use std::{
+    collections::BTreeSet,
+    io::{stderr, IsTerminal},
+};
+
+const HELP_TEXT: &str = "Type to filter | ↑ or ↓ to move | Enter to select | Esc to quit";
+
+/// An item that can be selected with [`pick`]
+pub struct PickerItem<T> {
+    /// The primary text shown for the item
+    pub label: String,
+    /// Additional text shown alongside the label, which is also searched
+    pub detail: Option<String>,
+    pub value: T,
+}
+
+struct App<T> {
+    title: String,
+    query: String,
+    items: Vec<PickerItem<T>>,
+    table_state: TableState,
+}
+
+impl<T> App<T> {
+    /// Indexes of the items that match the query, best matches first
+    fn matches(&self) -> Vec<usize> {
+        let mut matches = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| {
+                let label = subsequence(&self.query, &item.label);
+                let detail = item
+                    .detail
+                    .as_deref()
+                    .and_then(|x| subsequence(&self.query, x));
+                let score = match (label, detail) {
+                    (Some(label), Some(detail)) => label.min(detail),
+                    (Some(score), None) | (None, Some(score)) => score,
+                    (None, None) => return None,
+                };
+                Some((score, i))
+            })
+            .collect::<Vec<_>>();
+        matches.sort_unstable();
+        matches.into_iter().map(|(_, i)| i).collect()
+    }
+
+    fn update_query(&mut self) {
+        let selected = if self.matches().is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        self.table_state.select(selected);
+    }
+
+    fn next(&mut self) {
+        let count = self.matches().len();
+        if count == 0 {
+            return;
+        }
+        let i = self
+            .table_state
+            .selected()
+            .map_or(0, |i| if i + 1 >= count { 0 } else { i + 1 });
+        self.table_state.select(Some(i));
+    }
+
+    fn previous(&mut self) {
+        let count = self.matches().len();
+        if count == 0 {
+            return;
+        }
+        let i = self
+            .table_state
+            .selected()
+            .map_or(0, |i| if i == 0 { count - 1 } else { i - 1 });
+        self.table_state.select(Some(i));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [title, query, list, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(3),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(
+            Paragraph::new(self.title.clone())
+                .style(Style::default().add_modifier(Modifier::BOLD))
+                .alignment(Alignment::Center),
+            title,
+        );
+
+        frame.render_widget(
+            Paragraph::new(self.query.clone()).block(theme().block().title("Search")),
+            query,
+        );
+        #[allow(clippy::cast_possible_truncation)]
+        frame.set_cursor_position((query.x + self.query.len() as u16 + 1, query.y + 1));
+
+        let matches = self.matches();
+        let rows = matches
+            .iter()
+            .filter_map(|i| self.items.get(*i))
+            .map(|item| Row::new([item.label.clone(), item.detail.clone().unwrap_or_default()]));
+        let label_width = self
+            .items
+            .iter()
+            .map(|x| x.label.len())
+            .max()
+            .unwrap_or_default();
+        frame.render_stateful_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Length(u16::try_from(label_width).unwrap_or(u16::MAX)),
+                    Constraint::Fill(1),
+                ],
+            )
+            .row_highlight_style(theme().selection)
+            .highlight_spacing(HighlightSpacing::Always)
+            .block(theme().block().title(format!(
+                "Matches ({} of {})",
+                matches.len(),
+                self.items.len()
+            ))),
+            list,
+            &mut self.table_state,
+        );
+
+        frame.render_widget(
+            Paragraph::new(HELP_TEXT).centered().block(
+                theme()
+                    .block()
+                    .title("Help")
+                    .border_type(BorderType::Double),
+            ),
+            footer,
+        );
+    }
+
+    fn run<B: Backend>(mut self, terminal: &mut Terminal<B>) -> Result<Option<T>> {
+        loop {
+            terminal.draw(|f| self.draw(f))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                Char(c) => {
+                    self.query.push(c);
+                    self.update_query();
+                }
+                Backspace => {
+                    self.query.pop();
+                    self.update_query();
+                }
+                Down => self.next(),
+                Up => self.previous(),
+                Esc => return Ok(None),
+                Enter => {
+                    let Some(selected) = self
+                        .table_state
+                        .selected()
+                        .and_then(|i| self.matches().get(i).copied())
+                    else {
+                        continue;
+                    };
+                    return Ok(self.items.into_iter().nth(selected).map(|item| item.value));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Interactively select one of `items` using a fuzzy finder
+///
+/// The picker is drawn on stderr, such that stdout can be captured by the
+/// caller.  Returns `None` if the selection is cancelled.
+///
+/// # Errors
+/// Will return `Err` if there are no items to select from, or the terminal
+/// cannot be used interactively
+pub fn pick<T>(title: &str, items: Vec<PickerItem<T>>) -> Result<Option<T>> {
+    ensure!(!items.is_empty(), "nothing to select from");
+    ensure_interactive(&format!("selecting from {title:?}"))?;
+    ensure!(
+        stderr().is_terminal(),
+        "interactive selection requires a terminal"
+    );
+
+    enable_raw_mode()?;
+    let mut stderr = stderr();
+    execute!(stderr, EnterAlternateScreen)?;
+    let backend = CrosstermBackend::new(stderr);
+    let mut terminal = Terminal::new(backend)?;
+
+    let app = App {
+        title: title.to_string(),
+        query: String::new(),
+        items,
+        table_state: TableState::default().with_selected(0),
+    };
+    let res = app.run(&mut terminal);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    res
+}
+
+/// Interactively select one of the scopes where the current user has eligible
+/// assignments, or their child resources
+///
+/// Returns `None` if the selection is cancelled.
+///
+/// # Errors
+/// Will return `Err` if the scopes cannot be listed or the terminal cannot be
+/// used interactively
+pub fn pick_scope(client: &PimClient, nested: bool) -> Result<Option<Scope>> {
+    let items = client
+        .eligible_scopes(nested)?
+        .into_iter()
+        .map(|(scope, name)| PickerItem {
+            label: scope.to_string(),
+            detail: name,
+            value: scope,
+        })
+        .collect();
+    pick("Select a scope", items)
+}
+
+/// Interactively select one of the role assignments at `scope`
+///
+/// Returns `None` if the selection is cancelled.
+///
+/// # Errors
+/// Will return `Err` if there are no role assignments at `scope` or the
+/// terminal cannot be used interactively
+pub fn pick_role(
+    title: &str,
+    roles: &BTreeSet<RoleAssignment>,
+    scope: &Scope,
+) -> Result<Option<RoleAssignment>> {
+    let items = roles
+        .iter()
+        .filter(|x| &x.scope == scope)
+        .map(|x| PickerItem {
+            label: x.role.to_string(),
+            detail: x.scope_name.clone(),
+            value: x.clone(),
+        })
+        .collect::<Vec<_>>();
+    if items.is_empty() {
+        bail!("no roles found at {scope}");
+    }
+    pick(title, items)
+}
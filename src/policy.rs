@@ -0,0 +1,432 @@
+use crate::{
+    format_duration,
+    groups::GroupMembership,
+    models::{
+        roles::{Role, RoleAssignment},
+        scope::{Scope, ScopeLevel},
+    },
+    parse_duration,
+};
+use anyhow::{Context, Result};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Roles that grant control over access to everything within their scope
+const DANGEROUS_ROLES: &[&str] = &["Owner", "User Access Administrator"];
+
+/// Scope levels that contain entire subscriptions
+const DANGEROUS_SCOPE_LEVELS: &[ScopeLevel] = &[
+    ScopeLevel::Root,
+    ScopeLevel::ManagementGroup,
+    ScopeLevel::Subscription,
+];
+
+/// An activation that is not allowed by the policy in the config file, or
+/// that the user has not confirmed or provided compliant input for
+#[derive(thiserror::Error, Debug)]
+#[error("{0}")]
+pub struct PolicyViolation(pub String);
+
+impl PolicyViolation {
+    /// Check if the error was caused by a policy violation
+    #[must_use]
+    pub fn caused(err: &anyhow::Error) -> bool {
+        err.chain().any(<dyn std::error::Error>::is::<Self>)
+    }
+}
+
+/// Restrictions on activating roles, in addition to the roles' PIM policies
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Roles that require an explicit confirmation to activate at broad scopes
+    ///
+    /// Defaults to `Owner` and `User Access Administrator`.  Use an empty
+    /// list to disable the confirmation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dangerous_roles: Option<Vec<Role>>,
+
+    /// Scope levels at which activating the dangerous roles requires an
+    /// explicit confirmation
+    ///
+    /// Defaults to `root`, `management-group`, and `subscription`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dangerous_scope_levels: Option<Vec<ScopeLevel>>,
+
+    /// Regular expression that justifications must match, such as `INC-\d+`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub justification_pattern: Option<String>,
+
+    /// Regular expression that ticket numbers must match, such as `^INC-\d+$`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket_number_pattern: Option<String>,
+
+    /// Restrictions on activating or extending specific roles, scopes, or
+    /// group memberships
+    ///
+    /// Every rule that matches a role or group applies to its activation and
+    /// extension.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<PolicyRule>,
+}
+
+/// A restriction on activating the roles or group memberships that match the
+/// rule
+///
+/// A rule matches a role if it matches all of the specified `roles`,
+/// `scopes`, and `scope_levels`, and does not specify `groups`.  A rule
+/// matches a group membership if it matches the specified `groups`, and does
+/// not specify any of `roles`, `scopes`, or `scope_levels`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRule {
+    /// Names of the roles the rule applies to, or every role if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<Role>>,
+
+    /// Scopes the rule applies to, including every scope within them, or
+    /// every scope if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<Scope>>,
+
+    /// Scope levels the rule applies to, or every level if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope_levels: Option<Vec<ScopeLevel>>,
+
+    /// Names or object ids of the PIM groups the rule applies to, or every
+    /// group if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<String>>,
+
+    /// Prevent activating the roles using `az-pim`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub block: bool,
+
+    /// Require an explicit confirmation to activate the roles
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub confirm: bool,
+
+    /// Require a ticket number to activate the roles
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_ticket: bool,
+
+    /// Maximum duration to activate the roles for, such as `1h`, which may be
+    /// shorter than the roles' PIM policies allow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_duration: Option<String>,
+}
+
+impl PolicyRule {
+    fn matches(&self, assignment: &RoleAssignment) -> bool {
+        if self.groups.is_some() {
+            return false;
+        }
+        let role = &assignment.role.0;
+        let scope = assignment.scope.0.to_lowercase();
+        self.roles
+            .as_ref()
+            .is_none_or(|roles| roles.iter().any(|x| x.0.eq_ignore_ascii_case(role)))
+            && self.scopes.as_ref().is_none_or(|scopes| {
+                scopes.iter().any(|x| {
+                    let x = x.0.to_lowercase();
+                    scope == x || scope.starts_with(&format!("{}/", x.trim_end_matches('/')))
+                })
+            })
+            && self
+                .scope_levels
+                .as_ref()
+                .is_none_or(|levels| levels.contains(&assignment.scope.level()))
+    }
+
+    fn matches_group(&self, membership: &GroupMembership) -> bool {
+        if self.roles.is_some() || self.scopes.is_some() || self.scope_levels.is_some() {
+            return false;
+        }
+        self.groups.as_ref().is_none_or(|groups| {
+            groups.iter().any(|x| {
+                x.eq_ignore_ascii_case(&membership.group_id)
+                    || membership
+                        .group_name
+                        .as_deref()
+                        .is_some_and(|name| x.eq_ignore_ascii_case(name))
+            })
+        })
+    }
+
+    fn max_duration(&self) -> Result<Option<Duration>> {
+        self.max_duration
+            .as_deref()
+            .map(|x| parse_duration(x).with_context(|| format!("invalid max_duration {x:?}")))
+            .transpose()
+    }
+}
+
+impl PolicyConfig {
+    /// Check that the patterns and each of the rules are valid
+    ///
+    /// # Errors
+    /// Will return `Err` if a pattern is not a valid regular expression, or a
+    /// rule has an invalid `max_duration`
+    pub fn validate(&self) -> Result<()> {
+        self.justification_pattern()?;
+        self.ticket_number_pattern()?;
+        for rule in &self.rules {
+            rule.max_duration()?;
+        }
+        Ok(())
+    }
+
+    /// The pattern justifications must match, if any
+    ///
+    /// # Errors
+    /// Will return `Err` if the pattern is not a valid regular expression
+    pub fn justification_pattern(&self) -> Result<Option<Regex>> {
+        compile_pattern(
+            "justification_pattern",
+            self.justification_pattern.as_deref(),
+        )
+    }
+
+    /// The pattern ticket numbers must match, if any
+    ///
+    /// # Errors
+    /// Will return `Err` if the pattern is not a valid regular expression
+    pub fn ticket_number_pattern(&self) -> Result<Option<Regex>> {
+        compile_pattern(
+            "ticket_number_pattern",
+            self.ticket_number_pattern.as_deref(),
+        )
+    }
+
+    fn matching_rules<'a>(
+        &'a self,
+        assignment: &'a RoleAssignment,
+    ) -> impl Iterator<Item = &'a PolicyRule> {
+        self.rules.iter().filter(|x| x.matches(assignment))
+    }
+
+    /// Check that the policy allows activating or extending the role for
+    /// `duration`
+    ///
+    /// # Errors
+    /// Will return `Err` describing each restriction the activation does not
+    /// meet
+    pub fn check(
+        &self,
+        assignment: &RoleAssignment,
+        duration: Duration,
+        has_ticket: bool,
+    ) -> Result<()> {
+        check_rules(
+            self.matching_rules(assignment),
+            duration,
+            if has_ticket {
+                None
+            } else {
+                Some("the policy in the config file requires ticket information (use --ticket-number)")
+            },
+        )
+    }
+
+    /// Check that the policy allows activating or extending the group
+    /// membership for `duration`
+    ///
+    /// # Errors
+    /// Will return `Err` describing each restriction the activation does not
+    /// meet
+    pub fn check_group(&self, membership: &GroupMembership, duration: Duration) -> Result<()> {
+        check_rules(
+            self.rules.iter().filter(|x| x.matches_group(membership)),
+            duration,
+            Some("the policy in the config file requires ticket information, which group memberships do not support"),
+        )
+    }
+
+    /// Check if activating the role requires an explicit confirmation, either
+    /// as it is dangerous or as a rule requires it
+    #[must_use]
+    pub fn requires_confirmation(&self, assignment: &RoleAssignment) -> bool {
+        self.is_dangerous(assignment) || self.matching_rules(assignment).any(|x| x.confirm)
+    }
+
+    /// Check if activating the group membership requires an explicit
+    /// confirmation
+    #[must_use]
+    pub fn group_requires_confirmation(&self, membership: &GroupMembership) -> bool {
+        self.rules
+            .iter()
+            .any(|x| x.confirm && x.matches_group(membership))
+    }
+
+    /// Check if the role is one of the dangerous roles at a broad scope
+    #[must_use]
+    pub fn is_dangerous(&self, assignment: &RoleAssignment) -> bool {
+        let role = &assignment.role.0;
+        let dangerous_role = match &self.dangerous_roles {
+            Some(roles) => roles.iter().any(|x| x.0.eq_ignore_ascii_case(role)),
+            None => DANGEROUS_ROLES.iter().any(|x| x.eq_ignore_ascii_case(role)),
+        };
+        dangerous_role
+            && self
+                .dangerous_scope_levels
+                .as_deref()
+                .unwrap_or(DANGEROUS_SCOPE_LEVELS)
+                .contains(&assignment.scope.level())
+    }
+}
+
+/// Check the restrictions of each of the rules, where `missing_ticket`
+/// describes why rules requiring a ticket are not met, if they are not
+fn check_rules<'a>(
+    rules: impl Iterator<Item = &'a PolicyRule>,
+    duration: Duration,
+    missing_ticket: Option<&str>,
+) -> Result<()> {
+    let mut problems = Vec::new();
+    for rule in rules {
+        if rule.block {
+            problems.push("blocked by the policy in the config file".to_string());
+        }
+        if let (true, Some(missing_ticket)) = (rule.require_ticket, missing_ticket) {
+            problems.push(missing_ticket.to_string());
+        }
+        if let Some(max_duration) = rule.max_duration()? {
+            if duration > max_duration {
+                problems.push(format!(
+                    "the policy in the config file limits the duration to {}",
+                    format_duration(max_duration)?
+                ));
+            }
+        }
+    }
+    problems.dedup();
+    if !problems.is_empty() {
+        return Err(PolicyViolation(problems.join("\n")).into());
+    }
+    Ok(())
+}
+
+fn compile_pattern(name: &str, pattern: Option<&str>) -> Result<Option<Regex>> {
+    pattern
+        .map(|x| Regex::new(x).with_context(|| format!("invalid {name} {x:?}")))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PolicyConfig;
+    use crate::models::{
+        roles::{Role, RoleAssignment},
+        scope::Scope,
+    };
+    use anyhow::Result;
+    use std::time::Duration;
+
+    fn assignment(role: &str, scope: &str) -> Result<RoleAssignment> {
+        Ok(RoleAssignment {
+            role: Role(role.to_string()),
+            scope: Scope::new(scope)?,
+            scope_name: None,
+            role_definition_id: String::new(),
+            principal_id: None,
+            principal_type: None,
+            object: None,
+            end_date_time: None,
+        })
+    }
+
+    #[test]
+    fn test_is_dangerous() -> Result<()> {
+        let sub = "/subscriptions/00000000-0000-0000-0000-000000000000";
+        let rg = format!("{sub}/resourceGroups/rg");
+        let mg = "/providers/Microsoft.Management/managementGroups/mg";
+
+        let policy = PolicyConfig::default();
+        assert!(policy.is_dangerous(&assignment("Owner", sub)?));
+        assert!(policy.is_dangerous(&assignment("user access administrator", mg)?));
+        assert!(!policy.is_dangerous(&assignment("Owner", &rg)?));
+        assert!(!policy.is_dangerous(&assignment("Reader", sub)?));
+
+        let policy: PolicyConfig = toml::from_str(
+            r#"
+            dangerous_roles = ["Contributor"]
+            dangerous_scope_levels = ["resource-group"]
+            "#,
+        )?;
+        assert!(policy.is_dangerous(&assignment("Contributor", &rg)?));
+        assert!(!policy.is_dangerous(&assignment("Owner", &rg)?));
+        assert!(!policy.is_dangerous(&assignment("Contributor", sub)?));
+
+        let policy: PolicyConfig = toml::from_str("dangerous_roles = []")?;
+        assert!(!policy.is_dangerous(&assignment("Owner", sub)?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules() -> Result<()> {
+        let sub = "/subscriptions/00000000-0000-0000-0000-000000000000";
+        let rg = format!("{sub}/resourceGroups/prod");
+        let policy: PolicyConfig = toml::from_str(&format!(
+            r#"
+            dangerous_roles = []
+
+            [[rules]]
+            roles = ["Key Vault Administrator"]
+            block = true
+
+            [[rules]]
+            scopes = ["{rg}"]
+            confirm = true
+            require_ticket = true
+            max_duration = "1h"
+            "#
+        ))?;
+        policy.validate()?;
+        let hour = Duration::from_secs(60 * 60);
+
+        let blocked = assignment("key vault administrator", sub)?;
+        assert!(policy.check(&blocked, hour, true).is_err());
+        assert!(!policy.requires_confirmation(&blocked));
+
+        let prod = assignment("Reader", &format!("{rg}/providers/Microsoft.Web/sites/app"))?;
+        assert!(policy.requires_confirmation(&prod));
+        assert!(policy.check(&prod, hour, true).is_ok());
+        assert!(policy.check(&prod, hour, false).is_err());
+        assert!(policy.check(&prod, hour * 2, true).is_err());
+
+        let other = assignment("Reader", &format!("{rg}-2"))?;
+        assert!(!policy.requires_confirmation(&other));
+        assert!(policy.check(&other, hour * 8, false).is_ok());
+
+        let invalid: PolicyConfig = toml::from_str("[[rules]]\nmax_duration = \"soon\"")?;
+        assert!(invalid.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_patterns() -> Result<()> {
+        let policy: PolicyConfig = toml::from_str(
+            r"
+            justification_pattern = 'INC-\d+'
+            ticket_number_pattern = '^INC-\d+$'
+            ",
+        )?;
+        policy.validate()?;
+        let Some(justification) = policy.justification_pattern()? else {
+            anyhow::bail!("missing justification pattern");
+        };
+        assert!(justification.is_match("fixing INC-1234"));
+        assert!(!justification.is_match("fixing things"));
+        let Some(ticket) = policy.ticket_number_pattern()? else {
+            anyhow::bail!("missing ticket number pattern");
+        };
+        assert!(ticket.is_match("INC-1234"));
+        assert!(!ticket.is_match("see INC-1234"));
+
+        let invalid: PolicyConfig = toml::from_str("justification_pattern = '('")?;
+        assert!(invalid.validate().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{
+    latest::cache_path,
+    models::{
+        roles::{Role, RoleAssignment},
+        scope::Scope,
+    },
+};
+use anyhow::{Context, Result};
+use humantime::parse_rfc3339_weak;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs::{create_dir_all, metadata, read, write},
+    io::ErrorKind,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+const PROMPT_CACHE_FILE: &str = "prompt.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PromptRole {
+    pub role: Role,
+    pub scope: Scope,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub scope_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub end_date_time: Option<String>,
+}
+
+impl PromptRole {
+    fn expires_in(&self) -> Option<Duration> {
+        let end = parse_rfc3339_weak(self.end_date_time.as_deref()?).ok()?;
+        Some(end.duration_since(SystemTime::now()).unwrap_or_default())
+    }
+
+    /// The scope name, or the last component of the scope if it is unnamed
+    fn short_scope(&self) -> &str {
+        self.scope_name.as_deref().unwrap_or_else(|| {
+            self.scope
+                .0
+                .rsplit('/')
+                .find(|x| !x.is_empty())
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// Active roles cached for use in shell prompts, stored in
+/// `$HOME/.cache/az-pim-cli/prompt.json`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PromptCache {
+    pub roles: Vec<PromptRole>,
+}
+
+fn prompt_cache_path() -> Result<PathBuf> {
+    let cache_path = cache_path().context("unable to determine cache path")?;
+    Ok(cache_path.join(PROMPT_CACHE_FILE))
+}
+
+impl PromptCache {
+    #[must_use]
+    pub fn from_assignments(assignments: &BTreeSet<RoleAssignment>) -> Self {
+        let roles = assignments
+            .iter()
+            .map(|entry| PromptRole {
+                role: entry.role.clone(),
+                scope: entry.scope.clone(),
+                scope_name: entry.scope_name.clone(),
+                end_date_time: entry.end_date_time.clone(),
+            })
+            .collect();
+        Self { roles }
+    }
+
+    /// Load the cached roles along with the age of the cache
+    ///
+    /// Returns `None` if the cache does not exist.
+    ///
+    /// # Errors
+    /// Will return `Err` if the cache cannot be read or parsed
+    pub fn load() -> Result<Option<(Self, Duration)>> {
+        let path = prompt_cache_path()?;
+        let data = match read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("unable to read {}", path.display()));
+            }
+        };
+        let modified = metadata(&path)?.modified()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        let value = serde_json::from_slice(&data)
+            .with_context(|| format!("unable to parse {}", path.display()))?;
+        Ok(Some((value, age)))
+    }
+
+    /// Write the cache
+    ///
+    /// # Errors
+    /// Will return `Err` if the cache cannot be written
+    pub fn save(&self) -> Result<()> {
+        let path = prompt_cache_path()?;
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).context("unable to create cache path")?;
+        }
+        let data = serde_json::to_vec(self).context("unable to serialize prompt cache")?;
+        write(&path, data).with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    /// A compact description of the active roles, such as `PIM:Owner@prod(2h13m)`
+    ///
+    /// Returns `None` if none of the cached roles are still active.
+    #[must_use]
+    pub fn segment(&self) -> Option<String> {
+        let roles = self
+            .roles
+            .iter()
+            .filter_map(|entry| match entry.expires_in() {
+                Some(remaining) if remaining.is_zero() => None,
+                Some(remaining) => Some(format!(
+                    "{}@{}({})",
+                    entry.role,
+                    entry.short_scope(),
+                    compact_duration(remaining)
+                )),
+                None => Some(format!("{}@{}", entry.role, entry.short_scope())),
+            })
+            .collect::<Vec<_>>();
+        if roles.is_empty() {
+            None
+        } else {
+            Some(format!("PIM:{}", roles.join(",")))
+        }
+    }
+}
+
+fn compact_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, 0) => "<1m".to_string(),
+        (0, minutes) => format!("{minutes}m"),
+        (hours, 0) => format!("{hours}h"),
+        (hours, minutes) => format!("{hours}h{minutes}m"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compact_duration, PromptCache, PromptRole};
+    use crate::models::{roles::Role, scope::Scope};
+    use std::time::Duration;
+
+    #[test]
+    fn test_segment() {
+        assert_eq!(compact_duration(Duration::from_secs(30)), "<1m");
+        assert_eq!(compact_duration(Duration::from_secs(60 * 5)), "5m");
+        assert_eq!(compact_duration(Duration::from_secs(60 * 60 * 2)), "2h");
+        assert_eq!(compact_duration(Duration::from_secs(60 * 133)), "2h13m");
+
+        let cache = PromptCache {
+            roles: vec![
+                PromptRole {
+                    role: Role("Owner".to_string()),
+                    scope: Scope("/subscriptions/0000".to_string()),
+                    scope_name: Some("prod".to_string()),
+                    end_date_time: None,
+                },
+                PromptRole {
+                    role: Role("Reader".to_string()),
+                    scope: Scope("/subscriptions/1111".to_string()),
+                    scope_name: None,
+                    end_date_time: Some("2000-01-01T00:00:00Z".to_string()),
+                },
+            ],
+        };
+        assert_eq!(cache.segment().as_deref(), Some("PIM:Owner@prod"));
+        assert_eq!(PromptCache::default().segment(), None);
+    }
+}
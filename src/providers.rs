@@ -0,0 +1,108 @@
+use crate::{
+    entra::{list_active_directory_roles, list_eligible_directory_roles},
+    groups::{list_active_group_memberships, list_eligible_group_memberships},
+    ListFilter, PimClient,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The system that grants an elevated role
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    AzureRbac,
+    Entra,
+    Group,
+}
+
+impl Display for Provider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::AzureRbac => write!(f, "azure-rbac"),
+            Self::Entra => write!(f, "entra"),
+            Self::Group => write!(f, "group"),
+        }
+    }
+}
+
+/// A role from any provider, normalized to a common set of fields
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProviderAssignment {
+    pub provider: Provider,
+    pub role: String,
+    /// The Azure scope, Entra ID directory scope, or group the role applies to
+    pub target: String,
+    /// `eligible`, or how an active role was granted
+    pub state: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_date_time: Option<String>,
+}
+
+/// List the current user's Azure roles, Entra ID directory roles, and PIM
+/// group memberships
+///
+/// # Errors
+/// Will return `Err` if any of the listings fail
+pub fn list_all_providers(client: &PimClient, active: bool) -> Result<Vec<ProviderAssignment>> {
+    let (azure, (entra, groups)) = rayon::join(
+        || {
+            if active {
+                client.list_active_role_assignments(None, Some(ListFilter::AsTarget))
+            } else {
+                client.list_eligible_role_assignments(None, Some(ListFilter::AsTarget))
+            }
+        },
+        || {
+            rayon::join(
+                || {
+                    if active {
+                        list_active_directory_roles(client, false)
+                    } else {
+                        list_eligible_directory_roles(client, false)
+                    }
+                },
+                || {
+                    if active {
+                        list_active_group_memberships(client)
+                    } else {
+                        list_eligible_group_memberships(client)
+                    }
+                },
+            )
+        },
+    );
+
+    let state = |assignment_type: Option<String>| {
+        if active {
+            assignment_type.map_or_else(|| "active".to_string(), |x| x.to_lowercase())
+        } else {
+            "eligible".to_string()
+        }
+    };
+
+    let mut results = Vec::new();
+    results.extend(azure?.into_iter().map(|x| ProviderAssignment {
+        provider: Provider::AzureRbac,
+        role: x.role.to_string(),
+        target: x.scope_name.unwrap_or_else(|| x.scope.to_string()),
+        state: state(None),
+        end_date_time: x.end_date_time,
+    }));
+    results.extend(entra?.into_iter().map(|x| ProviderAssignment {
+        provider: Provider::Entra,
+        role: x.role.to_string(),
+        target: x.directory_scope_id,
+        state: state(x.assignment_type),
+        end_date_time: x.end_date_time,
+    }));
+    results.extend(groups?.into_iter().map(|x| ProviderAssignment {
+        provider: Provider::Group,
+        role: x.access.to_string(),
+        target: x.group_name.unwrap_or(x.group_id),
+        state: state(x.assignment_type),
+        end_date_time: x.end_date_time,
+    }));
+    results.sort();
+    Ok(results)
+}
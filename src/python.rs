@@ -0,0 +1,129 @@
+use crate::{
+    models::{
+        roles::{Role, RolesExt},
+        scope::Scope,
+    },
+    parse_duration, ListFilter, PimClient,
+};
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+use serde::Serialize;
+
+const DEFAULT_DURATION: &str = "8h";
+
+fn to_py_err(err: impl Into<anyhow::Error>) -> PyErr {
+    PyRuntimeError::new_err(format!("{:#}", err.into()))
+}
+
+/// Convert a value to the equivalent Python objects by way of JSON
+fn to_python<'py>(py: Python<'py>, value: &impl Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let data = serde_json::to_string(value).map_err(to_py_err)?;
+    py.import("json")?.call_method1("loads", (data,))
+}
+
+fn parse_scope(scope: Option<String>) -> PyResult<Option<Scope>> {
+    scope.map(Scope::new).transpose().map_err(to_py_err)
+}
+
+/// Client for the Azure PIM APIs, using the credentials of the Azure CLI
+#[pyclass(name = "PimClient", module = "azure_pim_cli")]
+struct PyPimClient {
+    client: PimClient,
+}
+
+#[pymethods]
+impl PyPimClient {
+    #[new]
+    #[pyo3(signature = (tenant=None))]
+    fn new(tenant: Option<String>) -> PyResult<Self> {
+        let mut client = PimClient::new().map_err(to_py_err)?;
+        if let Some(tenant) = tenant {
+            client = client.with_tenant(tenant);
+        }
+        Ok(Self { client })
+    }
+
+    /// List the roles available to the current user
+    #[pyo3(signature = (scope=None))]
+    fn list_eligible_role_assignments<'py>(
+        &self,
+        py: Python<'py>,
+        scope: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let scope = parse_scope(scope)?;
+        let roles = py
+            .detach(|| {
+                self.client
+                    .list_eligible_role_assignments(scope, Some(ListFilter::AsTarget))
+            })
+            .map_err(to_py_err)?;
+        to_python(py, &roles)
+    }
+
+    /// List the active role assignments for the current user
+    #[pyo3(signature = (scope=None))]
+    fn list_active_role_assignments<'py>(
+        &self,
+        py: Python<'py>,
+        scope: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let scope = parse_scope(scope)?;
+        let roles = py
+            .detach(|| {
+                self.client
+                    .list_active_role_assignments(scope, Some(ListFilter::AsTarget))
+            })
+            .map_err(to_py_err)?;
+        to_python(py, &roles)
+    }
+
+    /// Activate an eligible role, returning the status of the request
+    #[pyo3(signature = (role, scope, justification, duration=DEFAULT_DURATION))]
+    fn activate_role_assignment(
+        &self,
+        py: Python<'_>,
+        role: String,
+        scope: String,
+        justification: &str,
+        duration: &str,
+    ) -> PyResult<String> {
+        let scope = Scope::new(scope).map_err(to_py_err)?;
+        let duration = parse_duration(duration).map_err(to_py_err)?;
+        py.detach(|| {
+            let roles = self
+                .client
+                .list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?;
+            let entry = roles.resolve_role(&Role(role), &scope, true)?;
+            self.client
+                .activate_role_assignment_with_status(&entry, justification, duration)
+        })
+        .map(|status| status.to_string())
+        .map_err(to_py_err)
+    }
+
+    /// Deactivate an active role
+    fn deactivate_role_assignment(
+        &self,
+        py: Python<'_>,
+        role: String,
+        scope: String,
+    ) -> PyResult<()> {
+        let scope = Scope::new(scope).map_err(to_py_err)?;
+        py.detach(|| {
+            let roles = self
+                .client
+                .list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
+            let entry = roles.resolve_role(&Role(role), &scope, true)?;
+            self.client.deactivate_role_assignment(&entry)
+        })
+        .map_err(to_py_err)
+    }
+}
+
+/// Python bindings for listing, activating, and deactivating Azure PIM roles
+///
+/// Build the module with
+/// `cargo rustc --release --lib --features python --crate-type cdylib`.
+#[pymodule]
+fn azure_pim_cli(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyPimClient>()
+}
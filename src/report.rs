@@ -0,0 +1,342 @@
+use crate::{
+    backup::{Backup, BackupEntry},
+    models::{policies::ActivationPolicy, roles::Role, scope::Scope},
+    PimClient,
+};
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Formatter, Result as FmtResult, Write as _},
+    path::Path,
+};
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.5em; text-align: left; }
+th { background: #eee; }";
+
+#[allow(clippy::manual_assert, clippy::panic)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /// Determine the report format from the extension of the output path,
+    /// using markdown unless the path ends in `.html` or `.htm`
+    #[must_use]
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm") => {
+                Self::Html
+            }
+            _ => Self::Markdown,
+        }
+    }
+}
+
+impl Display for ReportFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Markdown => write!(f, "markdown"),
+            Self::Html => write!(f, "html"),
+        }
+    }
+}
+
+/// The activation policy for a role that has eligible assignments at a scope
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReportPolicy {
+    pub scope: Scope,
+    pub role: Role,
+    pub activation: ActivationPolicy,
+}
+
+/// A human readable summary of the access granted at a scope, intended for
+/// periodic access certifications
+#[derive(Serialize, Debug, Clone)]
+pub struct Report {
+    pub backup: Backup,
+    pub policies: Vec<ReportPolicy>,
+}
+
+struct Section {
+    title: String,
+    header: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+}
+
+impl Report {
+    /// Collect the assignments, custom roles, and activation policies at
+    /// `scope`
+    ///
+    /// If `nested` is set, assignments at scopes below `scope` are included.
+    ///
+    /// # Errors
+    /// Will return `Err` if any of the requests fail
+    pub fn collect(client: &PimClient, scope: &Scope, nested: bool) -> Result<Self> {
+        let backup = Backup::collect(client, scope, nested)?;
+
+        let mut eligible_roles: BTreeMap<&Scope, BTreeSet<String>> = BTreeMap::new();
+        for entry in &backup.eligible {
+            eligible_roles
+                .entry(&entry.scope)
+                .or_default()
+                .insert(entry.role.0.to_lowercase());
+        }
+
+        let mut policies = Vec::new();
+        for (scope, roles) in eligible_roles {
+            for policy in client.role_management_policies(scope)? {
+                if roles.contains(&policy.role.0.to_lowercase()) {
+                    policies.push(ReportPolicy {
+                        scope: scope.clone(),
+                        role: policy.role,
+                        activation: policy.activation,
+                    });
+                }
+            }
+        }
+        policies.sort();
+
+        Ok(Self { backup, policies })
+    }
+
+    /// Render the report in the specified format
+    ///
+    /// # Errors
+    /// Will return `Err` if the report cannot be formatted
+    pub fn render(&self, format: ReportFormat) -> Result<String> {
+        let title = format!("Access report for {}", self.backup.scope);
+        let summary = format!(
+            "Generated {}. {} active assignments, {} eligible assignments, {} custom roles, {} orphaned assignments.",
+            self.backup.created_on,
+            self.backup.active.len(),
+            self.backup.eligible.len(),
+            self.backup.role_definitions.len(),
+            self.orphans().count(),
+        );
+        let sections = self.sections();
+        match format {
+            ReportFormat::Markdown => markdown(&title, &summary, &sections),
+            ReportFormat::Html => html(&title, &summary, &sections),
+        }
+    }
+
+    fn orphans(&self) -> impl Iterator<Item = (&'static str, &BackupEntry)> {
+        let active = self.backup.active.iter().map(|x| ("active", x));
+        let eligible = self.backup.eligible.iter().map(|x| ("eligible", x));
+        active
+            .chain(eligible)
+            .filter(|(_, x)| x.principal_name.is_none())
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        let mut by_scope: BTreeMap<&Scope, Vec<Vec<String>>> = BTreeMap::new();
+        let active = self.backup.active.iter().map(|x| ("active", x));
+        let eligible = self.backup.eligible.iter().map(|x| ("eligible", x));
+        for (state, entry) in active.chain(eligible) {
+            let Some(principal_name) = &entry.principal_name else {
+                continue;
+            };
+            by_scope.entry(&entry.scope).or_default().push(vec![
+                entry.role.to_string(),
+                principal_name.clone(),
+                entry.principal_type.clone().unwrap_or_default(),
+                state.to_string(),
+                entry.end_date_time.clone().unwrap_or_default(),
+            ]);
+        }
+
+        let mut sections = by_scope
+            .into_iter()
+            .map(|(scope, mut rows)| {
+                rows.sort();
+                Section {
+                    title: scope.to_string(),
+                    header: &["Role", "Principal", "Type", "State", "Expires"],
+                    rows,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        sections.push(Section {
+            title: "Activation policies".to_string(),
+            header: &[
+                "Scope",
+                "Role",
+                "Max duration",
+                "Approval",
+                "MFA",
+                "Justification",
+                "Ticket",
+            ],
+            rows: self
+                .policies
+                .iter()
+                .map(|x| {
+                    let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+                    vec![
+                        x.scope.to_string(),
+                        x.role.to_string(),
+                        x.activation.max_duration.clone().unwrap_or_default(),
+                        yes_no(x.activation.approval_required),
+                        yes_no(x.activation.mfa_required),
+                        yes_no(x.activation.justification_required),
+                        yes_no(x.activation.ticket_required),
+                    ]
+                })
+                .collect(),
+        });
+
+        sections.push(Section {
+            title: "Custom roles".to_string(),
+            header: &["Role", "Description", "Assignable scopes"],
+            rows: self
+                .backup
+                .role_definitions
+                .iter()
+                .map(|x| {
+                    vec![
+                        x.properties.role_name.clone(),
+                        x.properties.description.clone(),
+                        x.properties.assignable_scopes.join(", "),
+                    ]
+                })
+                .collect(),
+        });
+
+        sections.push(Section {
+            title: "Orphaned assignments".to_string(),
+            header: &["Role", "Scope", "Principal ID", "State"],
+            rows: self
+                .orphans()
+                .map(|(state, x)| {
+                    vec![
+                        x.role.to_string(),
+                        x.scope.to_string(),
+                        x.principal_id.clone(),
+                        state.to_string(),
+                    ]
+                })
+                .collect(),
+        });
+
+        sections
+    }
+}
+
+fn escape_markdown(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+fn markdown(title: &str, summary: &str, sections: &[Section]) -> Result<String> {
+    let mut result = format!("# {}\n\n{}\n", escape_markdown(title), summary);
+    for section in sections {
+        write!(result, "\n## {}\n\n", escape_markdown(&section.title))?;
+        if section.rows.is_empty() {
+            result.push_str("None\n");
+            continue;
+        }
+        writeln!(result, "| {} |", section.header.join(" | "))?;
+        writeln!(result, "|{}", "---|".repeat(section.header.len()))?;
+        for row in &section.rows {
+            let row = row.iter().map(|x| escape_markdown(x)).collect::<Vec<_>>();
+            writeln!(result, "| {} |", row.join(" | "))?;
+        }
+    }
+    Ok(result)
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html(title: &str, summary: &str, sections: &[Section]) -> Result<String> {
+    let title = escape_html(title);
+    let mut result = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>{}</p>\n",
+        escape_html(summary)
+    );
+    for section in sections {
+        writeln!(result, "<h2>{}</h2>", escape_html(&section.title))?;
+        if section.rows.is_empty() {
+            result.push_str("<p>None</p>\n");
+            continue;
+        }
+        result.push_str("<table>\n<tr>");
+        for column in section.header {
+            write!(result, "<th>{}</th>", escape_html(column))?;
+        }
+        result.push_str("</tr>\n");
+        for row in &section.rows {
+            result.push_str("<tr>");
+            for value in row {
+                write!(result, "<td>{}</td>", escape_html(value))?;
+            }
+            result.push_str("</tr>\n");
+        }
+        result.push_str("</table>\n");
+    }
+    result.push_str("</body>\n</html>\n");
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::BACKUP_VERSION;
+    use insta::assert_snapshot;
+
+    fn entry(scope: &Scope, role: &str, principal_name: Option<&str>) -> BackupEntry {
+        BackupEntry {
+            role: Role(role.to_string()),
+            scope: scope.clone(),
+            role_definition_id: "id".to_string(),
+            principal_id: "11111111-1111-1111-1111-111111111111".to_string(),
+            principal_type: Some("User".to_string()),
+            principal_name: principal_name.map(ToString::to_string),
+            condition: None,
+            condition_version: None,
+            end_date_time: None,
+        }
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let scope = Scope::new("/subscriptions/00000000-0000-0000-0000-000000000000")?;
+        let report = Report {
+            backup: Backup {
+                version: BACKUP_VERSION,
+                created_on: "2024-01-02T03:04:05Z".to_string(),
+                scope: scope.clone(),
+                nested: false,
+                active: vec![
+                    entry(&scope, "Reader", Some("A | B")),
+                    entry(&scope, "Owner", None),
+                ],
+                eligible: vec![entry(&scope, "Contributor", Some("<script>"))],
+                role_definitions: vec![],
+            },
+            policies: vec![ReportPolicy {
+                scope,
+                role: Role("Contributor".to_string()),
+                activation: ActivationPolicy {
+                    max_duration: Some("PT8H".to_string()),
+                    justification_required: true,
+                    ..ActivationPolicy::default()
+                },
+            }],
+        };
+        assert_snapshot!(report.render(ReportFormat::Markdown)?);
+        assert_snapshot!(report.render(ReportFormat::Html)?);
+        Ok(())
+    }
+}
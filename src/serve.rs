@@ -0,0 +1,283 @@
+use crate::{
+    models::{
+        roles::{Role, RolesExt},
+        scope::Scope,
+    },
+    parse_duration,
+    policy::PolicyViolation,
+    use_non_interactive, ListFilter, PimClient,
+};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{io::Read, net::SocketAddr, time::Duration};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{info, warn};
+
+/// The largest request body that is accepted
+const MAX_BODY_SIZE: u64 = 64 * 1024;
+
+/// A problem with a request, rather than with handling it
+#[derive(thiserror::Error, Debug)]
+enum ClientError {
+    #[error("request body is larger than {MAX_BODY_SIZE} bytes")]
+    TooLarge,
+    #[error("{0}")]
+    BadRequest(String),
+}
+
+impl ClientError {
+    fn bad_request(err: impl std::fmt::Display) -> Self {
+        Self::BadRequest(format!("{err:#}"))
+    }
+}
+
+pub struct ServeSettings {
+    /// Address to listen on
+    pub listen: SocketAddr,
+    /// Token that requests must include as `Authorization: Bearer <token>`
+    pub token: String,
+    /// Justification used when an activation does not include one
+    pub justification: Option<String>,
+    /// Duration used when an activation does not include one
+    pub duration: Duration,
+}
+
+/// The body of requests to activate or deactivate a role
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoleRequest {
+    role: Role,
+    scope: Scope,
+    justification: Option<String>,
+    duration: Option<String>,
+}
+
+/// Serve a local HTTP API using a single `PimClient`
+///
+/// This allows other tools to reuse the client's tokens and caches, rather
+/// than running `az-pim` for each request.  The API includes:
+///
+/// * `GET /status`: the current user and their active roles
+/// * `GET /roles/active`: the active roles
+/// * `GET /roles/eligible`: the eligible roles
+/// * `POST /roles/activate`: activate the role in the request body
+/// * `POST /roles/deactivate`: deactivate the role in the request body
+///
+/// Requests are handled one at a time.  As there is no one to respond to
+/// prompts, activations that require confirmation or a compliant
+/// justification are rejected rather than waiting on input.
+///
+/// # Errors
+/// Will return `Err` if the server is unable to listen on the address
+pub fn serve(client: &PimClient, settings: &ServeSettings) -> Result<()> {
+    use_non_interactive(true);
+
+    if !settings.listen.ip().is_loopback() {
+        warn!(
+            "listening on {}, which is reachable from other machines",
+            settings.listen
+        );
+    }
+    let server = Server::http(settings.listen)
+        .map_err(|err| anyhow!("unable to listen on {}: {err}", settings.listen))?;
+    info!("listening on http://{}", settings.listen);
+    handle_requests(client, settings, &server);
+    Ok(())
+}
+
+fn handle_requests(client: &PimClient, settings: &ServeSettings, server: &Server) {
+    for mut request in server.incoming_requests() {
+        let (status, body) = if is_authorized(&request, &settings.token) {
+            match handle(client, settings, &mut request) {
+                Ok(Some(body)) => (200, body),
+                Ok(None) => (404, json!({"error": "not found"})),
+                Err(err) => (error_status(&err), json!({"error": format!("{err:#}")})),
+            }
+        } else {
+            (401, json!({"error": "missing or invalid bearer token"}))
+        };
+        info!("{} {} {status}", request.method(), request.url());
+
+        let mut response = Response::from_string(body.to_string()).with_status_code(status);
+        if let Ok(header) = Header::from_bytes("Content-Type", "application/json") {
+            response.add_header(header);
+        }
+        if let Err(err) = request.respond(response) {
+            warn!("unable to send response: {err}");
+        }
+    }
+}
+
+/// The HTTP status for an error, distinguishing problems with the request
+/// and activations the policy does not allow from failures handling it
+fn error_status(err: &anyhow::Error) -> u16 {
+    for cause in err.chain() {
+        match cause.downcast_ref::<ClientError>() {
+            Some(ClientError::TooLarge) => return 413,
+            Some(ClientError::BadRequest(_)) => return 400,
+            None if cause.is::<PolicyViolation>() => return 403,
+            None => {}
+        }
+    }
+    500
+}
+
+fn is_authorized(request: &Request, token: &str) -> bool {
+    let expected = format!("Bearer {token}");
+    request.headers().iter().any(|header| {
+        header.field.equiv("Authorization")
+            && constant_time_eq(header.value.as_bytes(), expected.as_bytes())
+    })
+}
+
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len() && left.iter().zip(right).fold(0, |acc, (l, r)| acc | (l ^ r)) == 0
+}
+
+fn read_body(request: &mut Request) -> Result<RoleRequest> {
+    if request
+        .body_length()
+        .is_some_and(|x| x as u64 > MAX_BODY_SIZE)
+    {
+        return Err(ClientError::TooLarge.into());
+    }
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_string(&mut body)
+        .map_err(ClientError::bad_request)?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return Err(ClientError::TooLarge.into());
+    }
+    Ok(serde_json::from_str(&body).map_err(ClientError::bad_request)?)
+}
+
+fn handle(
+    client: &PimClient,
+    settings: &ServeSettings,
+    request: &mut Request,
+) -> Result<Option<Value>> {
+    let path = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let value = match (request.method(), path.as_str()) {
+        (Method::Get, "/status") => {
+            let active = client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
+            json!({
+                "principal_id": client.current_user()?,
+                "active": active,
+            })
+        }
+        (Method::Get, "/roles/active") => {
+            json!(client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?)
+        }
+        (Method::Get, "/roles/eligible") => {
+            json!(client.list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?)
+        }
+        (Method::Post, "/roles/activate") => {
+            let body = read_body(request)?;
+            let justification = body
+                .justification
+                .or_else(|| settings.justification.clone())
+                .ok_or_else(|| ClientError::bad_request("justification must be provided"))?;
+            let duration = match body.duration.as_deref() {
+                Some(duration) => parse_duration(duration).map_err(ClientError::bad_request)?,
+                None => settings.duration,
+            };
+            let roles = client.list_eligible_role_assignments(None, Some(ListFilter::AsTarget))?;
+            let entry = roles
+                .resolve_role(&body.role, &body.scope, true)
+                .map_err(ClientError::bad_request)?;
+            let status =
+                client.activate_role_assignment_with_status(&entry, &justification, duration)?;
+            json!({"status": status.to_string()})
+        }
+        (Method::Post, "/roles/deactivate") => {
+            let body = read_body(request)?;
+            let roles = client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
+            let entry = roles
+                .resolve_role(&body.role, &body.scope, true)
+                .map_err(ClientError::bad_request)?;
+            client.deactivate_role_assignment(&entry)?;
+            json!({"status": "deactivated"})
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{constant_time_eq, handle_requests, ServeSettings, MAX_BODY_SIZE};
+    use crate::PimClient;
+    use anyhow::{Context, Result};
+    use reqwest::blocking::Client;
+    use std::{net::SocketAddr, thread, time::Duration};
+    use tiny_http::Server;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"Bearer abc", b"Bearer abc"));
+        assert!(!constant_time_eq(b"Bearer abc", b"Bearer abd"));
+        assert!(!constant_time_eq(b"Bearer ab", b"Bearer abc"));
+    }
+
+    /// Serve on an unused port in the background, returning its address
+    fn start() -> Result<SocketAddr> {
+        let server = Server::http("127.0.0.1:0").map_err(|err| anyhow::anyhow!("{err}"))?;
+        let listen = server
+            .server_addr()
+            .to_ip()
+            .context("server is not listening on an IP address")?;
+        let client = PimClient::new()?;
+        let settings = ServeSettings {
+            listen,
+            token: "secret".to_string(),
+            justification: None,
+            duration: Duration::from_secs(60 * 60),
+        };
+        thread::spawn(move || handle_requests(&client, &settings, &server));
+        Ok(listen)
+    }
+
+    #[test]
+    fn test_routes() -> Result<()> {
+        let listen = start()?;
+        let http = Client::new();
+        let url = |path: &str| format!("http://{listen}{path}");
+
+        for path in ["/status", "/roles/active", "/roles/eligible"] {
+            assert_eq!(http.get(url(path)).send()?.status(), 401);
+            let response = http.get(url(path)).bearer_auth("wrong").send()?;
+            assert_eq!(response.status(), 401);
+        }
+        for path in ["/roles/activate", "/roles/deactivate"] {
+            assert_eq!(http.post(url(path)).body("{}").send()?.status(), 401);
+            let response = http.post(url(path)).bearer_auth("wrong").send()?;
+            assert_eq!(response.status(), 401);
+        }
+
+        let response = http.get(url("/unknown")).bearer_auth("secret").send()?;
+        assert_eq!(response.status(), 404);
+
+        let response = http
+            .post(url("/roles/activate"))
+            .bearer_auth("secret")
+            .body("not json")
+            .send()?;
+        assert_eq!(response.status(), 400);
+
+        let response = http
+            .post(url("/roles/activate"))
+            .bearer_auth("secret")
+            .body(vec![b' '; usize::try_from(MAX_BODY_SIZE)? + 1])
+            .send()?;
+        assert_eq!(response.status(), 413);
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    latest::cache_path,
+    models::{
+        roles::{Role, RoleAssignment},
+        scope::Scope,
+    },
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs::{create_dir_all, read, remove_file, write},
+    io::ErrorKind,
+    path::PathBuf,
+    time::Duration,
+};
+
+const LAST_ACTIVATION_FILE: &str = "last-activation.json";
+
+#[derive(Serialize, Deserialize, PartialOrd, Ord, PartialEq, Eq, Debug, Clone)]
+pub struct SavedRole {
+    pub role: Role,
+    pub scope: Scope,
+    /// The justification used for this role, which may differ from the
+    /// justification of the rest of the activation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub justification: Option<String>,
+    /// The duration used for this role, which may differ from the duration
+    /// of the rest of the activation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Duration>,
+}
+
+impl SavedRole {
+    /// A role as it was activated
+    #[must_use]
+    pub fn new(entry: &RoleAssignment, justification: &str, duration: Duration) -> Self {
+        Self {
+            justification: Some(justification.to_string()),
+            duration: Some(duration),
+            ..Self::from(entry)
+        }
+    }
+
+    /// Check if this is the same role and scope as the assignment
+    #[must_use]
+    pub fn matches(&self, entry: &RoleAssignment) -> bool {
+        self.role == entry.role && self.scope == entry.scope
+    }
+}
+
+impl From<&RoleAssignment> for SavedRole {
+    fn from(entry: &RoleAssignment) -> Self {
+        Self {
+            role: entry.role.clone(),
+            scope: entry.scope.clone(),
+            justification: None,
+            duration: None,
+        }
+    }
+}
+
+/// The most recent successful activation, stored in `$HOME/.cache/az-pim-cli`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LastActivation {
+    pub roles: BTreeSet<SavedRole>,
+    pub justification: String,
+    pub duration: Duration,
+}
+
+fn last_activation_path() -> Result<PathBuf> {
+    let cache_path = cache_path().context("unable to determine cache path")?;
+    Ok(cache_path.join(LAST_ACTIVATION_FILE))
+}
+
+impl LastActivation {
+    /// Load the most recent activation
+    ///
+    /// Returns `None` if no activation has been recorded.
+    ///
+    /// # Errors
+    /// Will return `Err` if the state file cannot be read or parsed
+    pub fn load() -> Result<Option<Self>> {
+        let path = last_activation_path()?;
+        let data = match read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("unable to read {}", path.display()));
+            }
+        };
+        let value = serde_json::from_slice(&data)
+            .with_context(|| format!("unable to parse {}", path.display()))?;
+        Ok(Some(value))
+    }
+
+    /// Record this as the most recent activation
+    ///
+    /// # Errors
+    /// Will return `Err` if the state file cannot be written
+    pub fn save(&self) -> Result<()> {
+        let path = last_activation_path()?;
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).context("unable to create cache path")?;
+        }
+        let data = serde_json::to_vec_pretty(self).context("unable to serialize state")?;
+        write(&path, data).with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    /// Remove the recorded activation
+    ///
+    /// # Errors
+    /// Will return `Err` if the state file exists and cannot be removed
+    pub fn clear() -> Result<()> {
+        let path = last_activation_path()?;
+        match remove_file(&path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("unable to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
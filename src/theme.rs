@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    widgets::Block,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Use the theme for the interactive UIs
+///
+/// The theme can only be set once, before any of the UIs are shown.
+pub fn use_theme(theme: Theme) {
+    THEME.set(theme).ok();
+}
+
+/// The theme for the interactive UIs
+#[must_use]
+pub fn theme() -> Theme {
+    THEME.get().copied().unwrap_or_default()
+}
+
+/// The styles used by the interactive UIs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// The selected row, or the focused input
+    pub selection: Style,
+    /// Warnings about the current selections
+    pub warning: Style,
+    /// The borders around each section
+    pub border: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::builtin(ThemeName::Default)
+    }
+}
+
+impl Theme {
+    #[must_use]
+    pub fn builtin(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Default => Self {
+                selection: Style::new().add_modifier(Modifier::REVERSED),
+                warning: Style::new().add_modifier(Modifier::BOLD | Modifier::REVERSED),
+                border: Style::new(),
+            },
+            ThemeName::Dark => Self {
+                selection: Style::new()
+                    .fg(Color::White)
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD),
+                warning: Style::new()
+                    .fg(Color::LightRed)
+                    .add_modifier(Modifier::BOLD),
+                border: Style::new().fg(Color::Gray),
+            },
+            ThemeName::Light => Self {
+                selection: Style::new().fg(Color::Black).bg(Color::LightBlue),
+                warning: Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+                border: Style::new().fg(Color::DarkGray),
+            },
+            ThemeName::HighContrast => Self {
+                selection: Style::new()
+                    .fg(Color::Black)
+                    .bg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                warning: Style::new()
+                    .fg(Color::White)
+                    .bg(Color::Red)
+                    .add_modifier(Modifier::BOLD),
+                border: Style::new().fg(Color::White).add_modifier(Modifier::BOLD),
+            },
+        }
+    }
+
+    /// A bordered block using the theme's border style
+    #[must_use]
+    pub fn block(&self) -> Block<'static> {
+        Block::bordered().border_style(self.border)
+    }
+}
+
+/// The built-in themes
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    /// Reversed colors of the terminal's color scheme
+    #[default]
+    Default,
+    Dark,
+    Light,
+    HighContrast,
+}
+
+/// Theme settings in the config file
+///
+/// Styles that are set replace the style from the base theme.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// The built-in theme to start from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<ThemeName>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<StyleConfig>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<StyleConfig>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub border: Option<StyleConfig>,
+}
+
+impl ThemeConfig {
+    /// Build the theme from the settings
+    ///
+    /// # Errors
+    /// Will return `Err` if any of the colors are invalid
+    pub fn build(&self) -> Result<Theme> {
+        let mut theme = Theme::builtin(self.base.unwrap_or_default());
+        for (style, config, name) in [
+            (&mut theme.selection, &self.selection, "selection"),
+            (&mut theme.warning, &self.warning, "warning"),
+            (&mut theme.border, &self.border, "border"),
+        ] {
+            if let Some(config) = config {
+                *style = config
+                    .build()
+                    .with_context(|| format!("invalid {name} style"))?;
+            }
+        }
+        Ok(theme)
+    }
+}
+
+/// A style, such as `{ fg = "black", bg = "#ffd700", modifiers = ["bold"] }`
+///
+/// Colors are either names, such as `red` or `light-blue`, indexes into the
+/// terminal's 256 color palette, such as `208`, or hex RGB values, such as
+/// `#ff8700`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct StyleConfig {
+    /// Foreground color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fg: Option<String>,
+
+    /// Background color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bg: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<StyleModifier>,
+}
+
+impl StyleConfig {
+    fn build(&self) -> Result<Style> {
+        let mut style = Style::new();
+        if let Some(fg) = &self.fg {
+            style = style.fg(parse_color(fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(parse_color(bg)?);
+        }
+        for modifier in &self.modifiers {
+            style = style.add_modifier(modifier.to_modifier());
+        }
+        Ok(style)
+    }
+}
+
+fn parse_color(value: &str) -> Result<Color> {
+    value
+        .parse::<Color>()
+        .ok()
+        .with_context(|| format!("invalid color: {value:?}"))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StyleModifier {
+    Bold,
+    Dim,
+    Italic,
+    Underlined,
+    Reversed,
+}
+
+impl StyleModifier {
+    fn to_modifier(self) -> Modifier {
+        match self {
+            Self::Bold => Modifier::BOLD,
+            Self::Dim => Modifier::DIM,
+            Self::Italic => Modifier::ITALIC,
+            Self::Underlined => Modifier::UNDERLINED,
+            Self::Reversed => Modifier::REVERSED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Theme, ThemeConfig, ThemeName};
+    use anyhow::Result;
+    use ratatui::style::{Color, Modifier, Style};
+
+    #[test]
+    fn test_build() -> Result<()> {
+        let config: ThemeConfig = toml::from_str(
+            r##"
+            base = "high-contrast"
+            selection = { fg = "black", bg = "#ffd700", modifiers = ["bold", "underlined"] }
+            "##,
+        )?;
+        let theme = config.build()?;
+        let expected = Theme::builtin(ThemeName::HighContrast);
+        assert_eq!(
+            theme.selection,
+            Style::new()
+                .fg(Color::Black)
+                .bg(Color::Rgb(0xff, 0xd7, 0x00))
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+        );
+        assert_eq!(theme.warning, expected.warning);
+        assert_eq!(theme.border, expected.border);
+
+        let config: ThemeConfig = toml::from_str("border = { fg = \"not-a-color\" }")?;
+        assert!(config.build().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use crate::dashboard::format_remaining;
+use jiff::{tz::TimeZone, Timestamp};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static USE_UTC: AtomicBool = AtomicBool::new(false);
+
+/// Display timestamps in UTC rather than the local timezone
+pub fn use_utc(enabled: bool) {
+    USE_UTC.store(enabled, Ordering::Relaxed);
+}
+
+fn time_zone() -> TimeZone {
+    if USE_UTC.load(Ordering::Relaxed) {
+        TimeZone::UTC
+    } else {
+        TimeZone::system()
+    }
+}
+
+/// Format a timestamp in the display timezone, followed by how long until (or
+/// since) the timestamp, such as `2024-06-01 17:00 CEST (in 2h 13m)`
+///
+/// Values that are not valid timestamps are returned as-is.
+#[must_use]
+pub fn format_timestamp(value: &str) -> String {
+    let Ok(timestamp) = value.parse::<Timestamp>() else {
+        return value.to_string();
+    };
+    format_timestamp_in(timestamp, &time_zone(), Timestamp::now())
+}
+
+/// Format a timestamp as the time of day in the display timezone, such as
+/// `17:00`
+#[must_use]
+pub fn format_time_of_day(value: &str) -> Option<String> {
+    let timestamp = value.parse::<Timestamp>().ok()?;
+    Some(
+        timestamp
+            .to_zoned(time_zone())
+            .strftime("%H:%M")
+            .to_string(),
+    )
+}
+
+fn format_timestamp_in(timestamp: Timestamp, tz: &TimeZone, now: Timestamp) -> String {
+    let local = timestamp
+        .to_zoned(tz.clone())
+        .strftime("%Y-%m-%d %H:%M %Z")
+        .to_string();
+    let relative = if timestamp >= now {
+        format!(
+            "in {}",
+            format_remaining(now.duration_until(timestamp).unsigned_abs())
+        )
+    } else {
+        format!(
+            "{} ago",
+            format_remaining(timestamp.duration_until(now).unsigned_abs())
+        )
+    };
+    format!("{local} ({relative})")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_timestamp_in;
+    use anyhow::Result;
+    use jiff::{tz::TimeZone, Timestamp};
+
+    #[test]
+    fn test_format_timestamp() -> Result<()> {
+        let now = "2024-06-01T14:47:00Z".parse::<Timestamp>()?;
+        let end = "2024-06-01T17:00:00Z".parse::<Timestamp>()?;
+        assert_eq!(
+            format_timestamp_in(end, &TimeZone::UTC, now),
+            "2024-06-01 17:00 UTC (in 2h 13m)"
+        );
+        assert_eq!(
+            format_timestamp_in(now, &TimeZone::UTC, end),
+            "2024-06-01 14:47 UTC (2h 13m ago)"
+        );
+
+        let tz = TimeZone::fixed(jiff::tz::offset(2));
+        assert!(format_timestamp_in(end, &tz, now).starts_with("2024-06-01 19:00"));
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use crate::models::scope::{Scope, ScopeLevel};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A scope within a hierarchy of scopes, along with the values at the scope
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct ScopeTree<T> {
+    pub scope: Scope,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<T>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<ScopeTree<T>>,
+}
+
+impl<T> ScopeTree<T> {
+    /// Arrange values by their scope, where each scope is nested beneath the
+    /// narrowest scope that contains it
+    ///
+    /// The subscriptions and resource groups that contain a scope are
+    /// included, even without any values, such that resource groups and
+    /// resources are nested beneath their subscription.  Management groups
+    /// do not contain subscriptions by path, and are separate trees.
+    pub fn build<I>(entries: I) -> Vec<Self>
+    where
+        I: IntoIterator<Item = (Scope, Option<String>, T)>,
+    {
+        // scopes are compared case-insensitively, as ARM is not consistent
+        // about the case of scopes
+        let mut nodes = BTreeMap::<String, (Scope, Option<String>, Vec<T>)>::new();
+        for (scope, name, value) in entries {
+            for ancestor in implied_ancestors(&scope) {
+                nodes
+                    .entry(ancestor.0.to_lowercase())
+                    .or_insert_with(|| (ancestor, None, Vec::new()));
+            }
+            let node = nodes
+                .entry(scope.0.to_lowercase())
+                .or_insert_with(|| (scope, None, Vec::new()));
+            if node.1.is_none() {
+                node.1 = name;
+            }
+            node.2.push(value);
+        }
+
+        let mut roots = Vec::new();
+        let mut children = BTreeMap::<String, Vec<String>>::new();
+        for key in nodes.keys() {
+            match parent(key, &nodes) {
+                Some(parent) => children.entry(parent).or_default().push(key.clone()),
+                None => roots.push(key.clone()),
+            }
+        }
+
+        roots
+            .into_iter()
+            .filter_map(|key| Self::take(&key, &mut nodes, &children))
+            .collect()
+    }
+
+    fn take(
+        key: &str,
+        nodes: &mut BTreeMap<String, (Scope, Option<String>, Vec<T>)>,
+        children: &BTreeMap<String, Vec<String>>,
+    ) -> Option<Self> {
+        let (scope, name, values) = nodes.remove(key)?;
+        let children = children
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter_map(|child| Self::take(child, nodes, children))
+            .collect();
+        Some(Self {
+            scope,
+            name,
+            values,
+            children,
+        })
+    }
+
+    /// The name of the scope, or the path of the scope relative to `parent`
+    #[must_use]
+    pub fn display_name(&self, parent: Option<&Scope>) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        parent
+            .and_then(|parent| {
+                self.scope
+                    .0
+                    .get(parent.0.len()..)
+                    .filter(|_| parent.0.len() > 1)
+            })
+            .map_or_else(
+                || self.scope.to_string(),
+                |x| x.trim_start_matches('/').to_string(),
+            )
+    }
+
+    /// Remove the scopes nested more than `depth` levels beneath each tree's
+    /// top scope
+    pub fn truncate(trees: &mut [Self], depth: usize) {
+        for tree in trees {
+            match depth.checked_sub(1) {
+                Some(depth) => Self::truncate(&mut tree.children, depth),
+                None => tree.children.clear(),
+            }
+        }
+    }
+
+    /// Render the trees as text, with one line for each scope
+    ///
+    /// `label` is called with each scope and its parent's scope.
+    pub fn render<F>(trees: &[Self], label: F) -> String
+    where
+        F: Fn(&Self, Option<&Scope>) -> String,
+    {
+        let mut lines = Vec::new();
+        for tree in trees {
+            lines.push(label(tree, None));
+            tree.render_children("", &label, &mut lines);
+        }
+        lines.join("\n")
+    }
+
+    fn render_children<F>(&self, prefix: &str, label: &F, lines: &mut Vec<String>)
+    where
+        F: Fn(&Self, Option<&Scope>) -> String,
+    {
+        let count = self.children.len();
+        for (i, child) in self.children.iter().enumerate() {
+            let last = i + 1 == count;
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            lines.push(format!(
+                "{prefix}{branch}{}",
+                label(child, Some(&self.scope))
+            ));
+            child.render_children(&format!("{prefix}{indent}"), label, lines);
+        }
+    }
+}
+
+/// The subscription and resource group that contain a scope
+fn implied_ancestors(scope: &Scope) -> Vec<Scope> {
+    let level = scope.level();
+    let Some(subscription) = scope.subscription() else {
+        return Vec::new();
+    };
+    let mut ancestors = Vec::new();
+    if level > ScopeLevel::Subscription {
+        ancestors.push(Scope::from_subscription(&subscription));
+    }
+    if level > ScopeLevel::ResourceGroup {
+        let parts = scope.0.split('/').collect::<Vec<_>>();
+        if let (Some(kind), Some(name)) = (parts.get(3), parts.get(4)) {
+            if kind.eq_ignore_ascii_case("resourceGroups") {
+                ancestors.push(Scope::from_resource_group(&subscription, name));
+            }
+        }
+    }
+    ancestors
+}
+
+/// The narrowest of the other scopes that contains the scope
+fn parent<V>(key: &str, nodes: &BTreeMap<String, V>) -> Option<String> {
+    let mut current = key;
+    while let Some((prefix, _)) = current.rsplit_once('/') {
+        let candidate = if prefix.is_empty() { "/" } else { prefix };
+        if candidate != key && nodes.contains_key(candidate) {
+            return Some(candidate.to_string());
+        }
+        current = prefix;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScopeTree;
+    use crate::models::scope::Scope;
+    use anyhow::Result;
+
+    #[test]
+    fn test_build() -> Result<()> {
+        let sub = "/subscriptions/00000000-0000-0000-0000-000000000000";
+        let entries = [
+            (Scope::new(sub)?, Some("my sub".to_string()), "Reader"),
+            (
+                Scope::new(format!(
+                    "{sub}/resourcegroups/rg1/providers/Microsoft.Storage/storageAccounts/sa"
+                ))?,
+                None,
+                "Storage Blob Data Reader",
+            ),
+            (
+                Scope::new(format!("{sub}/resourceGroups/rg1"))?,
+                None,
+                "Contributor",
+            ),
+            (
+                Scope::new(format!("{sub}/resourceGroups/rg2"))?,
+                None,
+                "Owner",
+            ),
+            (
+                Scope::new("/providers/Microsoft.Management/managementGroups/mg")?,
+                None,
+                "Reader",
+            ),
+        ];
+        let trees = ScopeTree::build(entries);
+        let rendered = ScopeTree::render(&trees, |node, parent| {
+            format!("{} [{}]", node.display_name(parent), node.values.join(", "))
+        });
+        assert_eq!(
+            rendered,
+            [
+                "/providers/Microsoft.Management/managementGroups/mg [Reader]",
+                "my sub [Reader]",
+                "├── resourceGroups/rg1 [Contributor]",
+                "│   └── providers/Microsoft.Storage/storageAccounts/sa [Storage Blob Data Reader]",
+                "└── resourceGroups/rg2 [Owner]",
+            ]
+            .join("\n")
+        );
+
+        let mut trees = trees;
+        ScopeTree::truncate(&mut trees, 1);
+        let rendered = ScopeTree::render(&trees, ScopeTree::display_name);
+        assert_eq!(
+            rendered,
+            [
+                "/providers/Microsoft.Management/managementGroups/mg",
+                "my sub",
+                "├── resourceGroups/rg1",
+                "└── resourceGroups/rg2",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+}
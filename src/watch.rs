@@ -0,0 +1,157 @@
+use crate::{
+    dashboard::format_remaining,
+    models::roles::{RoleAssignment, RolesExt},
+    ActivationOverrides, ActivationResult, ListFilter, PimClient,
+};
+use anyhow::{ensure, Result};
+use humantime::format_duration;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    thread::sleep,
+    time::{Duration, SystemTime},
+};
+use tracing::{debug, error, info, warn};
+
+// upper bound on the delay between attempts after repeated failures
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 30);
+
+pub struct WatchSettings {
+    /// Justification used when activating or extending roles
+    pub justification: String,
+    /// Duration for roles to be active
+    pub duration: Duration,
+    /// Renew roles that expire within this duration
+    pub renew_before: Duration,
+    /// How often to check the active roles
+    pub interval: Duration,
+    /// Stop watching at this time
+    pub until: Option<SystemTime>,
+}
+
+/// Keep the specified roles active until `settings.until`, or forever
+///
+/// Roles that are not active are activated.  Active roles that expire within
+/// `settings.renew_before` are extended.  When any of the roles cannot be
+/// renewed, the next attempt is delayed using exponential backoff.
+///
+/// # Errors
+/// Will return `Err` if no roles are specified
+pub fn watch(
+    client: &PimClient,
+    roles: &BTreeMap<RoleAssignment, ActivationOverrides>,
+    settings: &WatchSettings,
+) -> Result<()> {
+    ensure!(!roles.is_empty(), "no roles specified");
+
+    let mut failures = 0_u32;
+    loop {
+        let now = SystemTime::now();
+        let remaining = settings
+            .until
+            .map(|until| until.duration_since(now).unwrap_or_default());
+        if remaining.is_some_and(|x| x.is_zero()) {
+            info!("reached the end time, no longer watching roles");
+            return Ok(());
+        }
+
+        let delay = match renew(client, roles, settings, remaining) {
+            Ok(()) => {
+                failures = 0;
+                settings.interval
+            }
+            Err(err) => {
+                failures = failures.saturating_add(1);
+                let delay = backoff(settings.interval, failures);
+                warn!(
+                    "unable to renew roles, trying again in {}: {err:#}",
+                    format_duration(delay)
+                );
+                delay
+            }
+        };
+
+        sleep(remaining.map_or(delay, |x| x.min(delay)));
+    }
+}
+
+fn backoff(interval: Duration, failures: u32) -> Duration {
+    interval
+        .saturating_mul(2_u32.saturating_pow(failures))
+        .min(MAX_BACKOFF.max(interval))
+}
+
+fn renew(
+    client: &PimClient,
+    roles: &BTreeMap<RoleAssignment, ActivationOverrides>,
+    settings: &WatchSettings,
+    remaining: Option<Duration>,
+) -> Result<()> {
+    // roles expire without any requests being made, so always check the
+    // current state rather than a cached listing
+    client.clear_cache();
+    let active = client.list_active_role_assignments(None, Some(ListFilter::AsTarget))?;
+
+    let mut failed = BTreeSet::new();
+    for (entry, overrides) in roles {
+        let justification = overrides
+            .justification
+            .as_deref()
+            .unwrap_or(&settings.justification);
+        // don't request roles to be active past the end time
+        let duration = overrides.duration.unwrap_or(settings.duration);
+        let duration = remaining.map_or(duration, |x| x.min(duration));
+
+        let result = match active.get(entry) {
+            None => client
+                .activate_role_assignment_with_status(entry, justification, duration)
+                .map(|status| {
+                    if status == ActivationResult::PendingApproval {
+                        warn!("activating {} requires approval", entry.friendly());
+                    }
+                }),
+            Some(current) => match current.expires_in() {
+                Some(expires_in) if expires_in <= settings.renew_before => {
+                    client.extend_role_assignment(current, justification, duration)
+                }
+                Some(expires_in) => {
+                    debug!(
+                        "{} expires in {}",
+                        entry.friendly(),
+                        format_remaining(expires_in)
+                    );
+                    Ok(())
+                }
+                None => {
+                    debug!("{} does not expire", entry.friendly());
+                    Ok(())
+                }
+            },
+        };
+
+        if let Err(err) = result {
+            error!("unable to renew {}: {err:?}", entry.friendly());
+            failed.insert(entry.clone());
+        }
+    }
+
+    ensure!(
+        failed.is_empty(),
+        "failed to renew the following roles:\n{}",
+        failed.friendly()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff, MAX_BACKOFF};
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff() {
+        let interval = Duration::from_secs(60);
+        assert_eq!(backoff(interval, 1), Duration::from_secs(120));
+        assert_eq!(backoff(interval, 3), Duration::from_secs(480));
+        assert_eq!(backoff(interval, 100), MAX_BACKOFF);
+    }
+}